@@ -0,0 +1,30 @@
+use connection::message::{self, Message};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+// What a busy channel sends most, tags and all
+const PRIVMSG: &str = "@time=2024-05-01T12:00:00.000Z;msgid=abc123;account=alice :alice!~alice@user/alice PRIVMSG #rust :did anyone try the new borrow checker yet?";
+const NAMES: &str = ":irc.example.org 353 tester = #rust :@alice +bob carol dave erin frank grace heidi ivan judy mallory niaj olivia peggy rupert sybil trent victor walter";
+const CTCP: &str = ":bob!b@host PRIVMSG tester :\x01ACTION waves at everyone\x01";
+
+fn parse(c: &mut Criterion) {
+    c.bench_function("parse privmsg with tags", |b| {
+        b.iter(|| Message::parse(black_box(PRIVMSG)))
+    });
+    c.bench_function("parse 353 names reply", |b| {
+        b.iter(|| Message::parse(black_box(NAMES)))
+    });
+    c.bench_function("parse ctcp action", |b| {
+        b.iter(|| Message::parse(black_box(CTCP)).map(|msg| msg.ctcp().is_some()))
+    });
+}
+
+fn split(c: &mut Criterion) {
+    let long = "word ".repeat(400);
+    c.bench_function("split 2000 bytes of outgoing text", |b| {
+        b.iter(|| message::split_text(black_box(&long), 400))
+    });
+}
+
+criterion_group!(benches, parse, split);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use connection::buffer::{Buffer, BufferKind};
+use connection::clock::TimeFormat;
+use connection::config::ThemeConfig;
+use connection::render::LayoutCache;
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+// A server buffer of `count` lines, like the TUI keeps them: raw lines with highlights
+// and long messages that wrap mixed in
+fn buffer(count: usize) -> Buffer {
+    let mut buffer = Buffer::new("server", BufferKind::Server, count);
+    for i in 0..count {
+        let line = match i % 4 {
+            0 => format!(":alice!a@host PRIVMSG #rust :message number {}", i),
+            1 => format!(
+                ":bob!b@host PRIVMSG #rust :{}",
+                "a long message that has to wrap over several rows ".repeat(4)
+            ),
+            2 => format!("!!! :carol!c@host PRIVMSG #rust :tester: ping {}", i),
+            _ => format!(":dave!d@host JOIN #rust {}", i),
+        };
+        buffer.push(line);
+    }
+    buffer
+}
+
+fn layout() -> LayoutCache {
+    LayoutCache::new(TimeFormat::default(), &ThemeConfig::default())
+}
+
+fn render(c: &mut Criterion) {
+    let full = buffer(1000);
+    // The first frame of a buffer, or any frame after a resize, wraps every kept line
+    c.bench_function("wrap 1000 lines at 120 columns", |b| {
+        b.iter_batched(
+            layout,
+            |mut layout| layout.rows(black_box(&full), 120, 50, true).len(),
+            BatchSize::SmallInput,
+        )
+    });
+    // A frame after one line arrived, which is what the TUI draws most
+    c.bench_function("frame after one new line", |b| {
+        let mut buffer = buffer(1000);
+        let mut layout = layout();
+        layout.rows(&buffer, 120, 50, true);
+        let mut i = 0;
+        b.iter(|| {
+            i += 1;
+            buffer.push(format!(":alice!a@host PRIVMSG #rust :new line {}", i));
+            layout.rows(black_box(&buffer), 120, 50, true).len()
+        })
+    });
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
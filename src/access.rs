@@ -0,0 +1,166 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::config::AccessStyle;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+
+// One row of a channel's ChanServ access list. `flags` holds the level with ACCESS
+// style services
+pub struct AccessEntry {
+    pub mask: String,
+    pub flags: String,
+}
+
+// The access list shown in the ChanServ panel
+pub struct AccessList {
+    pub channel: String,
+    pub entries: Vec<AccessEntry>,
+    // A listing was asked for and its ChanServ notices are still coming in
+    pub loading: bool,
+    // A change was sent, the list is reloaded once ChanServ has answered it
+    pub changing: bool,
+    // What ChanServ said instead of a list, e.g. that we lack access
+    pub status: Option<String>,
+}
+
+impl App {
+    // /access [#channel], /access add mask flags|level, /access del mask
+    pub(crate) fn access_command(&mut self, args: &str) {
+        let words: Vec<&str> = args.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {
+                let channel = self.client.current_channel.clone();
+                if channel.is_empty() {
+                    self.buffers
+                        .push_local(tr("Usage: /access #channel, or join one first").to_string());
+                } else {
+                    self.show_access(&channel);
+                }
+            }
+            [channel] if channel.starts_with('#') => self.show_access(channel),
+            ["add", mask, flags] => self.access_change(mask, Some(flags)),
+            ["del", mask] => self.access_change(mask, None),
+            _ => self.buffers.push_local(
+                tr("Usage: /access [#channel], /access add mask flags|level, /access del mask")
+                    .to_string(),
+            ),
+        }
+    }
+
+    // Opens the panel for `channel` and asks ChanServ for its list
+    pub fn show_access(&mut self, channel: &str) {
+        self.access = Some(AccessList {
+            channel: channel.to_string(),
+            entries: Vec::new(),
+            loading: false,
+            changing: false,
+            status: None,
+        });
+        self.access_selected = 0;
+        self.buffers.open("access", BufferKind::Access);
+        self.buffers.select_name("access");
+        self.refresh_access();
+    }
+
+    pub fn refresh_access(&mut self) {
+        let Some(list) = &mut self.access else {
+            return;
+        };
+        list.entries.clear();
+        list.status = None;
+        list.loading = true;
+        let command = match self.config.chanserv.style {
+            AccessStyle::Flags => format!("FLAGS {}", list.channel),
+            AccessStyle::Access => format!("ACCESS {} LIST", list.channel),
+        };
+        if let Err(e) = self.client.send_message("ChanServ", &command) {
+            list.loading = false;
+            list.status = Some(trf("Error sending to ChanServ: {}", &[&e]));
+        }
+    }
+
+    // Adds or changes the entry for `mask`, or removes it when `flags` is None, then
+    // reloads the list
+    pub fn access_change(&mut self, mask: &str, flags: Option<&str>) {
+        let channel = match &self.access {
+            Some(list) => list.channel.clone(),
+            None => self.client.current_channel.clone(),
+        };
+        if channel.is_empty() {
+            self.buffers
+                .push_local(tr("No channel, open one with /access #channel").to_string());
+            return;
+        }
+        let command = match (self.config.chanserv.style, flags) {
+            (AccessStyle::Flags, Some(flags)) => format!("FLAGS {} {} {}", channel, mask, flags),
+            (AccessStyle::Flags, None) => format!("FLAGS {} {} -*", channel, mask),
+            (AccessStyle::Access, Some(level)) => {
+                format!("ACCESS {} ADD {} {}", channel, mask, level)
+            }
+            (AccessStyle::Access, None) => format!("ACCESS {} DEL {}", channel, mask),
+        };
+        match self.client.send_message("ChanServ", &command) {
+            Ok(_) => self
+                .buffers
+                .server_mut()
+                .push(format!("-> *ChanServ* {}", command)),
+            Err(e) => {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Error sending to ChanServ: {}", &[&e]));
+                return;
+            }
+        }
+        if let Some(list) = &mut self.access {
+            list.changing = true;
+        }
+    }
+
+    // ChanServ notices answering a listing go into the panel instead of the buffer.
+    // Handles both Atheme's "1 mask +flags" and Anope's "1 level mask" rows
+    pub(crate) fn access_reply(&mut self, msg: &Message) -> bool {
+        let Some(list) = &mut self.access else {
+            return false;
+        };
+        if msg.command != "NOTICE"
+            || !msg
+                .nick()
+                .is_some_and(|n| n.eq_ignore_ascii_case("ChanServ"))
+        {
+            return false;
+        }
+        // The answer to a change, shown as usual
+        if list.changing {
+            list.changing = false;
+            self.refresh_access();
+            return false;
+        }
+        if !list.loading {
+            return false;
+        }
+        let text = msg.trailing().unwrap_or_default().trim();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() >= 3 && words[0].parse::<u32>().is_ok() {
+            let level_first = words[1].trim_start_matches('-').parse::<i32>().is_ok();
+            let (mask, flags) = match self.config.chanserv.style {
+                AccessStyle::Access if level_first => (words[2], words[1]),
+                _ => (words[1], words[2]),
+            };
+            list.entries.push(AccessEntry {
+                mask: mask.to_string(),
+                flags: flags.to_string(),
+            });
+        } else if text.to_lowercase().starts_with("end of") {
+            list.loading = false;
+        } else if !(text.starts_with('-')
+            || text.starts_with("Entry")
+            || text.starts_with("Number")
+            || text.to_lowercase().contains("list for"))
+        {
+            // Anything else means there is no list coming, e.g. "You are not authorized"
+            list.loading = false;
+            list.status = Some(text.to_string());
+        }
+        true
+    }
+}
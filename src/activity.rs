@@ -0,0 +1,120 @@
+use crate::app::App;
+use crate::channel;
+use crate::i18n::{tr, trf};
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// How far back the messages per hour go
+pub const HOURS: u64 = 24;
+
+// Messages in one channel during this session
+pub struct ChannelActivity {
+    pub name: String,
+    // (hour since the epoch, messages), oldest first, only hours with messages
+    hours: VecDeque<(u64, u64)>,
+    // Messages per nick on `day`, a UTC day since the epoch
+    talkers: HashMap<String, u64>,
+    day: u64,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl ChannelActivity {
+    fn new(name: &str) -> Self {
+        ChannelActivity {
+            name: name.to_string(),
+            hours: VecDeque::new(),
+            talkers: HashMap::new(),
+            day: 0,
+        }
+    }
+
+    fn count(&mut self, nick: &str) {
+        let now = now_secs();
+        let hour = now / 3600;
+        match self.hours.back_mut() {
+            Some((last, count)) if *last == hour => *count += 1,
+            _ => self.hours.push_back((hour, 1)),
+        }
+        while self.hours.front().is_some_and(|(h, _)| h + HOURS <= hour) {
+            self.hours.pop_front();
+        }
+        if now / 86400 != self.day {
+            self.day = now / 86400;
+            self.talkers.clear();
+        }
+        *self.talkers.entry(nick.to_string()).or_default() += 1;
+    }
+
+    // Messages in each of the last HOURS hours, oldest first, the current one last
+    pub fn per_hour(&self) -> Vec<u64> {
+        let hour = now_secs() / 3600;
+        let mut counts = vec![0; HOURS as usize];
+        for (h, count) in &self.hours {
+            if let Some(ago) = hour.checked_sub(*h).filter(|ago| *ago < HOURS) {
+                counts[(HOURS - 1 - ago) as usize] = *count;
+            }
+        }
+        counts
+    }
+
+    // The `count` nicks that said the most today (UTC), busiest first
+    pub fn top_talkers(&self, count: usize) -> Vec<(String, u64)> {
+        if self.day != now_secs() / 86400 {
+            return Vec::new();
+        }
+        let mut talkers: Vec<(String, u64)> = self
+            .talkers
+            .iter()
+            .map(|(nick, n)| (nick.clone(), *n))
+            .collect();
+        talkers.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        talkers.truncate(count);
+        talkers
+    }
+}
+
+// Activity of every channel we saw messages in, by channel key
+#[derive(Default)]
+pub struct Activity {
+    channels: HashMap<String, ChannelActivity>,
+}
+
+impl Activity {
+    pub fn record(&mut self, name: &str, nick: &str) {
+        self.channels
+            .entry(channel::nick_key(name))
+            .or_insert_with(|| ChannelActivity::new(name))
+            .count(nick);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ChannelActivity> {
+        self.channels.get(&channel::nick_key(name))
+    }
+}
+
+impl App {
+    // /stats [#channel] opens the activity overlay, for the current channel by default
+    pub(crate) fn stats_command(&mut self, name: &str) {
+        let name = match name {
+            "" => self.client.current_channel.clone(),
+            name => name.to_string(),
+        };
+        if name.is_empty() {
+            self.buffers.push_local(
+                tr("Usage: /stats #channel, or /stats <query> to ask the server").to_string(),
+            );
+            return;
+        }
+        if self.activity.get(&name).is_none() {
+            self.buffers
+                .push_local(trf("No messages in {} yet this session", &[&name]));
+            return;
+        }
+        self.stats_shown = Some(name);
+    }
+}
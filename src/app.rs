@@ -0,0 +1,1917 @@
+use crate::access::AccessList;
+use crate::activity::Activity;
+use crate::auth::SecretPrompt;
+use crate::automode::PendingMode;
+use crate::banlist::BanList;
+use crate::batch::NetBatch;
+use crate::buffer::{BufferKind, BufferList};
+use crate::channel;
+use crate::clock;
+use crate::composer::Composer;
+use crate::config::Config;
+use crate::control::ControlSocket;
+use crate::ctcp::CtcpLimiter;
+use crate::dbus::{Dbus, DbusSignal};
+use crate::dcc::{self, Transfer};
+use crate::dcc_chat::Chat;
+use crate::filter::Filter;
+use crate::fish::FishKey;
+use crate::help::HelpPager;
+use crate::http_api::{self, HttpRequest};
+use crate::i18n::{tr, trf};
+use crate::ignore::Ignore;
+use crate::invite::Invite;
+use crate::irc_client::{self, IrcClient};
+use crate::joins;
+use crate::keys::ChannelKeys;
+use crate::kick::Rejoin;
+use crate::logging;
+use crate::message::Message;
+use crate::nick_menu::NickMenu;
+use crate::outbox::Queued;
+use crate::palette::Palette;
+use crate::paste::PendingPaste;
+use crate::relay::{self, Relay};
+use crate::seen::Seen;
+use crate::server_info;
+use crate::servers::Target;
+use crate::services;
+use crate::settings::Settings;
+use crate::sounds::SoundEvent;
+use crate::topic::TopicEditor;
+use crate::transport::Connection;
+use encoding_rs::{Encoding, WINDOWS_1252};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// Longest message text we put into a single PRIVMSG, leaving room for the prefix the
+// server adds when relaying it
+pub const MAX_MESSAGE_BYTES: usize = 400;
+
+// Commands refused from anywhere but the input box, see execute_remote
+const LOCAL_ONLY: [&str; 1] = ["/exec"];
+
+// An /exec is stopped after EXEC_TIMEOUT, and keeps EXEC_MAX_BYTES of each of its stdout
+// and stderr. /exec -o sends at most EXEC_MAX_LINES lines to the channel
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+const EXEC_MAX_BYTES: usize = 64 * 1024;
+const EXEC_MAX_LINES: usize = 20;
+
+// Events produced by background jobs and delivered to the main loop
+pub enum AppEvent {
+    // Output of a finished /exec. stdout is either shown in `buffer` or sent to `channel`,
+    // stderr is always shown in `buffer`
+    ExecOutput {
+        command: String,
+        buffer: String,
+        channel: Option<String>,
+        lines: Vec<String>,
+        errors: Vec<String>,
+        status: Option<i32>,
+    },
+    // The shell for an /exec could not be started at all
+    ExecFailed {
+        command: String,
+        buffer: String,
+        error: String,
+    },
+    // A line received on the control socket, answered through `reply`
+    Control {
+        command: String,
+        reply: Sender<Result<String, String>>,
+    },
+    // A relay frontend authenticated, `writer` is its side of the connection. `local` ones
+    // came in on the Unix socket
+    RelayAttach {
+        id: usize,
+        writer: Box<dyn Write + Send>,
+        local: bool,
+    },
+    RelayRequest {
+        id: usize,
+        line: String,
+    },
+    RelayDetach {
+        id: usize,
+    },
+    // A request to the HTTP API, answered through `reply` with a status and a JSON body
+    Http {
+        request: HttpRequest,
+        reply: Sender<(u16, serde_json::Value)>,
+    },
+    // Bytes moved so far by a running DCC transfer
+    DccProgress {
+        id: usize,
+        transferred: u64,
+    },
+    DccFinished {
+        id: usize,
+        result: Result<u64, String>,
+    },
+    // A DCC CHAT connection is up, `writer` is our side of it
+    DccChatOpened {
+        id: usize,
+        writer: TcpStream,
+    },
+    DccChatLine {
+        id: usize,
+        line: String,
+    },
+    DccChatClosed {
+        id: usize,
+        error: Option<String>,
+    },
+    // A held back paste was uploaded, `result` is the link or why it failed
+    PasteUploaded {
+        target: String,
+        result: Result<String, String>,
+    },
+    // A webhook POST did not go through
+    WebhookFailed {
+        error: String,
+    },
+    Dbus(DbusSignal),
+    // A connection opened by servers.rs reached TCP and started its TLS handshake
+    Handshaking {
+        attempt: u32,
+    },
+    Connected {
+        attempt: u32,
+        target: Target,
+        result: Result<Connection, String>,
+    },
+}
+
+// Everything the client knows at runtime, shared by the input handlers and the drawing code
+pub struct App {
+    pub client: IrcClient,
+    pub buffers: BufferList,
+    pub config: Config,
+    // Commands with descriptions
+    pub commands: BTreeMap<&'static str, &'static str>,
+    pub should_quit: bool,
+    // Set when something wants the user's attention, the TUI rings the terminal bell
+    pub bell: bool,
+    pub relay: Relay,
+    // Desktop notifications shown and whether a suspend disconnected us, see dbus.rs
+    pub dbus: Dbus,
+    // DCC transfers of this session, offered, running and finished
+    pub transfers: Vec<Transfer>,
+    pub chats: Vec<Chat>,
+    pub ctcp_limiter: CtcpLimiter,
+    // Row highlighted in the transfers panel
+    pub transfer_selected: usize,
+    // Open netsplit and netjoin batches by reference, see batch.rs
+    pub batches: HashMap<String, NetBatch>,
+    pub invites: Vec<Invite>,
+    pub channel_keys: ChannelKeys,
+    // Hidden input the TUI is asking for, see auth.rs
+    pub secret_prompt: Option<SecretPrompt>,
+    // Keys sent with a JOIN, saved once the server lets us in
+    pub pending_keys: HashMap<String, String>,
+    // Row highlighted in the invitations panel
+    pub invite_selected: usize,
+    // Nick we sent NickServ GHOST for, taken with NICK once NickServ replies
+    pub ghosting: Option<String>,
+    // The ChanServ panel, see access.rs
+    pub access: Option<AccessList>,
+    pub access_selected: usize,
+    // The bans panel, see banlist.rs
+    pub bans: Option<BanList>,
+    pub ban_selected: usize,
+    // Lines waiting for /paste, see paste.rs
+    pub pending_paste: Option<PendingPaste>,
+    // Per-buffer preferences, see settings.rs
+    pub settings: Settings,
+    // Where the receiver thread of a new connection sends its lines, see servers.rs
+    pub server_lines: Option<Sender<String>>,
+    // Channels to rejoin after a kick by channel key, see kick.rs
+    pub rejoins: HashMap<String, Rejoin>,
+    // Messages typed while offline, see outbox.rs
+    pub(crate) outbox: Vec<Queued>,
+    // Added with /ignore or the nick menu, on top of [ignore] in the config
+    pub(crate) ignores: Vec<Ignore>,
+    // [[filters]] compiled, see filter.rs
+    pub(crate) filters: Vec<Filter>,
+    // FiSH keys by lowercased channel or nick, see fish.rs
+    pub(crate) fish_keys: HashMap<String, FishKey>,
+    // Server and "#channel [key]" of an irc:// link we are connecting for, joined on 001
+    pub url_join: Option<(String, String)>,
+    // The latest connection opened on a thread, and the wait before the last reconnect
+    // while we keep failing to get back, see servers.rs
+    pub(crate) connect_attempt: u32,
+    pub(crate) reconnect_delay: Option<u64>,
+    // Replies to /motd, /time and the like by command, until the last one is in
+    pub server_info: HashMap<&'static str, Vec<String>>,
+    // Messages per channel over time, and the channel /stats shows them for
+    pub activity: Activity,
+    pub stats_shown: Option<String>,
+    // The /help overlay while it is open, see help.rs
+    pub help: Option<HelpPager>,
+    // The Ctrl+P command palette while it is open, see palette.rs
+    pub palette: Option<Palette>,
+    // The /topic -edit overlay while it is open, see topic.rs
+    pub topic_editor: Option<TopicEditor>,
+    // The Alt+Enter composer for messages of several lines, see composer.rs
+    pub composer: Option<Composer>,
+    // When nicks were last seen and doing what, see seen.rs
+    pub seen: Seen,
+    // Modes for trusted users that just joined, see automode.rs
+    pub pending_modes: Vec<PendingMode>,
+    // Row highlighted in the nick list, and the action menu for a nick, see nick_menu.rs
+    pub nick_selected: Option<usize>,
+    // Some while the nick list has the keyboard, with what was typed to filter it
+    pub nick_filter: Option<String>,
+    pub nick_menu: Option<NickMenu>,
+    // Nick changes the Tab completion in progress has not caught up with, see nick_change.rs
+    pub renamed: Vec<(String, String)>,
+    // Alt+Z gives the chat pane the whole screen, without margins, nick list or topic bar
+    pub zoomed: bool,
+    // Esc was pressed once with [quit] confirm on, the next Esc quits
+    pub confirm_quit: bool,
+    // When the last WHO poll went out and which channel is next, see who.rs
+    pub(crate) who_polled: Instant,
+    pub(crate) who_next: usize,
+    // Running as a daemon, nobody sees anything unless a frontend is attached
+    pub headless: bool,
+    // Buffer that rang the bell last, announced in accessible mode
+    pub alert: Option<String>,
+    // /sounds off, see sounds.rs
+    pub sounds_muted: bool,
+    // Whether the terminal window has focus, as far as it reports focus changes
+    pub focused: bool,
+    // Lines put in the awaylog since the user was last told about them
+    pub missed: usize,
+    pub(crate) events: Sender<AppEvent>,
+    // Lines waiting to be sent, as (target, text)
+    pub(crate) outgoing: VecDeque<(String, String)>,
+    last_send: Option<Instant>,
+    // Lines the flood limit allowed at last_send that were not used yet, and how many of
+    // the queue went out since it was last empty, for the progress in the input title
+    send_credit: f64,
+    outgoing_sent: usize,
+    // Last keyboard input, for auto-away
+    last_activity: Instant,
+    // Whether the current away state was set by auto-away, which also clears it again
+    auto_away: bool,
+    // Channels we sent /who for, summarised once the reply ends
+    who_pending: HashSet<String>,
+    // Nicks we sent /whois for
+    pub(crate) whois_pending: HashSet<String>,
+    // Replies to /whowas by nick_key, until RPL_ENDOFWHOWAS
+    pub(crate) whowas: HashMap<String, Vec<String>>,
+    // Buffer on screen at the last tick, to notice when the user leaves one
+    shown_buffer: String,
+    // Current channel and buffer on screen at the last tick, see route.rs
+    pub(crate) followed: (String, String),
+}
+
+impl App {
+    pub fn new(client: IrcClient, config: Config, events: Sender<AppEvent>) -> Self {
+        let mut app = App {
+            client,
+            buffers: BufferList::new(config.history.clone()),
+            config,
+            commands: BTreeMap::from([
+                (
+                    "/help",
+                    "Browse every command, key and config setting, / searches",
+                ),
+                ("/away", "Mark yourself away: /away [message]"),
+                ("/back", "Clear your away status"),
+                (
+                    "/amsg",
+                    "Send a message to every channel you are in: /amsg message",
+                ),
+                (
+                    "/ban",
+                    "Ban a user's host from the current channel: /ban nick",
+                ),
+                (
+                    "/banlist",
+                    "Show a channel's bans: /banlist [#channel], u lifts the highlighted one",
+                ),
+                (
+                    "/caps",
+                    "List the IRCv3 capabilities we request, have enabled and the server offers",
+                ),
+                (
+                    "/access",
+                    "ChanServ access list: /access [#channel] opens the panel, /access add mask flags, /access del mask",
+                ),
+                ("/clear", "Clear the chat window"),
+                (
+                    "/ctcp",
+                    "Send a CTCP query and show the reply: /ctcp nick VERSION|PING|TIME|CLIENTINFO|<command> [args]",
+                ),
+                (
+                    "/connect",
+                    "Leave the current server for another: /connect host [port] [-tls], or /server",
+                ),
+                (
+                    "/disconnect",
+                    "Quit the server but keep the client open: /disconnect [network] [reason]",
+                ),
+                (
+                    "/reconnect",
+                    "Connect again to the last server, with the same port and TLS",
+                ),
+                (
+                    "/buffer",
+                    "Show or change this buffer's settings: /buffer set hide_joins|mute|log on|off, /buffer set highlights word,word, /buffer set sound file|off",
+                ),
+                (
+                    "/theme",
+                    "List the built-in color themes or switch to one: /theme [name]",
+                ),
+                (
+                    "/sounds",
+                    "Turn sound alerts and the bell on or off: /sounds [on|off]",
+                ),
+                (
+                    "/sortnicks",
+                    "Order this channel's nick list by status, name or who spoke last: /sortnicks [status|alpha|activity]",
+                ),
+                (
+                    "/filter",
+                    "Hide, dim or move messages matching regexes: /filter add hide|dim|move [sender:re] [target:re] [content re], /filter del <n>, /filter list",
+                ),
+                (
+                    "/fish",
+                    "Encrypt messages with a channel or nick using FiSH: /fish set [target] key, /fish del [target], /fish list",
+                ),
+                (
+                    "/ignore",
+                    "Drop messages from a nick or mask, for a while or this session: /ignore nick [30m|2h|1d], /ignore -list",
+                ),
+                ("/unignore", "Take back an /ignore: /unignore nick|mask"),
+                (
+                    "/open",
+                    "Connect to an irc:// or ircs:// link and join its channel: /open [url], the last link in the buffer by default",
+                ),
+                (
+                    "/queue",
+                    "List messages waiting for the connection or channel to come back, or drop them: /queue [clear]",
+                ),
+                (
+                    "/toggle",
+                    "Show or hide this buffer's timestamps, nick list or topic bar (F2, F3, F4): /toggle timestamps|nicklist|topic",
+                ),
+                (
+                    "/memory",
+                    "Show how many lines each buffer keeps and the memory they take",
+                ),
+                ("/dashboard", "Show the network dashboard"),
+                (
+                    "/dcc",
+                    "Direct connections: /dcc shows transfers, /dcc send nick file, /dcc get id, /dcc resume id, /dcc retry id, /dcc chat nick, /dcc close id, /dcc list",
+                ),
+                (
+                    "/encoding",
+                    "Show or set the character set for this network: /encoding [latin1|utf-8|...]",
+                ),
+                (
+                    "/paste",
+                    "Upload lines held back as too many to a paste service and send the link: /paste [send|cancel]",
+                ),
+                (
+                    "/exec",
+                    "Run a shell command: /exec [-o] command (-o sends output)",
+                ),
+                (
+                    "/ghost",
+                    "Free the nick we were given a fallback for with NickServ GHOST, then take it",
+                ),
+                (
+                    "/invites",
+                    "Show pending invitations, j joins the highlighted one and d dismisses it",
+                ),
+                (
+                    "/join",
+                    "Join a channel: /join #channel [key], keys are remembered. One you are in becomes the current one",
+                ),
+                (
+                    "/part",
+                    "Leave a channel: /part [#channel] [reason], the current one by default",
+                ),
+                ("/umode", "Show or change your user modes: /umode [+x-i]"),
+                (
+                    "/debug",
+                    "Toggle the debug buffer showing every line sent and received",
+                ),
+                ("/raw", "Send a raw protocol line: /raw COMMAND params"),
+                (
+                    "/loglevel",
+                    "Change what goes to the log file: /loglevel debug, or per module like connection::irc_client=trace",
+                ),
+                (
+                    "/oper",
+                    "Become an IRC operator: /oper name password, server notices then get their own buffer",
+                ),
+                (
+                    "/kill",
+                    "Disconnect a user from the network, operators only: /kill nick reason",
+                ),
+                (
+                    "/wallops",
+                    "Message all users with +w, operators only: /wallops message",
+                ),
+                (
+                    "/mode",
+                    "Change modes: /mode [target] +modes [args], the target defaults to the current channel",
+                ),
+                (
+                    "/msg",
+                    "Send a private message: /msg target[,target...] message",
+                ),
+                ("/nick", "Change your nick: /nick newnick"),
+                (
+                    "/query",
+                    "Talk to a nick instead of the current channel: /query nick",
+                ),
+                (
+                    "/ns",
+                    "Send a command to NickServ: /ns command, or /nickserv",
+                ),
+                (
+                    "/cs",
+                    "Send a command to ChanServ: /cs command [#channel] args, the channel defaults to the current one",
+                ),
+                (
+                    "/ms",
+                    "Send a command to MemoServ: /ms command, or /memoserv",
+                ),
+                (
+                    "/os",
+                    "Send a command to OperServ: /os command, or /operserv",
+                ),
+                (
+                    "/regain",
+                    "Take back the nick we were given a fallback for with NickServ REGAIN",
+                ),
+                (
+                    "/topic",
+                    "Show the current channel's topic, or set it: /topic [text], /topic -edit to edit it",
+                ),
+                ("/quit", "Exit the application: /quit [message]"),
+                (
+                    "/stats",
+                    "Show a channel's messages per hour and today's top talkers: /stats [#channel], or ask the server: /stats <query> [server]",
+                ),
+                (
+                    "/motd",
+                    "Show the server's message of the day: /motd [server]",
+                ),
+                ("/time", "Show the server's local time: /time [server]"),
+                (
+                    "/version",
+                    "Show which software the server runs: /version [server]",
+                ),
+                (
+                    "/admin",
+                    "Show who runs the server and how to reach them: /admin [server]",
+                ),
+                ("/links", "List the servers of the network: /links [mask]"),
+                (
+                    "/seen",
+                    "When a nick last talked, joined, left or quit on this network: /seen nick",
+                ),
+                ("/whois", "Show information about a user: /whois nick"),
+                (
+                    "/whowas",
+                    "Show who used a nick that is gone now: /whowas nick [count]",
+                ),
+                (
+                    "/host",
+                    "Show the nick!user@host of someone we have seen: /host nick",
+                ),
+                (
+                    "/who",
+                    "Refresh who is here and away: /who [#channel], defaults to the current one",
+                ),
+            ]),
+            should_quit: false,
+            bell: false,
+            relay: Relay::default(),
+            dbus: Dbus::default(),
+            transfers: Vec::new(),
+            chats: Vec::new(),
+            ctcp_limiter: CtcpLimiter::default(),
+            transfer_selected: 0,
+            batches: HashMap::new(),
+            invites: Vec::new(),
+            channel_keys: ChannelKeys::default(),
+            secret_prompt: None,
+            pending_keys: HashMap::new(),
+            invite_selected: 0,
+            events,
+            outgoing: VecDeque::new(),
+            last_send: None,
+            send_credit: 0.0,
+            outgoing_sent: 0,
+            ghosting: None,
+            access: None,
+            access_selected: 0,
+            bans: None,
+            ban_selected: 0,
+            pending_paste: None,
+            settings: Settings::load(),
+            server_lines: None,
+            rejoins: HashMap::new(),
+            outbox: Vec::new(),
+            ignores: Vec::new(),
+            filters: Vec::new(),
+            fish_keys: HashMap::new(),
+            url_join: None,
+            connect_attempt: 0,
+            reconnect_delay: None,
+            pending_modes: Vec::new(),
+            seen: Seen::load(),
+            activity: Activity::default(),
+            server_info: HashMap::new(),
+            help: None,
+            palette: None,
+            topic_editor: None,
+            composer: None,
+            stats_shown: None,
+            nick_selected: None,
+            nick_filter: None,
+            zoomed: false,
+            confirm_quit: false,
+            nick_menu: None,
+            renamed: Vec::new(),
+            who_polled: Instant::now(),
+            who_next: 0,
+            headless: false,
+            alert: None,
+            sounds_muted: false,
+            focused: true,
+            missed: 0,
+            last_activity: Instant::now(),
+            auto_away: false,
+            who_pending: HashSet::new(),
+            whois_pending: HashSet::new(),
+            whowas: HashMap::new(),
+            shown_buffer: String::new(),
+            followed: (String::new(), String::new()),
+        };
+        app.sounds_muted = app.config.sounds.muted;
+        app.apply_encoding();
+        app.apply_parser_mode();
+        app.load_filters();
+        app.load_fish_keys();
+        app.load_channel_keys();
+        app.check_quiet_hours();
+        app
+    }
+
+    // Picks the encoding configured for this server, or for the network once 005 has
+    // named it
+    fn apply_encoding(&mut self) {
+        let encoding = &self.config.encoding;
+        let configured = encoding.for_network(&self.client.network, &self.client.server);
+        match configured.and_then(|name| Encoding::for_label(name.as_bytes())) {
+            Some(found) => self.client.set_encoding(found),
+            None => {
+                if let Some(name) = configured {
+                    self.buffers
+                        .server_mut()
+                        .push(trf("Unknown encoding '{}' in the config", &[&name]));
+                }
+                let fallback = Encoding::for_label(encoding.fallback.as_bytes());
+                self.client.set_fallback(fallback.unwrap_or(WINDOWS_1252));
+            }
+        }
+    }
+
+    // Starts the control socket and the relay if the config enables them. The returned
+    // socket must be kept alive for as long as it should accept commands
+    pub fn start_services(&mut self) -> Option<ControlSocket> {
+        if self.config.relay.enabled {
+            let relay = &self.config.relay;
+            let line = match relay::start(&relay.bind, &relay.password, self.events()) {
+                Ok(_) => trf("Relay listening on {}", &[&relay.bind]),
+                Err(e) => trf("Relay disabled: {}", &[&e]),
+            };
+            self.buffers.server_mut().push(line);
+        }
+
+        self.start_dbus();
+
+        if self.config.http.enabled {
+            let http = &self.config.http;
+            let line = match http_api::start(&http.bind, &http.token, self.events()) {
+                Ok(_) => trf("HTTP API listening on http://{}", &[&http.bind]),
+                Err(e) => trf("HTTP API disabled: {}", &[&e]),
+            };
+            self.buffers.server_mut().push(line);
+        }
+
+        if !self.config.control.enabled {
+            return None;
+        }
+        let Some(path) = self
+            .config
+            .control
+            .path
+            .clone()
+            .or_else(ControlSocket::default_path)
+        else {
+            self.buffers.server_mut().push(
+                tr("Control socket disabled: no runtime directory, set $XDG_RUNTIME_DIR or $HOME")
+                    .to_string(),
+            );
+            return None;
+        };
+        match ControlSocket::start(&path, self.events()) {
+            Ok(socket) => {
+                self.buffers.server_mut().push(trf(
+                    "Control socket listening on {}",
+                    &[&socket.path().display()],
+                ));
+                Some(socket)
+            }
+            Err(e) => {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Control socket disabled: {}", &[&e]));
+                None
+            }
+        }
+    }
+
+    // A handle background threads use to feed events into the main loop
+    pub fn events(&self) -> Sender<AppEvent> {
+        self.events.clone()
+    }
+
+    pub fn handle_server_line(&mut self, line: String) {
+        if let Some(report) = line.strip_prefix(irc_client::PROTOCOL_VIOLATION) {
+            self.report_violation(report);
+            return;
+        }
+        self.debug_incoming(&line);
+        if line == irc_client::CONNECTION_CLOSED {
+            self.sound(SoundEvent::Disconnect, "server");
+            self.buffers.server_mut().push(line);
+            self.client.connection_lost();
+            self.schedule_reconnect();
+            return;
+        }
+        // Decrypted first, everything below sees the line as it is shown
+        let (line, parsed) = match Message::parse(&line) {
+            Some(parsed) => {
+                let (line, parsed) = self.fish_decrypt(line, parsed);
+                (line, Some(parsed))
+            }
+            None => (line, None),
+        };
+        if let Some(parsed) = parsed {
+            if self.replayed(&parsed) {
+                return;
+            }
+            // Error numerics are 400-599. With labeled-response we know which command of
+            // ours caused them
+            if let Some(command) = self.client.labeled_command(&parsed)
+                && parsed.command.starts_with(['4', '5'])
+                && parsed.command.len() == 3
+                && !(command.starts_with("/join") && joins::is_join_error(&parsed.command))
+            {
+                self.buffers.server_mut().push(format!(
+                    "!!! Your {} failed: {}",
+                    command,
+                    parsed.trailing().unwrap_or(&parsed.command)
+                ));
+            }
+            self.client.handle_message(&parsed);
+            if parsed.command == "JOIN" {
+                self.auto_mode_join(&parsed);
+            }
+            self.flush_outbox(&parsed);
+            self.seen_message(&parsed);
+            if parsed.command == "PRIVMSG"
+                && let (Some(nick), Some(target)) = (parsed.nick(), parsed.param(0))
+                && target.starts_with(['#', '&'])
+            {
+                self.activity.record(target, nick);
+                self.client.spoke(target, nick);
+            }
+            if parsed.command == "001" {
+                self.sound(SoundEvent::Connect, "server");
+                self.reconnect_delay = None;
+            }
+            // Registered, the ident query is over one way or another
+            if parsed.command == "001"
+                && let Some(identd) = self.client.identd.take()
+            {
+                self.buffers.server_mut().push(identd.report());
+            }
+            // Before the joins, a vhost or umode should be in place when we show up
+            if parsed.command == "001" {
+                self.perform();
+            }
+            // The channels given in the first-run setup, on the server they were given for
+            if parsed.command == "001"
+                && self
+                    .client
+                    .server
+                    .eq_ignore_ascii_case(&self.config.connect.server)
+            {
+                for channel in self.config.connect.channels.clone() {
+                    self.join(&channel);
+                }
+            }
+            if parsed.command == "001"
+                && let Some((host, args)) = self.url_join.take()
+                && self.client.server.eq_ignore_ascii_case(&host)
+            {
+                self.join(&args);
+            }
+            if parsed.command == "005" {
+                self.apply_encoding();
+                // Servers send several 005 lines, only warn for the one with NICKLEN
+                if parsed.params.iter().any(|p| p.starts_with("NICKLEN="))
+                    && let Some(max) = self.client.nicklen
+                    && self.client.nickname.len() > max
+                {
+                    self.buffers.server_mut().push(trf(
+                        "!!! Your nick {} is longer than this server allows ({} bytes)",
+                        &[&self.client.nickname, &max],
+                    ));
+                }
+            }
+            self.nick_recovery(&parsed);
+            if self.nick_refused(&parsed) {
+                return;
+            }
+            if self.access_reply(&parsed) || self.ban_reply(&parsed) {
+                return;
+            }
+            if self.net_batch(&parsed) {
+                return;
+            }
+            self.channel_key_reply(&parsed);
+            self.rejoin_reply(&parsed);
+            if self.join_failed(&parsed) {
+                return;
+            }
+            self.secret_needed(&parsed);
+            if parsed.command == "INVITE" {
+                self.invite(&parsed);
+            }
+            // Bookkeeping between our clients, not worth a line in the buffer
+            if parsed.command == "MARKREAD" {
+                self.read_marker(&parsed);
+                return;
+            }
+            // RPL_ENDOFWHO for a /who we asked for
+            if parsed.command == "315"
+                && let Some(name) = parsed.param(1)
+                && self.who_pending.remove(&channel::nick_key(name))
+            {
+                self.who_summary(name);
+            }
+            if self.quiet_who(&parsed)
+                || self.server_info_reply(&parsed)
+                || self.whowas_reply(&parsed)
+            {
+                return;
+            }
+            self.whois_missing(&parsed);
+            // RPL_ENDOFWHOIS, add the account we know about even if the server sent no 330
+            if parsed.command == "318"
+                && let Some(nick) = parsed.param(1)
+                && self.whois_pending.remove(&channel::nick_key(nick))
+            {
+                let line = match self.client.account(nick) {
+                    Some(account) => trf("{} is logged in as {}", &[&nick, &account]),
+                    None => trf("{} is not logged in to services", &[&nick]),
+                };
+                self.buffers.server_mut().push(line);
+            }
+            if matches!(parsed.command.as_str(), "PRIVMSG" | "NOTICE")
+                && (self.is_ignored(&parsed) || self.filter(&parsed, &line))
+            {
+                return;
+            }
+            if self.announcement(&parsed) || self.server_notice(&parsed) {
+                return;
+            }
+            self.away_log(&parsed);
+            self.webhook(&parsed);
+            if matches!(parsed.command.as_str(), "KICK" | "REMOVE" | "PART") && self.kick(&parsed) {
+                return;
+            }
+            if parsed.command == "NICK" && self.nick_changed(&parsed) {
+                return;
+            }
+            // hide_joins and highlight words of the buffer the line is shown in
+            let buffer = self.route(&parsed);
+            let settings = self.buffer_settings(&buffer);
+            if settings.hide_joins
+                && matches!(parsed.command.as_str(), "JOIN" | "PART" | "QUIT")
+                && !parsed.nick().is_some_and(|nick| self.client.is_me(nick))
+            {
+                return;
+            }
+            // NickServ talking to us might be important, checked against the nick we have now
+            if matches!(parsed.command.as_str(), "PRIVMSG" | "NOTICE")
+                && parsed
+                    .nick()
+                    .is_some_and(|nick| nick.eq_ignore_ascii_case("NickServ"))
+                && parsed
+                    .param(0)
+                    .is_some_and(|target| self.client.is_me(target))
+            {
+                self.buffers
+                    .server_mut()
+                    .push(format!("!!! NICKSERV: {}", line));
+                return;
+            }
+            if matches!(parsed.command.as_str(), "PRIVMSG" | "NOTICE")
+                && parsed
+                    .trailing()
+                    .is_some_and(|text| settings.highlighted(text))
+                && !self.highlight_excluded(&parsed)
+            {
+                self.buffers.push_to(&buffer, format!("!!! {}", line));
+                if !self.quiet_hours() {
+                    self.ring(&buffer, SoundEvent::Highlight);
+                }
+                return;
+            }
+            if parsed.command == "PRIVMSG"
+                && parsed.ctcp().is_none()
+                && parsed
+                    .param(0)
+                    .is_some_and(|target| self.client.is_me(target))
+                && !self.highlight_excluded(&parsed)
+            {
+                self.ring(&buffer, SoundEvent::Private);
+            }
+            if parsed.command == "NOTICE"
+                && let Some(ctcp) = parsed.ctcp()
+            {
+                self.ctcp_reply(&parsed, ctcp);
+                return;
+            }
+            if parsed.command == "PRIVMSG"
+                && let Some(ctcp) = parsed.ctcp()
+                && self.ctcp_request(&parsed, ctcp)
+            {
+                if let Some(args) = ctcp.strip_prefix("DCC SEND ") {
+                    self.dcc_offer(&parsed, args);
+                } else if let Some(args) = ctcp.strip_prefix("DCC CHAT ") {
+                    self.dcc_chat_offer(&parsed, args);
+                } else if let Some(args) = ctcp.strip_prefix("DCC RESUME ") {
+                    self.dcc_resume_request(&parsed, args);
+                } else if let Some(args) = ctcp.strip_prefix("DCC ACCEPT ") {
+                    self.dcc_resume_accepted(&parsed, args);
+                }
+            }
+            self.buffers.push_to(&buffer, line);
+            return;
+        }
+        self.buffers.server_mut().push(line);
+    }
+
+    pub fn handle_event(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::ExecOutput {
+                command,
+                buffer,
+                channel,
+                mut lines,
+                errors,
+                status,
+            } => {
+                match channel {
+                    Some(channel) => {
+                        if lines.len() > EXEC_MAX_LINES {
+                            self.buffers.push_to(
+                                &buffer,
+                                format!(
+                                    "[exec] '{}' printed {} lines, sending the first {}",
+                                    command,
+                                    lines.len(),
+                                    EXEC_MAX_LINES
+                                ),
+                            );
+                            lines.truncate(EXEC_MAX_LINES);
+                        }
+                        self.send_lines(&channel, lines);
+                    }
+                    None => {
+                        for line in lines {
+                            self.buffers.push_to(&buffer, line);
+                        }
+                    }
+                }
+                for line in errors {
+                    self.buffers.push_to(&buffer, line);
+                }
+                match status {
+                    Some(0) => {}
+                    Some(code) => self.buffers.push_to(
+                        &buffer,
+                        format!("[exec] '{}' exited with status {}", command, code),
+                    ),
+                    None => self
+                        .buffers
+                        .push_to(&buffer, format!("[exec] '{}' was terminated", command)),
+                }
+            }
+            AppEvent::ExecFailed {
+                command,
+                buffer,
+                error,
+            } => self.buffers.push_to(
+                &buffer,
+                format!("[exec] failed to run '{}': {}", command, error),
+            ),
+            AppEvent::Control { command, reply } => {
+                let _ = reply.send(self.handle_control(&command));
+            }
+            AppEvent::RelayAttach { id, writer, local } => {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Relay frontend #{} attached", &[&id]));
+                self.relay.attach(id, writer, local, &self.buffers);
+                if self.client.away.is_none() {
+                    self.away_summary();
+                }
+            }
+            AppEvent::RelayRequest { id, line } => {
+                if let Some(input) = line.strip_prefix("input ") {
+                    self.user_activity();
+                    if let Err(e) = self.relay_input(id, input) {
+                        self.relay.reply(id, &[format!("error {}", e)]);
+                    }
+                } else if let Some(name) = line.strip_prefix("switch ") {
+                    self.relay_switch(id, name.trim());
+                } else {
+                    self.relay.handle_request(id, &line, &self.buffers);
+                }
+            }
+            AppEvent::RelayDetach { id } => {
+                self.relay.detach(id);
+                self.buffers
+                    .server_mut()
+                    .push(trf("Relay frontend #{} detached", &[&id]));
+            }
+            AppEvent::Http { request, reply } => {
+                let _ = reply.send(self.http_request(&request));
+            }
+            AppEvent::DccProgress { id, transferred } => self.dcc_progress(id, transferred),
+            AppEvent::DccFinished { id, result } => self.dcc_finished(id, result),
+            AppEvent::DccChatOpened { id, writer } => self.dcc_chat_opened(id, writer),
+            AppEvent::DccChatLine { id, line } => self.dcc_chat_line(id, &line),
+            AppEvent::DccChatClosed { id, error } => self.dcc_chat_closed(id, error),
+            AppEvent::PasteUploaded { target, result } => self.paste_uploaded(target, result),
+            AppEvent::Dbus(signal) => self.dbus_signal(signal),
+            AppEvent::WebhookFailed { error } => self
+                .buffers
+                .server_mut()
+                .push(trf("Webhook failed: {}", &[&error])),
+            AppEvent::Handshaking { attempt } => self.handshaking(attempt),
+            AppEvent::Connected {
+                attempt,
+                target,
+                result,
+            } => self.connected(attempt, target, result),
+        }
+    }
+
+    // `input` from a relay frontend. Over TCP only chat lines for the buffer on screen, a
+    // leaked relay password should not give anyone our commands, --attach gets them all
+    // but /exec
+    fn relay_input(&mut self, id: usize, input: &str) -> Result<(), String> {
+        if self.relay.is_local(id) {
+            return self.execute_remote(input);
+        }
+        if input.starts_with('/') {
+            return Err("only chat messages are taken over the relay".to_string());
+        }
+        let target = self
+            .relay
+            .selected(id)
+            .and_then(|name| self.buffers.get(name))
+            .filter(|buffer| matches!(buffer.kind, BufferKind::Channel | BufferKind::Query))
+            .map(|buffer| buffer.name.clone());
+        match target {
+            Some(target) => {
+                if !input.is_empty() && !self.not_joined(&target) {
+                    self.say(&target, input);
+                }
+            }
+            None => self.say_here(input),
+        }
+        Ok(())
+    }
+
+    // Only the attach client on the Unix socket moves the view, a TCP frontend looking at
+    // another buffer leaves the screen alone
+    fn relay_switch(&mut self, id: usize, name: &str) {
+        let found = if self.relay.is_local(id) {
+            self.buffers.select_name(name)
+        } else {
+            match self.buffers.get(name).map(|buffer| buffer.name.clone()) {
+                Some(name) => {
+                    self.relay.select(id, &name);
+                    true
+                }
+                None => false,
+            }
+        };
+        if !found {
+            let error = format!("error no buffer named '{}'", name);
+            self.relay.reply(id, &[error]);
+        }
+    }
+
+    // Commands accepted on the control socket. Lines starting with '/' are run as if they
+    // had been typed into the input box, except the ones only the keyboard may run
+    pub(crate) fn handle_control(&mut self, command: &str) -> Result<String, String> {
+        if command.starts_with('/') {
+            self.execute_remote(command)?;
+            return Ok(String::new());
+        }
+
+        let (verb, args) = command.split_once(' ').unwrap_or((command, ""));
+        match verb {
+            "send" => {
+                let Some((target, text)) = args.trim().split_once(' ') else {
+                    return Err("usage: send <target> <text>".to_string());
+                };
+                let shown = self.send_chat(target, text)?;
+                self.push_sent(target, format!("-> {}: {}", target, shown));
+                Ok(String::new())
+            }
+            "switch-buffer" => {
+                if self.buffers.select_name(args.trim()) {
+                    Ok(String::new())
+                } else {
+                    Err(format!("no buffer named '{}'", args.trim()))
+                }
+            }
+            "notify-test" => {
+                self.notify(tr("Test notification from the control socket"));
+                Ok(String::new())
+            }
+            _ => Err(format!("unknown command '{}'", verb)),
+        }
+    }
+
+    // Shows a highlighted line in the server buffer and rings the bell
+    pub fn notify(&mut self, text: &str) {
+        self.buffers.server_mut().push(format!("!!! {}", text));
+        self.ring("server", SoundEvent::Highlight);
+    }
+
+    // Periodic work, called on every iteration of the main loop
+    pub fn tick(&mut self) {
+        self.flush_debug();
+        self.write_logs();
+        self.reconnect_due();
+        let idle = Duration::from_secs(self.config.away.auto_minutes * 60);
+        if !idle.is_zero()
+            && self.client.registered
+            && self.client.away.is_none()
+            && self.last_activity.elapsed() >= idle
+        {
+            let message = self.config.away.message.clone();
+            if self.client.set_away(Some(&message)).is_ok() {
+                self.auto_away = true;
+                self.buffers.server_mut().push(trf(
+                    "Idle for {} minutes, marked away: {}",
+                    &[&self.config.away.auto_minutes, &message],
+                ));
+            }
+        }
+        self.follow_target();
+        self.sync_read_marker();
+        self.rejoin_due();
+        self.expire_ignores();
+        self.poll_members();
+        self.apply_auto_modes();
+        if let Err(e) = self.seen.save(false) {
+            self.buffers.server_mut().push(e);
+        }
+        self.buffers.enforce_budget();
+        self.flush_outgoing();
+        self.relay.forward_new_lines(&self.buffers);
+    }
+
+    // Leaving a channel or query buffer means it was read up to its newest line. With
+    // draft/read-marker the server passes that on to our other clients
+    fn sync_read_marker(&mut self) {
+        let shown = self.buffers.active().name.clone();
+        if shown == self.shown_buffer {
+            return;
+        }
+        let left = std::mem::replace(&mut self.shown_buffer, shown);
+        let target = self
+            .buffer_of(&left, BufferKind::Channel)
+            .or_else(|| self.buffer_of(&left, BufferKind::Query));
+        // Not now, a line arriving meanwhile would be marked read on the other clients
+        let last = target
+            .as_deref()
+            .and_then(|name| self.buffers.get(name))
+            .and_then(|buffer| buffer.last_stamp);
+        if let (Some(target), Some(last)) = (target, last)
+            && self.client.caps.contains("draft/read-marker")
+        {
+            let stamp = clock::iso8601(last);
+            if let Err(e) = self
+                .client
+                .send_raw(&format!("MARKREAD {} timestamp={}\r\n", target, stamp))
+            {
+                warn!("syncing the read marker: {}", e);
+            }
+        }
+    }
+
+    // MARKREAD <target> timestamp=...: another client read `target` that far. If that
+    // covers our newest line there is nothing unread left
+    fn read_marker(&mut self, msg: &Message) {
+        let (Some(target), Some(stamp)) = (
+            msg.param(0),
+            msg.param(1).and_then(|p| p.strip_prefix("timestamp=")),
+        ) else {
+            return;
+        };
+        let Some(buffer) = self
+            .buffers
+            .get_mut(target)
+            .filter(|b| matches!(b.kind, BufferKind::Channel | BufferKind::Query))
+        else {
+            return;
+        };
+        // Both are ISO 8601 in UTC, so they compare as strings
+        if buffer
+            .last_stamp
+            .is_some_and(|last| clock::iso8601(last).as_str() <= stamp)
+        {
+            buffer.mark_all_read();
+        }
+    }
+
+    // Sends the next queued line when the flood limit from [paste] burst and interval_ms
+    // allows it, at most one per tick
+    fn flush_outgoing(&mut self) {
+        if self.outgoing.is_empty() {
+            self.outgoing_sent = 0;
+            return;
+        }
+        let burst = self.config.paste.burst.max(1) as f64;
+        let interval = Duration::from_millis(self.config.paste.interval_ms.max(1));
+        let credit = match self.last_send {
+            Some(t) => {
+                (self.send_credit + t.elapsed().as_secs_f64() / interval.as_secs_f64()).min(burst)
+            }
+            None => burst,
+        };
+        if credit < 1.0 {
+            return;
+        }
+        let Some((target, text)) = self.outgoing.pop_front() else {
+            return;
+        };
+        match self.send_chat(&target, &text) {
+            Ok(shown) => self.push_sent(&target, format!("-> {}: {}", target, shown)),
+            Err(e) => {
+                self.buffers
+                    .push_local(trf("Error sending message: {}", &[&e]));
+                self.outgoing.clear();
+            }
+        }
+        self.send_credit = credit - 1.0;
+        self.last_send = Some(Instant::now());
+        self.outgoing_sent += 1;
+    }
+
+    // Lines of the queue sent so far and in total, while it is being sent
+    pub fn outgoing_progress(&self) -> Option<(usize, usize)> {
+        (!self.outgoing.is_empty())
+            .then(|| (self.outgoing_sent, self.outgoing_sent + self.outgoing.len()))
+    }
+
+    // Esc while queued lines go out, or /paste cancel, drops what is left of them
+    pub(crate) fn cancel_outgoing(&mut self) {
+        let dropped = self.outgoing.len();
+        self.outgoing.clear();
+        self.outgoing_sent = 0;
+        self.buffers
+            .push_local(trf("Stopped sending, dropped {} queued lines", &[&dropped]));
+    }
+
+    // Called on every key press (or input from a frontend), ends auto-away
+    pub fn user_activity(&mut self) {
+        self.last_activity = Instant::now();
+        if self.auto_away {
+            self.auto_away = false;
+            if self.client.away.is_some() {
+                self.back();
+            }
+        }
+    }
+
+    fn back(&mut self) {
+        match self.client.set_away(None) {
+            Ok(_) => {
+                self.buffers
+                    .push_local(tr("You are no longer marked as away").to_string());
+                self.away_summary();
+            }
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error clearing away: {}", &[&e])),
+        }
+    }
+
+    fn who(&mut self, name: &str) {
+        let name = match name {
+            "" => self.client.current_channel.clone(),
+            name => name.to_string(),
+        };
+        if name.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /who #channel").to_string());
+            return;
+        }
+        match self
+            .client
+            .send_labeled(&self.client.who_query(&name), &format!("/who {}", name))
+        {
+            Ok(_) => {
+                self.who_pending.insert(channel::nick_key(&name));
+            }
+            Err(e) => self.buffers.push_local(trf("Error sending WHO: {}", &[&e])),
+        }
+    }
+
+    fn mode(&mut self, args: &str) {
+        // "/mode +o bob" means the current channel
+        let target = self.client.current_channel.clone();
+        let line = if args.starts_with(['+', '-']) && !target.is_empty() {
+            format!("MODE {} {}", target, args)
+        } else {
+            format!("MODE {}", args)
+        };
+        if let Err(e) = self.client.send_labeled(&line, &format!("/mode {}", args)) {
+            self.buffers
+                .push_local(trf("Error sending MODE: {}", &[&e]));
+        }
+    }
+
+    // /umode shows our user modes, /umode +x-i changes them
+    fn umode(&mut self, changes: &str) {
+        if changes.is_empty() {
+            let line = match self.client.user_modes.as_str() {
+                "" => "No user modes set".to_string(),
+                modes => trf("Your user modes: +{}", &[&modes]),
+            };
+            self.buffers.push_local(line);
+            return;
+        }
+        let line = format!("MODE {} {}", self.client.nickname, changes);
+        if let Err(e) = self
+            .client
+            .send_labeled(&line, &format!("/umode {}", changes))
+        {
+            self.buffers
+                .push_local(trf("Error sending MODE: {}", &[&e]));
+        }
+    }
+
+    // /memory, scrollback per buffer against the configured budget
+    fn show_memory(&mut self) {
+        let mut report: Vec<String> = self
+            .buffers
+            .iter()
+            .filter(|b| !b.kind.is_view())
+            .map(|b| {
+                format!(
+                    "{}: {} of {} lines, {}",
+                    b.name,
+                    b.lines.len(),
+                    b.limit,
+                    dcc::format_size(b.bytes as u64)
+                )
+            })
+            .collect();
+        report.push(format!(
+            "Total {} of {} MiB",
+            dcc::format_size(self.buffers.bytes() as u64),
+            self.config.history.max_memory_mb
+        ));
+        for line in report {
+            self.buffers.push_local(line);
+        }
+    }
+
+    fn show_caps(&mut self) {
+        let enabled = |cap: &str| self.client.caps.contains(cap);
+        let mut requested = Vec::new();
+        for cap in irc_client::WANTED_CAPS {
+            let state = if enabled(cap) {
+                "enabled"
+            } else if self.client.caps_offered.iter().any(|c| c == cap) {
+                "refused"
+            } else {
+                "not offered"
+            };
+            requested.push(format!("{} ({})", cap, state));
+        }
+        let unused: Vec<&str> = self
+            .client
+            .caps_offered
+            .iter()
+            .map(|c| c.as_str())
+            .filter(|c| !irc_client::WANTED_CAPS.contains(c))
+            .collect();
+        self.buffers
+            .push_local(format!("Requested: {}", requested.join(", ")));
+        self.buffers.push_local(format!(
+            "Also offered: {}",
+            if unused.is_empty() {
+                "nothing".to_string()
+            } else {
+                unused.join(", ")
+            }
+        ));
+    }
+
+    // One line about who is in a channel, after its WHO reply is in
+    fn who_summary(&mut self, name: &str) {
+        let line = match self.client.channel(name) {
+            Some(chan) => {
+                let ops = chan.members.iter().filter(|m| m.prefix.contains('@'));
+                let voiced = chan.members.iter().filter(|m| m.prefix.contains('+'));
+                let away = chan
+                    .members
+                    .iter()
+                    .filter(|m| {
+                        self.client
+                            .users
+                            .get(&channel::nick_key(&m.nick))
+                            .is_some_and(|u| u.away)
+                    })
+                    .count();
+                format!(
+                    "{}: {} users ({} ops, {} voiced), {} here, {} away",
+                    chan.name,
+                    chan.members.len(),
+                    ops.count(),
+                    voiced.count(),
+                    chan.members.len() - away,
+                    away
+                )
+            }
+            None => format!("{}: not joined, no member list", name),
+        };
+        self.buffers.server_mut().push(line);
+    }
+
+    // For input the server limits in length: what it is, its length in bytes and the
+    // limit. The TUI shows a counter from this, execute refuses what is too long
+    pub fn input_limit(&self, input: &str) -> Option<(&'static str, usize, usize)> {
+        if let Some(topic) = input.strip_prefix("/topic ")
+            && topic.trim() != "-edit"
+        {
+            return Some(("Topic", topic.trim().len(), self.client.topiclen?));
+        }
+        if let Some(args) = input.strip_prefix("/join ") {
+            let channel = args.split_whitespace().next().unwrap_or("");
+            return Some(("Channel name", channel.len(), self.client.channellen?));
+        }
+        None
+    }
+
+    fn amsg(&mut self, message: &str) {
+        let mut channels: Vec<String> = self
+            .client
+            .channels
+            .values()
+            .map(|c| c.name.clone())
+            .collect();
+        if channels.is_empty() || message.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /amsg message, while in at least one channel").to_string());
+            return;
+        }
+        channels.sort();
+        let targets: Vec<&str> = channels.iter().map(|c| c.as_str()).collect();
+        match self.send_chat_to_all(&targets, message) {
+            Ok(shown) => self
+                .buffers
+                .push_local(format!("-> {}: {}", channels.join(","), shown)),
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error sending message: {}", &[&e])),
+        }
+    }
+
+    fn topic(&mut self, text: &str) {
+        let channel = self.client.current_channel.clone();
+        if channel.is_empty() {
+            self.buffers
+                .push_local(tr("Join a channel first to see or set its topic").to_string());
+            return;
+        }
+        let line = if text.is_empty() {
+            format!("TOPIC {}", channel)
+        } else {
+            format!("TOPIC {} :{}", channel, text)
+        };
+        if let Err(e) = self.client.send_labeled(&line, "/topic") {
+            self.buffers
+                .push_local(trf("Error sending TOPIC: {}", &[&e]));
+        }
+    }
+
+    // Input from the control socket or a --attach frontend. Runs like typed input, but
+    // /exec would hand whoever can reach the socket a shell, so that stays with the keyboard
+    pub(crate) fn execute_remote(&mut self, input: &str) -> Result<(), String> {
+        let command = input.split_once(' ').map_or(input, |(command, _)| command);
+        if LOCAL_ONLY.contains(&command) {
+            return Err(format!("{} only runs from the keyboard", command));
+        }
+        self.execute(input);
+        Ok(())
+    }
+
+    // Process commands
+    pub fn execute(&mut self, input: &str) {
+        if let Some((what, used, max)) = self.input_limit(input)
+            && used > max
+        {
+            self.buffers.push_local(trf(
+                "{} too long: {} bytes, the server allows {}",
+                &[&what, &used, &max],
+            ));
+            return;
+        }
+        if let Some(channel) = input.strip_prefix("/join ") {
+            if channel.is_empty() {
+                self.buffers
+                    .push_local(tr("Usage: /join #channel").to_string());
+            } else {
+                self.join(channel);
+            }
+        } else if let Some(args) = input.strip_prefix("/msg ") {
+            let parts: Vec<&str> = args.splitn(2, ' ').collect();
+            if parts.len() != 2 {
+                self.buffers
+                    .push_local(tr("Usage: /msg target message").to_string());
+            } else {
+                let target = parts[0];
+                let message = parts[1];
+                // nick1,nick2 sends to each of them
+                let targets: Vec<&str> = target.split(',').filter(|t| !t.is_empty()).collect();
+                // @#chan and friends only go out when the server said it takes them
+                let statusmsg = &self.client.statusmsg;
+                let refused = targets.iter().find_map(|t| {
+                    let (symbols, _) = channel::status_target(t)?;
+                    match statusmsg.as_str() {
+                        "" => Some("the server cannot message only some of a channel".to_string()),
+                        allowed if !symbols.chars().all(|c| allowed.contains(c)) => Some(format!(
+                            "the server takes {} in front of a channel, not {}",
+                            allowed, symbols
+                        )),
+                        _ => None,
+                    }
+                });
+
+                let sent = match (refused, &targets[..]) {
+                    (Some(refused), _) => Err(refused),
+                    (None, [one]) => self.send_chat(one, message),
+                    (None, _) => self.send_chat_to_all(&targets, message),
+                };
+                match sent {
+                    Ok(shown) => self
+                        .buffers
+                        .push_local(format!("-> *{}* {}", target, shown)),
+                    Err(e) => self
+                        .buffers
+                        .push_local(trf("Error sending message: {}", &[&e])),
+                }
+            }
+        } else if input == "/query" || input.starts_with("/query ") {
+            self.query(input["/query".len()..].trim());
+        } else if let Some(message) = input.strip_prefix("/amsg ") {
+            self.amsg(message);
+        } else if let Some((service, command)) = services::parse(input) {
+            self.send_to_service(service, command);
+        } else if input == "/away" || input.starts_with("/away ") {
+            let message = match input["/away".len()..].trim() {
+                "" => self.config.away.message.clone(),
+                message => message.to_string(),
+            };
+            self.auto_away = false;
+            match self.client.set_away(Some(&message)) {
+                Ok(_) => self
+                    .buffers
+                    .push_local(trf("You are marked as away: {}", &[&message])),
+                Err(e) => self
+                    .buffers
+                    .push_local(trf("Error setting away: {}", &[&e])),
+            }
+        } else if let Some(nick) = input.strip_prefix("/whois ") {
+            let nick = nick.trim();
+            match self
+                .client
+                .send_labeled(&format!("WHOIS {}", nick), &format!("/whois {}", nick))
+            {
+                Ok(_) => {
+                    self.whois_pending.insert(channel::nick_key(nick));
+                }
+                Err(e) => self
+                    .buffers
+                    .push_local(trf("Error sending WHOIS: {}", &[&e])),
+            }
+        } else if input == "/umode" || input.starts_with("/umode ") {
+            self.umode(input["/umode".len()..].trim());
+        } else if let Some(args) = input.strip_prefix("/mode ") {
+            self.mode(args.trim());
+        } else if let Some(nick) = input.strip_prefix("/ban ") {
+            self.ban(nick.trim());
+        } else if input == "/who" || input.starts_with("/who ") {
+            self.who(input["/who".len()..].trim());
+        } else if input == "/back" {
+            self.auto_away = false;
+            self.back();
+        } else if input == "/dcc" || input.starts_with("/dcc ") {
+            self.dcc_command(input["/dcc".len()..].trim());
+        } else if input == "/buffer" || input.starts_with("/buffer ") {
+            self.buffer_command(input["/buffer".len()..].trim());
+        } else if input == "/theme" || input.starts_with("/theme ") {
+            self.theme_command(&input["/theme".len()..]);
+        } else if input == "/sounds" || input.starts_with("/sounds ") {
+            self.sounds_command(&input["/sounds".len()..]);
+        } else if input == "/sortnicks" || input.starts_with("/sortnicks ") {
+            self.sortnicks_command(input["/sortnicks".len()..].trim());
+        } else if input == "/filter" || input.starts_with("/filter ") {
+            self.filter_command(input["/filter".len()..].trim());
+        } else if input == "/fish" || input.starts_with("/fish ") {
+            self.fish_command(input["/fish".len()..].trim());
+        } else if input == "/ignore" || input.starts_with("/ignore ") {
+            self.ignore_command(&input["/ignore".len()..]);
+        } else if input == "/unignore" || input.starts_with("/unignore ") {
+            self.unignore_command(input["/unignore".len()..].trim());
+        } else if input == "/open" || input.starts_with("/open ") {
+            self.open_command(input["/open".len()..].trim());
+        } else if input == "/queue" || input.starts_with("/queue ") {
+            self.queue_command(&input["/queue".len()..]);
+        } else if input == "/toggle" || input.starts_with("/toggle ") {
+            self.toggle_command(&input["/toggle".len()..]);
+        } else if input == "/paste" || input.starts_with("/paste ") {
+            self.paste_command(input["/paste".len()..].trim());
+        } else if input == "/ctcp" || input.starts_with("/ctcp ") {
+            self.ctcp_command(input["/ctcp".len()..].trim());
+        } else if let Some(command) = server_info::COMMANDS.into_iter().find(|command| {
+            let name = format!("/{}", command.to_lowercase());
+            input == name || input.starts_with(&format!("{} ", name))
+        }) {
+            let args = input.split_once(' ').map_or("", |(_, args)| args.trim());
+            // /stats and /stats #channel are our own activity overlay
+            if command == "STATS" && (args.is_empty() || args.starts_with(['#', '&'])) {
+                self.stats_command(args);
+            } else {
+                self.server_info_command(command, args);
+            }
+        } else if input == "/seen" || input.starts_with("/seen ") {
+            self.seen_command(input["/seen".len()..].trim());
+        } else if input == "/whowas" || input.starts_with("/whowas ") {
+            self.whowas_command(input["/whowas".len()..].trim());
+        } else if input == "/host" || input.starts_with("/host ") {
+            self.host_command(input["/host".len()..].trim());
+        } else if input == "/exec" || input.starts_with("/exec ") {
+            self.exec(input["/exec".len()..].trim());
+        } else if input == "/clear" {
+            self.buffers.clear_active();
+            self.buffers.push_local(tr("Chat cleared.").to_string());
+        } else if input == "/encoding" || input.starts_with("/encoding ") {
+            match input["/encoding".len()..].trim() {
+                "" => self
+                    .buffers
+                    .push_local(trf("Encoding: {}", &[&self.client.encoding_name()])),
+                name => match Encoding::for_label(name.as_bytes()) {
+                    Some(encoding) => {
+                        self.client.set_encoding(encoding);
+                        self.buffers
+                            .push_local(trf("Encoding set to {}", &[&encoding.name()]));
+                    }
+                    None => self
+                        .buffers
+                        .push_local(trf("Unknown encoding: {}", &[&name])),
+                },
+            }
+        } else if input.trim_end() == "/topic -edit" {
+            self.edit_topic();
+        } else if input == "/topic" || input.starts_with("/topic ") {
+            self.topic(input["/topic".len()..].trim());
+        } else if input == "/banlist" || input.starts_with("/banlist ") {
+            self.show_bans(input["/banlist".len()..].trim());
+        } else if input == "/access" || input.starts_with("/access ") {
+            self.access_command(input["/access".len()..].trim());
+        } else if let Some(args) = input.strip_prefix("/oper ") {
+            self.oper(args.trim());
+        } else if input == "/kill" || input.starts_with("/kill ") {
+            self.oper_command("kill", input["/kill".len()..].trim());
+        } else if input == "/wallops" || input.starts_with("/wallops ") {
+            self.oper_command("wallops", input["/wallops".len()..].trim());
+        } else if let Some(level) = input.strip_prefix("/loglevel ") {
+            match logging::set_level(level.trim()) {
+                Ok(_) => self
+                    .buffers
+                    .push_local(trf("Log level set to {}", &[&level.trim()])),
+                Err(e) => self.buffers.push_local(e),
+            }
+        } else if input == "/debug" {
+            self.toggle_debug();
+        } else if input == "/raw" || input.starts_with("/raw ") {
+            self.send_raw_line(input["/raw".len()..].trim());
+        } else if input == "/nick" || input.starts_with("/nick ") {
+            self.change_nick(input["/nick".len()..].trim());
+        } else if input == "/regain" {
+            self.recover_nick(false);
+        } else if input == "/ghost" {
+            self.recover_nick(true);
+        } else if input == "/memory" {
+            self.show_memory();
+        } else if input == "/caps" {
+            self.show_caps();
+        } else if input == "/invites" {
+            self.show_invites();
+        } else if input == "/dashboard" {
+            self.buffers.select_kind(BufferKind::Dashboard);
+        } else if input == "/connect"
+            || input.starts_with("/connect ")
+            || input == "/server"
+            || input.starts_with("/server ")
+        {
+            let args = input.split_once(' ').map_or("", |(_, args)| args);
+            self.connect_command(args.trim());
+        } else if input == "/disconnect" || input.starts_with("/disconnect ") {
+            self.disconnect_command(input["/disconnect".len()..].trim());
+        } else if input == "/reconnect" {
+            self.reconnect_command();
+        } else if input == "/quit" || input == "/exit" || input.starts_with("/quit ") {
+            let reason = input.strip_prefix("/quit").unwrap_or_default().trim();
+            let _ = if reason.is_empty() {
+                self.client.quit()
+            } else {
+                self.client.quit_with(reason)
+            };
+            self.should_quit = true;
+        } else if input == "/part" || input.starts_with("/part ") {
+            self.part_command(input["/part".len()..].trim());
+        } else if input == "/help" {
+            self.help_command();
+        } else {
+            self.say_here(input);
+        }
+    }
+
+    // A line that is no command, for the DCC chat on screen or else the current channel
+    fn say_here(&mut self, input: &str) {
+        if input.is_empty() {
+            return;
+        }
+        if self.buffers.active().kind == BufferKind::DccChat {
+            self.dcc_chat_say(input);
+            return;
+        }
+        let current_channel = self.client.current_channel.clone();
+        if current_channel.is_empty() {
+            self.buffers
+                .push_local(tr("Join a channel first with /join #channel").to_string());
+        } else if !self.not_joined(&current_channel) {
+            self.say(&current_channel, input);
+        }
+    }
+
+    // Opens a query buffer with a nick and makes them the current target, what is typed
+    // goes to them until another /query or /join. Their away message shows in the input title
+    fn query(&mut self, nick: &str) {
+        if !channel::valid_nick(nick) {
+            self.buffers
+                .push_local(tr("Usage: /query nick").to_string());
+            return;
+        }
+        self.show_target(nick);
+        self.buffers.push_local(trf("Now talking to {}", &[&nick]));
+        if let Some(message) = self.client.away_message(nick).filter(|m| !m.is_empty()) {
+            self.buffers
+                .push_local(trf("{} is away: {}", &[&nick, &message]));
+        }
+    }
+
+    // Joins a channel, its buffer is shown once the server confirms. `args` is
+    // "#channel [key]". Without a key the one that worked last time is used
+    pub fn join(&mut self, args: &str) {
+        let mut parts = args.split_whitespace();
+        let Some(channel) = parts.next() else {
+            return;
+        };
+        // In there already, its buffer is shown and it becomes the current one
+        if let Some(name) = self.client.channel(channel).map(|c| c.name.clone()) {
+            self.show_target(&name);
+            self.buffers.push_local(trf("Now talking in {}", &[&name]));
+            return;
+        }
+        let key = match parts.next() {
+            Some(key) => Some(key.to_string()),
+            None => self
+                .channel_keys
+                .get(&self.network_id(), channel)
+                .map(|key| key.to_string()),
+        };
+        if let Some(key) = &key {
+            self.pending_keys
+                .insert(channel::nick_key(channel), key.clone());
+        }
+        match self.client.join_channel(channel, key.as_deref()) {
+            Ok(_) => self
+                .buffers
+                .push_local(trf("Joining channel: {}", &[&channel])),
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error joining channel: {}", &[&e])),
+        }
+        if self.buffers.active().kind.is_view() {
+            self.buffers.select_kind(BufferKind::Server);
+        }
+    }
+
+    // /part [#channel] [reason], the current channel and [quit] part_message by default
+    fn part_command(&mut self, args: &str) {
+        let (channel, reason) = match args.split_once(' ') {
+            Some((channel, reason)) if channel.starts_with(['#', '&']) => (channel, reason.trim()),
+            _ if args.starts_with(['#', '&']) => (args, ""),
+            _ => (self.client.current_channel.as_str(), args),
+        };
+        if channel.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /part [#channel] [reason]").to_string());
+            return;
+        }
+        let reason = if reason.is_empty() {
+            self.config.quit.part_message.as_str()
+        } else {
+            reason
+        };
+        let line = match reason {
+            "" => format!("PART {}", channel),
+            reason => format!("PART {} :{}", channel, reason),
+        };
+        let command = format!("/part {}", channel);
+        if let Err(e) = self.client.send_labeled(&line, &command) {
+            self.buffers
+                .push_local(trf("Error sending PART: {}", &[&e]));
+        }
+    }
+
+    // Chat lines only show the nick, this has the user@host they came from
+    fn host_command(&mut self, nick: &str) {
+        if nick.is_empty() {
+            self.buffers.push_local(tr("Usage: /host nick").to_string());
+            return;
+        }
+        let line = match self.client.hostmask(nick) {
+            Some(hostmask) => format!("{} is {}", nick, hostmask),
+            None => format!(
+                "No hostmask known for {}, /whois {} asks the server",
+                nick, nick
+            ),
+        };
+        self.buffers.push_local(line);
+    }
+
+    // Runs a shell command on a worker thread. The output comes back as an
+    // AppEvent::ExecOutput so a slow command never blocks the UI
+    fn exec(&mut self, args: &str) {
+        let (send_output, command) = match args.strip_prefix("-o") {
+            Some(command) if command.is_empty() || command.starts_with(' ') => {
+                (true, command.trim())
+            }
+            _ => (false, args),
+        };
+        if command.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /exec [-o] command").to_string());
+            return;
+        }
+
+        let channel = if send_output {
+            if self.client.current_channel.is_empty() {
+                self.buffers
+                    .push_local(tr("Join a channel first with /join #channel").to_string());
+                return;
+            }
+            Some(self.client.current_channel.clone())
+        } else {
+            None
+        };
+
+        let buffer = self.buffers.local_name().to_string();
+        let command = command.to_string();
+        let events = self.events.clone();
+        thread::spawn(move || {
+            let event = match run_shell(&command) {
+                Ok((lines, errors, status)) => AppEvent::ExecOutput {
+                    command,
+                    buffer,
+                    channel,
+                    lines,
+                    errors,
+                    status,
+                },
+                Err(e) => AppEvent::ExecFailed {
+                    command,
+                    buffer,
+                    error: e.to_string(),
+                },
+            };
+            let _ = events.send(event);
+        });
+    }
+}
+
+// Runs `command` in sh for at most EXEC_TIMEOUT. Returns the non-empty lines of stdout and
+// of stderr, and the exit code, None when a signal ended it. The pipes are read on threads
+// of their own, a command that keeps them open after being killed is not waited for
+fn run_shell(command: &str) -> std::io::Result<(Vec<String>, Vec<String>, Option<i32>)> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let (tx, rx) = mpsc::channel();
+    let pipes: [(bool, Box<dyn Read + Send>); 2] = [
+        (false, Box::new(child.stdout.take().expect("piped stdout"))),
+        (true, Box::new(child.stderr.take().expect("piped stderr"))),
+    ];
+    for (is_error, pipe) in pipes {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(pipe);
+            let mut line = Vec::new();
+            let mut kept = 0;
+            // Past EXEC_MAX_BYTES the rest is read and dropped, a full pipe would block
+            while reader.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+                kept += line.len();
+                if kept <= EXEC_MAX_BYTES {
+                    let text = String::from_utf8_lossy(&line).trim_end().to_string();
+                    if !text.trim().is_empty() && tx.send((is_error, text)).is_err() {
+                        return;
+                    }
+                }
+                line.clear();
+            }
+        });
+    }
+    drop(tx);
+
+    let deadline = Instant::now() + EXEC_TIMEOUT;
+    let (mut lines, mut errors) = (Vec::new(), Vec::new());
+    let mut timed_out = false;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(left) {
+            Ok((false, line)) => lines.push(line),
+            Ok((true, line)) => errors.push(line),
+            Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {
+                timed_out = true;
+                break;
+            }
+        }
+    }
+    // Both pipes closed, but the command can still be running
+    while !timed_out && child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            timed_out = true;
+        } else {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+    if timed_out {
+        let _ = child.kill();
+        errors.push(format!(
+            "[exec] '{}' ran for {}s, stopped it",
+            command,
+            EXEC_TIMEOUT.as_secs()
+        ));
+    }
+    Ok((lines, errors, child.wait()?.code()))
+}
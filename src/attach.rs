@@ -0,0 +1,252 @@
+use crate::daemon;
+use crate::render;
+use crate::terminal::TerminalGuard;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use ratatui::{
+    Terminal,
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Paragraph},
+};
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write, stdout};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+// Lines of scrollback fetched per buffer when attaching
+const HISTORY_LINES: usize = 500;
+
+// A buffer as mirrored from the daemon
+struct RemoteBuffer {
+    name: String,
+    lines: Vec<String>,
+    unread: usize,
+    // Scrollback lines received so far, live lines that raced ahead of them go after
+    history: usize,
+}
+
+// `irconic --attach`: a terminal frontend for a session started with --daemon. All state
+// lives in the daemon, this only mirrors buffers and forwards input, so detaching (Esc)
+// leaves connections, buffers and unread counts exactly as they were
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let path =
+        daemon::session_path().ok_or("No runtime directory, set $XDG_RUNTIME_DIR or $HOME")?;
+    let stream = UnixStream::connect(&path).map_err(|e| {
+        format!(
+            "No daemon session at {} ({}), start one with --daemon",
+            path.display(),
+            e
+        )
+    })?;
+    let mut writer = stream.try_clone()?;
+
+    // None marks the daemon going away
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.send(Some(line)).is_err() {
+                return;
+            }
+        }
+        let _ = tx.send(None);
+    });
+    writer.write_all(b"buffers\n")?;
+
+    let guard = TerminalGuard::enter()?;
+    let backend = CrosstermBackend::new(stdout());
+    let mut terminal = Terminal::new(backend)?;
+
+    let mut buffers: Vec<RemoteBuffer> = Vec::new();
+    let mut active: usize = 0;
+    let mut input = String::new();
+    let mut closed = false;
+
+    while !closed {
+        while let Ok(message) = rx.try_recv() {
+            let Some(line) = message else {
+                closed = true;
+                break;
+            };
+            let (kind, rest) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+            match kind {
+                // buffer <name> <kind> <total> <unread>
+                "buffer" => {
+                    let fields: Vec<&str> = rest.split(' ').collect();
+                    if fields.len() == 4
+                        && !matches!(
+                            fields[1],
+                            "dashboard" | "transfers" | "invites" | "access" | "bans"
+                        )
+                    {
+                        buffers.push(RemoteBuffer {
+                            name: fields[0].to_string(),
+                            lines: Vec::new(),
+                            unread: fields[3].parse().unwrap_or(0),
+                            history: 0,
+                        });
+                    }
+                }
+                "end" if rest == "buffers" => {
+                    for buffer in &buffers {
+                        writer.write_all(
+                            format!("history {} {}\n", buffer.name, HISTORY_LINES).as_bytes(),
+                        )?;
+                    }
+                    if let Some(buffer) = buffers.first() {
+                        writer.write_all(format!("switch {}\n", buffer.name).as_bytes())?;
+                    }
+                }
+                "history" | "line" => {
+                    let (name, text) = rest.split_once(' ').unwrap_or((rest, ""));
+                    let index = match buffers.iter().position(|b| b.name == name) {
+                        Some(index) => index,
+                        None => {
+                            buffers.push(RemoteBuffer {
+                                name: name.to_string(),
+                                lines: Vec::new(),
+                                unread: 0,
+                                history: 0,
+                            });
+                            buffers.len() - 1
+                        }
+                    };
+                    let buffer = &mut buffers[index];
+                    if kind == "history" {
+                        buffer.lines.insert(buffer.history, text.to_string());
+                        buffer.history += 1;
+                    } else {
+                        buffer.lines.push(text.to_string());
+                        if index != active {
+                            buffer.unread += 1;
+                        }
+                    }
+                }
+                "error" => {
+                    if let Some(buffer) = buffers.get_mut(active) {
+                        buffer.lines.push(format!("Daemon error: {}", rest));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(1)
+                .constraints([Constraint::Min(5), Constraint::Length(3)].as_ref())
+                .split(f.size());
+
+            // Other buffers with their unread counts, like a tab bar in the title
+            let mut title = vec![Span::raw("Attached: ")];
+            for (i, buffer) in buffers.iter().enumerate() {
+                let style = if i == active {
+                    Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+                } else if buffer.unread > 0 {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default()
+                };
+                let label = if i != active && buffer.unread > 0 {
+                    format!(" {} ({}) ", buffer.name, buffer.unread)
+                } else {
+                    format!(" {} ", buffer.name)
+                };
+                title.push(Span::styled(label, style));
+            }
+
+            let lines: &[String] = buffers.get(active).map(|b| &b.lines[..]).unwrap_or(&[]);
+            let height = (chunks[0].height as usize).saturating_sub(2);
+            let width = (chunks[0].width as usize).saturating_sub(2);
+            let shown = &lines[lines.len().saturating_sub(height)..];
+            let rows: Vec<Line> = shown
+                .iter()
+                .flat_map(|l| render::hanging_rows(l, width))
+                .collect();
+            let rows = rows[rows.len().saturating_sub(height)..].to_vec();
+            let messages = Paragraph::new(rows).block(
+                Block::default()
+                    .title(Line::from(title))
+                    .borders(Borders::ALL),
+            );
+            f.render_widget(messages, chunks[0]);
+
+            let input_block = Paragraph::new(Text::from(input.clone())).block(
+                Block::default()
+                    .title("Input (Esc detaches, /quit ends the session)")
+                    .borders(Borders::ALL),
+            );
+            f.render_widget(input_block, chunks[1]);
+            f.set_cursor(chunks[1].x + input.len() as u16 + 1, chunks[1].y + 1);
+        })?;
+
+        let event = if event::poll(Duration::from_millis(100))? {
+            Some(event::read()?)
+        } else {
+            None
+        };
+        // Pasted lines go to the daemon one by one, as if each was typed and sent
+        if let Some(Event::Paste(text)) = &event {
+            let text = text.replace("\r\n", "\n").replace('\r', "\n");
+            let mut lines = text.split('\n').peekable();
+            while let Some(line) = lines.next() {
+                input.push_str(line);
+                if lines.peek().is_some() && !input.is_empty() {
+                    writer.write_all(format!("input {}\n", input).as_bytes())?;
+                    input.clear();
+                }
+            }
+        }
+        if let Some(Event::Key(key)) = event {
+            let switch_to = match key.code {
+                KeyCode::Enter => {
+                    if !input.is_empty() {
+                        writer.write_all(format!("input {}\n", input).as_bytes())?;
+                        input.clear();
+                    }
+                    None
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    None
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                    None
+                }
+                KeyCode::Left if key.modifiers.contains(KeyModifiers::ALT) => {
+                    Some((active + buffers.len().max(1) - 1) % buffers.len().max(1))
+                }
+                KeyCode::Right if key.modifiers.contains(KeyModifiers::ALT) => {
+                    Some((active + 1) % buffers.len().max(1))
+                }
+                KeyCode::Esc => break,
+                _ => None,
+            };
+            if let Some(index) = switch_to
+                && let Some(buffer) = buffers.get_mut(index)
+            {
+                active = index;
+                buffer.unread = 0;
+                writer.write_all(format!("switch {}\n", buffer.name).as_bytes())?;
+            }
+        }
+    }
+
+    drop(guard);
+
+    if closed {
+        println!("The daemon session ended.");
+    } else {
+        let _ = writer.write_all(b"quit\n");
+        println!("Detached. Reattach with `irconic --attach`.");
+    }
+    Ok(())
+}
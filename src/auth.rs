@@ -0,0 +1,130 @@
+use crate::app::App;
+use crate::config::{Config, Privacy};
+use crate::i18n::{tr, trf};
+use crate::irc_client::{IrcClient, SaslLogin};
+use crate::message::Message;
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+// A secret the TUI is asking for. While one is open the input line is masked and Enter
+// hands the text to submit_secret instead of running it, so it never reaches a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretPrompt {
+    ServerPassword,
+    SaslPassword,
+}
+
+// Lowercase letters, different every time. Good enough to not be recognised, nothing more
+fn random_word(len: usize) -> String {
+    let mut seed = RandomState::new().build_hasher().finish();
+    (0..len)
+        .map(|_| {
+            let c = (b'a' + (seed % 26) as u8) as char;
+            seed /= 26;
+            c
+        })
+        .collect()
+}
+
+// Sets up the login configured for `host` before registering. With `interactive` false
+// (the daemon, nobody to ask) a SASL account without a password is left out
+pub fn apply_login(client: &mut IrcClient, config: &Config, host: &str, interactive: bool) {
+    let ident = config.ident(host);
+    client.identd_port = config.identd.enabled.then_some(config.identd.port);
+    let default_version = format!("Irconic {}", env!("CARGO_PKG_VERSION"));
+    match ident.privacy.unwrap_or_default() {
+        Privacy::Off => {
+            client.username = ident.username;
+            client.realname = ident.realname;
+            client.version = match ident.version {
+                Some(version) if version.is_empty() => None,
+                Some(version) => Some(version),
+                None => Some(default_version),
+            };
+        }
+        Privacy::Random => {
+            client.username = Some(random_word(8));
+            client.realname = Some(random_word(8));
+            client.version = None;
+        }
+        Privacy::Blank => {
+            client.username = Some("user".to_string());
+            client.realname = Some("*".to_string());
+            client.version = None;
+        }
+    }
+    // A reconnect keeps the nick we had there, a new network gets the one set for it
+    if !client.server.eq_ignore_ascii_case(host) {
+        let nick = ident.nick.unwrap_or_else(|| config.connect.nick.clone());
+        if !nick.is_empty() {
+            client.nickname = nick;
+            client.wanted_nick = None;
+        }
+    }
+    client.sasl = ident.sasl_username.map(|username| SaslLogin {
+        username,
+        password: ident.sasl_password,
+    });
+    if !interactive && client.sasl.as_ref().is_some_and(|s| s.password.is_none()) {
+        client.sasl = None;
+    }
+    client.password = config.server(host).and_then(|s| s.password.clone());
+}
+
+impl App {
+    // Opens a prompt when the server wants a secret we do not have
+    pub(crate) fn secret_needed(&mut self, msg: &Message) {
+        if self.secret_prompt.is_some() {
+            return;
+        }
+        if self.client.sasl_waiting {
+            let username = self
+                .client
+                .sasl
+                .as_ref()
+                .map(|s| s.username.clone())
+                .unwrap_or_default();
+            self.secret_prompt = Some(SecretPrompt::SaslPassword);
+            self.notify(&trf(
+                "SASL login as {}: type the password below, Esc to connect without it",
+                &[&username],
+            ));
+        } else if msg.command == "464" && !self.client.registered && self.client.password.is_none()
+        {
+            // ERR_PASSWDMISMATCH, bouncers send it and wait for PASS. Once registered it
+            // is about /oper
+            self.secret_prompt = Some(SecretPrompt::ServerPassword);
+            self.notify(tr(
+                "The server wants a password: type it below, Esc to skip",
+            ));
+        }
+    }
+
+    // Title of the input line while a prompt is open
+    pub fn secret_title(&self) -> Option<&'static str> {
+        match self.secret_prompt? {
+            SecretPrompt::ServerPassword => Some(tr("Server password (hidden, Esc to skip)")),
+            SecretPrompt::SaslPassword => Some(tr("SASL password (hidden, Esc to skip)")),
+        }
+    }
+
+    // What the user typed at the prompt, None when they skipped it
+    pub fn submit_secret(&mut self, secret: Option<String>) {
+        let Some(prompt) = self.secret_prompt.take() else {
+            return;
+        };
+        let secret = secret.filter(|s| !s.is_empty());
+        match prompt {
+            SecretPrompt::SaslPassword => self.client.sasl_password(secret),
+            SecretPrompt::ServerPassword => {
+                if let Some(password) = secret {
+                    if let Err(e) = self.client.send_raw(&format!("PASS {}\r\n", password)) {
+                        self.buffers
+                            .server_mut()
+                            .push(trf("Error sending password: {}", &[&e]));
+                    }
+                    self.client.password = Some(password);
+                }
+            }
+        }
+    }
+}
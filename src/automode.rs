@@ -0,0 +1,112 @@
+use crate::app::App;
+use crate::channel;
+use crate::config::AutoMode;
+use crate::i18n::trf;
+use crate::message::Message;
+use std::time::{Duration, Instant};
+
+// Services and other ops often give the mode themselves right after the join. Waiting a
+// moment lets us see that instead of sending the same MODE again
+const DELAY: Duration = Duration::from_secs(3);
+
+// A mode to give once DELAY has passed, if it is still missing then
+pub struct PendingMode {
+    pub channel: String,
+    pub nick: String,
+    pub mode: AutoMode,
+    pub at: Instant,
+}
+
+impl AutoMode {
+    fn symbol(self) -> char {
+        match self {
+            AutoMode::Op => '@',
+            AutoMode::Voice => '+',
+        }
+    }
+
+    fn letter(self) -> char {
+        match self {
+            AutoMode::Op => 'o',
+            AutoMode::Voice => 'v',
+        }
+    }
+}
+
+// Whether `prefix` holds `symbol` or anything above it in `symbols`
+fn has_rank(prefix: &str, symbol: char, symbols: &str) -> bool {
+    let Some(wanted) = symbols.find(symbol) else {
+        return false;
+    };
+    prefix
+        .chars()
+        .any(|c| symbols.find(c).is_some_and(|rank| rank <= wanted))
+}
+
+impl App {
+    // Someone joined, queue the mode of the first rule that matches them
+    pub(crate) fn auto_mode_join(&mut self, msg: &Message) {
+        let (Some(nick), Some(name), Some(hostmask)) =
+            (msg.nick(), msg.param(0), msg.prefix.as_deref())
+        else {
+            return;
+        };
+        if self.client.is_me(nick) {
+            return;
+        }
+        let account = self.client.account(nick);
+        let rule = self.config.automodes.iter().find(|rule| {
+            channel::mask_matches(&rule.channel, name)
+                && (rule.account.as_deref().is_some_and(|a| {
+                    account.is_some_and(|account| a.eq_ignore_ascii_case(account))
+                }) || rule
+                    .mask
+                    .as_deref()
+                    .is_some_and(|mask| channel::mask_matches(mask, hostmask)))
+        });
+        if let Some(rule) = rule {
+            self.pending_modes.push(PendingMode {
+                channel: name.to_string(),
+                nick: nick.to_string(),
+                mode: rule.mode,
+                at: Instant::now() + DELAY,
+            });
+        }
+    }
+
+    // Sends the queued modes that are due and still needed, called from tick
+    pub(crate) fn apply_auto_modes(&mut self) {
+        let now = Instant::now();
+        let (due, waiting) = std::mem::take(&mut self.pending_modes)
+            .into_iter()
+            .partition(|pending| pending.at <= now);
+        self.pending_modes = waiting;
+        for pending in due {
+            let symbols = &self.client.prefix_symbols;
+            let Some(chan) = self.client.channel(&pending.channel) else {
+                continue;
+            };
+            let ours = chan
+                .member(&self.client.nickname)
+                .is_some_and(|me| has_rank(&me.prefix, '@', symbols));
+            // Gone again, or someone was quicker
+            let needed = chan
+                .member(&pending.nick)
+                .is_some_and(|m| !has_rank(&m.prefix, pending.mode.symbol(), symbols));
+            if !ours || !needed {
+                continue;
+            }
+            let line = format!(
+                "MODE {} +{} {}",
+                pending.channel,
+                pending.mode.letter(),
+                pending.nick
+            );
+            if let Err(e) = self.client.send_labeled(&line, "auto mode") {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Error sending {}: {}", &[&line, &e]));
+            }
+        }
+    }
+}
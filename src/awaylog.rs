@@ -0,0 +1,112 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::channel;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+
+// Whether `text` names `nick` as a word of its own, "tester:" counts but "testers" does not
+fn mentions(text: &str, nick: &str) -> bool {
+    let text = text.to_lowercase();
+    let nick = nick.to_lowercase();
+    let word =
+        |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || "_-[]\\`^{}|".contains(c));
+    text.match_indices(&nick).any(|(at, _)| {
+        !word(text[..at].chars().next_back()) && !word(text[at + nick.len()..].chars().next())
+    })
+}
+
+// A private message or a message mentioning us
+pub struct Mention {
+    pub nick: String,
+    // The channel, or "private"
+    pub place: String,
+    // What was said, "* nick waves" for actions
+    pub text: String,
+    pub action: bool,
+}
+
+impl Mention {
+    // As shown in the awaylog, e.g. "#rust <bob> tester: hi"
+    fn line(&self) -> String {
+        if self.action {
+            format!("{} {}", self.place, self.text)
+        } else {
+            format!("{} <{}> {}", self.place, self.nick, self.text)
+        }
+    }
+}
+
+impl App {
+    // Nobody is reading along: we are marked away, or a daemon has no frontend attached
+    pub(crate) fn missing_out(&self) -> bool {
+        self.client.away.is_some() || (self.headless && !self.relay.is_attached())
+    }
+
+    // A PRIVMSG sent to us or naming us, nick or highlight word
+    pub(crate) fn mention(&self, msg: &Message) -> Option<Mention> {
+        if msg.command != "PRIVMSG" {
+            return None;
+        }
+        let (nick, target, text) = (msg.nick()?, msg.param(0)?, msg.trailing()?);
+        let (text, action) = match msg.ctcp() {
+            Some(ctcp) => (
+                format!("* {} {}", nick, ctcp.strip_prefix("ACTION ")?),
+                true,
+            ),
+            None => (text.to_string(), false),
+        };
+        if self.highlight_excluded(msg) {
+            return None;
+        }
+        let private = self.client.is_me(target);
+        // Highlight words of the channel's buffer, or of the query with them
+        let buffer = if private {
+            nick
+        } else {
+            channel::strip_status(target)
+        };
+        let highlight = mentions(&text, &self.client.nickname)
+            || self.buffer_settings(buffer).highlighted(&text);
+        if !private && !highlight {
+            return None;
+        }
+        Some(Mention {
+            nick: nick.to_string(),
+            place: if private { "private" } else { target }.to_string(),
+            text,
+            action,
+        })
+    }
+
+    // Copies private messages and messages mentioning us into the awaylog buffer while
+    // nobody is reading along
+    pub(crate) fn away_log(&mut self, msg: &Message) {
+        if !self.missing_out() {
+            return;
+        }
+        let Some(mention) = self.mention(msg) else {
+            return;
+        };
+        self.buffers.open("awaylog", BufferKind::AwayLog);
+        self.buffers.push_to("awaylog", mention.line());
+        self.missed += 1;
+    }
+
+    // Tells the user what the awaylog caught once they are back
+    pub(crate) fn away_summary(&mut self) {
+        if self.missed == 0 {
+            return;
+        }
+        let missed = std::mem::take(&mut self.missed);
+        let line = if missed == 1 {
+            tr("One private message or highlight while you were away, see the awaylog buffer")
+                .to_string()
+        } else {
+            trf(
+                "{} private messages and highlights while you were away, see the awaylog buffer",
+                &[&missed],
+            )
+        };
+        self.notify(&line);
+    }
+}
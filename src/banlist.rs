@@ -0,0 +1,180 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::channel;
+use crate::clock;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use std::net::IpAddr;
+use std::time::{Duration, UNIX_EPOCH};
+
+// One +b entry as RPL_BANLIST reports it
+pub struct BanEntry {
+    pub mask: String,
+    pub set_by: Option<String>,
+    // Unix time the ban was set
+    pub set_at: Option<u64>,
+}
+
+impl BanEntry {
+    // "set by nick on 2024-03-01 12:30", or as much of it as the server told us
+    pub fn origin(&self) -> String {
+        let by = self.set_by.as_deref().map(|by| {
+            // Some servers give the full nick!user@host of the setter
+            format!("set by {}", by.split('!').next().unwrap_or(by))
+        });
+        let at = self.set_at.map(|secs| {
+            format!(
+                "on {}",
+                clock::date_minutes(UNIX_EPOCH + Duration::from_secs(secs))
+            )
+        });
+        [by, at].into_iter().flatten().collect::<Vec<_>>().join(" ")
+    }
+}
+
+// The ban list shown in the bans panel
+pub struct BanList {
+    pub channel: String,
+    pub entries: Vec<BanEntry>,
+    // Waiting for RPL_ENDOFBANLIST
+    pub loading: bool,
+}
+
+// Ban mask for a nick!user@host. Bans the host so a new nick or ident does not get around
+// it, and for dynamic ISP hosts like 203-0-113-7.dsl.example.net the whole domain
+pub fn ban_mask(hostmask: &str) -> String {
+    let host = hostmask.rsplit('@').next().unwrap_or("*");
+    let labels: Vec<&str> = host.split('.').collect();
+    let dynamic = labels[0]
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|d| !d.is_empty())
+        .count()
+        >= 3;
+    // IPs and cloaks (user/nick, gateway/...) are only ever the one host
+    if host.parse::<IpAddr>().is_err() && !host.contains('/') && dynamic && labels.len() >= 3 {
+        format!("*!*@*.{}", labels[1..].join("."))
+    } else {
+        format!("*!*@{}", host)
+    }
+}
+
+impl App {
+    // Bans the host `nick` connects from, see ban_mask
+    pub(crate) fn ban(&mut self, nick: &str) {
+        let channel = self.client.current_channel.clone();
+        if channel.is_empty() || nick.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /ban nick, in a channel").to_string());
+            return;
+        }
+        let Some(hostmask) = self.client.hostmask(nick) else {
+            self.buffers
+                .push_local(trf("No host known for {}, try /who {}", &[&nick, &channel]));
+            return;
+        };
+        let mask = ban_mask(&hostmask);
+        match self.client.send_labeled(
+            &format!("MODE {} +b {}", channel, mask),
+            &format!("/ban {}", nick),
+        ) {
+            Ok(_) => self
+                .buffers
+                .push_local(trf("Banning {} from {} ({})", &[&mask, &channel, &nick])),
+            Err(e) => self.buffers.push_local(trf("Error setting ban: {}", &[&e])),
+        }
+    }
+
+    // /banlist [#channel], opens the bans panel and asks the server for the list
+    pub(crate) fn show_bans(&mut self, channel: &str) {
+        let channel = match channel {
+            "" => self.client.current_channel.clone(),
+            channel => channel.to_string(),
+        };
+        if channel.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /banlist #channel, or join one first").to_string());
+            return;
+        }
+        self.bans = Some(BanList {
+            channel: channel.clone(),
+            entries: Vec::new(),
+            loading: true,
+        });
+        self.ban_selected = 0;
+        self.buffers.open("bans", BufferKind::Bans);
+        self.buffers.select_name("bans");
+        if let Err(e) = self
+            .client
+            .send_labeled(&format!("MODE {} +b", channel), "/banlist")
+        {
+            self.buffers
+                .server_mut()
+                .push(trf("Error asking for bans: {}", &[&e]));
+        }
+    }
+
+    pub fn reload_bans(&mut self) {
+        if let Some(list) = &self.bans {
+            let channel = list.channel.clone();
+            self.show_bans(&channel);
+        }
+    }
+
+    // Lifts the highlighted ban
+    pub fn unban(&mut self, index: usize) {
+        let Some(list) = &mut self.bans else {
+            return;
+        };
+        if index >= list.entries.len() {
+            return;
+        }
+        let entry = list.entries.remove(index);
+        let channel = list.channel.clone();
+        self.ban_selected = self.ban_selected.min(list.entries.len().saturating_sub(1));
+        match self
+            .client
+            .send_labeled(&format!("MODE {} -b {}", channel, entry.mask), "unban")
+        {
+            Ok(_) => self
+                .buffers
+                .server_mut()
+                .push(trf("Unbanning {} in {}", &[&entry.mask, &channel])),
+            Err(e) => self
+                .buffers
+                .server_mut()
+                .push(trf("Error lifting ban: {}", &[&e])),
+        }
+    }
+
+    // RPL_BANLIST and RPL_ENDOFBANLIST for the open panel fill it instead of the buffer
+    pub(crate) fn ban_reply(&mut self, msg: &Message) -> bool {
+        let Some(list) = &mut self.bans else {
+            return false;
+        };
+        let for_list = list.loading
+            && msg
+                .param(1)
+                .is_some_and(|c| channel::nick_key(c) == channel::nick_key(&list.channel));
+        if !for_list {
+            return false;
+        }
+        match msg.command.as_str() {
+            // <me> <channel> <mask> [<set by> <set at>]
+            "367" => {
+                if let Some(mask) = msg.param(2) {
+                    list.entries.push(BanEntry {
+                        mask: mask.to_string(),
+                        set_by: msg.param(3).map(str::to_string),
+                        set_at: msg.param(4).and_then(|t| t.parse().ok()),
+                    });
+                }
+                true
+            }
+            "368" => {
+                list.loading = false;
+                true
+            }
+            _ => false,
+        }
+    }
+}
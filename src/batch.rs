@@ -0,0 +1,94 @@
+use crate::app::App;
+use crate::message::Message;
+
+// Nicks listed in a netsplit summary before it switches to "and N more"
+const NAMED_NICKS: usize = 8;
+
+// An open netsplit or netjoin batch. Its QUITs or JOINs are collected and shown as one
+// line when the batch ends, instead of one line per user
+pub struct NetBatch {
+    split: bool,
+    servers: String,
+    nicks: Vec<String>,
+}
+
+impl NetBatch {
+    fn summary(&self) -> String {
+        let mut names = self
+            .nicks
+            .iter()
+            .take(NAMED_NICKS)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        if self.nicks.len() > NAMED_NICKS {
+            names.push_str(&format!(" and {} more", self.nicks.len() - NAMED_NICKS));
+        }
+        let (what, verb) = if self.split {
+            ("Netsplit", "quit")
+        } else {
+            ("Netjoin", "rejoined")
+        };
+        format!(
+            "{}: {} users {} ({}): {}",
+            what,
+            self.nicks.len(),
+            verb,
+            self.servers,
+            names
+        )
+    }
+}
+
+impl App {
+    // Follows BATCH for netsplit and netjoin. Returns true when `msg` was folded into a
+    // batch and should not get a line of its own
+    pub(crate) fn net_batch(&mut self, msg: &Message) -> bool {
+        if msg.command == "BATCH" {
+            let Some(reference) = msg.param(0) else {
+                return false;
+            };
+            if let Some(reference) = reference.strip_prefix('+') {
+                let split = match msg.param(1) {
+                    Some("netsplit") => true,
+                    Some("netjoin") => false,
+                    _ => return false,
+                };
+                let servers = match (msg.param(2), msg.param(3)) {
+                    (Some(a), Some(b)) => format!("{} <-> {}", a, b),
+                    (Some(a), None) => a.to_string(),
+                    _ => "unknown servers".to_string(),
+                };
+                self.batches.insert(
+                    reference.to_string(),
+                    NetBatch {
+                        split,
+                        servers,
+                        nicks: Vec::new(),
+                    },
+                );
+                return true;
+            }
+            if let Some(reference) = reference.strip_prefix('-')
+                && let Some(batch) = self.batches.remove(reference)
+            {
+                if !batch.nicks.is_empty() {
+                    self.buffers.server_mut().push(batch.summary());
+                }
+                return true;
+            }
+            return false;
+        }
+
+        let Some(batch) = msg.tags.get("batch").and_then(|r| self.batches.get_mut(r)) else {
+            return false;
+        };
+        // A netjoin repeats each user once per channel, count them once
+        if let Some(nick) = msg.nick()
+            && !batch.nicks.iter().any(|n| n.eq_ignore_ascii_case(nick))
+        {
+            batch.nicks.push(nick.to_string());
+        }
+        true
+    }
+}
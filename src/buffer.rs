@@ -0,0 +1,353 @@
+use crate::config::HistoryConfig;
+use std::collections::{HashSet, VecDeque};
+use std::mem;
+use std::time::SystemTime;
+
+// What a kept line costs beyond its text, for the memory budget
+const LINE_OVERHEAD: usize = mem::size_of::<Line>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    // Landing view summarizing the network, has no lines of its own
+    Dashboard,
+    // Raw server traffic and local command feedback
+    Server,
+    // A channel's messages, joins and parts, named after the channel
+    Channel,
+    // Private messages with one nick, named after them
+    Query,
+    // A direct DCC CHAT conversation, named "=nick"
+    DccChat,
+    // Panel listing DCC file transfers, like the dashboard it has no lines of its own
+    Transfers,
+    // Panel of pending channel invitations, also a view
+    Invites,
+    // ChanServ access list of one channel, see access.rs
+    Access,
+    // A channel's ban list, see banlist.rs
+    Bans,
+    // Server notices, see oper.rs
+    Notices,
+    // Every line sent and received while /debug is on, see debug.rs
+    Debug,
+    // Private messages and highlights that came in while we were away, see awaylog.rs
+    AwayLog,
+    // Messages a [[filters]] rule moved out of the way, see filter.rs
+    Filtered,
+}
+
+impl BufferKind {
+    // Views drawn from App state rather than from lines, local feedback goes elsewhere
+    pub fn is_view(self) -> bool {
+        matches!(
+            self,
+            BufferKind::Dashboard
+                | BufferKind::Transfers
+                | BufferKind::Invites
+                | BufferKind::Access
+                | BufferKind::Bans
+        )
+    }
+}
+
+// One line of a buffer with when it arrived, the time is formatted when it is drawn
+pub struct Line {
+    pub time: SystemTime,
+    pub text: String,
+}
+
+pub struct Buffer {
+    pub name: String,
+    pub kind: BufferKind,
+    // Oldest first, at most `limit` of them
+    pub lines: VecDeque<Line>,
+    pub limit: usize,
+    // Approximate memory the kept lines take, see LINE_OVERHEAD
+    pub bytes: usize,
+    // Number of lines ever pushed, keeps growing when old lines are dropped or cleared so
+    // observers can tell which lines they have not seen yet
+    pub total: usize,
+    // Value of `total` when the buffer was last on screen
+    pub read: usize,
+    // Value of `total` when the user last switched away, the unread rule is drawn there
+    pub marker: usize,
+    // When the newest line arrived, to compare with read markers from other clients
+    pub last_stamp: Option<SystemTime>,
+    // msgid tags of recent lines, oldest first, to drop lines a bouncer replays twice
+    msgids: HashSet<String>,
+    msgid_order: VecDeque<String>,
+}
+
+impl Buffer {
+    pub fn new(name: &str, kind: BufferKind, limit: usize) -> Self {
+        Buffer {
+            name: name.to_string(),
+            kind,
+            lines: VecDeque::new(),
+            limit,
+            bytes: 0,
+            total: 0,
+            read: 0,
+            marker: 0,
+            last_stamp: None,
+            msgids: HashSet::new(),
+            msgid_order: VecDeque::new(),
+        }
+    }
+
+    // Records `msgid`, returns false when this buffer already had a line with it.
+    // Remembers as many ids as the buffer keeps lines
+    pub fn record_msgid(&mut self, msgid: &str) -> bool {
+        if !self.msgids.insert(msgid.to_string()) {
+            return false;
+        }
+        self.msgid_order.push_back(msgid.to_string());
+        if self.msgid_order.len() > self.limit
+            && let Some(old) = self.msgid_order.pop_front()
+        {
+            self.msgids.remove(&old);
+        }
+        true
+    }
+
+    pub fn push(&mut self, text: String) {
+        let time = SystemTime::now();
+        self.bytes += text.capacity() + LINE_OVERHEAD;
+        self.lines.push_back(Line { time, text });
+        self.total += 1;
+        self.last_stamp = Some(time);
+        // Keep message list at a reasonable size
+        while self.lines.len() > self.limit {
+            self.drop_oldest();
+        }
+    }
+
+    fn drop_oldest(&mut self) {
+        if let Some(line) = self.lines.pop_front() {
+            self.bytes -= line.text.capacity() + LINE_OVERHEAD;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.bytes = 0;
+    }
+
+    pub fn unread(&self) -> usize {
+        self.total - self.read
+    }
+
+    // Another client read everything up to now
+    pub fn mark_all_read(&mut self) {
+        self.read = self.total;
+        self.marker = self.total;
+    }
+
+    // How many of the kept lines came in after the unread rule, None when there is no rule
+    // to draw because nothing or everything kept is new
+    pub fn lines_after_marker(&self) -> Option<usize> {
+        let new = self.total - self.marker;
+        (new > 0 && new < self.lines.len()).then_some(new)
+    }
+
+    // Lines pushed after the observer last saw `seen` lines in total, limited to what is
+    // still kept in the buffer
+    pub fn lines_since(&self, seen: usize) -> impl Iterator<Item = &Line> {
+        let new = self.total.saturating_sub(seen).min(self.lines.len());
+        self.recent(new)
+    }
+
+    // The last `count` kept lines, oldest first
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &Line> {
+        self.lines.range(self.lines.len().saturating_sub(count)..)
+    }
+}
+
+// The ordered set of buffers shown in the TUI together with the one currently on screen
+pub struct BufferList {
+    buffers: Vec<Buffer>,
+    active: usize,
+    history: HistoryConfig,
+}
+
+impl BufferList {
+    pub fn new(history: HistoryConfig) -> Self {
+        BufferList {
+            buffers: vec![
+                Buffer::new("dashboard", BufferKind::Dashboard, 0),
+                Buffer::new("server", BufferKind::Server, history.server),
+            ],
+            active: 0,
+            history,
+        }
+    }
+
+    // Lines kept in buffers of `kind`, views keep none
+    fn limit(&self, kind: BufferKind) -> usize {
+        match kind {
+            BufferKind::Server => self.history.server,
+            BufferKind::Channel => self.history.channel,
+            BufferKind::Query => self.history.query,
+            BufferKind::DccChat => self.history.dcc_chat,
+            BufferKind::Notices => self.history.notices,
+            BufferKind::Debug => self.history.debug,
+            BufferKind::AwayLog => self.history.awaylog,
+            BufferKind::Filtered => self.history.filtered,
+            _ => 0,
+        }
+    }
+
+    // Memory all buffers take for their lines
+    pub fn bytes(&self) -> usize {
+        self.buffers.iter().map(|b| b.bytes).sum()
+    }
+
+    // Drops the oldest lines of the biggest buffers until everything fits in the budget
+    pub fn enforce_budget(&mut self) {
+        let budget = self.history.max_memory_mb * 1024 * 1024;
+        let mut total = self.bytes();
+        while total > budget {
+            let Some(biggest) = self.buffers.iter_mut().max_by_key(|b| b.bytes) else {
+                return;
+            };
+            if biggest.lines.is_empty() {
+                return;
+            }
+            let before = biggest.bytes;
+            biggest.drop_oldest();
+            total -= before - biggest.bytes;
+        }
+    }
+
+    pub fn active(&self) -> &Buffer {
+        &self.buffers[self.active]
+    }
+
+    pub fn server_mut(&mut self) -> &mut Buffer {
+        self.buffers
+            .iter_mut()
+            .find(|b| b.kind == BufferKind::Server)
+            .expect("server buffer always exists")
+    }
+
+    // Local feedback goes to the buffer on screen, or to the server buffer when a view
+    // like the dashboard (which has no scrollback) is active
+    pub fn push_local(&mut self, line: String) {
+        if self.active().kind.is_view() {
+            self.server_mut().push(line);
+        } else {
+            self.buffers[self.active].push(line);
+        }
+    }
+
+    // Name of the buffer local feedback currently goes to, see push_local
+    pub fn local_name(&self) -> &str {
+        if self.active().kind.is_view() {
+            "server"
+        } else {
+            &self.active().name
+        }
+    }
+
+    // Adds a buffer at the end unless one with that name exists already
+    pub fn open(&mut self, name: &str, kind: BufferKind) {
+        if self.get(name).is_none() {
+            let limit = self.limit(kind);
+            self.buffers.push(Buffer::new(name, kind, limit));
+        }
+    }
+
+    // Appends to the named buffer, falling back to the server buffer if it no longer exists
+    pub fn push_to(&mut self, name: &str, line: String) {
+        match self.buffers.iter_mut().find(|b| b.name == name) {
+            Some(buffer) if !buffer.kind.is_view() => buffer.push(line),
+            _ => self.server_mut().push(line),
+        }
+    }
+
+    // Called while the active buffer is being displayed
+    pub fn mark_active_read(&mut self) {
+        let buffer = &mut self.buffers[self.active];
+        buffer.read = buffer.total;
+    }
+
+    // The user stopped looking at the active buffer without switching away from it
+    pub fn mark_active_left(&mut self) {
+        let buffer = &mut self.buffers[self.active];
+        buffer.marker = buffer.total;
+    }
+
+    pub fn clear_active(&mut self) {
+        self.buffers[self.active].clear();
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Buffer> {
+        self.buffers
+            .iter()
+            .find(|b| b.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Buffer> {
+        self.buffers
+            .iter_mut()
+            .find(|b| b.name.eq_ignore_ascii_case(name))
+    }
+
+    // For buffers named after someone, e.g. "=nick" for a DCC chat, when they change nick
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if self.get(new).is_some() {
+            return;
+        }
+        if let Some(buffer) = self.get_mut(old) {
+            buffer.name = new.to_string();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Buffer> {
+        self.buffers.iter()
+    }
+
+    pub fn select_kind(&mut self, kind: BufferKind) {
+        if let Some(index) = self.buffers.iter().position(|b| b.kind == kind) {
+            self.set_active(index);
+        }
+    }
+
+    // Leaving a buffer moves its unread rule to the end
+    fn set_active(&mut self, index: usize) {
+        if index != self.active {
+            let left = &mut self.buffers[self.active];
+            left.marker = left.total;
+            self.active = index;
+        }
+    }
+
+    // Returns false if there is no buffer with that name
+    pub fn select_name(&mut self, name: &str) -> bool {
+        match self
+            .buffers
+            .iter()
+            .position(|b| b.name.eq_ignore_ascii_case(name))
+        {
+            Some(index) => {
+                self.set_active(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.set_active((self.active + 1) % self.buffers.len());
+    }
+
+    pub fn previous(&mut self) {
+        self.set_active((self.active + self.buffers.len() - 1) % self.buffers.len());
+    }
+}
+
+impl Default for BufferList {
+    fn default() -> Self {
+        Self::new(HistoryConfig::default())
+    }
+}
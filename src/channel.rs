@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+// Nicks are compared case-insensitively, rfc1459 style casemapping is close enough to ascii
+// for the names people actually use
+pub fn nick_key(nick: &str) -> String {
+    nick.to_ascii_lowercase()
+}
+
+// Letters, digits and []\`_^{|}-, not starting with a digit or -
+pub fn valid_nick(nick: &str) -> bool {
+    let special = |c: char| "[]\\`_^{|}".contains(c);
+    !nick.is_empty()
+        && nick.starts_with(|c: char| c.is_ascii_alphabetic() || special(c))
+        && nick
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || special(c) || c == '-')
+}
+
+// Status symbols that can go in front of a channel, see STATUSMSG in ISUPPORT
+const STATUS_SYMBOLS: &str = "~&@%+!";
+
+// Splits a STATUSMSG target like "@#chan", a message only for the channel's ops, into
+// ("@", "#chan"). None for anything else, "&chan" included
+pub fn status_target(target: &str) -> Option<(&str, &str)> {
+    for (i, c) in target.char_indices() {
+        if i > 0 && (c == '#' || c == '&') {
+            return Some(target.split_at(i));
+        }
+        if !STATUS_SYMBOLS.contains(c) {
+            return None;
+        }
+    }
+    None
+}
+
+// The channel or nick a message went to, without STATUSMSG symbols
+pub fn strip_status(target: &str) -> &str {
+    status_target(target).map_or(target, |(_, name)| name)
+}
+
+// Matches an IRC mask like *!*@*.example.org against nick!user@host. `*` stands for any
+// run of characters and `?` for exactly one
+pub fn mask_matches(mask: &str, hostmask: &str) -> bool {
+    let mask: Vec<char> = mask.to_ascii_lowercase().chars().collect();
+    let text: Vec<char> = hostmask.to_ascii_lowercase().chars().collect();
+    let (mut m, mut t) = (0, 0);
+    // Where the last * was and how much text it has taken so far, for backtracking
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if m < mask.len() && (mask[m] == '?' || mask[m] == text[t]) {
+            m += 1;
+            t += 1;
+        } else if m < mask.len() && mask[m] == '*' {
+            star = Some((m, t));
+            m += 1;
+        } else if let Some((star_m, star_t)) = star {
+            m = star_m + 1;
+            t = star_t + 1;
+            star = Some((star_m, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    mask[m..].iter().all(|c| *c == '*')
+}
+
+// How the nick list of a channel is ordered, see /sortnicks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NickSort {
+    // Highest status first, then by name, like most clients
+    #[default]
+    Status,
+    Alpha,
+    // Most recent speakers first
+    Activity,
+}
+
+impl NickSort {
+    pub fn name(self) -> &'static str {
+        match self {
+            NickSort::Status => "status",
+            NickSort::Alpha => "alpha",
+            NickSort::Activity => "activity",
+        }
+    }
+}
+
+// Someone in a channel, `prefix` holds their status symbols like "@" or "+"
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub nick: String,
+    pub prefix: String,
+    // When they last said something in the channel, for sorting by activity
+    pub last_spoke: Option<Instant>,
+}
+
+// A channel we are in and who is there with us
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub name: String,
+    pub members: Vec<Member>,
+    // Empty when there is none or the server has not told us yet
+    pub topic: String,
+}
+
+impl Channel {
+    pub fn new(name: &str) -> Self {
+        Channel {
+            name: name.to_string(),
+            members: Vec::new(),
+            topic: String::new(),
+        }
+    }
+
+    pub fn add(&mut self, nick: &str, prefix: &str) {
+        let key = nick_key(nick);
+        match self.members.iter_mut().find(|m| nick_key(&m.nick) == key) {
+            Some(member) => member.prefix = prefix.to_string(),
+            None => self.members.push(Member {
+                nick: nick.to_string(),
+                prefix: prefix.to_string(),
+                last_spoke: None,
+            }),
+        }
+    }
+
+    pub fn member(&self, nick: &str) -> Option<&Member> {
+        let key = nick_key(nick);
+        self.members.iter().find(|m| nick_key(&m.nick) == key)
+    }
+
+    pub fn spoke(&mut self, nick: &str) {
+        let key = nick_key(nick);
+        if let Some(member) = self.members.iter_mut().find(|m| nick_key(&m.nick) == key) {
+            member.last_spoke = Some(Instant::now());
+        }
+    }
+
+    // Who spoke here, newest first, for Tab on an empty input line
+    pub fn recent_speakers(&self) -> Vec<&str> {
+        let mut speakers: Vec<&Member> = self
+            .members
+            .iter()
+            .filter(|m| m.last_spoke.is_some())
+            .collect();
+        speakers.sort_by_key(|m| std::cmp::Reverse(m.last_spoke));
+        speakers.iter().map(|m| m.nick.as_str()).collect()
+    }
+
+    pub fn remove(&mut self, nick: &str) {
+        let key = nick_key(nick);
+        self.members.retain(|m| nick_key(&m.nick) != key);
+    }
+
+    pub fn rename(&mut self, old: &str, new: &str) {
+        let key = nick_key(old);
+        if let Some(member) = self.members.iter_mut().find(|m| nick_key(&m.nick) == key) {
+            member.nick = new.to_string();
+        }
+    }
+
+    // Gives or takes one status symbol, keeping the prefix ordered highest first
+    pub fn set_status(&mut self, nick: &str, symbol: char, on: bool, symbols: &str) {
+        let key = nick_key(nick);
+        let Some(member) = self.members.iter_mut().find(|m| nick_key(&m.nick) == key) else {
+            return;
+        };
+        member.prefix.retain(|c| c != symbol);
+        if on {
+            member.prefix = symbols
+                .chars()
+                .filter(|c| *c == symbol || member.prefix.contains(*c))
+                .collect();
+        }
+    }
+
+    // Members ordered like most clients show them: by their highest status, then by name.
+    // `symbols` is the PREFIX list from ISUPPORT, highest first
+    pub fn sorted_members(&self, symbols: &str) -> Vec<&Member> {
+        let rank = |m: &Member| {
+            m.prefix
+                .chars()
+                .filter_map(|c| symbols.find(c))
+                .min()
+                .unwrap_or(symbols.len())
+        };
+        let mut members: Vec<&Member> = self.members.iter().collect();
+        members.sort_by(|a, b| {
+            rank(a)
+                .cmp(&rank(b))
+                .then_with(|| nick_key(&a.nick).cmp(&nick_key(&b.nick)))
+        });
+        members
+    }
+
+    // Members in the order `sort` asks for. By activity, whoever spoke last comes first and
+    // those who did not speak yet follow by status
+    pub fn members_by(&self, sort: NickSort, symbols: &str) -> Vec<&Member> {
+        let mut members = self.sorted_members(symbols);
+        match sort {
+            NickSort::Status => {}
+            NickSort::Alpha => members.sort_by_key(|m| nick_key(&m.nick)),
+            NickSort::Activity => members.sort_by_key(|m| std::cmp::Reverse(m.last_spoke)),
+        }
+        members
+    }
+}
+
+// What we know about a user independent of any channel
+#[derive(Debug, Clone, Default)]
+pub struct User {
+    pub away: bool,
+    pub away_message: Option<String>,
+    // Services account they are logged into, from account-notify, extended-join or WHOIS
+    pub account: Option<String>,
+    // user@host, from userhost-in-names, WHO or anything they send
+    pub userhost: Option<String>,
+    // From WHO or extended-join
+    pub realname: Option<String>,
+}
+
+// Users keyed by nick_key
+pub type Users = HashMap<String, User>;
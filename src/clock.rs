@@ -0,0 +1,159 @@
+use crate::config::ThemeConfig;
+use crate::i18n::trf;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Utc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How buffer lines show when they arrived and when the day changed, from [theme]
+pub struct TimeFormat {
+    stamp: String,
+    day: String,
+    // None is the system's local time
+    offset: Option<FixedOffset>,
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        TimeFormat {
+            stamp: "%H:%M".to_string(),
+            day: "%A, %-d %B".to_string(),
+            offset: None,
+        }
+    }
+}
+
+impl TimeFormat {
+    pub fn new(theme: &ThemeConfig) -> Result<TimeFormat, String> {
+        let stamp = match &theme.timestamp {
+            Some(stamp) => stamp.clone(),
+            None if theme.hour12 => "%I:%M %p".to_string(),
+            None => "%H:%M".to_string(),
+        };
+        for format in [&stamp, &theme.day_format] {
+            if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                return Err(trf("Invalid time format '{}'", &[&format]));
+            }
+        }
+        let offset = match theme.timezone.to_lowercase().as_str() {
+            "local" => None,
+            "utc" => FixedOffset::east_opt(0),
+            zone => Some(zone.parse::<FixedOffset>().map_err(|_| {
+                format!(
+                    "Invalid timezone '{}', use local, utc or an offset like +05:30",
+                    theme.timezone
+                )
+            })?),
+        };
+        Ok(TimeFormat {
+            stamp,
+            day: theme.day_format.clone(),
+            offset,
+        })
+    }
+
+    fn at(&self, time: SystemTime) -> DateTime<FixedOffset> {
+        let utc = DateTime::<Utc>::from(time);
+        match self.offset {
+            Some(offset) => utc.with_timezone(&offset),
+            None => utc.with_timezone(&Local).fixed_offset(),
+        }
+    }
+
+    // What goes in front of a line, None when timestamps are turned off
+    pub fn stamp(&self, time: SystemTime) -> Option<String> {
+        (!self.stamp.is_empty()).then(|| self.at(time).format(&self.stamp).to_string())
+    }
+
+    pub fn day(&self, time: SystemTime) -> NaiveDate {
+        self.at(time).date_naive()
+    }
+
+    // e.g. "Tuesday, 12 March"
+    pub fn day_name(&self, time: SystemTime) -> String {
+        self.at(time).format(&self.day).to_string()
+    }
+}
+
+// UTC timestamp in the format IRCv3 uses for server-time and read markers,
+// e.g. 2024-03-01T12:30:05.120Z
+pub fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let of_day = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// UTC date and time to the minute for lists, e.g. 2024-03-01 12:30
+pub fn date_minutes(time: SystemTime) -> String {
+    let stamp = iso8601(time);
+    format!("{} {}", &stamp[..10], &stamp[11..16])
+}
+
+// UTC time of day with milliseconds, e.g. 12:30:05.120
+pub fn time_millis(time: SystemTime) -> String {
+    iso8601(time)[11..23].to_string()
+}
+
+// Rough length of a duration in its two largest units, e.g. "3d 4h" or "12m 5s"
+pub fn ago(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+// "90s", "30m", "2h", "1d" or combined like "1h30m". A bare number is minutes
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    if let Ok(minutes) = text.parse::<u64>() {
+        return Some(Duration::from_secs(minutes * 60));
+    }
+    let mut secs = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return None,
+        };
+        secs += number.parse::<u64>().ok()? * unit;
+        number.clear();
+    }
+    (number.is_empty() && secs > 0).then(|| Duration::from_secs(secs))
+}
+
+// Days since 1970-01-01 to (year, month, day), Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,56 @@
+use crate::app::{App, MAX_MESSAGE_BYTES};
+use crate::i18n::tr;
+use crate::message;
+
+// The Alt+Enter composer: a message of several lines for `target`, sent line by line
+// through the outgoing queue
+pub struct Composer {
+    pub target: String,
+    pub text: String,
+}
+
+impl Composer {
+    // The PRIVMSG texts it becomes, in order: every line that is not blank, long ones
+    // split the same way queue_lines does
+    pub fn parts(&self) -> Vec<String> {
+        self.text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .flat_map(|line| message::split_text(line, MAX_MESSAGE_BYTES))
+            .collect()
+    }
+}
+
+impl App {
+    // Alt+Enter, what was typed so far becomes the first line and the cursor goes below it
+    pub(crate) fn open_composer(&mut self, input: &mut String) {
+        let target = self.client.current_channel.clone();
+        if target.is_empty() {
+            self.buffers
+                .push_local(tr("Join a channel first with /join #channel").to_string());
+            return;
+        }
+        let mut text = std::mem::take(input);
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        self.composer = Some(Composer { target, text });
+    }
+
+    // Alt+Enter again, the lines go out as fast as [paste] burst and interval_ms allow
+    pub(crate) fn send_composer(&mut self) {
+        let Some(composer) = self.composer.take() else {
+            return;
+        };
+        let lines: Vec<String> = composer
+            .text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.to_string())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        self.queue_lines(&composer.target, lines);
+    }
+}
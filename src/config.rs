@@ -0,0 +1,994 @@
+use crate::channel;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+type Result<T> = std::result::Result<T, String>;
+
+// User configuration, read from $XDG_CONFIG_HOME/irconic/config.toml (or ~/.config/irconic)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    // Where to connect at startup, asked for on the first run
+    pub connect: ConnectConfig,
+    // Channels offered as quick-join shortcuts on the dashboard
+    pub favorites: Vec<String>,
+    // Tried in order when our nick is taken or refused at connect time, e.g. ["foo_", "foo__"]
+    pub alt_nicks: Vec<String>,
+    pub control: ControlConfig,
+    pub relay: RelayConfig,
+    pub http: HttpConfig,
+    pub webhook: WebhookConfig,
+    pub dbus: DbusConfig,
+    pub sounds: SoundsConfig,
+    pub dcc: DccConfig,
+    pub ctcp: CtcpConfig,
+    pub away: AwayConfig,
+    pub ignore: IgnoreConfig,
+    pub highlight: HighlightConfig,
+    pub encoding: EncodingConfig,
+    pub nickserv: NickServConfig,
+    pub chanserv: ChanServConfig,
+    pub log: LogConfig,
+    pub history: HistoryConfig,
+    pub paste: PasteConfig,
+    pub kick: KickConfig,
+    pub reconnect: ReconnectConfig,
+    pub quit: QuitConfig,
+    pub who: WhoConfig,
+    pub theme: ThemeConfig,
+    pub locale: LocaleConfig,
+    pub parser: ParserConfig,
+    // Defaults for every server, each [servers."host"] can override them
+    pub ident: IdentConfig,
+    pub identd: IdentdConfig,
+    // Trusted users, opped or voiced on join, see automode.rs
+    pub automodes: Vec<AutoModeRule>,
+    // Regex rules that hide, dim or move messages, see filter.rs
+    pub filters: Vec<FilterRule>,
+    pub fish: FishConfig,
+    pub keys: KeysConfig,
+    // Logins by server host, e.g. [servers."irc.libera.chat"]
+    pub servers: HashMap<String, ServerConfig>,
+}
+
+// The local control socket, see control.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ControlConfig {
+    pub enabled: bool,
+    // Defaults to $XDG_RUNTIME_DIR/irconic.sock. Its directory must be private (mode 0700)
+    pub path: Option<PathBuf>,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        ControlConfig {
+            enabled: true,
+            path: None,
+        }
+    }
+}
+
+// Relay server for remote frontends, see relay.rs. Off unless explicitly enabled
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RelayConfig {
+    pub enabled: bool,
+    pub bind: String,
+    pub password: String,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        RelayConfig {
+            enabled: false,
+            bind: "127.0.0.1:9001".to_string(),
+            password: String::new(),
+        }
+    }
+}
+
+// The local HTTP API, see http_api.rs. Off unless explicitly enabled
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub enabled: bool,
+    pub bind: String,
+    // Sent by clients as `Authorization: Bearer <token>`
+    pub token: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            enabled: false,
+            bind: "127.0.0.1:9002".to_string(),
+            token: String::new(),
+        }
+    }
+}
+
+// Private messages and highlights POSTed somewhere, see webhook.rs. Off while url is empty
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub url: String,
+    // The JSON body, {nick}, {target}, {text} and {network} are filled in
+    pub template: String,
+    // Extra headers, e.g. "Authorization: Bearer tk_..."
+    pub headers: Vec<String>,
+    // Also while someone is reading along, not only when away or detached
+    pub always: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            url: String::new(),
+            template: r#"{"title":"{nick} in {target}","message":"{text}"}"#.to_string(),
+            headers: Vec::new(),
+            always: false,
+        }
+    }
+}
+
+// Desktop integration over D-Bus, see dbus.rs
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DbusConfig {
+    // A desktop notification whenever the bell rings, clicking it shows the buffer
+    pub notifications: bool,
+    // Leave the server before the machine suspends and reconnect after it resumes
+    pub sleep: bool,
+}
+
+// Sound files played for events, see sounds.rs. Empty ones ring the bell for highlights
+// and private messages and play nothing otherwise
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SoundsConfig {
+    // Run with the file as its only argument, e.g. paplay, aplay or afplay
+    pub player: String,
+    pub highlight: String,
+    pub private: String,
+    pub connect: String,
+    pub disconnect: String,
+    // Start with sounds off, /sounds turns them back on
+    pub muted: bool,
+}
+
+impl Default for SoundsConfig {
+    fn default() -> Self {
+        SoundsConfig {
+            player: "paplay".to_string(),
+            highlight: String::new(),
+            private: String::new(),
+            connect: String::new(),
+            disconnect: String::new(),
+            muted: false,
+        }
+    }
+}
+
+// DCC file transfers, see dcc.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DccConfig {
+    // Where accepted files are saved, defaults to ~/Downloads
+    pub download_dir: Option<PathBuf>,
+    // Ports offered for outgoing sends, e.g. the range forwarded on the router. 0 lets the
+    // system pick any free port
+    pub port_min: u16,
+    pub port_max: u16,
+    // Address announced in offers when the one used for the server connection is not
+    // reachable from outside, e.g. behind NAT
+    pub own_ip: Option<IpAddr>,
+    // Offer chats passively (reverse DCC): the other side listens and we connect to it,
+    // for when nobody can connect to us
+    pub passive: bool,
+    // Offers of bigger files are ignored, as are offers that do not say their size
+    pub max_size_mb: u64,
+}
+
+impl Default for DccConfig {
+    fn default() -> Self {
+        DccConfig {
+            download_dir: None,
+            port_min: 0,
+            port_max: 0,
+            own_ip: None,
+            passive: false,
+            max_size_mb: 4096,
+        }
+    }
+}
+
+impl DccConfig {
+    pub fn download_dir(&self) -> PathBuf {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        if let Some(dir) = &self.download_dir {
+            return match (dir.strip_prefix("~"), home) {
+                (Ok(rest), Some(home)) => home.join(rest),
+                _ => dir.clone(),
+            };
+        }
+        match home {
+            Some(home) => home.join("Downloads"),
+            None => std::env::temp_dir(),
+        }
+    }
+}
+
+// Automatic CTCP replies and their flood protection, see ctcp.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CtcpConfig {
+    // Answer VERSION, PING and CLIENTINFO
+    pub reply: bool,
+    // Requests one host may make per window before it is ignored
+    pub per_sender: usize,
+    // Replies we send per window in total, requests beyond that are dropped
+    pub global: usize,
+    pub window_secs: u64,
+    // How long an offender's CTCP is ignored
+    pub cooldown_secs: u64,
+}
+
+impl Default for CtcpConfig {
+    fn default() -> Self {
+        CtcpConfig {
+            reply: true,
+            per_sender: 3,
+            global: 8,
+            window_secs: 30,
+            cooldown_secs: 300,
+        }
+    }
+}
+
+// /away defaults and auto-away
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AwayConfig {
+    // Used by /away without a message and by auto-away
+    pub message: String,
+    // Minutes without keyboard input before going away automatically, 0 turns it off
+    pub auto_minutes: u64,
+}
+
+impl Default for AwayConfig {
+    fn default() -> Self {
+        AwayConfig {
+            message: "Away from keyboard".to_string(),
+            auto_minutes: 0,
+        }
+    }
+}
+
+// How to log in to one server. Passwords left out are asked for when the server wants
+// them, with hidden input
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    // Server password (PASS), e.g. for a bouncer
+    pub password: Option<String>,
+    // For NickServ GHOST/REGAIN, the SASL password is used when this is left out
+    pub nickserv_password: Option<String>,
+    // Commands run after 001, e.g. ["/msg HostServ ON", "/mode $me +x"]
+    pub perform: Vec<String>,
+    #[serde(flatten)]
+    pub ident: IdentConfig,
+}
+
+// One [[automodes]] entry. Either the account or the mask has to match
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AutoModeRule {
+    // Channel name, * and ? wildcards allowed
+    pub channel: String,
+    pub account: Option<String>,
+    // nick!user@host with * and ? wildcards
+    pub mask: Option<String>,
+    pub mode: AutoMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoMode {
+    Op,
+    Voice,
+}
+
+// One [[filters]] entry. Every regex given has to match, at least one must be given
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FilterRule {
+    // Against nick!user@host
+    pub sender: Option<String>,
+    // Against the channel or our nick
+    pub target: Option<String>,
+    pub content: Option<String>,
+    pub action: FilterAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    // Dropped like an ignored user's messages
+    Hide,
+    // Shown in the dim color
+    Dim,
+    // Moved to the "filtered" buffer
+    Move,
+}
+
+// The language of the interface, see i18n.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LocaleConfig {
+    // "auto" follows LANG, or a language like "de"
+    pub language: String,
+}
+
+impl Default for LocaleConfig {
+    fn default() -> Self {
+        LocaleConfig {
+            language: "auto".to_string(),
+        }
+    }
+}
+
+// How closely incoming lines are checked. Both modes repair what they can, bare LF line
+// ends, NUL bytes, text that is not UTF-8, strict also reports it in the debug buffer
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ParserConfig {
+    // "lenient" or "strict"
+    pub mode: String,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        ParserConfig {
+            mode: "lenient".to_string(),
+        }
+    }
+}
+
+// FiSH keys by channel or nick, e.g. "#secret" = "cbc:hunter2", see fish.rs
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FishConfig {
+    pub keys: HashMap<String, String>,
+}
+
+// Channel keys remembered in keys.toml, see keys.rs
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    // Environment variable holding a passphrase, 4 to 56 bytes. When set keys.toml keeps
+    // the keys encrypted with it
+    pub passphrase_env: Option<String>,
+}
+
+// Written by the first-run setup, see setup.rs. Without a nick and server the setup runs
+// again on the next start
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConnectConfig {
+    pub nick: String,
+    pub server: String,
+    pub port: u16,
+    pub tls: bool,
+    // Joined once we are registered on that server
+    pub channels: Vec<String>,
+}
+
+impl Default for ConnectConfig {
+    fn default() -> Self {
+        ConnectConfig {
+            nick: String::new(),
+            server: String::new(),
+            port: 6667,
+            tls: false,
+            channels: Vec::new(),
+        }
+    }
+}
+
+impl ConnectConfig {
+    pub fn is_complete(&self) -> bool {
+        !self.nick.is_empty() && !self.server.is_empty()
+    }
+}
+
+// A responder for the server's ident query while we connect, see identd.rs. Port 113
+// needs root or a port forward, e.g. from 113 to a port above 1024
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IdentdConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for IdentdConfig {
+    fn default() -> Self {
+        IdentdConfig {
+            enabled: false,
+            port: 113,
+        }
+    }
+}
+
+// What we tell the server and other clients about ourselves. [ident] holds the defaults,
+// a [servers."host"] entry can give a network an identity of its own
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IdentConfig {
+    // Defaults to the [connect] nick
+    pub nick: Option<String>,
+    // The user part of our hostmask, shown as ~username without identd. Defaults to the nick
+    pub username: Option<String>,
+    // Defaults to the nick
+    pub realname: Option<String>,
+    // Answer to CTCP VERSION, "" sends none
+    pub version: Option<String>,
+    pub privacy: Option<Privacy>,
+    // SASL PLAIN account, used when the server offers SASL
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+}
+
+// Hiding who we are from the hostmask, WHOIS and CTCP VERSION
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Privacy {
+    // Whatever username, realname and version say
+    #[default]
+    Off,
+    // A username and realname made up for each connection
+    Random,
+    // The same generic ones everybody could be using
+    Blank,
+}
+
+// What to do when our nick is taken at connect time and we end up as nick_
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recover {
+    Off,
+    // Say so and leave it to /regain or /ghost
+    #[default]
+    Ask,
+    // NickServ REGAIN, which frees the nick and switches us to it
+    Regain,
+    // NickServ GHOST, then NICK once NickServ answers, for services without REGAIN
+    Ghost,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NickServConfig {
+    pub recover: Recover,
+}
+
+// The diagnostics log, see logging.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogConfig {
+    // error, warn, info, debug or trace, or a filter per module
+    pub level: String,
+    // Days of old log files kept next to today's
+    pub keep_days: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_string(),
+            keep_days: 7,
+        }
+    }
+}
+
+// How much scrollback is kept, see buffer.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    // Lines kept per buffer, by kind
+    pub server: usize,
+    pub channel: usize,
+    pub query: usize,
+    pub dcc_chat: usize,
+    pub notices: usize,
+    pub debug: usize,
+    pub awaylog: usize,
+    pub filtered: usize,
+    // Once all buffers together take more than this, the oldest lines of the biggest
+    // buffers are dropped
+    pub max_memory_mb: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            server: 1000,
+            channel: 1000,
+            query: 1000,
+            dcc_chat: 1000,
+            notices: 500,
+            debug: 2000,
+            awaylog: 1000,
+            filtered: 1000,
+            max_memory_mb: 64,
+        }
+    }
+}
+
+// Look of the chat panes
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    // Colors, one of the built-in themes in theme.rs
+    pub name: String,
+    // strftime format shown in front of every line, "" shows none. Defaults to %H:%M, or
+    // %I:%M %p with hour12
+    pub timestamp: Option<String>,
+    pub hour12: bool,
+    // Date in the separator drawn when the day changes, also strftime
+    pub day_format: String,
+    // "local", "utc" or a fixed offset like "+05:30"
+    pub timezone: String,
+    // Consecutive messages from one sender leave out the nick after the first
+    pub group: Grouping,
+    // How far apart they may be to count as consecutive
+    pub group_secs: u64,
+    // Where wrapped rows of a message continue, see Indent
+    pub indent: Indent,
+    // Columns of the nick with indent = "fixed", longer ones are cut with a +
+    pub nick_width: usize,
+    // For screen readers: no box drawing, no side-by-side panes, every line with its nick
+    // and a row announcing buffer switches and alerts
+    pub accessible: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grouping {
+    Off,
+    // Blank where the nick would be
+    #[default]
+    Hide,
+    // The nick repeated, dimmed
+    Dim,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Indent {
+    // At the left edge
+    Off,
+    // Under the message text, wherever the nick in front of it ends
+    #[default]
+    Dynamic,
+    // Nicks padded to nick_width, so text and wrapped rows line up across messages
+    Fixed,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            name: "default".to_string(),
+            timestamp: None,
+            hour12: false,
+            day_format: "%A, %-d %B".to_string(),
+            timezone: "local".to_string(),
+            group: Grouping::default(),
+            group_secs: 120,
+            indent: Indent::default(),
+            nick_width: 16,
+            accessible: false,
+        }
+    }
+}
+
+// Where long pastes can go instead of the channel, see paste.rs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PasteService {
+    #[default]
+    #[serde(rename = "0x0.st")]
+    ZeroXZero,
+    #[serde(rename = "paste.rs")]
+    PasteRs,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PasteConfig {
+    // Sending more lines at once than this asks about uploading them first, 0 never asks
+    pub max_lines: usize,
+    pub service: PasteService,
+    // Queued lines go out like an ircd's flood limit allows: `burst` right away, then one
+    // per `interval_ms`
+    pub burst: u32,
+    pub interval_ms: u64,
+}
+
+impl Default for PasteConfig {
+    fn default() -> Self {
+        PasteConfig {
+            max_lines: 5,
+            service: PasteService::default(),
+            burst: 3,
+            interval_ms: 1000,
+        }
+    }
+}
+
+// Refreshing what we know about channel members, see who.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WhoConfig {
+    // Every channel is asked about once in this many minutes, 0 only asks on join
+    pub poll_minutes: u64,
+    // Bigger channels are left out of polling, their replies are a lot of traffic
+    pub max_members: usize,
+}
+
+impl Default for WhoConfig {
+    fn default() -> Self {
+        WhoConfig {
+            poll_minutes: 5,
+            max_members: 500,
+        }
+    }
+}
+
+// What happens when we are kicked, see kick.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KickConfig {
+    pub rejoin: bool,
+    // Seconds to wait before rejoining, and between tries when the channel refuses us
+    pub delay_secs: u64,
+    // Tries before giving up on a channel, e.g. when we are banned
+    pub max_attempts: u32,
+}
+
+impl Default for KickConfig {
+    fn default() -> Self {
+        KickConfig {
+            rejoin: false,
+            delay_secs: 5,
+            max_attempts: 3,
+        }
+    }
+}
+
+// Connecting again after the connection was lost, see servers.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    pub enabled: bool,
+    // Seconds before the first try, doubled after every failed one up to max_delay_secs
+    pub delay_secs: u64,
+    pub max_delay_secs: u64,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            enabled: true,
+            delay_secs: 10,
+            max_delay_secs: 300,
+        }
+    }
+}
+
+// What others see when we leave, and whether Esc asks before quitting
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QuitConfig {
+    // Used by Esc, /quit and /disconnect without a reason
+    pub message: String,
+    // Used by /part without a reason, empty means no part message
+    pub part_message: String,
+    // Esc has to be pressed twice to quit
+    pub confirm: bool,
+}
+
+impl Default for QuitConfig {
+    fn default() -> Self {
+        QuitConfig {
+            message: format!("Irconic {}, see you later", env!("CARGO_PKG_VERSION")),
+            part_message: "See you later".to_string(),
+            confirm: true,
+        }
+    }
+}
+
+// Character sets for networks that predate UTF-8. Names are the usual labels like
+// "latin1", "iso-8859-15" or "windows-1251"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EncodingConfig {
+    // Reads incoming lines that are not valid UTF-8
+    pub fallback: String,
+    // By NETWORK name or server host, used for both reading and sending
+    pub networks: HashMap<String, String>,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        EncodingConfig {
+            fallback: "windows-1252".to_string(),
+            networks: HashMap::new(),
+        }
+    }
+}
+
+impl EncodingConfig {
+    pub fn for_network(&self, network: &str, server: &str) -> Option<&str> {
+        self.networks
+            .iter()
+            .find(|(name, _)| {
+                name.eq_ignore_ascii_case(network) || name.eq_ignore_ascii_case(server)
+            })
+            .map(|(_, encoding)| encoding.as_str())
+    }
+}
+
+// Messages from ignored users are dropped before they reach any buffer
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IgnoreConfig {
+    // Services accounts, these follow a user across nick changes. Needs a server with
+    // account-notify and extended-join
+    pub accounts: Vec<String>,
+    // nick!user@host masks with * and ? wildcards, e.g. "*!*@*.spam.example"
+    pub masks: Vec<String>,
+    // Realnames with * and ? wildcards, known once WHO or extended-join told us
+    pub realnames: Vec<String>,
+}
+
+// What does not count as a highlight, and when only private messages alert us, see
+// highlight.rs
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    // Channels with * and ? wildcards, e.g. "#*-bots"
+    pub exclude_channels: Vec<String>,
+    // Nicks, or nick!user@host masks, with * and ? wildcards
+    pub exclude_senders: Vec<String>,
+    // Local time ranges like "23:00-07:00", highlights stay quiet during them
+    pub quiet_hours: Vec<String>,
+    // By NETWORK name or server host. The exclusions add to the ones above, quiet_hours
+    // replaces them when given
+    pub networks: HashMap<String, NetworkHighlight>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NetworkHighlight {
+    pub exclude_channels: Vec<String>,
+    pub exclude_senders: Vec<String>,
+    pub quiet_hours: Option<Vec<String>>,
+}
+
+// Which command set ChanServ speaks, for the access panel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessStyle {
+    // FLAGS #channel mask +flags, Atheme (Libera and most others)
+    #[default]
+    Flags,
+    // ACCESS #channel ADD mask level, Anope
+    Access,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChanServConfig {
+    pub style: AccessStyle,
+}
+
+impl IgnoreConfig {
+    pub fn matches(&self, hostmask: &str, account: Option<&str>, realname: Option<&str>) -> bool {
+        let by_account = account.is_some_and(|account| {
+            self.accounts
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(account))
+        });
+        by_account
+            || self
+                .masks
+                .iter()
+                .any(|m| channel::mask_matches(m, hostmask))
+            || realname.is_some_and(|realname| {
+                self.realnames
+                    .iter()
+                    .any(|m| channel::mask_matches(m, realname))
+            })
+    }
+}
+
+impl Config {
+    pub fn dir() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            return Some(PathBuf::from(dir).join("irconic"));
+        }
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("irconic"))
+    }
+
+    // $XDG_STATE_HOME/irconic (or ~/.local/state/irconic), for the diagnostics log
+    pub fn log_dir() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+            return Some(PathBuf::from(dir).join("irconic"));
+        }
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join(".local")
+                .join("state")
+                .join("irconic")
+        })
+    }
+
+    // Location for sockets and other per-session files: $XDG_RUNTIME_DIR, or a directory of
+    // our own in the state directory. Never a shared one like /tmp, where anyone could
+    // put a socket at the name first
+    pub fn runtime_path(file: &str) -> Option<PathBuf> {
+        match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+            Some(dir) => Some(PathBuf::from(dir).join(file)),
+            None => Self::log_dir().map(|dir| dir.join("run").join(file)),
+        }
+    }
+
+    pub fn server(&self, host: &str) -> Option<&ServerConfig> {
+        self.servers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            .map(|(_, server)| server)
+    }
+
+    // The ident settings for `host`, its own where it has them and the defaults otherwise
+    pub fn ident(&self, host: &str) -> IdentConfig {
+        let global = self.ident.clone();
+        let Some(server) = self.server(host).map(|s| s.ident.clone()) else {
+            return global;
+        };
+        // The account and its password go together, a network with its own account does
+        // not get the default password
+        let (sasl_username, sasl_password) = match server.sasl_username {
+            Some(username) => (Some(username), server.sasl_password),
+            None => (
+                global.sasl_username,
+                server.sasl_password.or(global.sasl_password),
+            ),
+        };
+        IdentConfig {
+            nick: server.nick.or(global.nick),
+            username: server.username.or(global.username),
+            realname: server.realname.or(global.realname),
+            version: server.version.or(global.version),
+            privacy: server.privacy.or(global.privacy),
+            sasl_username,
+            sasl_password,
+        }
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Self::dir().map(|dir| dir.join("config.toml"))
+    }
+
+    // The config file as a plain table, for changing a few values and keeping the rest
+    fn read_table() -> Result<(PathBuf, toml::Table)> {
+        let Some(path) = Self::path() else {
+            return Err("No config directory, set $HOME or $XDG_CONFIG_HOME".to_string());
+        };
+        let table = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok((path, table))
+    }
+
+    // A new file is private from the start, it may get a password in it later
+    fn write_table(path: &Path, table: &toml::Table) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let contents =
+            toml::to_string(table).map_err(|e| format!("Failed to encode the config: {}", e))?;
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    // Puts the first-run answers into the config file, keeping whatever else is in it. The
+    // file is made private since it may hold the SASL password
+    pub fn save_connect(connect: &ConnectConfig, sasl: Option<(&str, &str)>) -> Result<()> {
+        let (path, mut table) = Self::read_table()?;
+        let value = toml::Value::try_from(connect)
+            .map_err(|e| format!("Failed to encode the config: {}", e))?;
+        table.insert("connect".to_string(), value);
+        if let Some((username, password)) = sasl {
+            let server = table
+                .entry("servers")
+                .or_insert_with(|| toml::Table::new().into())
+                .as_table_mut()
+                .ok_or("servers in the config is not a table")?
+                .entry(connect.server.as_str())
+                .or_insert_with(|| toml::Table::new().into())
+                .as_table_mut()
+                .ok_or("The server's entry in the config is not a table")?;
+            server.insert("sasl_username".to_string(), username.into());
+            server.insert("sasl_password".to_string(), password.into());
+        }
+        Self::write_table(&path, &table)?;
+        // An existing file keeps its mode, make it private once it holds a password
+        if sasl.is_some() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    // [theme] name, as picked with /theme
+    pub fn save_theme(name: &str) -> Result<()> {
+        let (path, mut table) = Self::read_table()?;
+        table
+            .entry("theme")
+            .or_insert_with(|| toml::Table::new().into())
+            .as_table_mut()
+            .ok_or("theme in the config is not a table")?
+            .insert("name".to_string(), name.into());
+        Self::write_table(&path, &table)
+    }
+
+    // [[filters]], as changed with /filter
+    pub fn save_filters(filters: &[FilterRule]) -> Result<()> {
+        let (path, mut table) = Self::read_table()?;
+        let value = toml::Value::try_from(filters)
+            .map_err(|e| format!("Failed to encode the config: {}", e))?;
+        table.insert("filters".to_string(), value);
+        Self::write_table(&path, &table)
+    }
+
+    // [fish] keys, as changed with /fish
+    pub fn save_fish_keys(keys: &HashMap<String, String>) -> Result<()> {
+        let (path, mut table) = Self::read_table()?;
+        let value = toml::Value::try_from(keys)
+            .map_err(|e| format!("Failed to encode the config: {}", e))?;
+        table
+            .entry("fish")
+            .or_insert_with(|| toml::Table::new().into())
+            .as_table_mut()
+            .ok_or("fish in the config is not a table")?
+            .insert("keys".to_string(), value);
+        Self::write_table(&path, &table)
+    }
+
+    // A missing config file is not an error, the client simply runs with defaults
+    pub fn load() -> Result<Config> {
+        let Some(path) = Self::path() else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Invalid config {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+}
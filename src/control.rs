@@ -0,0 +1,129 @@
+use crate::app::AppEvent;
+use crate::config::Config;
+use crate::i18n::trf;
+use std::fs::{self, DirBuilder};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, String>;
+
+// How long a control client waits for the main loop to answer a command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A local Unix socket that accepts one command per line from scripts and window manager
+// keybindings, e.g. `echo "send #rust hello" | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/irconic.sock`
+// Every command is answered with a single "ok ..." or "error ..." line.
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    pub fn default_path() -> Option<PathBuf> {
+        Config::runtime_path("irconic.sock")
+    }
+
+    // Binds the socket and starts accepting clients on a background thread. Commands are
+    // forwarded to the main loop as AppEvent::Control
+    pub fn start(path: &Path, events: Sender<AppEvent>) -> Result<ControlSocket> {
+        let listener = bind_private(path)?;
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let events = events.clone();
+                thread::spawn(move || Self::serve_client(stream, events));
+            }
+        });
+
+        Ok(ControlSocket {
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn serve_client(stream: UnixStream, events: Sender<AppEvent>) {
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let command = line.trim();
+            if command.is_empty() {
+                continue;
+            }
+
+            let (reply_tx, reply_rx) = channel();
+            let event = AppEvent::Control {
+                command: command.to_string(),
+                reply: reply_tx,
+            };
+            if events.send(event).is_err() {
+                break;
+            }
+            let reply = match reply_rx.recv_timeout(REPLY_TIMEOUT) {
+                Ok(Ok(message)) => format!("ok {}", message),
+                Ok(Err(message)) => format!("error {}", message),
+                Err(_) => "error no reply from client".to_string(),
+            };
+            if writer
+                .write_all(format!("{}\n", reply.trim_end()).as_bytes())
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+}
+
+// Binds a socket in a directory only we can enter, created with mode 0700 if missing.
+// Nobody else can reach the socket, not even in the moment before it is chmodded to 0600
+pub(crate) fn bind_private(path: &Path) -> Result<UnixListener> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| trf("Failed to create {}: {}", &[&dir.display(), &e]))?;
+    let mode = fs::metadata(dir)
+        .map_err(|e| trf("Failed to check {}: {}", &[&dir.display(), &e]))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(trf(
+            "{} is open to other users, sockets need a private directory (chmod 700)",
+            &[&dir.display()],
+        ));
+    }
+
+    if path.exists() {
+        // A socket nobody answers on is left over from a crashed session
+        if UnixStream::connect(path).is_ok() {
+            return Err(trf("{} is in use by another instance", &[&path.display()]));
+        }
+        fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove stale {}: {}", path.display(), e))?;
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|e| format!("Failed to bind {}: {}", path.display(), e))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+    Ok(listener)
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::app::App;
+use crate::config::CtcpConfig;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Requests we answer automatically
+const SUPPORTED: &str = "ACTION CLIENTINFO DCC PING VERSION";
+
+// What to do with a CTCP request, see CtcpLimiter::check
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Reply,
+    // Over the global limit, dropped without punishing this sender
+    Drop,
+    // This sender just went over its own limit and is ignored from now on
+    Offender,
+    // Still in its cooldown
+    Ignored,
+}
+
+// Keeps a burst of CTCP requests from making us send more than the server allows, which
+// would get us disconnected for flooding. Senders are keyed by host, so changing nicks
+// does not reset their count
+#[derive(Default)]
+pub struct CtcpLimiter {
+    senders: HashMap<String, VecDeque<Instant>>,
+    replies: VecDeque<Instant>,
+    // Host -> when its cooldown ends
+    ignored: HashMap<String, Instant>,
+    // When senders and ignored were last cleared of entries that ran out
+    swept: Option<Instant>,
+}
+
+impl CtcpLimiter {
+    // Key for the sender of `msg`: the host part of the prefix, or the nick without one
+    pub fn sender_key(msg: &Message) -> String {
+        let prefix = msg.prefix.as_deref().unwrap_or("");
+        match prefix.split_once('@') {
+            Some((_, host)) => host.to_ascii_lowercase(),
+            None => prefix.to_ascii_lowercase(),
+        }
+    }
+
+    pub fn is_ignored(&mut self, sender: &str) -> bool {
+        match self.ignored.get(sender) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                self.ignored.remove(sender);
+                false
+            }
+            None => false,
+        }
+    }
+
+    // Counts a request from `sender` and decides whether it may be answered
+    pub fn check(&mut self, sender: &str, config: &CtcpConfig) -> Verdict {
+        if self.is_ignored(sender) {
+            return Verdict::Ignored;
+        }
+        let now = Instant::now();
+        let window = Duration::from_secs(config.window_secs);
+        self.sweep(now, window);
+
+        let requests = self.senders.entry(sender.to_string()).or_default();
+        requests.retain(|t| now.duration_since(*t) < window);
+        requests.push_back(now);
+        if requests.len() > config.per_sender {
+            self.senders.remove(sender);
+            let cooldown = Duration::from_secs(config.cooldown_secs);
+            self.ignored.insert(sender.to_string(), now + cooldown);
+            return Verdict::Offender;
+        }
+
+        self.replies.retain(|t| now.duration_since(*t) < window);
+        if self.replies.len() >= config.global {
+            return Verdict::Drop;
+        }
+        self.replies.push_back(now);
+        Verdict::Reply
+    }
+
+    // Once per window, forgets senders with no request left in it and cooldowns that are
+    // over, so a long session does not keep every host that ever sent a CTCP
+    fn sweep(&mut self, now: Instant, window: Duration) {
+        if self.swept.is_some_and(|t| now.duration_since(t) < window) {
+            return;
+        }
+        self.swept = Some(now);
+        self.senders.retain(|_, requests| {
+            requests
+                .back()
+                .is_some_and(|t| now.duration_since(*t) < window)
+        });
+        self.ignored.retain(|_, until| *until > now);
+    }
+}
+
+// Milliseconds since the epoch, what our CTCP PING carries so the reply tells the round trip
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis())
+}
+
+impl App {
+    // /ctcp nick VERSION|PING|TIME|CLIENTINFO|<anything> [args]
+    pub(crate) fn ctcp_command(&mut self, args: &str) {
+        let mut parts = args.splitn(3, ' ');
+        let (Some(nick), Some(command)) = (parts.next(), parts.next()) else {
+            self.buffers.push_local(
+                tr("Usage: /ctcp nick VERSION|PING|TIME|CLIENTINFO|<command> [args]").to_string(),
+            );
+            return;
+        };
+        let command = command.to_ascii_uppercase();
+        let body = match (command.as_str(), parts.next()) {
+            ("PING", None) => format!("PING {}", now_millis()),
+            (_, Some(args)) => format!("{} {}", command, args),
+            (_, None) => command.clone(),
+        };
+        match self.client.send_labeled(
+            &format!("PRIVMSG {} :\x01{}\x01", nick, body),
+            &format!("/ctcp {} {}", nick, command),
+        ) {
+            Ok(_) => self
+                .buffers
+                .push_local(trf("CTCP {} sent to {}", &[&command, &nick])),
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error sending CTCP: {}", &[&e])),
+        }
+    }
+
+    // A CTCP reply in a NOTICE, shown as one readable line. PING replies to our own
+    // /ctcp carry the time it was sent, so they show the round trip instead
+    pub(crate) fn ctcp_reply(&mut self, msg: &Message, ctcp: &str) {
+        let nick = msg.nick().unwrap_or("?");
+        let (command, args) = ctcp.split_once(' ').unwrap_or((ctcp, ""));
+        let command = command.to_ascii_uppercase();
+        let text = match args.parse::<u128>() {
+            Ok(sent) if command == "PING" && sent <= now_millis() => {
+                format!("{:.3}s", (now_millis() - sent) as f64 / 1000.0)
+            }
+            _ if args.is_empty() => "(empty)".to_string(),
+            _ => args.to_string(),
+        };
+        self.buffers
+            .server_mut()
+            .push(trf("CTCP {} reply from {}: {}", &[&command, &nick, &text]));
+    }
+
+    // A CTCP request in a PRIVMSG. Returns false when it comes from an ignored sender and
+    // must not be acted on at all, DCC offers included
+    pub(crate) fn ctcp_request(&mut self, msg: &Message, ctcp: &str) -> bool {
+        let sender = CtcpLimiter::sender_key(msg);
+        let (command, args) = ctcp.split_once(' ').unwrap_or((ctcp, ""));
+        let command = command.to_ascii_uppercase();
+
+        // Actions and DCC are not answered, only the ignore list applies to them
+        if command == "ACTION" {
+            return true;
+        }
+        if command == "DCC" {
+            return !self.ctcp_limiter.is_ignored(&sender);
+        }
+        if !self.config.ctcp.reply {
+            return true;
+        }
+
+        let nick = msg.nick().unwrap_or("?").to_string();
+        match self.ctcp_limiter.check(&sender, &self.config.ctcp) {
+            Verdict::Reply => {}
+            Verdict::Drop => return true,
+            Verdict::Ignored => return false,
+            Verdict::Offender => {
+                self.buffers.server_mut().push(trf(
+                    "CTCP flood from {} ({}), ignoring their CTCP for {}s",
+                    &[&nick, &sender, &self.config.ctcp.cooldown_secs],
+                ));
+                return false;
+            }
+        }
+
+        let reply = match command.as_str() {
+            "VERSION" => match &self.client.version {
+                Some(version) => format!("VERSION {}", version),
+                None => return true,
+            },
+            "PING" => format!("PING {}", args),
+            "CLIENTINFO" => format!("CLIENTINFO {}", SUPPORTED),
+            _ => return true,
+        };
+        if let Err(e) = self
+            .client
+            .send_notice(&nick, &format!("\x01{}\x01", reply))
+        {
+            self.buffers
+                .server_mut()
+                .push(trf("Error answering CTCP {}: {}", &[&command, &e]));
+        }
+        true
+    }
+}
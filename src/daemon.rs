@@ -0,0 +1,116 @@
+use crate::app::App;
+use crate::auth;
+use crate::config::Config;
+use crate::i18n;
+use crate::i18n::{tr, trf};
+use crate::irc_client::IrcClient;
+use crate::logging;
+use crate::relay;
+use crate::tui_client::{self, ConnectionDetails};
+use std::error::Error;
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+use tracing::info;
+
+// How often the headless main loop wakes up to process server lines and frontend requests
+const TICK: Duration = Duration::from_millis(50);
+
+// Socket the daemon serves the relay protocol on for `--attach`
+pub fn session_path() -> Option<PathBuf> {
+    Config::runtime_path("irconic-session.sock")
+}
+
+// `irconic --daemon`: asks for the connection details in the foreground, then leaves the
+// session running in a background process that `irconic --attach` connects to
+pub fn start() -> Result<(), Box<dyn Error>> {
+    let Some(details) = tui_client::connection_details()? else {
+        return Ok(());
+    };
+    let child = Command::new(std::env::current_exe()?)
+        .arg("--daemon-child")
+        .arg(&details.nickname)
+        .arg(&details.server)
+        .arg(details.port.to_string())
+        .arg(details.tls.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Own process group, so closing the terminal does not take the session with it
+        .process_group(0)
+        .spawn()?;
+
+    println!("Irconic daemon running as pid {}.", child.id());
+    println!("Attach with `irconic --attach`, press Esc there to detach again.");
+    Ok(())
+}
+
+// The background half of --daemon: the same App the TUI uses, minus the terminal
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [nickname, server, port, tls] = args else {
+        return Err("usage: --daemon-child <nick> <server> <port> <tls>".into());
+    };
+    let details = ConnectionDetails {
+        nickname: nickname.clone(),
+        server: server.clone(),
+        port: port.parse()?,
+        tls: tls.parse()?,
+    };
+
+    let config = Config::load()?;
+    let language_error = i18n::init(&config.locale.language).err();
+    let log_error = logging::init(&config.log).err();
+    info!(server = %details.server, port = details.port, "starting the daemon");
+    let mut client = IrcClient::new(&details.nickname);
+    client.alt_nicks = config.alt_nicks.clone();
+    client.quit_message = config.quit.message.clone();
+    auth::apply_login(&mut client, &config, &details.server, false);
+    client.connect(&details.server, details.port, details.tls)?;
+    client.register()?;
+    let (tx, rx) = channel();
+    client.start_receiver(tx.clone())?;
+
+    let (events_tx, events_rx) = channel();
+    let mut app = App::new(client, config, events_tx);
+    app.headless = true;
+    app.server_lines = Some(tx);
+    app.buffers
+        .server_mut()
+        .push(tr("Irconic daemon started").to_string());
+    if let Some(e) = language_error {
+        app.buffers
+            .server_mut()
+            .push(trf("Config error: {}", &[&e]));
+    }
+    if let Some(e) = log_error {
+        app.buffers.server_mut().push(trf("Log error: {}", &[&e]));
+    }
+    let _control = app.start_services();
+
+    let path = session_path().ok_or("No runtime directory, set $XDG_RUNTIME_DIR or $HOME")?;
+    relay::start_unix(&path, app.events())?;
+
+    while !app.should_quit {
+        while let Ok(line) = rx.try_recv() {
+            app.handle_server_line(line);
+        }
+        while let Ok(event) = events_rx.try_recv() {
+            app.handle_event(event);
+        }
+        // Without a frontend nobody is reading, so unread counts keep growing until one
+        // attaches and looks at the buffer
+        if app.relay.is_attached() {
+            app.buffers.mark_active_read();
+        }
+        app.tick();
+        thread::sleep(TICK);
+    }
+
+    let _ = fs::remove_file(&path);
+    app.seen.save(true)?;
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use crate::app::{App, AppEvent};
+use crate::i18n::{tr, trf};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+type Result<T> = std::result::Result<T, String>;
+
+// Desktop notifications and suspend handling on Linux. Both talk D-Bus through gdbus from
+// glib, which every desktop has, the way paste uploads go through curl
+
+// What the D-Bus threads report to the main loop
+pub enum DbusSignal {
+    // A notification we sent is up, clicking it focuses `buffer`
+    Notified { id: u32, buffer: String },
+    ActionInvoked(u32),
+    NotificationClosed(u32),
+    // logind is about to suspend (true) or just resumed (false)
+    PrepareForSleep(bool),
+}
+
+// Notifications on screen and whether we left the server for a suspend
+#[derive(Default)]
+pub struct Dbus {
+    notifications: HashMap<u32, String>,
+    pub(crate) suspended: bool,
+}
+
+// The first uint32 in gdbus output, e.g. 42 in "(uint32 42,)" or
+// "...ActionInvoked (uint32 42, 'default')"
+fn first_uint(text: &str) -> Option<u32> {
+    let rest = &text[text.find("uint32 ")? + 7..];
+    let end = rest.find(|c: char| !c.is_ascii_digit())?;
+    rest[..end].parse().ok()
+}
+
+// Runs `gdbus monitor` with `args` and hands every line it prints to `signal`, which picks
+// the ones that matter
+fn monitor(
+    args: &[&str],
+    events: Sender<AppEvent>,
+    signal: fn(&str) -> Option<DbusSignal>,
+) -> Result<()> {
+    let mut child = Command::new("gdbus")
+        .arg("monitor")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("could not run gdbus: {}", e))?;
+    let stdout = child.stdout.take().ok_or("gdbus has no output")?;
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(signal) = signal(&line)
+                && events.send(AppEvent::Dbus(signal)).is_err()
+            {
+                break;
+            }
+        }
+        let _ = child.kill();
+    });
+    Ok(())
+}
+
+fn notification_signal(line: &str) -> Option<DbusSignal> {
+    if line.contains("Notifications.ActionInvoked") {
+        Some(DbusSignal::ActionInvoked(first_uint(line)?))
+    } else if line.contains("Notifications.NotificationClosed") {
+        Some(DbusSignal::NotificationClosed(first_uint(line)?))
+    } else {
+        None
+    }
+}
+
+fn sleep_signal(line: &str) -> Option<DbusSignal> {
+    if !line.contains("login1.Manager.PrepareForSleep") {
+        return None;
+    }
+    Some(DbusSignal::PrepareForSleep(line.contains("(true,)")))
+}
+
+// Shows a notification with a default action, which is what a click on it runs
+fn notify(summary: &str, body: &str) -> Result<u32> {
+    let output = Command::new("gdbus")
+        .args([
+            "call",
+            "--session",
+            "--dest",
+            "org.freedesktop.Notifications",
+            "--object-path",
+            "/org/freedesktop/Notifications",
+            "--method",
+            "org.freedesktop.Notifications.Notify",
+            "Irconic",
+            "0",
+            "",
+            summary,
+            body,
+            "['default', 'Open']",
+            "{}",
+            "-1",
+        ])
+        .output()
+        .map_err(|e| format!("could not run gdbus: {}", e))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    match first_uint(&text) {
+        Some(id) if output.status.success() => Ok(id),
+        _ => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
+
+impl App {
+    // Starts listening for clicks on our notifications and for suspends, as configured
+    pub(crate) fn start_dbus(&mut self) {
+        let config = self.config.dbus.clone();
+        if config.notifications {
+            let args = ["--session", "--dest", "org.freedesktop.Notifications"];
+            if let Err(e) = monitor(&args, self.events(), notification_signal) {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Desktop notifications disabled: {}", &[&e]));
+            }
+        }
+        if config.sleep {
+            let args = [
+                "--system",
+                "--dest",
+                "org.freedesktop.login1",
+                "--object-path",
+                "/org/freedesktop/login1",
+            ];
+            if let Err(e) = monitor(&args, self.events(), sleep_signal) {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Suspend handling disabled: {}", &[&e]));
+            }
+        }
+    }
+
+    // A desktop notification for the latest line of `buffer`, called when it rings
+    pub(crate) fn desktop_notify(&mut self, buffer: &str) {
+        if !self.config.dbus.notifications {
+            return;
+        }
+        let Some(line) = self.buffers.get(buffer).and_then(|b| b.recent(1).next()) else {
+            return;
+        };
+        let summary = format!("Irconic: {}", buffer);
+        let body = line.text.clone();
+        let buffer = buffer.to_string();
+        let events = self.events();
+        thread::spawn(move || {
+            // Without a notification daemon there is nothing to click, nothing to report
+            if let Ok(id) = notify(&summary, &body) {
+                let _ = events.send(AppEvent::Dbus(DbusSignal::Notified { id, buffer }));
+            }
+        });
+    }
+
+    pub(crate) fn dbus_signal(&mut self, signal: DbusSignal) {
+        match signal {
+            DbusSignal::Notified { id, buffer } => {
+                self.dbus.notifications.insert(id, buffer);
+            }
+            DbusSignal::ActionInvoked(id) => {
+                // The same as `switch-buffer` on the control socket
+                if let Some(buffer) = self.dbus.notifications.remove(&id)
+                    && let Err(e) = self.handle_control(&format!("switch-buffer {}", buffer))
+                {
+                    self.buffers.server_mut().push(e);
+                }
+            }
+            DbusSignal::NotificationClosed(id) => {
+                self.dbus.notifications.remove(&id);
+            }
+            DbusSignal::PrepareForSleep(true) => {
+                if self.client.stream.is_some() {
+                    self.client.disconnect_with("Suspending");
+                    self.dbus.suspended = true;
+                    self.buffers
+                        .server_mut()
+                        .push(tr("Disconnected for the suspend").to_string());
+                }
+            }
+            DbusSignal::PrepareForSleep(false) => {
+                if std::mem::take(&mut self.dbus.suspended) {
+                    self.buffers
+                        .server_mut()
+                        .push(tr("Resumed, reconnecting").to_string());
+                    self.reconnect_command();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,961 @@
+use crate::app::{App, AppEvent};
+use crate::buffer::BufferKind;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long an outgoing offer waits for the other side to connect
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(180);
+
+// A transfer with no data moving for this long is given up
+const STALL_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Progress events are sent at most this often per transfer
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Receive,
+    Send,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferState {
+    // An incoming offer waiting for /dcc get or /dcc close
+    Offered,
+    // Our offer, waiting for the other side to connect
+    Waiting,
+    // We asked the sender to continue a partial file, waiting for its DCC ACCEPT
+    Resuming,
+    Active,
+    Done,
+    Failed(String),
+    Cancelled,
+    Declined,
+}
+
+// Shared between a transfer and the thread moving its data
+#[derive(Default)]
+pub struct TransferHandle {
+    cancel: AtomicBool,
+    // Where in the file to start, set once a resume is agreed on
+    offset: AtomicU64,
+}
+
+pub struct Transfer {
+    pub id: usize,
+    pub direction: Direction,
+    pub nick: String,
+    pub filename: String,
+    pub size: u64,
+    pub transferred: u64,
+    pub state: TransferState,
+    // Where the sender listens, for incoming offers
+    pub addr: Option<SocketAddr>,
+    // The port in the offer, resume requests refer to the transfer by it
+    pub port: u16,
+    pub path: PathBuf,
+    // When data started moving and how much of the file was there already, for the speed
+    pub started: Option<(Instant, u64)>,
+    handle: Arc<TransferHandle>,
+}
+
+impl Transfer {
+    pub fn percent(&self) -> u64 {
+        if self.size == 0 {
+            return 0;
+        }
+        (self.transferred * 100 / self.size).min(100)
+    }
+
+    // Bytes per second since the transfer (or its latest resume) started
+    pub fn speed(&self) -> Option<f64> {
+        let (since, base) = self.started?;
+        let elapsed = since.elapsed().as_secs_f64();
+        if elapsed < 0.5 {
+            return None;
+        }
+        Some(self.transferred.saturating_sub(base) as f64 / elapsed)
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let speed = self.speed().filter(|s| *s > 0.0)?;
+        let left = self.size.saturating_sub(self.transferred) as f64;
+        Some(Duration::from_secs_f64(left / speed))
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self.state,
+            TransferState::Waiting | TransferState::Resuming | TransferState::Active
+        )
+    }
+
+    pub fn can_retry(&self) -> bool {
+        matches!(
+            self.state,
+            TransferState::Failed(_) | TransferState::Cancelled
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        let state = match &self.state {
+            TransferState::Offered => tr("offered").to_string(),
+            TransferState::Waiting => tr("waiting for peer").to_string(),
+            TransferState::Resuming => tr("asking to resume").to_string(),
+            TransferState::Active => format!("{}%", self.percent()),
+            TransferState::Done => tr("done").to_string(),
+            TransferState::Failed(e) => trf("failed: {}", &[&e]),
+            TransferState::Cancelled => tr("cancelled").to_string(),
+            TransferState::Declined => tr("declined").to_string(),
+        };
+        let arrow = match self.direction {
+            Direction::Receive => "<-",
+            Direction::Send => "->",
+        };
+        format!(
+            "#{} {} {} {} ({}) [{}]",
+            self.id,
+            arrow,
+            self.nick,
+            self.filename,
+            format_size(self.size),
+            state
+        )
+    }
+}
+
+// Parses the arguments of a `DCC SEND <filename> <ip> <port> <size>` CTCP. The filename may
+// be quoted when it contains spaces, the ip is usually a 32 bit integer
+pub fn parse_send_offer(args: &str) -> Option<(String, SocketAddr, u64)> {
+    let args = args.trim();
+    let (filename, rest) = match args.strip_prefix('"') {
+        Some(quoted) => {
+            let (name, rest) = quoted.split_once('"')?;
+            (name.to_string(), rest)
+        }
+        None => {
+            let (name, rest) = args.split_once(' ')?;
+            (name.to_string(), rest)
+        }
+    };
+
+    let mut fields = rest.split_whitespace();
+    let addr = parse_addr(fields.next()?, fields.next()?)?;
+    let size = fields
+        .next()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(0);
+    Some((filename, addr, size))
+}
+
+// Parses `DCC RESUME <filename> <port> <position>` and the matching `DCC ACCEPT`. Clients
+// disagree on quoting the filename, and some send "file.ext" instead of the real name, so
+// only the port and position are used to find the transfer
+pub fn parse_resume(args: &str) -> Option<(u16, u64)> {
+    let mut fields = args.rsplitn(3, ' ');
+    let position = fields.next()?.parse().ok()?;
+    let port = fields.next()?.parse().ok()?;
+    Some((port, position))
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// Never trust the sender's idea of a path, only the last component is used
+fn safe_filename(name: &str) -> String {
+    let name = Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = name.trim_start_matches('.').to_string();
+    if name.is_empty() {
+        "download".to_string()
+    } else {
+        name
+    }
+}
+
+// Picks a path in `dir` that does not exist yet: file.txt, file.txt.1, file.txt.2 ...
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(name);
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    path
+}
+
+pub fn bind_in_range(min: u16, max: u16) -> std::io::Result<TcpListener> {
+    if min == 0 || max < min {
+        return TcpListener::bind(("0.0.0.0", 0));
+    }
+    let mut last_error = None;
+    for port in min..=max {
+        match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| ErrorKind::AddrNotAvailable.into()))
+}
+
+// Waits for the peer to connect to an offer we made, giving up after ACCEPT_TIMEOUT or
+// when `cancel` is set
+pub fn accept_with_timeout(
+    listener: TcpListener,
+    cancel: &AtomicBool,
+) -> Result<TcpStream, String> {
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let started = Instant::now();
+    let stream = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if cancel.load(Ordering::Relaxed) {
+                    return Err(tr("cancelled").to_string());
+                }
+                if started.elapsed() > ACCEPT_TIMEOUT {
+                    return Err(tr("the offer timed out").to_string());
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+// How an address is written in DCC offers, IPv4 as a 32 bit integer
+pub fn ip_field(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4).to_string(),
+        IpAddr::V6(v6) => v6.to_string(),
+    }
+}
+
+// Parses "<ip> <port>" as found in DCC offers
+pub fn parse_addr(ip: &str, port: &str) -> Option<SocketAddr> {
+    let ip = match ip.parse::<u32>() {
+        Ok(number) => IpAddr::V4(Ipv4Addr::from(number)),
+        Err(_) => ip.parse::<IpAddr>().ok()?,
+    };
+    Some(SocketAddr::new(ip, port.parse().ok()?))
+}
+
+// Copies `reader` into `writer` while reporting progress. `copied` starts at the resume
+// offset, so progress and acks count the whole file. Returns the final position
+fn copy_with_progress(
+    id: usize,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    mut after_chunk: impl FnMut(u64) -> std::io::Result<()>,
+    (mut copied, limit): (u64, u64),
+    handle: &TransferHandle,
+    events: &Sender<AppEvent>,
+) -> Result<u64, String> {
+    let mut buffer = [0u8; CHUNK_SIZE];
+    let mut last_report = Instant::now();
+    let mut last_data = Instant::now();
+    loop {
+        if handle.cancel.load(Ordering::Relaxed) {
+            return Err(tr("cancelled").to_string());
+        }
+        if limit > 0 && copied >= limit {
+            break;
+        }
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            // Sockets use a short read timeout so a cancel is noticed on a stalled transfer
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if last_data.elapsed() > STALL_TIMEOUT {
+                    return Err(tr("the transfer stalled").to_string());
+                }
+                continue;
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        last_data = Instant::now();
+        // Whatever the peer sends past the announced size is not written
+        let n = if limit > 0 {
+            n.min((limit - copied) as usize)
+        } else {
+            n
+        };
+        writer.write_all(&buffer[..n]).map_err(|e| e.to_string())?;
+        copied += n as u64;
+        after_chunk(copied).map_err(|e| e.to_string())?;
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            let _ = events.send(AppEvent::DccProgress {
+                id,
+                transferred: copied,
+            });
+            last_report = Instant::now();
+        }
+    }
+    Ok(copied)
+}
+
+fn receive(
+    id: usize,
+    addr: SocketAddr,
+    path: &Path,
+    size: u64,
+    handle: &TransferHandle,
+    events: &Sender<AppEvent>,
+) -> Result<u64, String> {
+    let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(30))
+        .map_err(|e| format!("connect to {} failed: {}", addr, e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .map_err(|e| e.to_string())?;
+    let mut acks = stream.try_clone().map_err(|e| e.to_string())?;
+
+    let offset = handle.offset.load(Ordering::Relaxed);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(offset == 0)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    if offset > 0 {
+        // The agreed offset can only take us back into what we have, never past it
+        let have = file
+            .metadata()
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .len();
+        if offset > have || offset >= size {
+            return Err(trf(
+                "the sender resumed at {} but we have {} of {}",
+                &[&offset, &have, &size],
+            ));
+        }
+        // Anything past the agreed offset is from a broken chunk, drop it
+        file.set_len(offset)
+            .and_then(|_| file.seek(SeekFrom::Start(offset)))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    let _ = events.send(AppEvent::DccProgress {
+        id,
+        transferred: offset,
+    });
+
+    // Classic DCC: acknowledge every chunk with the total received so far (32 bit, big endian)
+    let received = copy_with_progress(
+        id,
+        &mut stream,
+        &mut file,
+        |total| acks.write_all(&(total as u32).to_be_bytes()),
+        (offset, size),
+        handle,
+        events,
+    )?;
+
+    if size > 0 && received < size {
+        return Err(trf(
+            "connection closed after {} of {} bytes",
+            &[&received, &size],
+        ));
+    }
+    Ok(received)
+}
+
+fn send(
+    id: usize,
+    listener: TcpListener,
+    path: &Path,
+    handle: &TransferHandle,
+    events: &Sender<AppEvent>,
+) -> Result<u64, String> {
+    let mut stream = accept_with_timeout(listener, &handle.cancel)?;
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    // Set by a DCC RESUME that came in before the receiver connected
+    let offset = handle.offset.load(Ordering::Relaxed);
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let _ = events.send(AppEvent::DccProgress {
+        id,
+        transferred: offset,
+    });
+
+    let mut output = stream.try_clone().map_err(|e| e.to_string())?;
+    let sent = copy_with_progress(
+        id,
+        &mut file,
+        &mut output,
+        |_| Ok(()),
+        (offset, 0),
+        handle,
+        events,
+    )?;
+
+    // Wait for the receiver to acknowledge everything before closing the connection
+    stream
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .map_err(|e| e.to_string())?;
+    let mut ack = [0u8; 4];
+    while stream.read_exact(&mut ack).is_ok() {
+        if u32::from_be_bytes(ack) == sent as u32 {
+            break;
+        }
+    }
+    Ok(sent)
+}
+
+impl App {
+    // An incoming `DCC SEND` offer, remembered until the user accepts or declines it
+    pub(crate) fn dcc_offer(&mut self, msg: &Message, args: &str) {
+        let nick = msg.nick().unwrap_or("?").to_string();
+        let Some((filename, addr, size)) = parse_send_offer(args) else {
+            self.buffers.server_mut().push(trf(
+                "Ignoring malformed DCC SEND from {}: {}",
+                &[&nick, &args],
+            ));
+            return;
+        };
+        // Without a size nothing stops the sender from filling the disk
+        let max = self.config.dcc.max_size_mb * 1024 * 1024;
+        if size == 0 || size > max {
+            let why = if size == 0 {
+                "it does not say how big the file is".to_string()
+            } else {
+                trf("{} is over dcc.max_size_mb", &[&format_size(size)])
+            };
+            self.buffers.server_mut().push(trf(
+                "Ignoring DCC SEND of {} from {}: {}",
+                &[&filename, &nick, &why],
+            ));
+            return;
+        }
+        let id = self.next_dcc_id();
+        let filename = safe_filename(&filename);
+        let partial = self
+            .config
+            .dcc
+            .download_dir()
+            .join(&filename)
+            .metadata()
+            .is_ok_and(|meta| meta.len() < size);
+        let resume = if partial {
+            trf(", continue the partial file with /dcc resume {}", &[&id])
+        } else {
+            String::new()
+        };
+        self.notify(&trf(
+            "DCC SEND offer #{} from {}: {} ({}). Accept with /dcc get {}{}, decline with /dcc close {}",
+            &[&id, &nick, &filename, &format_size(size), &id, &resume, &id],
+        ));
+        self.transfers.push(Transfer {
+            id,
+            direction: Direction::Receive,
+            nick,
+            filename,
+            size,
+            transferred: 0,
+            state: TransferState::Offered,
+            addr: Some(addr),
+            port: addr.port(),
+            path: PathBuf::new(),
+            started: None,
+            handle: Arc::default(),
+        });
+    }
+
+    // `DCC RESUME` from a receiver of one of our offers: start from their position
+    pub(crate) fn dcc_resume_request(&mut self, msg: &Message, args: &str) {
+        let nick = msg.nick().unwrap_or("?");
+        let Some((port, position)) = parse_resume(args) else {
+            return;
+        };
+        let Some(transfer) = self.transfers.iter_mut().find(|t| {
+            t.direction == Direction::Send
+                && t.state == TransferState::Waiting
+                && t.port == port
+                && t.nick.eq_ignore_ascii_case(nick)
+        }) else {
+            return;
+        };
+        if position >= transfer.size {
+            return;
+        }
+        transfer.handle.offset.store(position, Ordering::Relaxed);
+        let reply = format!(
+            "\x01DCC ACCEPT {} {} {}\x01",
+            transfer.filename, port, position
+        );
+        let line = trf(
+            "{} resumes DCC {} at {}",
+            &[&transfer.nick, &transfer.describe(), &format_size(position)],
+        );
+        let nick = transfer.nick.clone();
+        if let Err(e) = self.client.send_message(&nick, &reply) {
+            self.buffers
+                .server_mut()
+                .push(trf("Error accepting DCC resume: {}", &[&e]));
+            return;
+        }
+        self.buffers.server_mut().push(line);
+    }
+
+    // `DCC ACCEPT`, the sender agreed to our resume request
+    pub(crate) fn dcc_resume_accepted(&mut self, msg: &Message, args: &str) {
+        let nick = msg.nick().unwrap_or("?");
+        let Some((port, position)) = parse_resume(args) else {
+            return;
+        };
+        let Some(index) = self.transfers.iter().position(|t| {
+            t.direction == Direction::Receive
+                && t.state == TransferState::Resuming
+                && t.port == port
+                && t.nick.eq_ignore_ascii_case(nick)
+        }) else {
+            return;
+        };
+        self.transfers[index]
+            .handle
+            .offset
+            .store(position, Ordering::Relaxed);
+        self.start_receive(index);
+    }
+
+    // Transfers and chats share one sequence of ids, so /dcc close works on either
+    pub(crate) fn next_dcc_id(&self) -> usize {
+        let transfers = self.transfers.iter().map(|t| t.id);
+        let chats = self.chats.iter().map(|c| c.id);
+        transfers.chain(chats).max().unwrap_or(0) + 1
+    }
+
+    // Address put into our offers, the configured one or the end of the server connection
+    pub(crate) fn dcc_own_ip(&mut self) -> Option<IpAddr> {
+        let ip = self.config.dcc.own_ip.or_else(|| self.client.local_ip());
+        if ip.is_none() {
+            self.buffers
+                .push_local(tr("Not connected to server, cannot tell our address").to_string());
+        }
+        ip
+    }
+
+    pub(crate) fn dcc_command(&mut self, args: &str) {
+        let mut parts = args.splitn(3, ' ');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("send"), Some(nick), Some(file)) => self.dcc_send(nick, file.trim()),
+            (Some("get"), Some(id), None) => self.dcc_get(id, false),
+            (Some("resume"), Some(id), None) => self.dcc_get(id, true),
+            (Some("retry"), Some(id), None) => self.dcc_retry(id),
+            (Some("chat"), Some(nick), None) => self.dcc_chat(nick),
+            (Some("close"), Some(id), None) => {
+                if self.chats.iter().any(|c| c.matches(id)) {
+                    self.dcc_chat_close(id);
+                } else {
+                    self.dcc_close(id);
+                }
+            }
+            (Some("close"), None, None)
+                if self.buffers.active().kind == BufferKind::DccChat =>
+            {
+                let name = self.buffers.active().name.clone();
+                self.dcc_chat_close(&name);
+            }
+            (Some("list"), None, None) => {
+                if self.transfers.is_empty() && self.chats.is_empty() {
+                    self.buffers.push_local(tr("No DCC transfers.").to_string());
+                }
+                let transfers = self.transfers.iter().map(|t| t.describe());
+                let chats = self.chats.iter().map(|c| c.describe());
+                let lines: Vec<String> = transfers.chain(chats).collect();
+                for line in lines {
+                    self.buffers.push_local(line);
+                }
+            }
+            (Some(""), None, None) => self.show_transfers(),
+            _ => self.buffers.push_local(
+                tr("Usage: /dcc send <nick> <file> | /dcc get <id> | /dcc resume <id> | /dcc retry <id> | /dcc chat <nick> | /dcc close <id> | /dcc list")
+                    .to_string(),
+            ),
+        }
+    }
+
+    // Opens the transfers panel
+    pub fn show_transfers(&mut self) {
+        self.buffers.open("transfers", BufferKind::Transfers);
+        self.buffers.select_name("transfers");
+    }
+
+    pub(crate) fn dcc_progress(&mut self, id: usize, transferred: u64) {
+        if let Some(transfer) = self.transfers.iter_mut().find(|t| t.id == id) {
+            if transfer.state != TransferState::Active {
+                transfer.state = TransferState::Active;
+                transfer.started = Some((Instant::now(), transferred));
+            }
+            transfer.transferred = transferred;
+        }
+    }
+
+    pub(crate) fn dcc_finished(&mut self, id: usize, result: Result<u64, String>) {
+        let Some(transfer) = self.transfers.iter_mut().find(|t| t.id == id) else {
+            return;
+        };
+        match result {
+            Ok(bytes) => {
+                transfer.transferred = bytes;
+                transfer.state = TransferState::Done;
+            }
+            Err(_) if transfer.handle.cancel.load(Ordering::Relaxed) => {
+                transfer.state = TransferState::Cancelled;
+            }
+            Err(e) => transfer.state = TransferState::Failed(e),
+        }
+        let mut line = format!("DCC {}", transfer.describe());
+        match transfer.state {
+            TransferState::Done if transfer.direction == Direction::Receive => {
+                line.push_str(&trf(", saved to {}", &[&transfer.path.display()]));
+            }
+            TransferState::Failed(_) => {
+                line.push_str(&trf(", /dcc retry {} to try again", &[&transfer.id]));
+            }
+            _ => {}
+        }
+        self.notify(&line);
+    }
+
+    fn find_transfer(&mut self, id: &str) -> Option<usize> {
+        let index = id
+            .trim_start_matches('#')
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| self.transfers.iter().position(|t| t.id == id));
+        if index.is_none() {
+            self.buffers
+                .push_local(trf("No DCC transfer {}, see /dcc list", &[&id]));
+        }
+        index
+    }
+
+    // Accepts an offer into a new file, or with `resume` continues the file of the same
+    // name already in the download directory
+    fn dcc_get(&mut self, id: &str, resume: bool) {
+        let Some(index) = self.find_transfer(id) else {
+            return;
+        };
+        if self.transfers[index].state != TransferState::Offered {
+            let line = trf(
+                "DCC {} is not waiting to be accepted",
+                &[&self.transfers[index].describe()],
+            );
+            self.buffers.push_local(line);
+            return;
+        }
+
+        let dir = self.config.dcc.download_dir();
+        if let Err(e) = fs::create_dir_all(&dir) {
+            self.buffers
+                .push_local(trf("Cannot create {}: {}", &[&dir.display(), &e]));
+            return;
+        }
+
+        let transfer = &mut self.transfers[index];
+        transfer.path = if resume {
+            dir.join(&transfer.filename)
+        } else {
+            unique_path(&dir, &transfer.filename)
+        };
+        let line = trf(
+            "Receiving {} into {}",
+            &[&transfer.filename, &transfer.path.display()],
+        );
+        self.buffers.push_local(line);
+        self.resume_or_receive(index);
+    }
+
+    // Continues from whatever part of the file is on disk: asks the sender to resume when
+    // there is some, otherwise connects right away. A file as big as the offer or bigger is
+    // no part of it and is left alone, the download goes next to it
+    fn resume_or_receive(&mut self, index: usize) {
+        let transfer = &mut self.transfers[index];
+        transfer.handle = Arc::default();
+        transfer.transferred = 0;
+        let have = fs::metadata(&transfer.path).map(|m| m.len()).unwrap_or(0);
+        if have >= transfer.size {
+            let dir = transfer.path.parent().unwrap_or(Path::new("."));
+            transfer.path = unique_path(dir, &transfer.filename);
+            let line = trf(
+                "There is a file of {} already, receiving into {} instead",
+                &[&format_size(have), &transfer.path.display()],
+            );
+            self.buffers.push_local(line);
+            self.start_receive(index);
+            return;
+        }
+        if have == 0 {
+            self.start_receive(index);
+            return;
+        }
+
+        transfer.state = TransferState::Resuming;
+        transfer.transferred = have;
+        let request = format!(
+            "\x01DCC RESUME {} {} {}\x01",
+            transfer.filename, transfer.port, have
+        );
+        let line = trf(
+            "Asking {} to resume {} at {}",
+            &[&transfer.nick, &transfer.filename, &format_size(have)],
+        );
+        let nick = transfer.nick.clone();
+        match self.client.send_message(&nick, &request) {
+            Ok(_) => self.buffers.push_local(line),
+            Err(e) => {
+                self.transfers[index].state = TransferState::Failed(e.clone());
+                self.buffers
+                    .push_local(trf("Error sending DCC resume: {}", &[&e]));
+            }
+        }
+    }
+
+    fn start_receive(&mut self, index: usize) {
+        let transfer = &mut self.transfers[index];
+        let Some(addr) = transfer.addr else {
+            return;
+        };
+        transfer.state = TransferState::Waiting;
+        let (id, path, size) = (transfer.id, transfer.path.clone(), transfer.size);
+        let handle = transfer.handle.clone();
+        let events = self.events();
+        thread::spawn(move || {
+            let result = receive(id, addr, &path, size, &handle, &events);
+            let _ = events.send(AppEvent::DccFinished { id, result });
+        });
+    }
+
+    // Stops a running transfer or declines an offer
+    fn dcc_close(&mut self, id: &str) {
+        let Some(index) = self.find_transfer(id) else {
+            return;
+        };
+        let transfer = &mut self.transfers[index];
+        let line = if transfer.state == TransferState::Offered {
+            transfer.state = TransferState::Declined;
+            trf("Declined DCC {}", &[&transfer.describe()])
+        } else if transfer.state == TransferState::Resuming {
+            // No thread yet, the sender may never answer
+            transfer.state = TransferState::Cancelled;
+            trf("Cancelled DCC {}", &[&transfer.describe()])
+        } else if transfer.is_running() {
+            // The thread notices and reports it with DccFinished
+            transfer.handle.cancel.store(true, Ordering::Relaxed);
+            trf("Cancelling DCC {}", &[&transfer.describe()])
+        } else {
+            trf("DCC {} cannot be closed", &[&transfer.describe()])
+        };
+        self.buffers.push_local(line);
+    }
+
+    // Starts a failed or cancelled transfer again: a receive resumes from the partial file,
+    // a send is offered again
+    pub fn dcc_retry(&mut self, id: &str) {
+        let Some(index) = self.find_transfer(id) else {
+            return;
+        };
+        let transfer = &self.transfers[index];
+        if !transfer.can_retry() {
+            let line = trf("DCC {} cannot be retried", &[&transfer.describe()]);
+            self.buffers.push_local(line);
+            return;
+        }
+        match transfer.direction {
+            Direction::Receive => self.resume_or_receive(index),
+            Direction::Send => {
+                let (nick, path) = (transfer.nick.clone(), transfer.path.clone());
+                self.offer_file(&nick, &path, Some(index));
+            }
+        }
+    }
+
+    // Pauses and cancels are the same thing in DCC, closing a transfer and retrying it
+    // later resumes where it stopped
+    pub fn dcc_cancel_index(&mut self, index: usize) {
+        if let Some(transfer) = self.transfers.get(index) {
+            let id = transfer.id.to_string();
+            self.dcc_close(&id);
+        }
+    }
+
+    fn dcc_send(&mut self, nick: &str, file: &str) {
+        self.offer_file(nick, Path::new(file), None);
+    }
+
+    // Offers `path` to `nick`, as a new transfer or again for `retry`
+    fn offer_file(&mut self, nick: &str, path: &Path, retry: Option<usize>) {
+        let size = match fs::metadata(path) {
+            Ok(meta) if meta.is_file() => meta.len(),
+            Ok(_) => {
+                self.buffers
+                    .push_local(trf("{} is not a regular file", &[&path.display()]));
+                return;
+            }
+            Err(e) => {
+                self.buffers
+                    .push_local(trf("Cannot read {}: {}", &[&path.display(), &e]));
+                return;
+            }
+        };
+
+        let Some(ip) = self.dcc_own_ip() else {
+            return;
+        };
+        let listener = match bind_in_range(self.config.dcc.port_min, self.config.dcc.port_max) {
+            Ok(listener) => listener,
+            Err(e) => {
+                self.buffers
+                    .push_local(trf("No free port for DCC: {}", &[&e]));
+                return;
+            }
+        };
+        let port = match listener.local_addr() {
+            Ok(addr) => addr.port(),
+            Err(e) => {
+                self.buffers
+                    .push_local(trf("DCC listener failed: {}", &[&e]));
+                return;
+            }
+        };
+
+        let filename = safe_filename(&path.to_string_lossy()).replace(' ', "_");
+        let offer = format!(
+            "\x01DCC SEND {} {} {} {}\x01",
+            filename,
+            ip_field(ip),
+            port,
+            size
+        );
+        if let Err(e) = self.client.send_message(nick, &offer) {
+            self.buffers
+                .push_local(trf("Error sending DCC offer: {}", &[&e]));
+            return;
+        }
+
+        let handle = Arc::new(TransferHandle::default());
+        let transfer = Transfer {
+            id: self.next_dcc_id(),
+            direction: Direction::Send,
+            nick: nick.to_string(),
+            filename,
+            size,
+            transferred: 0,
+            state: TransferState::Waiting,
+            addr: None,
+            port,
+            path: path.to_path_buf(),
+            started: None,
+            handle: handle.clone(),
+        };
+        let id = match retry {
+            Some(index) => {
+                let id = self.transfers[index].id;
+                self.transfers[index] = Transfer { id, ..transfer };
+                id
+            }
+            None => {
+                let id = transfer.id;
+                self.transfers.push(transfer);
+                id
+            }
+        };
+        let index = self.transfers.iter().position(|t| t.id == id).unwrap_or(0);
+        let line = trf("Offered DCC {}", &[&self.transfers[index].describe()]);
+        self.buffers.push_local(line);
+
+        let events = self.events();
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            let result = send(id, listener, &path, &handle, &events);
+            let _ = events.send(AppEvent::DccFinished { id, result });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_resume, parse_send_offer, safe_filename};
+    use std::net::SocketAddr;
+
+    fn addr(text: &str) -> SocketAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn send_offers_take_quoted_names_and_any_address() {
+        // 3232235777 is 192.168.1.1
+        assert_eq!(
+            parse_send_offer("\"my file.txt\" 3232235777 5000 1024"),
+            Some(("my file.txt".to_string(), addr("192.168.1.1:5000"), 1024))
+        );
+        assert_eq!(
+            parse_send_offer("notes.txt 10.0.0.2 5000 7"),
+            Some(("notes.txt".to_string(), addr("10.0.0.2:5000"), 7))
+        );
+        assert_eq!(
+            parse_send_offer("notes.txt 2001:db8::1 5000 7"),
+            Some(("notes.txt".to_string(), addr("[2001:db8::1]:5000"), 7))
+        );
+    }
+
+    #[test]
+    fn send_offers_without_a_size_have_size_zero() {
+        assert_eq!(
+            parse_send_offer("notes.txt 3232235777 5000"),
+            Some(("notes.txt".to_string(), addr("192.168.1.1:5000"), 0))
+        );
+        assert_eq!(parse_send_offer("notes.txt 3232235777"), None);
+        assert_eq!(parse_send_offer("\"unclosed 3232235777 5000 7"), None);
+        assert_eq!(parse_send_offer("notes.txt nowhere 5000 7"), None);
+    }
+
+    #[test]
+    fn resumes_read_port_and_position_from_the_end() {
+        assert_eq!(parse_resume("file.ext 5000 1024"), Some((5000, 1024)));
+        assert_eq!(
+            parse_resume("\"my file.txt\" 5000 1024"),
+            Some((5000, 1024))
+        );
+        assert_eq!(parse_resume("5000 1024"), Some((5000, 1024)));
+        assert_eq!(parse_resume("file.ext 5000"), None);
+        assert_eq!(parse_resume("file.ext 5000 x"), None);
+    }
+
+    #[test]
+    fn filenames_lose_their_path_and_leading_dots() {
+        assert_eq!(safe_filename("../../etc/passwd"), "passwd");
+        assert_eq!(safe_filename("/home/alice/report.pdf"), "report.pdf");
+        assert_eq!(safe_filename(".bashrc"), "bashrc");
+        assert_eq!(safe_filename(".."), "download");
+        assert_eq!(safe_filename("..."), "download");
+        assert_eq!(safe_filename(""), "download");
+        assert_eq!(safe_filename("my file.txt"), "my file.txt");
+    }
+}
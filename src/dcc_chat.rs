@@ -0,0 +1,408 @@
+use crate::app::{App, AppEvent};
+use crate::buffer::BufferKind;
+use crate::dcc;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use crate::sounds::SoundEvent;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatState {
+    // They offered a chat, waiting for /dcc chat <nick>
+    Offered,
+    // We offered, waiting for them to connect (or, passively, to tell us where to connect)
+    Waiting,
+    Connected,
+    Closed,
+}
+
+// A DCC CHAT session, a direct connection to another user that bypasses the server. Shown
+// as its own buffer named "=nick"
+pub struct Chat {
+    pub id: usize,
+    pub nick: String,
+    pub state: ChatState,
+    // Where they listen, for offers that came in
+    pub addr: Option<SocketAddr>,
+    // Set for passive (reverse) DCC, where the side that offers does not listen
+    pub token: Option<String>,
+    writer: Option<TcpStream>,
+}
+
+impl Chat {
+    pub fn buffer_name(&self) -> String {
+        format!("={}", self.nick)
+    }
+
+    // `/dcc close` takes an id, a nick or the buffer name
+    pub fn matches(&self, key: &str) -> bool {
+        let key = key.trim_start_matches('#');
+        key.parse::<usize>().ok() == Some(self.id)
+            || key.trim_start_matches('=').eq_ignore_ascii_case(&self.nick)
+    }
+
+    pub fn describe(&self) -> String {
+        let state = match self.state {
+            ChatState::Offered => tr("offered"),
+            ChatState::Waiting => tr("waiting for peer"),
+            ChatState::Connected => tr("connected"),
+            ChatState::Closed => tr("closed"),
+        };
+        let passive = if self.token.is_some() { " passive" } else { "" };
+        format!("#{} chat{} {} [{}]", self.id, passive, self.nick, state)
+    }
+}
+
+// Parses the arguments of `DCC CHAT chat <ip> <port> [token]`. Port 0 with a token is a
+// passive offer: the sender is not reachable and asks us to listen instead
+pub fn parse_chat_offer(args: &str) -> Option<(SocketAddr, Option<String>)> {
+    let mut fields = args.split_whitespace();
+    if !fields.next()?.eq_ignore_ascii_case("chat") {
+        return None;
+    }
+    let addr = dcc::parse_addr(fields.next()?, fields.next()?)?;
+    Some((addr, fields.next().map(|t| t.to_string())))
+}
+
+fn new_token() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    (nanos % 1_000_000).to_string()
+}
+
+// Reads lines until the peer hangs up. The main loop gets its own handle for writing
+fn run_chat(id: usize, stream: TcpStream, events: Sender<AppEvent>) {
+    let writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            let error = Some(e.to_string());
+            let _ = events.send(AppEvent::DccChatClosed { id, error });
+            return;
+        }
+    };
+    if events.send(AppEvent::DccChatOpened { id, writer }).is_err() {
+        return;
+    }
+    for line in BufReader::new(stream).lines() {
+        match line {
+            Ok(line) => {
+                let line = line.trim_end_matches('\r').to_string();
+                if events.send(AppEvent::DccChatLine { id, line }).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let error = Some(e.to_string());
+                let _ = events.send(AppEvent::DccChatClosed { id, error });
+                return;
+            }
+        }
+    }
+    let _ = events.send(AppEvent::DccChatClosed { id, error: None });
+}
+
+fn connect(id: usize, addr: SocketAddr, events: Sender<AppEvent>) {
+    thread::spawn(
+        move || match TcpStream::connect_timeout(&addr, Duration::from_secs(30)) {
+            Ok(stream) => run_chat(id, stream, events),
+            Err(e) => {
+                let error = Some(format!("connect to {} failed: {}", addr, e));
+                let _ = events.send(AppEvent::DccChatClosed { id, error });
+            }
+        },
+    );
+}
+
+// The chat `found` picks, a live one before any closed chat with the same nick. Closed
+// ones stay in the list for /dcc list
+fn find_chat(chats: &mut [Chat], found: impl Fn(&Chat) -> bool) -> Option<&mut Chat> {
+    let index = chats
+        .iter()
+        .position(|c| c.state != ChatState::Closed && found(c))
+        .or_else(|| chats.iter().position(&found))?;
+    chats.get_mut(index)
+}
+
+impl App {
+    // An incoming `DCC CHAT`, either a new offer or the answer to a passive one of ours
+    pub(crate) fn dcc_chat_offer(&mut self, msg: &Message, args: &str) {
+        let nick = msg.nick().unwrap_or("?").to_string();
+        let Some((addr, token)) = parse_chat_offer(args) else {
+            self.buffers.server_mut().push(trf(
+                "Ignoring malformed DCC CHAT from {}: {}",
+                &[&nick, &args],
+            ));
+            return;
+        };
+
+        // They are listening now for the passive offer we sent, connect to them
+        if addr.port() != 0
+            && let Some(chat) = self.chats.iter_mut().find(|c| {
+                c.state == ChatState::Waiting
+                    && c.token.is_some()
+                    && c.token == token
+                    && c.nick.eq_ignore_ascii_case(&nick)
+            })
+        {
+            chat.addr = Some(addr);
+            connect(chat.id, addr, self.events());
+            return;
+        }
+
+        let id = self.next_dcc_id();
+        let passive = if addr.port() == 0 { " (passive)" } else { "" };
+        self.notify(&trf(
+            "DCC CHAT offer #{} from {}{}. Accept with /dcc chat {}, decline with /dcc close {}",
+            &[&id, &nick, &passive, &nick, &id],
+        ));
+        self.chats.push(Chat {
+            id,
+            nick,
+            state: ChatState::Offered,
+            addr: Some(addr),
+            token,
+            writer: None,
+        });
+    }
+
+    // Accepts a pending offer from `nick`, or offers them a chat
+    pub(crate) fn dcc_chat(&mut self, nick: &str) {
+        if let Some(index) = self
+            .chats
+            .iter()
+            .position(|c| c.state == ChatState::Offered && c.nick.eq_ignore_ascii_case(nick))
+        {
+            self.accept_chat(index);
+            return;
+        }
+        if let Some(chat) = self.chats.iter().find(|c| {
+            c.nick.eq_ignore_ascii_case(nick)
+                && matches!(c.state, ChatState::Waiting | ChatState::Connected)
+        }) {
+            let name = chat.buffer_name();
+            self.buffers.select_name(&name);
+            return;
+        }
+
+        let Some(ip) = self.dcc_own_ip() else {
+            return;
+        };
+        let id = self.next_dcc_id();
+        let mut chat = Chat {
+            id,
+            nick: nick.to_string(),
+            state: ChatState::Waiting,
+            addr: None,
+            token: None,
+            writer: None,
+        };
+
+        // Behind NAT nobody can connect to us, so ask them to listen instead
+        let offer = if self.config.dcc.passive {
+            let token = new_token();
+            let offer = format!("\x01DCC CHAT chat {} 0 {}\x01", dcc::ip_field(ip), token);
+            chat.token = Some(token);
+            offer
+        } else {
+            let Some(port) = self.listen_for_chat(id) else {
+                return;
+            };
+            format!("\x01DCC CHAT chat {} {}\x01", dcc::ip_field(ip), port)
+        };
+        if let Err(e) = self.client.send_message(nick, &offer) {
+            self.buffers
+                .push_local(trf("Error sending DCC offer: {}", &[&e]));
+            return;
+        }
+
+        let name = chat.buffer_name();
+        let line = trf("Offered DCC {}", &[&chat.describe()]);
+        self.chats.push(chat);
+        self.buffers.open(&name, BufferKind::DccChat);
+        self.buffers.select_name(&name);
+        self.buffers.push_to(&name, line);
+    }
+
+    fn accept_chat(&mut self, index: usize) {
+        let (id, addr) = (self.chats[index].id, self.chats[index].addr);
+        let Some(addr) = addr else {
+            return;
+        };
+
+        if addr.port() == 0 {
+            // Passive offer: we listen and tell them where, echoing their token
+            let Some(ip) = self.dcc_own_ip() else {
+                return;
+            };
+            let Some(port) = self.listen_for_chat(id) else {
+                return;
+            };
+            let chat = &self.chats[index];
+            let reply = format!(
+                "\x01DCC CHAT chat {} {} {}\x01",
+                dcc::ip_field(ip),
+                port,
+                chat.token.as_deref().unwrap_or("")
+            );
+            let nick = chat.nick.clone();
+            if let Err(e) = self.client.send_message(&nick, &reply) {
+                self.buffers
+                    .push_local(trf("Error answering DCC offer: {}", &[&e]));
+                return;
+            }
+        } else {
+            connect(id, addr, self.events());
+        }
+
+        let chat = &mut self.chats[index];
+        chat.state = ChatState::Waiting;
+        let name = chat.buffer_name();
+        let line = trf("Connecting DCC {}", &[&chat.describe()]);
+        self.buffers.open(&name, BufferKind::DccChat);
+        self.buffers.select_name(&name);
+        self.buffers.push_to(&name, line);
+    }
+
+    // Listens on a port from the configured range, returns the port to put into the offer
+    fn listen_for_chat(&mut self, id: usize) -> Option<u16> {
+        let listener = match dcc::bind_in_range(self.config.dcc.port_min, self.config.dcc.port_max)
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                self.buffers
+                    .push_local(trf("No free port for DCC: {}", &[&e]));
+                return None;
+            }
+        };
+        let port = match listener.local_addr() {
+            Ok(addr) => addr.port(),
+            Err(e) => {
+                self.buffers
+                    .push_local(trf("DCC listener failed: {}", &[&e]));
+                return None;
+            }
+        };
+        let events = self.events();
+        thread::spawn(
+            move || match dcc::accept_with_timeout(listener, &AtomicBool::new(false)) {
+                Ok(stream) => run_chat(id, stream, events),
+                Err(error) => {
+                    let error = Some(error);
+                    let _ = events.send(AppEvent::DccChatClosed { id, error });
+                }
+            },
+        );
+        Some(port)
+    }
+
+    // Text typed into a chat buffer
+    pub(crate) fn dcc_chat_say(&mut self, text: &str) {
+        let name = self.buffers.active().name.clone();
+        let Some(chat) = find_chat(&mut self.chats, |c| c.buffer_name() == name) else {
+            return;
+        };
+        let Some(writer) = chat.writer.as_mut() else {
+            let line = trf("DCC {} is not connected", &[&chat.describe()]);
+            self.buffers.push_to(&name, line);
+            return;
+        };
+        let (wire, shown) = match text.strip_prefix("/me ") {
+            Some(action) => (
+                format!("\x01ACTION {}\x01", action),
+                format!("* {} {}", self.client.nickname, action),
+            ),
+            None => (
+                text.to_string(),
+                format!("<{}> {}", self.client.nickname, text),
+            ),
+        };
+        match writer.write_all(format!("{}\n", wire).as_bytes()) {
+            Ok(_) => self.buffers.push_to(&name, shown),
+            Err(e) => self
+                .buffers
+                .push_to(&name, trf("Error sending to {}: {}", &[&chat.nick, &e])),
+        }
+    }
+
+    pub(crate) fn dcc_chat_close(&mut self, key: &str) {
+        let Some(chat) = find_chat(&mut self.chats, |c| c.matches(key)) else {
+            return;
+        };
+        let line = match chat.writer.take() {
+            // The reader thread notices and reports the chat closed
+            Some(writer) => {
+                let _ = writer.shutdown(Shutdown::Both);
+                return;
+            }
+            None if chat.state == ChatState::Offered => {
+                chat.state = ChatState::Closed;
+                trf("Declined DCC {}", &[&chat.describe()])
+            }
+            // A connection that still comes in is dropped by dcc_chat_opened
+            None if chat.state == ChatState::Waiting => {
+                chat.state = ChatState::Closed;
+                trf("Cancelled DCC {}", &[&chat.describe()])
+            }
+            None => trf("DCC {} cannot be closed", &[&chat.describe()]),
+        };
+        self.buffers.push_local(line);
+    }
+
+    pub(crate) fn dcc_chat_opened(&mut self, id: usize, writer: TcpStream) {
+        let Some(chat) = self.chats.iter_mut().find(|c| c.id == id) else {
+            return;
+        };
+        if chat.state == ChatState::Closed {
+            let _ = writer.shutdown(Shutdown::Both);
+            return;
+        }
+        chat.state = ChatState::Connected;
+        chat.writer = Some(writer);
+        let name = chat.buffer_name();
+        let line = trf("DCC CHAT with {} established", &[&chat.nick]);
+        self.buffers.open(&name, BufferKind::DccChat);
+        self.buffers.push_to(&name, line);
+    }
+
+    pub(crate) fn dcc_chat_line(&mut self, id: usize, line: &str) {
+        let Some(chat) = self.chats.iter().find(|c| c.id == id) else {
+            return;
+        };
+        let shown = match line
+            .strip_prefix("\x01ACTION ")
+            .map(|a| a.trim_end_matches('\x01'))
+        {
+            Some(action) => format!("* {} {}", chat.nick, action),
+            None => format!("<{}> {}", chat.nick, line),
+        };
+        let name = chat.buffer_name();
+        if self.buffer_settings(&name).highlighted(line) {
+            self.buffers.push_to(&name, format!("!!! {}", shown));
+            self.ring(&name, SoundEvent::Highlight);
+        } else {
+            self.buffers.push_to(&name, shown);
+        }
+    }
+
+    pub(crate) fn dcc_chat_closed(&mut self, id: usize, error: Option<String>) {
+        let Some(chat) = self.chats.iter_mut().find(|c| c.id == id) else {
+            return;
+        };
+        chat.state = ChatState::Closed;
+        chat.writer = None;
+        let name = chat.buffer_name();
+        let line = match error {
+            Some(e) => trf("DCC CHAT with {} failed: {}", &[&chat.nick, &e]),
+            None => trf("DCC CHAT with {} closed", &[&chat.nick]),
+        };
+        self.buffers.open(&name, BufferKind::DccChat);
+        self.buffers.push_to(&name, line);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::clock;
+use crate::i18n::{tr, trf};
+use std::time::SystemTime;
+
+// Commands whose arguments are secrets, logged without them
+const SECRET_COMMANDS: &[&str] = &["PASS", "OPER", "AUTHENTICATE"];
+
+// Hides passwords in a protocol line. Anything for NickServ keeps only its verb, as
+// IDENTIFY, GHOST, REGAIN, REGISTER and SET PASSWORD all carry one
+fn redact(line: &str) -> String {
+    // Skip tags so "@label=irc1 OPER name pw" is caught too
+    let (tags, rest) = match line.strip_prefix('@').and_then(|l| l.split_once(' ')) {
+        Some((tags, rest)) => (format!("@{} ", tags), rest),
+        None => (String::new(), line),
+    };
+    let mut words = rest.splitn(3, ' ');
+    let (command, target) = (
+        words.next().unwrap_or_default(),
+        words.next().unwrap_or_default(),
+    );
+    // PRIVMSG NickServ :verb args, or NickServ@services.example
+    let nickserv = |target: &str| {
+        target
+            .split('@')
+            .next()
+            .is_some_and(|nick| nick.eq_ignore_ascii_case("NickServ"))
+    };
+    if command.eq_ignore_ascii_case("PRIVMSG") && nickserv(target) {
+        let text = words.next().unwrap_or_default();
+        let verb = text
+            .trim_start_matches(':')
+            .split(' ')
+            .next()
+            .unwrap_or_default();
+        return format!("{}{} {} :{} <hidden>", tags, command, target, verb);
+    }
+    // The NS and NICKSERV aliases some servers have
+    if ["NS", "NICKSERV"]
+        .iter()
+        .chain(SECRET_COMMANDS)
+        .any(|c| command.eq_ignore_ascii_case(c))
+    {
+        format!("{}{} <hidden>", tags, command)
+    } else {
+        line.to_string()
+    }
+}
+
+impl App {
+    // /debug turns the raw protocol log on or off
+    pub(crate) fn toggle_debug(&mut self) {
+        if self.client.sent_log.take().is_some() {
+            self.buffers.push_local(tr("Protocol log off").to_string());
+            return;
+        }
+        self.client.sent_log = Some(Vec::new());
+        self.buffers.open("debug", BufferKind::Debug);
+        self.buffers.select_name("debug");
+        self.buffers.push_to(
+            "debug",
+            tr("Protocol log on, /debug again turns it off").to_string(),
+        );
+    }
+
+    // Logs a line from the server, after whatever we sent before it arrived
+    pub(crate) fn debug_incoming(&mut self, line: &str) {
+        if self.client.sent_log.is_some() {
+            self.flush_debug();
+            self.debug_line("<-", line);
+        }
+    }
+
+    // Moves what we sent into the debug buffer
+    pub(crate) fn flush_debug(&mut self) {
+        let sent = match &mut self.client.sent_log {
+            Some(log) if !log.is_empty() => std::mem::take(log),
+            _ => return,
+        };
+        for line in sent {
+            self.debug_line("->", &line);
+        }
+    }
+
+    fn debug_line(&mut self, arrow: &str, line: &str) {
+        let stamp = clock::time_millis(SystemTime::now());
+        self.buffers
+            .push_to("debug", format!("{} {} {}", stamp, arrow, redact(line)));
+    }
+
+    // [parser] mode, "lenient" unless it says "strict"
+    pub(crate) fn apply_parser_mode(&mut self) {
+        let strict = match self.config.parser.mode.as_str() {
+            "strict" => true,
+            "lenient" => false,
+            other => {
+                self.buffers.server_mut().push(trf(
+                    "Config error: [parser] mode is lenient or strict, not '{}'",
+                    &[&other],
+                ));
+                false
+            }
+        };
+        self.client.set_strict(strict);
+    }
+
+    // A line the receiver found broken in strict mode, right after the line itself. The
+    // debug buffer opens for it even while /debug is off
+    pub(crate) fn report_violation(&mut self, report: &str) {
+        self.buffers.open("debug", BufferKind::Debug);
+        self.flush_debug();
+        self.debug_line("!!", report);
+    }
+
+    // /raw sends a line as is
+    pub(crate) fn send_raw_line(&mut self, line: &str) {
+        if line.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /raw COMMAND params, e.g. /raw WHO #channel").to_string());
+            return;
+        }
+        match self.client.send_raw(&format!("{}\r\n", line)) {
+            Ok(_) => self.buffers.push_local(format!("-> {}", redact(line))),
+            Err(e) => self.buffers.push_local(trf("Error sending: {}", &[&e])),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::app::App;
+use crate::auth;
+use crate::buffer::BufferKind;
+use crate::config::Config;
+use crate::i18n;
+use crate::i18n::trf;
+use crate::irc_client::IrcClient;
+use crate::json::JsonEvent;
+use crate::logging;
+use crate::message::Message;
+use crate::tui_client::ConnectionDetails;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, Write, stdin, stdout};
+use std::sync::mpsc::{TryRecvError, channel};
+use std::thread;
+use std::time::Duration;
+use tracing::info;
+
+// How often the loop wakes up to print new lines and run what was typed
+const TICK: Duration = Duration::from_millis(50);
+
+// Where to connect: `--dumb <nick> <server> [port] [-tls]`, or [connect] in the config
+fn details(args: &[String], config: &Config) -> Result<ConnectionDetails, String> {
+    let tls = args.iter().any(|a| a == "-tls");
+    let mut args = args.iter().filter(|a| *a != "-tls");
+    match (args.next(), args.next(), args.next()) {
+        (Some(nickname), Some(server), port) => Ok(ConnectionDetails {
+            nickname: nickname.clone(),
+            server: server.clone(),
+            port: match port {
+                Some(port) => port.parse().map_err(|_| format!("Bad port '{}'", port))?,
+                None if tls => 6697,
+                None => 6667,
+            },
+            tls,
+        }),
+        (None, _, _) if config.connect.is_complete() => Ok(ConnectionDetails {
+            nickname: config.connect.nick.clone(),
+            server: config.connect.server.clone(),
+            port: config.connect.port,
+            tls: config.connect.tls,
+        }),
+        _ => Err(
+            "usage: --dumb <nick> <server> [port] [-tls], or set [connect] in the config"
+                .to_string(),
+        ),
+    }
+}
+
+// `irconic --dumb`: no TUI, just lines. Whatever comes in is printed as it arrives, lines
+// of other buffers than the server one with "[buffer] " in front, and every line read from
+// stdin runs like it was typed in the input line. Input waits until we are registered, and
+// the end of it quits once everything typed is sent.
+//
+// With --json both ways are JSON instead, see json.rs: every server line and buffer line
+// comes out as an event and every input line is a command
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let json = args.iter().any(|a| a == "--json");
+    let args: Vec<String> = args.iter().filter(|a| *a != "--json").cloned().collect();
+    let config = Config::load()?;
+    let details = details(&args, &config)?;
+    let language_error = i18n::init(&config.locale.language).err();
+    let log_error = logging::init(&config.log).err();
+    info!(server = %details.server, port = details.port, "starting the line mode");
+
+    let mut client = IrcClient::new(&details.nickname);
+    client.alt_nicks = config.alt_nicks.clone();
+    client.quit_message = config.quit.message.clone();
+    auth::apply_login(&mut client, &config, &details.server, false);
+    client.connect(&details.server, details.port, details.tls)?;
+    client.register()?;
+    let (tx, rx) = channel();
+    client.start_receiver(tx.clone())?;
+
+    let (events_tx, events_rx) = channel();
+    let mut app = App::new(client, config, events_tx);
+    // Nothing can be drawn or asked for, e.g. /help lists the commands as lines
+    app.headless = true;
+    app.server_lines = Some(tx);
+    if let Some(e) = language_error {
+        app.buffers
+            .server_mut()
+            .push(trf("Config error: {}", &[&e]));
+    }
+    if let Some(e) = log_error {
+        app.buffers.server_mut().push(trf("Log error: {}", &[&e]));
+    }
+    let _control = app.start_services();
+
+    let (input_tx, input) = channel();
+    thread::spawn(move || {
+        for line in stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if input_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Lines of each buffer printed so far, by name
+    let mut printed: HashMap<String, usize> = HashMap::new();
+    let mut input_done = false;
+    while !app.should_quit {
+        while let Ok(line) = rx.try_recv() {
+            if json && let Some(message) = Message::parse(&line) {
+                println!("{}", JsonEvent::Irc { message: &message }.to_line());
+            }
+            app.handle_server_line(line);
+        }
+        while let Ok(event) = events_rx.try_recv() {
+            app.handle_event(event);
+        }
+        while app.client.registered && !input_done {
+            match input.try_recv() {
+                Ok(line) if json => {
+                    if let Err(message) = app.json_command(&line) {
+                        println!("{}", JsonEvent::Error { message }.to_line());
+                    }
+                }
+                Ok(line) => app.execute(line.trim_end()),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => input_done = true,
+            }
+        }
+        if input_done && app.outgoing.is_empty() {
+            app.execute("/quit");
+        }
+        app.tick();
+        print_new_lines(&app, &mut printed, json)?;
+        app.buffers.mark_active_read();
+        thread::sleep(TICK);
+    }
+
+    print_new_lines(&app, &mut printed, json)?;
+    app.seen.save(true)?;
+    Ok(())
+}
+
+fn print_new_lines(
+    app: &App,
+    printed: &mut HashMap<String, usize>,
+    json: bool,
+) -> std::io::Result<()> {
+    let mut out = stdout().lock();
+    for buffer in app.buffers.iter() {
+        // Copies of lines shown elsewhere already
+        if matches!(buffer.kind, BufferKind::Debug | BufferKind::AwayLog) {
+            continue;
+        }
+        let seen = printed.entry(buffer.name.clone()).or_insert(0);
+        for line in buffer.lines_since(*seen) {
+            if json {
+                let event = JsonEvent::Line {
+                    buffer: &buffer.name,
+                    text: &line.text,
+                };
+                writeln!(out, "{}", event.to_line())?;
+            } else if buffer.kind == BufferKind::Server {
+                writeln!(out, "{}", line.text)?;
+            } else {
+                writeln!(out, "[{}] {}", buffer.name, line.text)?;
+            }
+        }
+        *seen = buffer.total;
+    }
+    out.flush()
+}
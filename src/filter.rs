@@ -0,0 +1,224 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::config::{Config, FilterAction, FilterRule};
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use regex_automata::meta::Regex;
+
+// Lines a dim rule matched start with this, render.rs draws them in the dim color
+pub const DIM: &str = "~~~ ";
+
+// A [[filters]] rule with its regexes compiled
+pub struct Filter {
+    pub rule: FilterRule,
+    sender: Option<Regex>,
+    target: Option<Regex>,
+    content: Option<Regex>,
+}
+
+fn compile(pattern: Option<&String>) -> Result<Option<Regex>, String> {
+    pattern
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|e| {
+                // The syntax error ends with the useful part, e.g. "unclosed character class"
+                let reason = match e.syntax_error() {
+                    Some(syntax) => syntax
+                        .to_string()
+                        .lines()
+                        .last()
+                        .unwrap_or_default()
+                        .trim_start_matches("error: ")
+                        .to_string(),
+                    None => e.to_string(),
+                };
+                trf("Bad regex '{}': {}", &[&pattern, &reason])
+            })
+        })
+        .transpose()
+}
+
+impl Filter {
+    pub fn new(rule: FilterRule) -> Result<Filter, String> {
+        if rule.sender.is_none() && rule.target.is_none() && rule.content.is_none() {
+            return Err(tr("A filter needs a sender, target or content regex").to_string());
+        }
+        Ok(Filter {
+            sender: compile(rule.sender.as_ref())?,
+            target: compile(rule.target.as_ref())?,
+            content: compile(rule.content.as_ref())?,
+            rule,
+        })
+    }
+
+    fn matches(&self, sender: &str, target: &str, content: &str) -> bool {
+        let hit =
+            |regex: &Option<Regex>, text: &str| regex.as_ref().is_none_or(|r| r.is_match(text));
+        hit(&self.sender, sender) && hit(&self.target, target) && hit(&self.content, content)
+    }
+
+    // How /filter list shows it
+    fn describe(&self) -> String {
+        let action = match self.rule.action {
+            FilterAction::Hide => "hide",
+            FilterAction::Dim => "dim",
+            FilterAction::Move => "move",
+        };
+        let mut parts = vec![action.to_string()];
+        if let Some(sender) = &self.rule.sender {
+            parts.push(format!("sender:{}", sender));
+        }
+        if let Some(target) = &self.rule.target {
+            parts.push(format!("target:{}", target));
+        }
+        if let Some(content) = &self.rule.content {
+            parts.push(content.clone());
+        }
+        parts.join(" ")
+    }
+}
+
+// "hide|dim|move [sender:regex] [target:regex] [content regex]", the content regex is
+// the rest of the line, spaces included
+fn parse_rule(args: &str) -> Result<FilterRule, String> {
+    let usage = "Usage: /filter add hide|dim|move [sender:regex] [target:regex] [content regex]";
+    let (action, mut rest) = args.split_once(' ').unwrap_or((args, ""));
+    let action = match action {
+        "hide" => FilterAction::Hide,
+        "dim" => FilterAction::Dim,
+        "move" => FilterAction::Move,
+        _ => return Err(usage.to_string()),
+    };
+    let mut rule = FilterRule {
+        sender: None,
+        target: None,
+        content: None,
+        action,
+    };
+    loop {
+        rest = rest.trim_start();
+        let (word, after) = rest.split_once(' ').unwrap_or((rest, ""));
+        if let Some(sender) = word.strip_prefix("sender:") {
+            rule.sender = Some(sender.to_string());
+        } else if let Some(target) = word.strip_prefix("target:") {
+            rule.target = Some(target.to_string());
+        } else {
+            break;
+        }
+        rest = after;
+    }
+    if !rest.is_empty() {
+        rule.content = Some(rest.to_string());
+    }
+    Ok(rule)
+}
+
+impl App {
+    // Compiles [[filters]] from the config, a broken rule is reported and left out
+    pub(crate) fn load_filters(&mut self) {
+        self.filters.clear();
+        for rule in self.config.filters.clone() {
+            match Filter::new(rule) {
+                Ok(filter) => self.filters.push(filter),
+                Err(e) => self
+                    .buffers
+                    .server_mut()
+                    .push(trf("Filter left out: {}", &[&e])),
+            }
+        }
+    }
+
+    // Runs a PRIVMSG or NOTICE through the filters. Returns true when a rule took the
+    // line, it then must not be shown the usual way
+    pub(crate) fn filter(&mut self, msg: &Message, line: &str) -> bool {
+        // DCC offers and other CTCP are left alone, only actions count as chat
+        if msg.ctcp().is_some_and(|ctcp| !ctcp.starts_with("ACTION")) {
+            return false;
+        }
+        let sender = msg.prefix.as_deref().unwrap_or_default();
+        let target = msg.param(0).unwrap_or_default();
+        let content = msg.trailing().unwrap_or_default();
+        let Some(filter) = self
+            .filters
+            .iter()
+            .find(|filter| filter.matches(sender, target, content))
+        else {
+            return false;
+        };
+        match filter.rule.action {
+            FilterAction::Hide => {}
+            FilterAction::Dim => {
+                let buffer = self.route(msg);
+                self.buffers.push_to(&buffer, format!("{}{}", DIM, line));
+            }
+            FilterAction::Move => {
+                self.buffers.open("filtered", BufferKind::Filtered);
+                self.buffers.push_to("filtered", line.to_string());
+            }
+        }
+        true
+    }
+
+    // /filter add <rule>, /filter del <number>, /filter list
+    pub(crate) fn filter_command(&mut self, args: &str) {
+        let (sub, rest) = args.split_once(' ').unwrap_or((args, ""));
+        match sub {
+            "" | "list" => self.list_filters(),
+            "add" => match parse_rule(rest.trim()).and_then(Filter::new) {
+                Ok(filter) => {
+                    self.buffers.push_local(trf(
+                        "Added filter {}: {}",
+                        &[&(self.filters.len() + 1), &filter.describe()],
+                    ));
+                    self.config.filters.push(filter.rule.clone());
+                    self.filters.push(filter);
+                    self.save_filters();
+                }
+                Err(e) => self.buffers.push_local(e),
+            },
+            "del" => match rest.trim().parse::<usize>() {
+                Ok(number) if (1..=self.filters.len()).contains(&number) => {
+                    let filter = self.filters.remove(number - 1);
+                    self.buffers
+                        .push_local(trf("Removed filter {}: {}", &[&number, &filter.describe()]));
+                    // Rules the config has but that did not compile stay in it
+                    if let Some(index) = self.config.filters.iter().position(|r| *r == filter.rule)
+                    {
+                        self.config.filters.remove(index);
+                    }
+                    self.save_filters();
+                }
+                _ => self
+                    .buffers
+                    .push_local(tr("Usage: /filter del <number>, see /filter list").to_string()),
+            },
+            _ => self
+                .buffers
+                .push_local(tr("Usage: /filter add|del|list").to_string()),
+        }
+    }
+
+    fn list_filters(&mut self) {
+        if self.filters.is_empty() {
+            self.buffers
+                .push_local(tr("No filters, add one with /filter add").to_string());
+            return;
+        }
+        let lines: Vec<String> = self
+            .filters
+            .iter()
+            .enumerate()
+            .map(|(i, filter)| format!("  {}. {}", i + 1, filter.describe()))
+            .collect();
+        self.buffers
+            .push_local(tr("Filters, first match wins:").to_string());
+        for line in lines {
+            self.buffers.push_local(line);
+        }
+    }
+
+    fn save_filters(&mut self) {
+        if let Err(e) = Config::save_filters(&self.config.filters) {
+            self.buffers.push_local(e);
+        }
+    }
+}
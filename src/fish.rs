@@ -0,0 +1,358 @@
+use crate::app::{App, MAX_MESSAGE_BYTES};
+use crate::blowfish::{self, Blowfish};
+use crate::channel;
+use crate::config::Config;
+use crate::i18n::{tr, trf};
+use crate::irc_client;
+use crate::message::{self, Message};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Put before the text of messages that came or went encrypted
+pub const LOCKED: &str = "🔒 ";
+// And before plaintext from where a key is set, someone is not using it
+pub const PLAIN: &str = "[plaintext] ";
+
+// FiSH's own base64, 12 characters for every 8 byte block
+const FISH64: &[u8; 64] = b"./0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Plaintext bytes per PRIVMSG, ECB turns 264 bytes into 396 and CBC into 364
+const MAX_PLAIN_BYTES: usize = 264;
+
+// A key from [fish] keys. "cbc:secret" uses CBC like FiSH 10 and mircryption, plain
+// "secret" or "ecb:secret" the older ECB mode
+pub struct FishKey {
+    cipher: Blowfish,
+    cbc: bool,
+}
+
+impl FishKey {
+    pub fn new(key: &str) -> Result<FishKey, String> {
+        let (cbc, secret) = match key.split_once(':') {
+            Some(("cbc", secret)) => (true, secret),
+            Some(("ecb", secret)) => (false, secret),
+            _ => (false, key),
+        };
+        if secret.len() < 4 || secret.len() > 56 {
+            return Err(tr("A FiSH key needs 4 to 56 bytes").to_string());
+        }
+        Ok(FishKey {
+            cipher: Blowfish::new(secret.as_bytes()),
+            cbc,
+        })
+    }
+
+    // The message to send, "+OK ..."
+    pub fn encrypt(&self, text: &str) -> String {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(bytes.len().div_ceil(8) * 8, 0);
+        if !self.cbc {
+            let mut out = String::from("+OK ");
+            for block in bytes.chunks(8) {
+                let block = self
+                    .cipher
+                    .encrypt_block(block.try_into().unwrap_or_default());
+                let (l, r) = blowfish::split(block);
+                for mut word in [r, l] {
+                    for _ in 0..6 {
+                        out.push(FISH64[word as usize & 63] as char);
+                        word >>= 6;
+                    }
+                }
+            }
+            return out;
+        }
+        let mut previous = random_iv();
+        let mut out = previous.to_vec();
+        for block in bytes.chunks(8) {
+            let mut block: [u8; 8] = block.try_into().unwrap_or_default();
+            for (byte, prev) in block.iter_mut().zip(previous) {
+                *byte ^= prev;
+            }
+            previous = self.cipher.encrypt_block(block);
+            out.extend_from_slice(&previous);
+        }
+        format!("+OK *{}", irc_client::base64(&out))
+    }
+
+    // The text of a "+OK ..." or "mcps ..." message, None when it does not decrypt
+    pub fn decrypt(&self, text: &str) -> Option<String> {
+        let data = text
+            .strip_prefix("+OK ")
+            .or_else(|| text.strip_prefix("mcps "))?
+            .trim();
+        let mut bytes = Vec::new();
+        match data.strip_prefix('*') {
+            Some(data) => {
+                let data = base64_decode(data)?;
+                if data.len() < 16 || data.len() % 8 != 0 {
+                    return None;
+                }
+                let mut previous: [u8; 8] = data[..8].try_into().ok()?;
+                for block in data[8..].chunks(8) {
+                    let block: [u8; 8] = block.try_into().ok()?;
+                    let plain = self.cipher.decrypt_block(block);
+                    bytes.extend(plain.iter().zip(previous).map(|(byte, prev)| byte ^ prev));
+                    previous = block;
+                }
+            }
+            None => {
+                // Some clients add junk after the last block
+                let data = &data.as_bytes()[..data.len() / 12 * 12];
+                if data.is_empty() {
+                    return None;
+                }
+                for chunk in data.chunks(12) {
+                    let mut words = [0u32; 2];
+                    for (word, chars) in words.iter_mut().zip(chunk.chunks(6)) {
+                        for (i, c) in chars.iter().enumerate() {
+                            let value = FISH64.iter().position(|f| f == c)? as u32;
+                            *word |= value << (6 * i);
+                        }
+                    }
+                    let [r, l] = words;
+                    bytes.extend(self.cipher.decrypt_block(blowfish::join(l, r)));
+                }
+            }
+        }
+        while bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+// Needs to differ for every message, not to be secret
+fn random_iv() -> [u8; 8] {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    hasher.write_u128(now.as_nanos());
+    hasher.finish().to_be_bytes()
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        bits = bits << 6 | BASE64.iter().position(|&b| b == c)? as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+impl App {
+    // Reads [fish] keys, a bad key is reported and left out
+    pub(crate) fn load_fish_keys(&mut self) {
+        self.fish_keys.clear();
+        for (target, key) in self.config.fish.keys.clone() {
+            match FishKey::new(&key) {
+                Ok(key) => {
+                    self.fish_keys.insert(channel::nick_key(&target), key);
+                }
+                Err(e) => self
+                    .buffers
+                    .server_mut()
+                    .push(trf("FiSH key for {} left out: {}", &[&target, &e])),
+            }
+        }
+    }
+
+    // Where a PRIVMSG or NOTICE belongs for its key: the channel, or the other side of a
+    // private conversation
+    fn fish_key_for(&self, msg: &Message) -> Option<&FishKey> {
+        let target = channel::strip_status(msg.param(0)?);
+        let name = if target.starts_with(['#', '&']) {
+            target
+        } else {
+            msg.nick()?
+        };
+        self.fish_keys.get(&channel::nick_key(name))
+    }
+
+    // Decrypts messages from where we have a key and marks them, plaintext from there is
+    // marked too
+    pub(crate) fn fish_decrypt(&self, line: String, msg: Message) -> (String, Message) {
+        if !matches!(msg.command.as_str(), "PRIVMSG" | "NOTICE") || msg.ctcp().is_some() {
+            return (line, msg);
+        }
+        let (Some(key), Some(text)) = (self.fish_key_for(&msg), msg.trailing()) else {
+            return (line, msg);
+        };
+        let shown = if text.starts_with("+OK ") || text.starts_with("mcps ") {
+            match key.decrypt(text) {
+                // A line break in there would end the line early
+                Some(plain) => format!("{}{}", LOCKED, plain.replace(['\r', '\n'], " ")),
+                None => format!("[undecryptable] {}", text),
+            }
+        } else {
+            format!("{}{}", PLAIN, text)
+        };
+        let Some(start) = line.strip_suffix(text) else {
+            return (line, msg);
+        };
+        let line = format!("{}{}", start, shown);
+        match Message::parse(&line) {
+            Some(parsed) => (line, parsed),
+            None => (line, msg),
+        }
+    }
+
+    // Sends `text` to `target`, encrypted when we have a key for it. Returns the text to
+    // show for it
+    pub(crate) fn send_chat(&mut self, target: &str, text: &str) -> Result<String, String> {
+        let name = channel::strip_status(target);
+        let Some(key) = self.fish_keys.get(&channel::nick_key(name)) else {
+            self.client.send_message(target, text)?;
+            return Ok(text.to_string());
+        };
+        for part in message::split_text(text, MAX_PLAIN_BYTES) {
+            self.client.send_message(target, &key.encrypt(&part))?;
+        }
+        Ok(format!("{}{}", LOCKED, text))
+    }
+
+    // send_chat for several targets at once. Those with a key get their own encrypted
+    // messages, the rest share as few PRIVMSGs as TARGMAX allows
+    pub(crate) fn send_chat_to_all(
+        &mut self,
+        targets: &[&str],
+        text: &str,
+    ) -> Result<String, String> {
+        let (keyed, plain): (Vec<&str>, Vec<&str>) = targets.iter().partition(|target| {
+            let name = channel::strip_status(target);
+            self.fish_keys.contains_key(&channel::nick_key(name))
+        });
+        for target in &keyed {
+            self.send_chat(target, text)?;
+        }
+        if !plain.is_empty() {
+            for part in message::split_text(text, MAX_MESSAGE_BYTES) {
+                self.client.send_message_to_all(&plain, &part)?;
+            }
+        }
+        Ok(match (keyed.is_empty(), plain.is_empty()) {
+            (true, _) => text.to_string(),
+            (false, true) => format!("{}{}", LOCKED, text),
+            (false, false) => format!("{} ({}{})", text, LOCKED, keyed.join(",")),
+        })
+    }
+
+    // /fish set [target] key, /fish del [target], /fish list. The target defaults to
+    // the current channel
+    pub(crate) fn fish_command(&mut self, args: &str) {
+        let mut words = args.split_whitespace();
+        let current = self.client.current_channel.clone();
+        let (sub, first, second) = (words.next(), words.next(), words.next());
+        match (sub.unwrap_or("list"), first, second) {
+            ("list", None, None) => self.list_fish_keys(),
+            ("set", Some(key), None) if !current.is_empty() => self.set_fish_key(&current, key),
+            ("set", Some(target), Some(key)) => self.set_fish_key(target, key),
+            ("del", None, None) if !current.is_empty() => self.del_fish_key(&current),
+            ("del", Some(target), None) => self.del_fish_key(target),
+            _ => self.buffers.push_local(
+                tr("Usage: /fish set [target] key, /fish del [target], /fish list").to_string(),
+            ),
+        }
+    }
+
+    fn set_fish_key(&mut self, target: &str, key: &str) {
+        match FishKey::new(key) {
+            Ok(fish_key) => {
+                let mode = if fish_key.cbc { "CBC" } else { "ECB" };
+                self.fish_keys.insert(channel::nick_key(target), fish_key);
+                self.config
+                    .fish
+                    .keys
+                    .retain(|name, _| channel::nick_key(name) != channel::nick_key(target));
+                self.config
+                    .fish
+                    .keys
+                    .insert(target.to_string(), key.to_string());
+                self.buffers.push_local(trf(
+                    "Messages with {} are now encrypted ({})",
+                    &[&target, &mode],
+                ));
+                self.save_fish_keys();
+            }
+            Err(e) => self.buffers.push_local(e),
+        }
+    }
+
+    fn del_fish_key(&mut self, target: &str) {
+        let key = channel::nick_key(target);
+        if self.fish_keys.remove(&key).is_none() {
+            self.buffers
+                .push_local(trf("No FiSH key for {}", &[&target]));
+            return;
+        }
+        self.config
+            .fish
+            .keys
+            .retain(|name, _| channel::nick_key(name) != key);
+        self.buffers
+            .push_local(trf("Messages with {} are plaintext again", &[&target]));
+        self.save_fish_keys();
+    }
+
+    // Targets only, the keys stay off the screen
+    fn list_fish_keys(&mut self) {
+        let mut targets: Vec<String> = self.config.fish.keys.keys().cloned().collect();
+        if targets.is_empty() {
+            self.buffers
+                .push_local(tr("No FiSH keys, add one with /fish set").to_string());
+            return;
+        }
+        targets.sort();
+        self.buffers
+            .push_local(trf("Encrypted with FiSH: {}", &[&targets.join(", ")]));
+    }
+
+    fn save_fish_keys(&mut self) {
+        if let Err(e) = Config::save_fish_keys(&self.config.fish.keys) {
+            self.buffers.push_local(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FishKey;
+
+    #[test]
+    fn ecb_round_trip() {
+        let key = FishKey::new("ecb:my secret key").unwrap();
+        let sent = key.encrypt("hello #rust, this is longer than one block");
+        assert!(sent.starts_with("+OK "));
+        // 48 bytes of padded text, 12 characters for each block of 8
+        assert_eq!(sent.len(), "+OK ".len() + 6 * 12);
+        assert_eq!(
+            key.decrypt(&sent).as_deref(),
+            Some("hello #rust, this is longer than one block")
+        );
+    }
+
+    #[test]
+    fn cbc_round_trip() {
+        let key = FishKey::new("cbc:my secret key").unwrap();
+        let sent = key.encrypt("grüße");
+        assert!(sent.starts_with("+OK *"));
+        assert_eq!(key.decrypt(&sent).as_deref(), Some("grüße"));
+        // A fresh IV every time, the same text does not look the same twice
+        assert_ne!(key.encrypt("grüße"), sent);
+    }
+
+    #[test]
+    fn wrong_key_does_not_give_the_text() {
+        let sent = FishKey::new("right key").unwrap().encrypt("secret plans");
+        let read = FishKey::new("wrong key").unwrap().decrypt(&sent);
+        assert_ne!(read.as_deref(), Some("secret plans"));
+        assert!(FishKey::new("key").is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use crate::app::App;
+use crate::i18n::tr;
+use std::collections::BTreeMap;
+
+// Keys of the main screen, as (key, what it does)
+const KEYS: [(&str, &str); 22] = [
+    (
+        "Enter",
+        "Send the input line, or run it when it starts with /",
+    ),
+    (
+        "Alt+Enter",
+        "Write a message of several lines, with a preview of how it is sent",
+    ),
+    (
+        "Tab",
+        "Complete the command being typed, on an empty line the last speaker's nick",
+    ),
+    ("Esc", "Close the open overlay or menu, otherwise quit"),
+    ("PgUp / PgDn", "Scroll the current buffer"),
+    (
+        "Alt+Left / Alt+Right",
+        "Switch to the previous or next buffer",
+    ),
+    ("Alt+U", "Jump back to the first unread line"),
+    (
+        "Alt+, / Alt+.",
+        "Jump to the previous or next highlight, on into other buffers",
+    ),
+    ("Alt+Up / Alt+Down", "Pick a nick in the nick list"),
+    ("Alt+N", "Open the action menu for the typed or picked nick"),
+    (
+        "Alt+L",
+        "Focus the nick list, typing filters it, Enter opens the menu",
+    ),
+    (
+        "Alt+Z",
+        "Give the chat pane the whole screen, or put the rest back",
+    ),
+    (
+        "F2 / F3 / F4",
+        "Show or hide the timestamps, nick list and topic bar",
+    ),
+    ("Ctrl+P", "Open the palette of every command and action"),
+    ("1-9", "On the dashboard, join that favorite channel"),
+    (
+        "Up / Down",
+        "Move the selection in the transfers, invites, bans and access panels",
+    ),
+    (
+        "c / r",
+        "Transfers panel: cancel or retry the selected transfer",
+    ),
+    (
+        "j / d",
+        "Invitations panel: join or dismiss the selected invite",
+    ),
+    (
+        "u / r",
+        "Bans panel: lift the selected ban, reload the list",
+    ),
+    ("a / e", "Access panel: add an entry, edit the selected one"),
+    (
+        "d / r",
+        "Access panel: remove the selected entry, reload the list",
+    ),
+    (
+        "w q i o d k b",
+        "Nick menu: whois, query, ignore, op, deop, kick, ban",
+    ),
+];
+
+// Settings in config.toml, as (section and key, what it does)
+const CONFIG: [(&str, &str); 69] = [
+    (
+        "favorites",
+        "Channels listed on the dashboard, joined with 1-9",
+    ),
+    ("alt_nicks", "Nicks to try when ours is taken"),
+    ("[control] enabled, path", "The local control socket"),
+    (
+        "[relay] enabled, bind, password",
+        "Relay server for remote frontends",
+    ),
+    (
+        "[http] enabled, bind, token",
+        "Local HTTP API for dashboards and scripts",
+    ),
+    (
+        "[webhook] url, template, headers",
+        "POST private messages and highlights as JSON",
+    ),
+    (
+        "[webhook] always",
+        "Also while reading along, not only when away",
+    ),
+    (
+        "[dbus] notifications, sleep",
+        "Desktop notifications, reconnect after suspend",
+    ),
+    (
+        "[sounds] highlight, private, connect, disconnect",
+        "Sound files per event, the bell without one",
+    ),
+    (
+        "[sounds] player, muted",
+        "What plays them, start with sounds off",
+    ),
+    (
+        "[highlight] exclude_channels, exclude_senders",
+        "Channels and senders that never highlight or notify",
+    ),
+    (
+        "[highlight] quiet_hours",
+        "Times like 23:00-07:00 when only private messages notify",
+    ),
+    (
+        "[highlight.networks.\"name\"]",
+        "The same per network, by NETWORK name or server host",
+    ),
+    ("[dcc] download_dir", "Where received files go"),
+    (
+        "[dcc] port_min, port_max",
+        "Ports offered for DCC connections",
+    ),
+    (
+        "[dcc] own_ip, passive",
+        "Address we advertise, reverse DCC behind NAT",
+    ),
+    ("[ctcp] reply", "Whether to answer CTCP requests at all"),
+    (
+        "[ctcp] per_sender, global, window_secs",
+        "How many requests we answer per window",
+    ),
+    ("[ctcp] cooldown_secs", "How long a flooder is ignored"),
+    ("[away] message", "Default /away message"),
+    (
+        "[away] auto_minutes",
+        "Go away after this long without typing, 0 never",
+    ),
+    (
+        "[ignore] accounts, masks",
+        "Who to ignore by account or nick!user@host mask",
+    ),
+    ("[ignore] realnames", "Who to ignore by realname pattern"),
+    (
+        "[encoding] fallback",
+        "Encoding of lines that are not UTF-8",
+    ),
+    ("[encoding] networks", "The fallback per network"),
+    (
+        "[nickserv] recover",
+        "off, ask, regain or ghost when our nick is taken",
+    ),
+    (
+        "[chanserv] style",
+        "Which ChanServ access commands the network speaks",
+    ),
+    ("[log] level", "How much goes into the diagnostics log"),
+    ("[log] keep_days", "How long diagnostics logs are kept"),
+    (
+        "[history] server, dcc_chat, notices",
+        "Scrollback lines kept per buffer kind",
+    ),
+    (
+        "[history] debug, awaylog, filtered",
+        "Scrollback of the debug, awaylog and filtered buffers",
+    ),
+    (
+        "[history] max_memory_mb",
+        "Total scrollback memory before old lines go",
+    ),
+    (
+        "[paste] max_lines",
+        "Pastes longer than this ask first, 0 never asks",
+    ),
+    ("[paste] service", "Where long pastes are uploaded"),
+    (
+        "[paste] burst, interval_ms",
+        "Lines sent at once, then one per interval",
+    ),
+    (
+        "[fish] keys",
+        "FiSH keys by channel or nick, cbc: for CBC mode",
+    ),
+    (
+        "[keys] passphrase_env",
+        "Variable with a passphrase that encrypts remembered channel keys",
+    ),
+    ("[kick] rejoin", "Rejoin channels we were kicked from"),
+    (
+        "[kick] delay_secs, max_attempts",
+        "How long to wait and how often to try",
+    ),
+    (
+        "[locale] language",
+        "Language of the interface, auto follows LANG",
+    ),
+    (
+        "[parser] mode",
+        "lenient repairs broken server lines quietly, strict also reports them",
+    ),
+    (
+        "[reconnect] enabled, delay_secs, max_delay_secs",
+        "Connect again after losing the connection, waiting longer each time",
+    ),
+    (
+        "[quit] message, part_message, confirm",
+        "What others see when you quit or leave a channel, and whether Esc asks first",
+    ),
+    (
+        "[who] poll_minutes",
+        "How often away and account states are refreshed",
+    ),
+    (
+        "[who] max_members",
+        "Channels bigger than this are not polled",
+    ),
+    (
+        "[theme] name",
+        "default, solarized, high-contrast or deuteranopia",
+    ),
+    (
+        "[theme] timestamp, hour12",
+        "Time shown in front of every line",
+    ),
+    (
+        "[theme] day_format",
+        "Date in the separator when the day changes",
+    ),
+    ("[theme] timezone", "local, utc or an offset like +05:30"),
+    (
+        "[theme] group, group_secs",
+        "Leave out repeated nicks of consecutive messages",
+    ),
+    (
+        "[theme] indent, nick_width",
+        "Wrapped rows under the text: dynamic, fixed or off",
+    ),
+    (
+        "[theme] accessible",
+        "Screen reader friendly output without box drawing",
+    ),
+    ("[ident] nick", "Our nick, [connect] nick by default"),
+    ("[ident] username, realname", "What we register as"),
+    ("[ident] version", "Our CTCP VERSION reply, empty for none"),
+    (
+        "[ident] privacy",
+        "off, random or blank for the fields above",
+    ),
+    (
+        "[ident] sasl_username, sasl_password",
+        "SASL login for servers without their own",
+    ),
+    (
+        "[identd] enabled, port",
+        "Answer ident queries while registering",
+    ),
+    (
+        "[[automodes]] channel",
+        "Channel an auto-op or auto-voice rule is for",
+    ),
+    ("[[automodes]] account, mask", "Who the rule matches"),
+    ("[[automodes]] mode", "op or voice"),
+    (
+        "[[filters]] sender, target, content",
+        "Regexes a message has to match, as /filter adds them",
+    ),
+    (
+        "[[filters]] action",
+        "hide, dim or move to the filtered buffer",
+    ),
+    ("[servers.\"host\"] password", "Server password"),
+    (
+        "[servers.\"host\"] sasl_username, sasl_password",
+        "SASL login",
+    ),
+    (
+        "[servers.\"host\"] nickserv_password",
+        "For GHOST and REGAIN, else the SASL one",
+    ),
+    (
+        "[servers.\"host\"] perform",
+        "Commands run once registered, with $nick and $network",
+    ),
+    (
+        "[servers.\"host\"] nick, username, realname",
+        "[ident] settings for this server only",
+    ),
+    (
+        "[servers.\"host\"] version, privacy",
+        "[ident] settings for this server only",
+    ),
+];
+
+// The full-screen help opened by /help
+pub struct HelpPager {
+    pub lines: Vec<String>,
+    // First line on screen
+    pub scroll: usize,
+    // What is being typed after /, None when not searching
+    pub typing: Option<String>,
+    // The last search, highlighted and repeated with n
+    pub query: String,
+}
+
+impl HelpPager {
+    fn new(commands: &BTreeMap<&str, &'static str>) -> Self {
+        let mut lines = vec![tr("Commands").to_string(), String::new()];
+        lines.extend(
+            commands
+                .iter()
+                .map(|(cmd, desc)| format!("  {:<14} {}", cmd, tr(desc))),
+        );
+        lines.extend(["".to_string(), tr("Keys").to_string(), String::new()]);
+        lines.extend(
+            KEYS.iter()
+                .map(|(key, desc)| format!("  {:<22} {}", key, tr(desc))),
+        );
+        lines.extend([
+            "".to_string(),
+            tr("Config (config.toml)").to_string(),
+            String::new(),
+        ]);
+        lines.extend(
+            CONFIG
+                .iter()
+                .map(|(key, desc)| format!("  {:<46} {}", key, tr(desc))),
+        );
+        HelpPager {
+            lines,
+            scroll: 0,
+            typing: None,
+            query: String::new(),
+        }
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        !self.query.is_empty() && line.to_lowercase().contains(&self.query.to_lowercase())
+    }
+
+    // Scrolls to the first line matching the query at or after `from`,
+    // wrapping around
+    pub fn find_next(&mut self, from: usize) {
+        let count = self.lines.len();
+        let found = (0..count)
+            .map(|i| (from + i) % count)
+            .find(|&i| self.matches(&self.lines[i]));
+        if let Some(index) = found {
+            self.scroll = index;
+        }
+    }
+
+    // Keeps the last screenful full
+    pub fn scroll_by(&mut self, delta: isize, height: usize) {
+        let max = self.lines.len().saturating_sub(height);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+}
+
+impl App {
+    // /help opens the pager, a daemon has no screen for it and lists the commands instead
+    pub(crate) fn help_command(&mut self) {
+        if self.headless {
+            self.buffers
+                .push_local(tr("---- Command Help ----").to_string());
+            for (cmd, desc) in &self.commands {
+                self.buffers.push_local(format!("{} - {}", cmd, tr(desc)));
+            }
+            return;
+        }
+        self.help = Some(HelpPager::new(&self.commands));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::app::App;
+use crate::channel;
+use crate::config::NetworkHighlight;
+use crate::i18n::trf;
+use crate::message::Message;
+use chrono::{Local, Timelike};
+
+// "23:00-07:00" as minutes since midnight, the end may be on the next day
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let minutes = |time: &str| {
+        let (hours, minutes) = time.trim().split_once(':')?;
+        let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    };
+    let (start, end) = range.split_once('-')?;
+    Some((minutes(start)?, minutes(end)?))
+}
+
+fn in_range((start, end): (u32, u32), now: u32) -> bool {
+    if start <= end {
+        (start..end).contains(&now)
+    } else {
+        now >= start || now < end
+    }
+}
+
+impl App {
+    // The [highlight.networks] entry for where we are
+    fn network_highlight(&self) -> Option<&NetworkHighlight> {
+        let (network, server) = (&self.client.network, &self.client.server);
+        self.config
+            .highlight
+            .networks
+            .iter()
+            .find(|(name, _)| {
+                name.eq_ignore_ascii_case(network) || name.eq_ignore_ascii_case(server)
+            })
+            .map(|(_, rules)| rules)
+    }
+
+    // A message from an excluded sender, or to an excluded channel, never highlights
+    pub(crate) fn highlight_excluded(&self, msg: &Message) -> bool {
+        let config = &self.config.highlight;
+        let network = self.network_highlight();
+        let (nick, hostmask) = (
+            msg.nick().unwrap_or_default(),
+            msg.prefix.as_deref().unwrap_or_default(),
+        );
+        let target = channel::strip_status(msg.param(0).unwrap_or_default());
+        let mut senders = config
+            .exclude_senders
+            .iter()
+            .chain(network.into_iter().flat_map(|n| &n.exclude_senders));
+        let mut channels = config
+            .exclude_channels
+            .iter()
+            .chain(network.into_iter().flat_map(|n| &n.exclude_channels));
+        let by_sender = senders.any(|mask| {
+            let who = if mask.contains(['!', '@']) {
+                hostmask
+            } else {
+                nick
+            };
+            channel::mask_matches(mask, who)
+        });
+        let by_channel = target.starts_with(['#', '&'])
+            && channels.any(|mask| channel::mask_matches(mask, target));
+        by_sender || by_channel
+    }
+
+    // Inside [highlight] quiet_hours: highlights are still marked, but only private
+    // messages ring, notify or go to the webhook
+    pub(crate) fn quiet_hours(&self) -> bool {
+        let ranges = match self
+            .network_highlight()
+            .and_then(|n| n.quiet_hours.as_ref())
+        {
+            Some(ranges) => ranges,
+            None => &self.config.highlight.quiet_hours,
+        };
+        let now = Local::now();
+        let now = now.hour() * 60 + now.minute();
+        ranges
+            .iter()
+            .filter_map(|range| parse_range(range))
+            .any(|range| in_range(range, now))
+    }
+
+    // Tells about quiet_hours that can not be read, they would silently never apply
+    pub(crate) fn check_quiet_hours(&mut self) {
+        let config = &self.config.highlight;
+        let ranges = config.quiet_hours.iter().chain(
+            config
+                .networks
+                .values()
+                .flat_map(|n| n.quiet_hours.iter().flatten()),
+        );
+        let bad: Vec<String> = ranges
+            .filter(|range| parse_range(range).is_none())
+            .map(|range| {
+                trf(
+                    "Invalid quiet_hours '{}', use a range like 23:00-07:00",
+                    &[&range],
+                )
+            })
+            .collect();
+        for line in bad {
+            self.buffers.server_mut().push(line);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::app::{App, AppEvent};
+use crate::relay;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Sender, channel};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+type Result<T> = std::result::Result<T, String>;
+
+// A small HTTP API on localhost for dashboards, Stream Deck buttons and the like. Every
+// request needs `Authorization: Bearer <token>` and gets a JSON answer:
+//   GET  /status                            nick, server, away, the buffer on screen
+//   GET  /buffers                           every buffer with its kind and unread count
+//   GET  /messages?buffer=server&count=50  the last lines of a buffer, oldest first
+//   POST /send     {"target":"#rust","text":"hi"}
+// Commands are left out on purpose, the token should not be worth more than chatting
+// One request per connection, answered by the main loop like the control socket
+
+// How long a client gets to send its request, and waits for the main loop to answer it
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Bodies are a target and a line of text, anything bigger is not for us
+const MAX_BODY: usize = 64 * 1024;
+
+// The request line and each header, and how many headers, read before the token is checked
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+
+// What the main loop gets to answer, with the query string split off and decoded
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpRequest {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub fn start(bind: &str, token: &str, events: Sender<AppEvent>) -> Result<()> {
+    if token.is_empty() {
+        return Err("http.token must be set".to_string());
+    }
+    let listener =
+        TcpListener::bind(bind).map_err(|e| format!("Failed to bind {}: {}", bind, e))?;
+    let token = token.to_string();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let events = events.clone();
+            let token = token.clone();
+            thread::spawn(move || serve(stream, &token, events));
+        }
+    });
+    Ok(())
+}
+
+fn serve(stream: TcpStream, token: &str, events: Sender<AppEvent>) {
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let (status, body) = match read_request(stream, token) {
+        Ok(request) => {
+            let (reply_tx, reply_rx) = channel();
+            let event = AppEvent::Http {
+                request,
+                reply: reply_tx,
+            };
+            match events.send(event).map(|_| reply_rx.recv_timeout(TIMEOUT)) {
+                Ok(Ok(reply)) => reply,
+                _ => (503, error("no reply from client")),
+            }
+        }
+        Err(reply) => reply,
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    );
+    let _ = writer.write_all(response.as_bytes());
+}
+
+// The request if it is well formed and carries the token, otherwise the answer to give
+fn read_request(stream: TcpStream, token: &str) -> std::result::Result<HttpRequest, (u16, Value)> {
+    let bad = |text: &str| (400, error(text));
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_capped(&mut reader, &mut line).map_err(|_| bad("no request"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad("bad request line"));
+    };
+
+    let mut length = 0;
+    let mut authorized = false;
+    for count in 0.. {
+        if count == MAX_HEADERS {
+            return Err(bad("too many headers"));
+        }
+        let mut header = String::new();
+        read_capped(&mut reader, &mut header).map_err(|_| bad("bad headers"))?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(bad("bad header"));
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => length = value.parse().map_err(|_| bad("bad length"))?,
+            "authorization" => {
+                authorized = value
+                    .strip_prefix("Bearer ")
+                    .is_some_and(|given| same_token(given, token))
+            }
+            _ => {}
+        }
+    }
+    if !authorized {
+        return Err((401, error("missing or wrong token")));
+    }
+    if length > MAX_BODY {
+        return Err((413, error("body too large")));
+    }
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad("body shorter than its length"))?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Ok(HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(key), decode(value))
+            })
+            .collect(),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+// One line of at most MAX_LINE bytes, longer ones are an error instead of more memory
+fn read_capped(reader: &mut BufReader<TcpStream>, line: &mut String) -> std::io::Result<()> {
+    reader.by_ref().take(MAX_LINE).read_line(line)?;
+    if !line.ends_with('\n') {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+    Ok(())
+}
+
+// Compares every byte whatever the first difference is, so the time taken does not give
+// away how much of a guessed token was right
+pub(crate) fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// Undoes %XX escapes and + for spaces, e.g. ?buffer=%3Dbob for the DCC chat with bob
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Service Unavailable",
+    }
+}
+
+fn error(text: &str) -> Value {
+    json!({ "error": text })
+}
+
+#[derive(Deserialize)]
+struct SendBody {
+    target: String,
+    text: String,
+}
+
+impl App {
+    // Answers a request from the HTTP API with a status and a JSON body
+    pub(crate) fn http_request(&mut self, request: &HttpRequest) -> (u16, Value) {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/status") => (
+                200,
+                json!({
+                    "nick": self.client.nickname,
+                    "server": self.client.server,
+                    "network": self.client.network,
+                    "registered": self.client.registered,
+                    "state": self.client.state.label(),
+                    "away": self.client.away,
+                    "buffer": self.buffers.active().name,
+                }),
+            ),
+            ("GET", "/buffers") => {
+                let buffers: Vec<Value> = self
+                    .buffers
+                    .iter()
+                    .map(|b| {
+                        json!({
+                            "name": b.name,
+                            "kind": relay::kind_name(b.kind),
+                            "unread": b.unread(),
+                            "total": b.total,
+                        })
+                    })
+                    .collect();
+                (200, json!(buffers))
+            }
+            ("GET", "/messages") => {
+                let name = request.param("buffer").unwrap_or("");
+                let count = request
+                    .param("count")
+                    .and_then(|c| c.parse().ok())
+                    .unwrap_or(100);
+                let Some(buffer) = self.buffers.get(name) else {
+                    return (404, error(&format!("no buffer named '{}'", name)));
+                };
+                let lines: Vec<Value> = buffer
+                    .recent(count)
+                    .map(|l| {
+                        let time = l.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                        json!({ "time": time.as_secs(), "text": l.text })
+                    })
+                    .collect();
+                (200, json!(lines))
+            }
+            ("POST", "/send") => {
+                let body: SendBody = match serde_json::from_str(&request.body) {
+                    Ok(body) => body,
+                    Err(e) => return (400, error(&e.to_string())),
+                };
+                // A line break would start a command of its own
+                if format!("{}{}", body.target, body.text).contains(['\r', '\n', '\0']) {
+                    return (400, error("target and text must be one line"));
+                }
+                match self.send_chat(&body.target, &body.text) {
+                    Ok(shown) => {
+                        let line = format!("-> {}: {}", body.target, shown);
+                        self.push_sent(&body.target, line);
+                    }
+                    Err(e) => return (503, error(&e)),
+                }
+                (200, json!({}))
+            }
+            (_, "/status" | "/buffers" | "/messages" | "/send") => (405, error("wrong method")),
+            _ => (404, error("no such route")),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+// Built-in translations, each a TOML table from the English text to the translated one.
+// What a catalog lacks stays English
+const CATALOGS: [(&str, &str); 1] = [("de", include_str!("../locales/de.toml"))];
+
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+// [locale] language, "auto" takes it from LC_ALL, LC_MESSAGES or LANG, e.g. de_DE.UTF-8
+pub fn language(setting: &str) -> String {
+    let setting = setting.trim();
+    if setting != "auto" {
+        return setting.to_lowercase();
+    }
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .split(['_', '.', '@'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase()
+        })
+        .unwrap_or_default()
+}
+
+// Picks the catalog for [locale] language, once at startup. Errors for a language asked
+// for by name that has no catalog, "auto" falls back to English quietly
+pub fn init(setting: &str) -> Result<(), String> {
+    let setting = setting.trim();
+    let language = language(setting);
+    let source = CATALOGS
+        .iter()
+        .find(|(name, _)| *name == language)
+        .map(|(_, source)| *source);
+    let catalog = match source {
+        Some(source) => toml::from_str(source)
+            .map_err(|e| format!("The {} translation is broken: {}", language, e))?,
+        None => HashMap::new(),
+    };
+    let _ = CATALOG.set(catalog);
+    match source {
+        None if !matches!(language.as_str(), "" | "en" | "c" | "posix") && setting != "auto" => {
+            let known: Vec<&str> = CATALOGS.iter().map(|(name, _)| *name).collect();
+            Err(format!(
+                "No translation for '{}', there is {} and English",
+                language,
+                known.join(", ")
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+// `text` in the picked language
+pub fn tr(text: &'static str) -> &'static str {
+    CATALOG
+        .get()
+        .and_then(|catalog| catalog.get(text))
+        .map_or(text, |translated| translated.as_str())
+}
+
+// tr for text with {} in it, filled with `args` in order
+pub fn trf(text: &'static str, args: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut parts = tr(text).split("{}").peekable();
+    while let Some(part) = parts.next() {
+        out.push_str(part);
+        if parts.peek().is_some()
+            && let Some(arg) = args.next()
+        {
+            out.push_str(&arg.to_string());
+        }
+    }
+    out
+}
@@ -0,0 +1,112 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+// Registration that takes longer than this has given up on ident anyway
+const LIFETIME: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct State {
+    // Our port and the server's port of the connection we answer for, once it is open
+    ports: Option<(u16, u16)>,
+    stop: bool,
+    // What happened, told to the user once we are registered
+    report: Option<String>,
+}
+
+// An RFC 1413 ident responder that only lives while we register. It answers queries for
+// our own connection with `username` and everything else with NO-USER
+pub struct Identd {
+    state: Arc<Mutex<State>>,
+}
+
+impl Identd {
+    // Listens on `port` on every address, the server connects back to the one we came from
+    pub fn start(port: u16, username: String) -> Identd {
+        let state = Arc::new(Mutex::new(State::default()));
+        // Bound right here so it is listening by the time we connect
+        let listener = match TcpListener::bind(("0.0.0.0", port))
+            .and_then(|l| l.set_nonblocking(true).map(|_| l))
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!(port, "identd: {}", e);
+                state.lock().unwrap().report =
+                    Some(format!("identd could not listen on port {}: {}", port, e));
+                return Identd { state };
+            }
+        };
+        let shared = state.clone();
+        thread::spawn(move || {
+            let started = Instant::now();
+            while started.elapsed() < LIFETIME && !shared.lock().unwrap().stop {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = answer(stream, &shared, &username) {
+                            debug!("identd query: {}", e);
+                        }
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(50)),
+                }
+            }
+        });
+        Identd { state }
+    }
+
+    // The connection is open, queries about these ports are ours
+    pub fn expect(&self, local: u16, remote: u16) {
+        self.state.lock().unwrap().ports = Some((local, remote));
+    }
+
+    // How it went, for the server buffer
+    pub fn report(&self) -> String {
+        self.state
+            .lock()
+            .unwrap()
+            .report
+            .clone()
+            .unwrap_or_else(|| "identd got no query from the server".to_string())
+    }
+}
+
+impl Drop for Identd {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stop = true;
+        }
+    }
+}
+
+// One query: "<our port> , <their port>"
+fn answer(stream: TcpStream, state: &Mutex<State>, username: &str) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let query = line.trim();
+    let ports = query
+        .split_once(',')
+        .and_then(|(ours, theirs)| Some((ours.trim().parse().ok()?, theirs.trim().parse().ok()?)));
+    // The query can come in before connect() has told us the ports
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut expected = state.lock().unwrap().ports;
+    while expected.is_none() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+        expected = state.lock().unwrap().ports;
+    }
+    let reply = match ports {
+        Some((ours, theirs)) if Some((ours, theirs)) == expected => {
+            state.lock().unwrap().report = Some(format!(
+                "identd answered the server's query as {}",
+                username
+            ));
+            format!("{}, {} : USERID : UNIX : {}", ours, theirs, username)
+        }
+        Some((ours, theirs)) => format!("{}, {} : ERROR : NO-USER", ours, theirs),
+        None => format!("{} : ERROR : INVALID-PORT", query),
+    };
+    (&stream).write_all(format!("{}\r\n", reply).as_bytes())
+}
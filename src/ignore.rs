@@ -0,0 +1,166 @@
+use crate::app::App;
+use crate::banlist;
+use crate::channel;
+use crate::clock;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use std::time::{Duration, Instant};
+
+// An ignore added with /ignore or the nick menu, gone when Irconic exits or at `until`
+pub struct Ignore {
+    // The nick it was added for, to name it in messages and for /unignore
+    pub nick: Option<String>,
+    pub mask: String,
+    pub until: Option<Instant>,
+}
+
+impl App {
+    // PRIVMSG and NOTICE from someone in [ignore] or on the /ignore list
+    pub(crate) fn is_ignored(&self, msg: &Message) -> bool {
+        let (Some(nick), Some(hostmask)) = (msg.nick(), msg.prefix.as_deref()) else {
+            return false;
+        };
+        self.config.ignore.matches(
+            hostmask,
+            self.client.account(nick),
+            self.client.realname(nick),
+        ) || self
+            .ignores
+            .iter()
+            .any(|ignore| channel::mask_matches(&ignore.mask, hostmask))
+    }
+
+    // Ignores `nick` by host, or `mask` as given, until Irconic exits or for `duration`
+    pub(crate) fn ignore(&mut self, target: &str, duration: Option<Duration>) {
+        let (nick, mask) = if target.contains(['!', '@']) {
+            (None, target.to_string())
+        } else {
+            let mask = match self.client.hostmask(target) {
+                Some(hostmask) => banlist::ban_mask(&hostmask),
+                None => format!("{}!*@*", target),
+            };
+            (Some(target.to_string()), mask)
+        };
+        let name = match &nick {
+            Some(nick) => format!("{} ({})", nick, mask),
+            None => mask.clone(),
+        };
+        let line = match duration {
+            Some(duration) => trf("Ignoring {} for {}", &[&name, &clock::ago(duration)]),
+            None => trf(
+                "Ignoring {} for this session, add the mask to [ignore] masks to keep it",
+                &[&name],
+            ),
+        };
+        self.buffers.push_local(line);
+        // Ignoring again replaces the old duration
+        self.ignores.retain(|ignore| ignore.mask != mask);
+        let until = duration.map(|duration| Instant::now() + duration);
+        self.ignores.push(Ignore { nick, mask, until });
+    }
+
+    // /ignore nick|mask [duration], /ignore -list, or /ignore on its own for the list
+    pub(crate) fn ignore_command(&mut self, args: &str) {
+        let mut words = args.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (None | Some("-list"), None, None) => self.list_ignores(),
+            (Some(target), duration, None) if !target.starts_with('-') => {
+                let duration = match duration.map(clock::parse_duration) {
+                    None => None,
+                    Some(Some(duration)) => Some(duration),
+                    Some(None) => {
+                        self.buffers.push_local(trf(
+                            "'{}' is not a duration, try 30m, 2h or 1d",
+                            &[&duration.unwrap_or_default()],
+                        ));
+                        return;
+                    }
+                };
+                self.ignore(target, duration);
+            }
+            _ => self.buffers.push_local(
+                tr("Usage: /ignore [nick|mask] [30m|2h|1d], /ignore -list").to_string(),
+            ),
+        }
+    }
+
+    // /unignore nick|mask, for what /ignore added
+    pub(crate) fn unignore_command(&mut self, target: &str) {
+        let key = channel::nick_key(target);
+        let before = self.ignores.len();
+        self.ignores.retain(|ignore| {
+            let by_nick = ignore
+                .nick
+                .as_deref()
+                .is_some_and(|nick| channel::nick_key(nick) == key);
+            !by_nick && ignore.mask != target
+        });
+        let line = if self.ignores.len() < before {
+            trf("No longer ignoring {}", &[&target])
+        } else if target.is_empty() {
+            tr("Usage: /unignore nick|mask").to_string()
+        } else {
+            trf(
+                "{} is not on the /ignore list, [ignore] in the config has the rest",
+                &[&target],
+            )
+        };
+        self.buffers.push_local(line);
+    }
+
+    fn list_ignores(&mut self) {
+        let config = &self.config.ignore;
+        let mut lines: Vec<String> = config
+            .accounts
+            .iter()
+            .map(|account| format!("  account {} (config)", account))
+            .chain(
+                config
+                    .masks
+                    .iter()
+                    .map(|mask| format!("  {} (config)", mask)),
+            )
+            .chain(
+                config
+                    .realnames
+                    .iter()
+                    .map(|realname| format!("  realname {} (config)", realname)),
+            )
+            .collect();
+        let now = Instant::now();
+        lines.extend(self.ignores.iter().map(|ignore| {
+            let name = match &ignore.nick {
+                Some(nick) => format!("{} ({})", ignore.mask, nick),
+                None => ignore.mask.clone(),
+            };
+            match ignore.until {
+                Some(until) => format!("  {}, {} left", name, clock::ago(until - now)),
+                None => format!("  {}, this session", name),
+            }
+        }));
+        if lines.is_empty() {
+            self.buffers.push_local(tr("Nobody is ignored").to_string());
+            return;
+        }
+        self.buffers
+            .push_local(trf("Ignoring {}:", &[&lines.len()]));
+        for line in lines {
+            self.buffers.push_local(line);
+        }
+    }
+
+    // Called on every tick
+    pub(crate) fn expire_ignores(&mut self) {
+        let now = Instant::now();
+        let (expired, kept): (Vec<Ignore>, Vec<Ignore>) = std::mem::take(&mut self.ignores)
+            .into_iter()
+            .partition(|ignore| ignore.until.is_some_and(|until| until <= now));
+        self.ignores = kept;
+        for ignore in expired {
+            let name = ignore.nick.unwrap_or(ignore.mask);
+            self.buffers
+                .server_mut()
+                .push(trf("The ignore on {} ran out", &[&name]));
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::channel;
+use crate::i18n::trf;
+use crate::message::Message;
+
+// An invitation to a channel we have not acted on yet
+pub struct Invite {
+    pub from: String,
+    pub channel: String,
+}
+
+impl App {
+    // INVITE <nick> <channel>. With invite-notify we also see invitations to others in
+    // channels we are in, those only get a line
+    pub(crate) fn invite(&mut self, msg: &Message) {
+        let (Some(from), Some(target), Some(name)) = (msg.nick(), msg.param(0), msg.param(1))
+        else {
+            return;
+        };
+        if !self.client.is_me(target) {
+            self.buffers
+                .server_mut()
+                .push(trf("{} invited {} to {}", &[&from, &target, &name]));
+            return;
+        }
+        // A repeated invitation replaces the old one
+        let key = channel::nick_key(name);
+        self.invites
+            .retain(|i| channel::nick_key(&i.channel) != key);
+        self.invites.push(Invite {
+            from: from.to_string(),
+            channel: name.to_string(),
+        });
+        self.buffers.open("invites", BufferKind::Invites);
+        self.notify(&trf(
+            "{} invites you to {}, /invites to accept or dismiss",
+            &[&from, &name],
+        ));
+    }
+
+    // Opens the invitations panel
+    pub fn show_invites(&mut self) {
+        self.buffers.open("invites", BufferKind::Invites);
+        self.buffers.select_name("invites");
+    }
+
+    pub fn accept_invite(&mut self, index: usize) {
+        if index < self.invites.len() {
+            let invite = self.invites.remove(index);
+            self.invite_selected = self
+                .invite_selected
+                .min(self.invites.len().saturating_sub(1));
+            self.join(&invite.channel);
+        }
+    }
+
+    pub fn dismiss_invite(&mut self, index: usize) {
+        if index < self.invites.len() {
+            self.invites.remove(index);
+            self.invite_selected = self
+                .invite_selected
+                .min(self.invites.len().saturating_sub(1));
+        }
+    }
+}
@@ -1,17 +1,191 @@
+use crate::channel::{self, Channel, Users};
+use crate::i18n::{tr, trf};
+use crate::identd::Identd;
+use crate::message::{self, Message};
+use crate::transport::Connection;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Read, Write};
-use std::net::TcpStream;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, trace, warn};
 
 type Result<T> = std::result::Result<T, String>;
 
+// Where the connection to the network stands, shown in the chat title and the input line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    TlsHandshake,
+    // NICK and USER are sent, waiting for 001
+    Registering,
+    // SASL login in progress, or waiting for its password
+    Authenticating,
+    Ready,
+    // The connection was lost, the next try is at `at`
+    Reconnecting { at: Instant },
+}
+
+impl ConnectionState {
+    pub fn label(self) -> String {
+        match self {
+            ConnectionState::Disconnected => "disconnected".to_string(),
+            ConnectionState::Connecting => "connecting".to_string(),
+            ConnectionState::TlsHandshake => "TLS handshake".to_string(),
+            ConnectionState::Registering => "registering".to_string(),
+            ConnectionState::Authenticating => "authenticating".to_string(),
+            ConnectionState::Ready => "ready".to_string(),
+            ConnectionState::Reconnecting { at } => format!(
+                "reconnecting in {}s",
+                at.saturating_duration_since(Instant::now()).as_secs() + 1
+            ),
+        }
+    }
+}
+
+// SASL PLAIN account. Without a password the TUI asks for it, registration waits
+#[derive(Debug, Clone)]
+pub struct SaslLogin {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+// Nicks tried at connect time before giving up, so a server refusing them all does not
+// leave us waiting forever
+const MAX_NICK_ATTEMPTS: usize = 8;
+
+// WHOX fields we ask for: token, channel, user, host, nick, flags, account, realname. The
+// token tells our replies apart from anyone else's
+const WHO_FIELDS: &str = "%tcuhnfar";
+const WHOX_TOKEN: &str = "152";
+
+// The last line the receiver thread hands over, after the server or the network hung up
+pub const CONNECTION_CLOSED: &str = "Connection to server closed.";
+
+// What the receiver thread hands over after a line that breaks the protocol, in strict
+// parser mode. The details follow, then the line as it came
+pub const PROTOCOL_VIOLATION: &str = "Protocol violation: ";
+
+// IRCv3 capabilities we ask for when the server offers them
+pub const WANTED_CAPS: &[&str] = &[
+    "away-notify",
+    "account-notify",
+    "extended-join",
+    "multi-prefix",
+    "userhost-in-names",
+    "batch",
+    "labeled-response",
+    "message-tags",
+    "draft/read-marker",
+    "invite-notify",
+    "cap-notify",
+];
+
+// Server software that sends REMOVE as a PART of the removed nick, see part_is_removal
+const REMOVE_AS_PART: &[&str] = &["solanum", "charybdis", "ircd-seven"];
+
 // Defining a basic Structure for the application
 pub struct IrcClient {
-    pub stream: Option<TcpStream>,
+    pub stream: Option<Connection>,
     pub nickname: String,
     pub server: String,
+    // Where the last connection went, for /reconnect
+    pub port: u16,
+    pub tls: bool,
+    // Where typed messages go: a channel we are in, one we were kicked from (see
+    // kicked_from), a nick from /query, or empty for nowhere. Kept over a reconnect so
+    // what is typed meanwhile waits for the rejoin
     pub current_channel: String,
+    // Network name as advertised in RPL_ISUPPORT (NETWORK=...)
+    pub network: String,
+    // Server software and version from RPL_MYINFO, e.g. "solanum-1.0-dev"
+    pub software: String,
+    // Our own user modes without the leading '+', e.g. "iw"
+    pub user_modes: String,
+    pub motd: Vec<String>,
+    pub registered: bool,
+    pub state: ConnectionState,
+    // Our away message while marked away
+    pub away: Option<String>,
+    // Capabilities the server acknowledged
+    pub caps: HashSet<String>,
+    // Offered by the server, in CAP LS (which may span several lines) or later CAP NEW
+    pub caps_offered: Vec<String>,
+    // Channels we are in, keyed by channel::nick_key of the name
+    pub channels: HashMap<String, Channel>,
+    pub users: Users,
+    // TARGMAX from ISUPPORT: command -> targets per line, usize::MAX when unlimited
+    pub targmax: HashMap<String, usize>,
+    // Lengths from ISUPPORT in bytes, the server truncates anything longer
+    pub nicklen: Option<usize>,
+    pub channellen: Option<usize>,
+    pub topiclen: Option<usize>,
+    // The server understands WHO with %fields (WHOX in ISUPPORT)
+    pub whox: bool,
+    // Channels we sent WHO for only to learn about their members, their replies are not
+    // worth showing
+    pub who_quiet: HashSet<String>,
+    // Status symbols from PREFIX in ISUPPORT, highest first, and the modes that set them
+    pub prefix_symbols: String,
+    prefix_modes: String,
+    // Symbols allowed in front of a channel to message only its ops or voiced, from
+    // STATUSMSG in ISUPPORT. Empty when the server has none
+    pub statusmsg: String,
+    // Channel modes that take a parameter, always (lists and keys) or only when set,
+    // from CHANMODES in ISUPPORT. Needed to pair MODE parameters with their modes
+    param_modes: String,
+    set_param_modes: String,
+    // Channels whose NAMES reply is still coming in
+    names_pending: HashSet<String>,
+    // Channels we sent JOIN for, by nick_key. The server's echo makes one the current
+    joining: HashSet<String>,
+    // Channels we were kicked or removed from, by nick_key, until we join them again.
+    // One of them stays current so what is typed is not sent somewhere else
+    kicked: HashSet<String>,
+    // Channels we sent PART for, by nick_key, so our own "requested by" reason is no REMOVE
+    parting: HashSet<String>,
+    // labeled-response: label -> the command it was sent with, until its answer is in
+    labels: HashMap<String, String>,
+    // Open labeled-response batches, batch reference -> label
+    label_batches: HashMap<String, String>,
+    next_label: u64,
+    // USER fields, the nick stands in for those left out
+    pub username: Option<String>,
+    pub realname: Option<String>,
+    // Answer to CTCP VERSION, None sends none
+    pub version: Option<String>,
+    // Where to answer ident queries while connecting, and the responder doing it until
+    // we are registered
+    pub identd_port: Option<u16>,
+    pub identd: Option<Identd>,
+    // Server password, sent as PASS before registering
+    pub password: Option<String>,
+    pub sasl: Option<SaslLogin>,
+    // The server offers SASL and we have an account but no password yet, CAP END is held
+    // back until sasl_password is called
+    pub sasl_waiting: bool,
+    // The nick we asked for while registered under a fallback because it was taken
+    pub wanted_nick: Option<String>,
+    // Fallbacks from the config, tried in order before the generated ones
+    pub alt_nicks: Vec<String>,
+    // Reason sent by quit and disconnect, [quit] message in the config
+    pub quit_message: String,
+    // Fallbacks tried so far this connection, and whether we ran out of them
+    nick_attempts: usize,
+    pub nicks_exhausted: bool,
+    // Lines we sent that the debug buffer has not shown yet, None while it is off
+    pub sent_log: Option<Vec<String>>,
+    // Decodes incoming lines that are not valid UTF-8, shared with the receiver thread
+    fallback: Arc<Mutex<&'static Encoding>>,
+    // [parser] mode = "strict", the receiver thread reports what it repairs
+    strict: Arc<AtomicBool>,
+    // What we send in, UTF-8 unless the network is set to a legacy encoding
+    outbound: &'static Encoding,
 }
 
 // This impl block function like a classes in the rust
@@ -21,147 +195,1097 @@ impl IrcClient {
             stream: None,
             nickname: nickname.to_string(),
             server: String::new(),
+            port: 6667,
+            tls: false,
             current_channel: String::new(),
+            network: String::new(),
+            software: String::new(),
+            user_modes: String::new(),
+            motd: Vec::new(),
+            registered: false,
+            state: ConnectionState::Disconnected,
+            away: None,
+            caps: HashSet::new(),
+            caps_offered: Vec::new(),
+            channels: HashMap::new(),
+            users: Users::new(),
+            targmax: HashMap::new(),
+            nicklen: None,
+            channellen: None,
+            topiclen: None,
+            whox: false,
+            who_quiet: HashSet::new(),
+            prefix_symbols: "@+".to_string(),
+            prefix_modes: "ov".to_string(),
+            statusmsg: String::new(),
+            param_modes: "beIk".to_string(),
+            set_param_modes: "l".to_string(),
+            names_pending: HashSet::new(),
+            joining: HashSet::new(),
+            kicked: HashSet::new(),
+            parting: HashSet::new(),
+            labels: HashMap::new(),
+            label_batches: HashMap::new(),
+            next_label: 0,
+            username: None,
+            realname: None,
+            version: Some(format!("Irconic {}", env!("CARGO_PKG_VERSION"))),
+            identd_port: None,
+            identd: None,
+            password: None,
+            sasl: None,
+            sasl_waiting: false,
+            wanted_nick: None,
+            alt_nicks: Vec::new(),
+            quit_message: "Leaving".to_string(),
+            nick_attempts: 0,
+            nicks_exhausted: false,
+            sent_log: None,
+            fallback: Arc::new(Mutex::new(WINDOWS_1252)),
+            strict: Arc::new(AtomicBool::new(false)),
+            outbound: UTF_8,
         }
     }
 
     // This function is responsible for the connection to the server using a TcpStream or tcpstream
     // socket that constantly connects using the ping and pong in the irc protocol
-    pub fn connect(&mut self, server: &str, port: u16) -> Result<()> {
+    pub fn connect(&mut self, server: &str, port: u16, tls: bool) -> Result<()> {
+        self.prepare_connect()?;
+        // This match condition handles the connection with the given credencials
+        let state = &mut self.state;
+        match Connection::open(server, port, tls, || *state = ConnectionState::TlsHandshake) {
+            Ok(stream) => {
+                self.attach(server, port, tls, stream);
+                Ok(())
+            }
+            // This handles the error if anything fails in the connection this will disconnect
+            // and emit an error that the connection to the server is disconnected
+            Err(e) => {
+                self.connect_failed();
+                Err(e)
+            }
+        }
+    }
+
+    // Leaves the old server and gets ready for a new connection. The TUI opens the
+    // connection on a thread in between and hands it to attach, or calls connect_failed
+    pub fn prepare_connect(&mut self) -> Result<()> {
         if self.stream.is_some() {
             // this checks if it receives the username and pass or some user
             self.disconnect()?;
         }
 
-        let address = format!("{}:{}", server, port); // This creates an address that is accepted by
-        // the irc server
-        // This match condition handles the connection with the given credencials
-        match TcpStream::connect(address) {
-            Ok(mut stream) => {
-                stream
-                    .set_read_timeout(Some(Duration::from_secs(30)))
-                    .map_err(|e| format!("Failed to set read timeout: {}", e))?;
-
-                stream
-                    .set_write_timeout(Some(Duration::from_secs(10)))
-                    .map_err(|e| format!("Failed to set write timeout: {}", e))?;
-
-                self.stream = Some(stream);
-                self.server = server.to_string();
-                Ok(())
-            }
-            Err(e) => Err(format!("Failed to connect: {}", e)), // This handles the error if
-                                                                // anything fails in the connection this will disconnect and emit an error that the
-                                                                // connection to the server is disconnected
+        // Listening before connecting, the server asks as soon as we are there
+        self.identd = self.identd_port.map(|port| {
+            let username = self
+                .username
+                .clone()
+                .unwrap_or_else(|| self.nickname.clone());
+            Identd::start(port, username)
+        });
+        self.state = ConnectionState::Connecting;
+        Ok(())
+    }
+
+    pub fn connect_failed(&mut self) {
+        self.identd = None;
+        self.state = ConnectionState::Disconnected;
+    }
+
+    // Takes over a freshly opened connection, forgetting everything about the last one.
+    // Back on the same server the current channel and the JOINs we sent stay, see
+    // current_channel
+    pub fn attach(&mut self, server: &str, port: u16, tls: bool, stream: Connection) {
+        info!(server, port, tls, "connected");
+        if let (Some(identd), Some((local, remote))) = (&self.identd, stream.ports()) {
+            identd.expect(local, remote);
         }
+        if !self.server.eq_ignore_ascii_case(server) {
+            self.current_channel.clear();
+            self.joining.clear();
+        }
+        self.stream = Some(stream);
+        self.server = server.to_string();
+        self.port = port;
+        self.tls = tls;
+        self.network.clear();
+        self.software.clear();
+        self.parting.clear();
+        self.user_modes.clear();
+        self.motd.clear();
+        self.registered = false;
+        self.away = None;
+        self.caps.clear();
+        self.caps_offered.clear();
+        self.channels.clear();
+        self.kicked.clear();
+        self.users.clear();
+        self.targmax.clear();
+        self.nicklen = None;
+        self.channellen = None;
+        self.topiclen = None;
+        self.whox = false;
+        self.who_quiet.clear();
+        self.prefix_symbols = "@+".to_string();
+        self.prefix_modes = "ov".to_string();
+        self.statusmsg.clear();
+        self.param_modes = "beIk".to_string();
+        self.set_param_modes = "l".to_string();
+        self.names_pending.clear();
+        self.labels.clear();
+        self.label_batches.clear();
+        self.sasl_waiting = false;
+        if let Some(nick) = self.wanted_nick.take() {
+            self.nickname = nick;
+        }
+        self.nick_attempts = 0;
+        self.nicks_exhausted = false;
+    }
+
+    // Our end of the server connection, the address DCC offers point other clients at
+    pub fn local_ip(&self) -> Option<IpAddr> {
+        self.stream.as_ref()?.local_ip()
     }
 
     // This function is used to actually disconnect to the server
+
     pub fn disconnect(&mut self) -> Result<()> {
+        let reason = self.quit_message.clone();
+        self.disconnect_with(&reason);
+        Ok(())
+    }
+
+    // QUIT with `reason`, then closes the connection so the receiver thread ends too
+    pub fn disconnect_with(&mut self, reason: &str) {
         if self.stream.is_some() {
-            let _ = self.quit();
-            self.stream = None;
-            self.current_channel.clear();
+            let _ = self.send_raw(&format!("QUIT :{}\r\n", reason));
         }
-        Ok(())
+        if let Some(stream) = self.stream.take() {
+            stream.shutdown();
+            self.registered = false;
+            // current_channel stays, what is typed there is queued until we rejoin it
+            self.channels.clear();
+            info!(server = %self.server, "disconnected");
+        }
+        self.state = ConnectionState::Disconnected;
+    }
+
+    // The server or the network hung up on us, nothing left to QUIT from
+    pub fn connection_lost(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            stream.shutdown();
+        }
+        self.registered = false;
+        self.channels.clear();
+        self.state = ConnectionState::Disconnected;
+        info!(server = %self.server, "connection lost");
     }
 
     // This function is used to register the user with the given username
     pub fn register(&mut self) -> Result<()> {
-        if let Some(stream) = &mut self.stream {
+        if self.stream.is_some() {
+            // Servers without IRCv3 ignore this, the rest hold registration until CAP END
+            self.send_raw("CAP LS 302\r\n")?;
+            if let Some(password) = self.password.clone() {
+                self.send_raw(&format!("PASS {}\r\n", password))?;
+            }
             self.send_raw(&format!("NICK {}\r\n", self.nickname))?; // In this function this uses
             // the NickServ command to
             // register the user to the
             // server
-            self.send_raw(&format!(
-                "USER {} 0 * :{}\r\n",
-                self.nickname, self.nickname
-            ))?;
+            let username = self
+                .username
+                .clone()
+                .unwrap_or_else(|| self.nickname.clone());
+            let realname = self
+                .realname
+                .clone()
+                .unwrap_or_else(|| self.nickname.clone());
+            self.send_raw(&format!("USER {} 0 * :{}\r\n", username, realname))?;
+            self.state = ConnectionState::Registering;
             Ok(())
         } else {
-            Err("Not connected to server".to_string())
+            Err(tr("Not connected to server").to_string())
         }
     }
 
     // Defines the join channel command
-    pub fn join_channel(&mut self, channel: &str) -> Result<()> {
-        let result = self.send_raw(&format!("JOIN {}\r\n", channel));
+    pub fn join_channel(&mut self, channel: &str, key: Option<&str>) -> Result<()> {
+        let line = match key {
+            Some(key) => format!("JOIN {} {}", channel, key),
+            None => format!("JOIN {}", channel),
+        };
+        let result = self.send_labeled(&line, &format!("/join {}", channel));
         if result.is_ok() {
-            self.current_channel = channel.to_string();
+            self.joining
+                .extend(channel.split(',').map(channel::nick_key));
         }
         result
     }
 
+    // A JOIN for `name` was refused. Returns whether we had asked for it
+    pub fn forget_join(&mut self, name: &str) -> bool {
+        self.joining.remove(&channel::nick_key(name))
+    }
+
+    // A PART with "requested by <kicker> (<reason>)", which is how REMOVE_AS_PART servers
+    // show a REMOVE. Anyone can part with such a reason elsewhere
+    pub fn part_is_removal(&self, msg: &Message) -> bool {
+        let software = self.software.to_ascii_lowercase();
+        msg.command == "PART"
+            && REMOVE_AS_PART.iter().any(|name| software.starts_with(name))
+            && msg
+                .param(1)
+                .is_some_and(|reason| reason.starts_with("requested by "))
+    }
+
+    // Whether we were kicked or removed from `name` and have not joined it since
+    pub fn kicked_from(&self, name: &str) -> bool {
+        self.kicked.contains(&channel::nick_key(name))
+    }
+
     //  This command defines the private message capabilities of the function
     pub fn send_message(&mut self, target: &str, message: &str) -> Result<()> {
         self.send_raw(&format!("PRIVMSG {} :{}\r\n", target, message))
     }
 
+    // One message to several targets, as few PRIVMSGs as TARGMAX allows
+    pub fn send_message_to_all(&mut self, targets: &[&str], message: &str) -> Result<()> {
+        for group in targets.chunks(self.max_targets("PRIVMSG")) {
+            self.send_message(&group.join(","), message)?;
+        }
+        Ok(())
+    }
+
+    // How many comma separated targets `command` may have. Without TARGMAX we cannot know,
+    // so one at a time
+    pub fn max_targets(&self, command: &str) -> usize {
+        self.targmax.get(command).copied().unwrap_or(1).max(1)
+    }
+
+    // Marks us away with `message`, or back with None
+    pub fn set_away(&mut self, message: Option<&str>) -> Result<()> {
+        match message {
+            Some(message) => self.send_raw(&format!("AWAY :{}\r\n", message))?,
+            None => self.send_raw("AWAY\r\n")?,
+        }
+        self.away = message.map(|m| m.to_string());
+        Ok(())
+    }
+
+    pub fn send_notice(&mut self, target: &str, message: &str) -> Result<()> {
+        self.send_raw(&format!("NOTICE {} :{}\r\n", target, message))
+    }
+
+    // Sends a command the user gave, tagged with a label when the server supports
+    // labeled-response so its errors can be traced back to `command`
+    pub fn send_labeled(&mut self, line: &str, command: &str) -> Result<()> {
+        if !self.caps.contains("labeled-response") {
+            return self.send_raw(&format!("{}\r\n", line));
+        }
+        self.next_label += 1;
+        let label = format!("irc{}", self.next_label);
+        self.send_raw(&format!("@label={} {}\r\n", label, line))?;
+        self.labels.insert(label, command.to_string());
+        Ok(())
+    }
+
+    // The command `msg` answers, if it carries one of our labels. The label is forgotten
+    // once its answer is complete: a single reply, or the end of its batch
+    pub fn labeled_command(&mut self, msg: &Message) -> Option<String> {
+        if let Some(label) = msg.tags.get("label") {
+            if msg.command == "BATCH"
+                && let Some(reference) = msg.param(0).and_then(|r| r.strip_prefix('+'))
+            {
+                self.label_batches
+                    .insert(reference.to_string(), label.clone());
+                return self.labels.get(label).cloned();
+            }
+            return self.labels.remove(label);
+        }
+        if msg.command == "BATCH"
+            && let Some(reference) = msg.param(0).and_then(|r| r.strip_prefix('-'))
+        {
+            let label = self.label_batches.remove(reference)?;
+            return self.labels.remove(&label);
+        }
+        let label = self.label_batches.get(msg.tags.get("batch")?)?;
+        self.labels.get(label).cloned()
+    }
+
+    // Switches both directions to `encoding`, e.g. for an old network that only speaks
+    // latin1. Incoming UTF-8 is still accepted as such
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        *self.fallback.lock().unwrap() = encoding;
+        self.outbound = encoding;
+    }
+
+    // Only changes how incoming lines that are not UTF-8 are read
+    pub fn set_fallback(&mut self, encoding: &'static Encoding) {
+        *self.fallback.lock().unwrap() = encoding;
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict.store(strict, Ordering::Relaxed);
+    }
+
+    pub fn encoding_name(&self) -> &'static str {
+        self.outbound.name()
+    }
+
     // This function handles all the messages that can or will be sent through the tcp socket
     pub fn send_raw(&mut self, message: &str) -> Result<()> {
+        // One line at a time, a break inside would smuggle in another command
+        let line = message.strip_suffix("\r\n").unwrap_or(message);
+        if line.contains(['\r', '\n', '\0']) {
+            return Err(tr("Refusing to send a line break or NUL inside a message").to_string());
+        }
+        if let Some(log) = &mut self.sent_log {
+            log.push(message.trim_end().to_string());
+        }
+        if let Some(sent) = Message::parse(line)
+            && sent.command.eq_ignore_ascii_case("PART")
+            && let Some(channels) = sent.param(0)
+        {
+            self.parting
+                .extend(channels.split(',').map(channel::nick_key));
+        }
+        // Only the command, the rest may be a password
+        trace!(
+            command = message.split(' ').next().unwrap_or_default(),
+            "sending"
+        );
+        let (bytes, _, _) = self.outbound.encode(message);
         if let Some(stream) = &mut self.stream {
             stream
-                .write_all(message.as_bytes())
-                .map_err(|e| format!("Failed to send message: {}", e))?;
+                .write_all(&bytes)
+                .map_err(|e| trf("Failed to send message: {}", &[&e]))?;
             stream
                 .flush()
                 .map_err(|e| format!("Failed to flush message: {}", e))?;
             Ok(())
         } else {
-            Err("Not connected to server".to_string())
+            Err(tr("Not connected to server").to_string())
+        }
+    }
+
+    // For replies during negotiation, where a failure has no one to report to and the
+    // receiver notices the broken connection anyway
+    fn send_logged(&mut self, message: &str) {
+        if let Err(e) = self.send_raw(message) {
+            warn!("{}", e);
+        }
+    }
+
+    // This function keeps track of the server state we care about (network name, MOTD and our
+    // own modes) as messages arrive from the receiver thread
+    pub fn handle_message(&mut self, msg: &Message) {
+        // Every message from a user shows where they connect from
+        if let Some(prefix) = &msg.prefix
+            && prefix.contains('!')
+            && msg.command != "QUIT"
+        {
+            self.note_hostmask(prefix);
+        }
+        match msg.command.as_str() {
+            // RPL_WELCOME, the first parameter is the nick the server actually registered
+            "001" => {
+                self.registered = true;
+                self.state = ConnectionState::Ready;
+                if let Some(nick) = msg.param(0) {
+                    self.nickname = nick.to_string();
+                }
+            }
+            // ERR_ERRONEUSNICKNAME or ERR_NICKNAMEINUSE while registering, carry on with
+            // the next fallback and remember the nick we wanted
+            "432" | "433" if !self.registered => {
+                if self.wanted_nick.is_none() {
+                    self.wanted_nick = Some(self.nickname.clone());
+                }
+                debug!(nick = msg.param(1), code = %msg.command, "nick refused while registering");
+                match self.next_nick() {
+                    Some(nick) => {
+                        if self.send_raw(&format!("NICK {}\r\n", nick)).is_ok() {
+                            self.nickname = nick;
+                        }
+                    }
+                    None => {
+                        warn!("ran out of nicks to try");
+                        self.nicks_exhausted = true;
+                    }
+                }
+            }
+            // RPL_MYINFO: <nick> <server> <version> <user modes> <channel modes>
+            "004" => {
+                if let Some(version) = msg.param(2) {
+                    self.software = version.to_string();
+                }
+            }
+            // RPL_ISUPPORT
+            "005" => {
+                for token in msg.params.iter().skip(1) {
+                    if let Some(name) = token.strip_prefix("NETWORK=") {
+                        self.network = name.to_string();
+                    } else if let Some(prefix) = token.strip_prefix("PREFIX=") {
+                        // PREFIX=(qaohv)~&@%+
+                        if let Some((modes, symbols)) = prefix.split_once(')') {
+                            self.prefix_modes = modes.trim_start_matches('(').to_string();
+                            self.prefix_symbols = symbols.to_string();
+                        }
+                    } else if let Some(limits) = token.strip_prefix("TARGMAX=") {
+                        // TARGMAX=PRIVMSG:4,NOTICE:4,JOIN:, an empty limit means none
+                        for limit in limits.split(',') {
+                            if let Some((command, max)) = limit.split_once(':') {
+                                let max = if max.is_empty() {
+                                    usize::MAX
+                                } else {
+                                    max.parse().unwrap_or(1)
+                                };
+                                self.targmax.insert(command.to_ascii_uppercase(), max);
+                            }
+                        }
+                    } else if let Some(symbols) = token.strip_prefix("STATUSMSG=") {
+                        self.statusmsg = symbols.to_string();
+                    } else if token == "WHOX" {
+                        self.whox = true;
+                    } else if let Some(len) = token.strip_prefix("NICKLEN=") {
+                        self.nicklen = len.parse().ok();
+                    } else if let Some(len) = token.strip_prefix("CHANNELLEN=") {
+                        self.channellen = len.parse().ok();
+                    } else if let Some(len) = token.strip_prefix("TOPICLEN=") {
+                        self.topiclen = len.parse().ok();
+                    } else if let Some(chanmodes) = token.strip_prefix("CHANMODES=") {
+                        // CHANMODES=A,B,C,D: lists, always a parameter, only when set, never
+                        let groups: Vec<&str> = chanmodes.split(',').collect();
+                        if groups.len() >= 3 {
+                            self.param_modes = format!("{}{}", groups[0], groups[1]);
+                            self.set_param_modes = groups[2].to_string();
+                        }
+                    }
+                }
+            }
+            // RPL_UMODEIS
+            "221" => {
+                if let Some(modes) = msg.param(1) {
+                    self.user_modes.clear();
+                    self.apply_user_modes(modes);
+                }
+            }
+            // RPL_MOTDSTART and RPL_MOTD
+            "375" => self.motd.clear(),
+            "372" => {
+                if let Some(text) = msg.trailing() {
+                    let text = text.strip_prefix("- ").unwrap_or(text);
+                    self.motd.push(text.to_string());
+                }
+            }
+            // RPL_UNAWAY, also sent when another client of a bouncer comes back
+            "305" => self.away = None,
+            // RPL_NOWAWAY
+            "306" if self.away.is_none() => self.away = Some(String::new()),
+            "CAP" => self.handle_cap(msg),
+            "AUTHENTICATE" if msg.param(0) == Some("+") => self.sasl_authenticate(),
+            // RPL_SASLSUCCESS, ERR_SASLFAIL, ERR_SASLTOOLONG, ERR_SASLABORTED, ERR_SASLALREADY
+            "903" | "904" | "905" | "906" | "907" if !self.registered => {
+                self.state = ConnectionState::Registering;
+                match msg.command.as_str() {
+                    "903" => info!("SASL login succeeded"),
+                    code => warn!(code, reason = msg.trailing(), "SASL login failed"),
+                }
+                self.send_logged("CAP END\r\n");
+            }
+            // RPL_NAMREPLY: <me> <type> <channel> :<names>
+            "353" => {
+                let (Some(name), Some(names)) = (msg.param(2), msg.param(3)) else {
+                    return;
+                };
+                let key = channel::nick_key(name);
+                let Some(chan) = self.channels.get_mut(&key) else {
+                    return;
+                };
+                // A new NAMES reply replaces the whole list
+                if self.names_pending.insert(key) {
+                    chan.members.clear();
+                }
+                let mut seen = Vec::new();
+                for entry in names.split_whitespace() {
+                    let name = entry.trim_start_matches(|c| self.prefix_symbols.contains(c));
+                    let prefix = &entry[..entry.len() - name.len()];
+                    // userhost-in-names sends nick!user@host instead of the bare nick
+                    let nick = name.split('!').next().unwrap_or(name);
+                    chan.add(nick, prefix);
+                    seen.push(name.to_string());
+                }
+                for name in seen {
+                    self.note_hostmask(&name);
+                }
+            }
+            // RPL_TOPIC: <me> <channel> :<topic>, RPL_NOTOPIC, and TOPIC when it changes
+            "332" | "331" | "TOPIC" => {
+                let (name, topic) = match msg.command.as_str() {
+                    "332" => (msg.param(1), msg.param(2)),
+                    "331" => (msg.param(1), None),
+                    _ => (msg.param(0), msg.param(1)),
+                };
+                if let Some(chan) = name.and_then(|n| self.channels.get_mut(&channel::nick_key(n)))
+                {
+                    chan.topic = topic.unwrap_or_default().to_string();
+                }
+            }
+            // RPL_ENDOFNAMES
+            "366" => {
+                if let Some(name) = msg.param(1) {
+                    self.names_pending.remove(&channel::nick_key(name));
+                }
+            }
+            "JOIN" => {
+                let (Some(nick), Some(name)) = (msg.nick(), msg.param(0)) else {
+                    return;
+                };
+                // extended-join: JOIN <channel> <account> :<realname>, "*" when logged out
+                if self.caps.contains("extended-join")
+                    && let Some(account) = msg.param(1)
+                {
+                    self.set_account(nick, account);
+                    if let Some(realname) = msg.param(2) {
+                        let user = self.users.entry(channel::nick_key(nick)).or_default();
+                        user.realname = Some(realname.to_string());
+                    }
+                }
+                let key = channel::nick_key(name);
+                if self.is_me(nick) {
+                    // Joins we did not ask for, e.g. replayed by a bouncer, only become
+                    // current when there is none
+                    self.kicked.remove(&key);
+                    self.parting.remove(&key);
+                    if self.joining.remove(&key) || self.current_channel.is_empty() {
+                        self.current_channel = name.to_string();
+                    }
+                    self.channels.insert(key, Channel::new(name));
+                    // WHO fills in accounts, hosts and the away state of everyone already there
+                    let name = name.to_string();
+                    self.poll_who(&name);
+                } else if let Some(chan) = self.channels.get_mut(&key) {
+                    chan.add(nick, "");
+                }
+            }
+            "PART" => {
+                let (Some(nick), Some(name)) = (msg.nick(), msg.param(0)) else {
+                    return;
+                };
+                let nick = nick.to_string();
+                // REMOVE on charybdis and friends looks like this to the one removed, unless
+                // we asked to leave
+                let asked = self.is_me(&nick) && self.parting.remove(&channel::nick_key(name));
+                if self.is_me(&nick) && !asked && self.part_is_removal(msg) {
+                    self.kicked.insert(channel::nick_key(name));
+                }
+                self.leave(name, &nick);
+            }
+            "KICK" | "REMOVE" => {
+                if let (Some(name), Some(nick)) = (msg.param(0), msg.param(1)) {
+                    let nick = nick.to_string();
+                    if self.is_me(&nick) {
+                        self.kicked.insert(channel::nick_key(name));
+                    }
+                    self.leave(name, &nick);
+                }
+            }
+            "QUIT" => {
+                if let Some(nick) = msg.nick() {
+                    for chan in self.channels.values_mut() {
+                        chan.remove(nick);
+                    }
+                    self.users.remove(&channel::nick_key(nick));
+                }
+            }
+            "NICK" => {
+                if let (Some(old), Some(new)) = (msg.nick(), msg.param(0)) {
+                    if self.is_me(old) {
+                        self.nickname = new.to_string();
+                        if self.wanted_nick.as_deref().is_some_and(|w| self.is_me(w)) {
+                            self.wanted_nick = None;
+                        }
+                    } else if self.current_channel.eq_ignore_ascii_case(old) {
+                        // The query follows them
+                        self.current_channel = new.to_string();
+                    }
+                    for chan in self.channels.values_mut() {
+                        chan.rename(old, new);
+                    }
+                    if let Some(user) = self.users.remove(&channel::nick_key(old)) {
+                        self.users.insert(channel::nick_key(new), user);
+                    }
+                }
+            }
+            // away-notify: AWAY with a message means gone, without one means back
+            "AWAY" => {
+                if let Some(nick) = msg.nick() {
+                    let user = self.users.entry(channel::nick_key(nick)).or_default();
+                    user.away_message = msg.param(0).map(|m| m.to_string());
+                    user.away = user.away_message.is_some();
+                }
+            }
+            // account-notify, sent when someone logs in or out of services
+            "ACCOUNT" => {
+                if let (Some(nick), Some(account)) = (msg.nick(), msg.param(0)) {
+                    let nick = nick.to_string();
+                    self.set_account(&nick, account);
+                }
+            }
+            // RPL_WHOISACCOUNT: <me> <nick> <account> :is logged in as
+            "330" => {
+                if let (Some(nick), Some(account)) = (msg.param(1), msg.param(2)) {
+                    let nick = nick.to_string();
+                    self.set_account(&nick, account);
+                }
+            }
+            // RPL_AWAY, the reply to messaging someone who is away
+            "301" => {
+                if let (Some(nick), Some(text)) = (msg.param(1), msg.param(2)) {
+                    let user = self.users.entry(channel::nick_key(nick)).or_default();
+                    user.away = true;
+                    user.away_message = Some(text.to_string());
+                }
+            }
+            // RPL_WHOREPLY: <me> <channel> <user> <host> <server> <nick> <flags> :<hops> <real>
+            "352" => {
+                let (Some(name), Some(nick), Some(flags)) =
+                    (msg.param(1), msg.param(5), msg.param(6))
+                else {
+                    return;
+                };
+                let user = self.users.entry(channel::nick_key(nick)).or_default();
+                user.away = flags.starts_with('G');
+                if let (Some(ident), Some(host)) = (msg.param(2), msg.param(3)) {
+                    user.userhost = Some(format!("{}@{}", ident, host));
+                }
+                if let Some((_, realname)) = msg.param(7).and_then(|p| p.split_once(' ')) {
+                    user.realname = Some(realname.to_string());
+                }
+                self.member_status(name, nick, flags);
+            }
+            // RPL_WHOSPCRPL for our WHO_FIELDS:
+            // <me> <token> <channel> <user> <host> <nick> <flags> <account> :<realname>
+            "354" if msg.param(1) == Some(WHOX_TOKEN) => {
+                let (Some(name), Some(nick), Some(flags)) =
+                    (msg.param(2), msg.param(5), msg.param(6))
+                else {
+                    return;
+                };
+                let nick = nick.to_string();
+                if let Some(account) = msg.param(7) {
+                    self.set_account(&nick, account);
+                }
+                let user = self.users.entry(channel::nick_key(&nick)).or_default();
+                user.away = flags.starts_with('G');
+                if let (Some(ident), Some(host)) = (msg.param(3), msg.param(4)) {
+                    user.userhost = Some(format!("{}@{}", ident, host));
+                }
+                user.realname = msg.param(8).map(|r| r.to_string());
+                self.member_status(name, &nick, flags);
+            }
+            "MODE" if msg.param(0).is_some_and(|target| self.is_me(target)) => {
+                if let Some(modes) = msg.param(1) {
+                    self.apply_user_modes(modes);
+                }
+            }
+            "MODE" => {
+                if let (Some(name), Some(modes)) = (msg.param(0), msg.param(1)) {
+                    let args: Vec<&str> = msg.params.iter().skip(2).map(|a| a.as_str()).collect();
+                    self.apply_channel_modes(name, modes, &args);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The configured fallbacks, then nick_, nick__ and numbered ones, up to MAX_NICK_ATTEMPTS
+    fn next_nick(&mut self) -> Option<String> {
+        let wanted = self
+            .wanted_nick
+            .clone()
+            .unwrap_or_else(|| self.nickname.clone());
+        let attempt = self.nick_attempts;
+        if attempt >= MAX_NICK_ATTEMPTS {
+            return None;
+        }
+        self.nick_attempts += 1;
+        Some(match attempt.checked_sub(self.alt_nicks.len()) {
+            None => self.alt_nicks[attempt].clone(),
+            Some(0) => format!("{}_", wanted),
+            Some(1) => format!("{}__", wanted),
+            Some(n) => format!("{}{}", wanted, n),
+        })
+    }
+
+    pub fn is_me(&self, nick: &str) -> bool {
+        nick.eq_ignore_ascii_case(&self.nickname)
+    }
+
+    // The services account `nick` is known to be logged into
+    pub fn account(&self, nick: &str) -> Option<&str> {
+        self.users
+            .get(&channel::nick_key(nick))
+            .and_then(|u| u.account.as_deref())
+    }
+
+    // Why `nick` is away, "" when they are without saying why. None when they are not, or
+    // we have not heard. 301 and away-notify tell us
+    pub fn away_message(&self, nick: &str) -> Option<&str> {
+        let user = self.users.get(&channel::nick_key(nick))?;
+        user.away
+            .then(|| user.away_message.as_deref().unwrap_or_default())
+    }
+
+    pub fn realname(&self, nick: &str) -> Option<&str> {
+        self.users
+            .get(&channel::nick_key(nick))
+            .and_then(|u| u.realname.as_deref())
+    }
+
+    // nick!user@host for `nick`, when we have seen where they connect from
+    pub fn hostmask(&self, nick: &str) -> Option<String> {
+        let user = self.users.get(&channel::nick_key(nick))?;
+        let userhost = user.userhost.as_ref()?;
+        Some(format!("{}!{}", nick, userhost))
+    }
+
+    // Remembers user@host from a full nick!user@host
+    fn note_hostmask(&mut self, hostmask: &str) {
+        if let Some((nick, userhost)) = hostmask.split_once('!')
+            && userhost.contains('@')
+        {
+            let user = self.users.entry(channel::nick_key(nick)).or_default();
+            user.userhost = Some(userhost.to_string());
+        }
+    }
+
+    fn set_account(&mut self, nick: &str, account: &str) {
+        let user = self.users.entry(channel::nick_key(nick)).or_default();
+        user.account = match account {
+            "*" | "0" => None,
+            account => Some(account.to_string()),
+        };
+    }
+
+    // Status prefixes from a WHO reply's flags, e.g. "H@" or "G*+"
+    fn member_status(&mut self, name: &str, nick: &str, flags: &str) {
+        if let Some(chan) = self.channels.get_mut(&channel::nick_key(name)) {
+            let prefix: String = flags
+                .chars()
+                .filter(|c| self.prefix_symbols.contains(*c))
+                .collect();
+            chan.add(nick, &prefix);
+        }
+    }
+
+    // WHO for `name`, asking for accounts and realnames too when the server has WHOX
+    pub fn who_query(&self, name: &str) -> String {
+        if self.whox {
+            format!("WHO {} {},{}", name, WHO_FIELDS, WHOX_TOKEN)
+        } else {
+            format!("WHO {}", name)
+        }
+    }
+
+    // WHO whose replies only update what we know about the members of `name`
+    pub fn poll_who(&mut self, name: &str) {
+        self.who_quiet.insert(channel::nick_key(name));
+        let query = self.who_query(name);
+        self.send_logged(&format!("{}\r\n", query));
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.get(&channel::nick_key(name))
+    }
+
+    // `nick` said something in `name`, for the nick list sorted by activity
+    pub fn spoke(&mut self, name: &str, nick: &str) {
+        if let Some(chan) = self.channels.get_mut(&channel::nick_key(name)) {
+            chan.spoke(nick);
+        }
+    }
+
+    // `nick` left `name`, by parting or being kicked
+    fn leave(&mut self, name: &str, nick: &str) {
+        let key = channel::nick_key(name);
+        if self.is_me(nick) {
+            self.channels.remove(&key);
+            // Typing goes on in another channel we are still in, unless we were thrown out
+            if channel::nick_key(&self.current_channel) == key && !self.kicked.contains(&key) {
+                self.current_channel = self
+                    .channels
+                    .values()
+                    .map(|c| c.name.clone())
+                    .min()
+                    .unwrap_or_default();
+            }
+        } else if let Some(chan) = self.channels.get_mut(&key) {
+            chan.remove(nick);
+        }
+    }
+
+    // CAP LS / ACK / NAK during registration, which only finishes after CAP END. Later,
+    // cap-notify brings CAP NEW and DEL when the server's capabilities change
+    fn handle_cap(&mut self, msg: &Message) {
+        let subcommand = msg.param(1).unwrap_or("");
+        debug!(subcommand, caps = msg.trailing(), "CAP");
+        match subcommand {
+            "LS" => {
+                // "CAP * LS * :caps" means more lines follow
+                let more = msg.params.len() > 3 && msg.param(2) == Some("*");
+                self.note_offered(msg.trailing().unwrap_or(""));
+                if more {
+                    return;
+                }
+                if self.offered("sasl") && self.sasl.as_ref().is_some_and(|s| s.password.is_none())
+                {
+                    self.sasl_waiting = true;
+                    self.state = ConnectionState::Authenticating;
+                    return;
+                }
+                if !self.request_wanted() {
+                    self.send_logged("CAP END\r\n");
+                }
+            }
+            "NEW" => {
+                self.note_offered(msg.trailing().unwrap_or(""));
+                self.request_wanted();
+            }
+            "DEL" => {
+                for cap in msg.trailing().unwrap_or("").split_whitespace() {
+                    self.caps.remove(cap);
+                    self.caps_offered.retain(|c| c != cap);
+                }
+            }
+            "ACK" => {
+                for cap in msg.trailing().unwrap_or("").split_whitespace() {
+                    match cap.strip_prefix('-') {
+                        Some(removed) => {
+                            self.caps.remove(removed);
+                        }
+                        None => {
+                            self.caps.insert(cap.to_string());
+                        }
+                    }
+                }
+                if self.registered {
+                    return;
+                }
+                // SASL runs before CAP END, which comes with its result (903 or 904)
+                if self.caps.contains("sasl") && self.sasl.is_some() {
+                    self.state = ConnectionState::Authenticating;
+                    self.send_logged("AUTHENTICATE PLAIN\r\n");
+                } else {
+                    self.send_logged("CAP END\r\n");
+                }
+            }
+            "NAK" if !self.registered => {
+                self.send_logged("CAP END\r\n");
+            }
+            _ => {}
+        }
+    }
+
+    fn offered(&self, cap: &str) -> bool {
+        self.caps_offered.iter().any(|c| c == cap)
+    }
+
+    // The password for a held SASL login, or None to register without SASL
+    pub fn sasl_password(&mut self, password: Option<String>) {
+        match (password, &mut self.sasl) {
+            (Some(password), Some(sasl)) => sasl.password = Some(password),
+            _ => self.sasl = None,
+        }
+        self.sasl_waiting = false;
+        self.state = ConnectionState::Registering;
+        if !self.request_wanted() {
+            self.send_logged("CAP END\r\n");
+        }
+    }
+
+    // AUTHENTICATE + from the server asks for our PLAIN credentials: authzid, authcid
+    // and password separated by NUL, base64 encoded and sent in chunks of 400
+    fn sasl_authenticate(&mut self) {
+        let Some(SaslLogin {
+            username,
+            password: Some(password),
+        }) = &self.sasl
+        else {
+            self.send_logged("AUTHENTICATE *\r\n");
+            return;
+        };
+        let encoded = base64(format!("{}\0{}\0{}", username, username, password).as_bytes());
+        let mut chunks: Vec<&str> = Vec::new();
+        let mut rest = encoded.as_str();
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(rest.len().min(400));
+            chunks.push(chunk);
+            rest = tail;
+        }
+        // A final chunk of exactly 400 bytes has to be followed by an empty one
+        if encoded.len().is_multiple_of(400) {
+            chunks.push("+");
+        }
+        for chunk in chunks {
+            self.send_logged(&format!("AUTHENTICATE {}\r\n", chunk));
+        }
+    }
+
+    fn note_offered(&mut self, caps: &str) {
+        for cap in caps.split_whitespace() {
+            // 302 servers may attach values, e.g. sasl=PLAIN
+            let name = cap.split('=').next().unwrap_or(cap);
+            if !self.caps_offered.iter().any(|c| c == name) {
+                self.caps_offered.push(name.to_string());
+            }
+        }
+    }
+
+    // Asks for whatever we want that is offered but not enabled yet. Returns false when
+    // there was nothing to ask for
+    fn request_wanted(&mut self) -> bool {
+        // SASL only once we have the whole login
+        let sasl = self.sasl.as_ref().is_some_and(|s| s.password.is_some());
+        let wanted: Vec<&str> = WANTED_CAPS
+            .iter()
+            .copied()
+            .chain(sasl.then_some("sasl"))
+            .filter(|cap| !self.caps.contains(*cap))
+            .filter(|cap| self.caps_offered.iter().any(|c| c == cap))
+            .collect();
+        if wanted.is_empty() {
+            return false;
+        }
+        self.send_logged(&format!("CAP REQ :{}\r\n", wanted.join(" ")));
+        true
+    }
+
+    fn apply_user_modes(&mut self, changes: &str) {
+        let mut adding = true;
+        for mode in changes.chars() {
+            match mode {
+                '+' => adding = true,
+                '-' => adding = false,
+                _ if adding => {
+                    if !self.user_modes.contains(mode) {
+                        self.user_modes.push(mode);
+                    }
+                }
+                _ => self.user_modes.retain(|m| m != mode),
+            }
+        }
+    }
+
+    // Follows status changes (+o, -v, ...) in a channel MODE. Other modes only matter for
+    // skipping over their parameters
+    fn apply_channel_modes(&mut self, name: &str, changes: &str, args: &[&str]) {
+        let Some(chan) = self.channels.get_mut(&channel::nick_key(name)) else {
+            return;
+        };
+        let mut args = args.iter();
+        let mut adding = true;
+        for mode in changes.chars() {
+            match mode {
+                '+' => adding = true,
+                '-' => adding = false,
+                _ => {
+                    if let Some(rank) = self.prefix_modes.find(mode) {
+                        let symbol = self.prefix_symbols.chars().nth(rank);
+                        if let (Some(nick), Some(symbol)) = (args.next(), symbol) {
+                            chan.set_status(nick, symbol, adding, &self.prefix_symbols);
+                        }
+                    } else if self.param_modes.contains(mode)
+                        || (adding && self.set_param_modes.contains(mode))
+                    {
+                        args.next();
+                    }
+                }
+            }
         }
     }
 
     // This functon is used to turn on the receiver_loop to retrieve the messages from the server
     pub fn start_receiver(&mut self, tx: Sender<String>) -> Result<JoinHandle<()>> {
         if let Some(stream) = &self.stream {
-            let stream_clone = stream
-                .try_clone()
-                .map_err(|e| format!("Failed to clone stream: {}", e))?;
-            let nickname = self.nickname.clone();
+            let stream_clone = stream.clone();
+            let fallback = Arc::clone(&self.fallback);
+            let strict = Arc::clone(&self.strict);
 
             let handle = thread::spawn(move || {
-                Self::receiver_loop(stream_clone, tx, nickname);
+                Self::receiver_loop(stream_clone, tx, fallback, strict);
             });
 
             Ok(handle)
         } else {
-            Err("Not connected to server".to_string())
+            Err(tr("Not connected to server").to_string())
         }
     }
 
-    fn receiver_loop(mut stream: TcpStream, tx: Sender<String>, nickname: String) {
-        let mut pong_stream = match stream.try_clone() {
-            Ok(clone) => clone,
-            Err(e) => {
-                let _ = tx.send(format!("Error: Failed to clone stream for PONG: {}", e));
-                return;
-            }
-        };
+    fn receiver_loop(
+        mut stream: Connection,
+        tx: Sender<String>,
+        fallback: Arc<Mutex<&'static Encoding>>,
+        strict: Arc<AtomicBool>,
+    ) {
+        let mut pong_stream = stream.clone();
 
         let mut buffer = [0; 512];
-        let mut read_buffer = String::new();
+        // Raw bytes, lines are only decoded once complete since a multi-byte character
+        // may be split across reads
+        let mut read_buffer: Vec<u8> = Vec::new();
 
         loop {
             match stream.read(&mut buffer) {
                 Ok(0) => break, // Connection closed
                 Ok(n) => {
-                    read_buffer.push_str(&String::from_utf8_lossy(&buffer[..n]));
+                    read_buffer.extend_from_slice(&buffer[..n]);
 
-                    while let Some(pos) = read_buffer.find("\r\n") {
-                        let line = read_buffer[..pos].to_string();
-                        read_buffer.drain(..pos + 2);
+                    // Lines end in CRLF, a bare LF is taken as the end of one too
+                    while let Some(pos) = read_buffer.iter().position(|&b| b == b'\n') {
+                        let mut raw: Vec<u8> = read_buffer.drain(..=pos).collect();
+                        raw.pop();
+                        let crlf = raw.last() == Some(&b'\r');
+                        if crlf {
+                            raw.pop();
+                        }
+                        let strict = strict.load(Ordering::Relaxed);
+                        let (mut report, shown) = if strict {
+                            (byte_violations(&raw, crlf), escape_bytes(&raw))
+                        } else {
+                            (Vec::new(), String::new())
+                        };
+                        // A NUL ends the line for most servers and C clients
+                        raw.retain(|&b| b != 0);
+                        let encoding = *fallback.lock().unwrap();
+                        let line = decode_line(&raw, encoding);
+                        if strict {
+                            report.extend(message::violations(&line));
+                        }
 
-                        if let Some(processed) =
-                            Self::process_message(&line, &mut pong_stream, &nickname)
+                        if let Some(processed) = Self::process_message(&line, &mut pong_stream)
+                            && tx.send(processed).is_err()
                         {
-                            if tx.send(processed).is_err() {
-                                break;
-                            }
+                            break;
+                        }
+                        if !report.is_empty() {
+                            let _ = tx.send(format!(
+                                "{}{} | {}",
+                                PROTOCOL_VIOLATION,
+                                report.join(", "),
+                                shown
+                            ));
                         }
                     }
                 }
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                // A signal got in the way, e.g. a sound player we started exiting
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // TLS reads time out often so writes get a turn at the session
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
                     thread::sleep(Duration::from_millis(100));
                     continue;
                 }
@@ -172,20 +1296,25 @@ impl IrcClient {
                     break;
                 }
                 Err(e) => {
+                    warn!("reading from the server: {}", e);
                     let _ = tx.send(format!("Error reading from server: {}", e));
                     break;
                 }
             }
         }
 
-        let _ = tx.send("Connection to server closed.".to_string());
+        // After our own disconnect the main loop knows already, and may be on to the next
+        // connection
+        if !stream.closed_by_us() {
+            info!("connection to the server closed");
+            let _ = tx.send(CONNECTION_CLOSED.to_string());
+        }
     }
 
     // This function is responsible for handling ping and pong replies and to not drop the
-    // connection
-    fn process_message(msg: &str, stream: &mut TcpStream, nickname: &str) -> Option<String> {
-        if msg.starts_with("ping") {
-            let pong = msg.replace("PING", "PONG");
+    // connection. Everything else goes to the App as it came
+    fn process_message(msg: &str, stream: &mut Connection) -> Option<String> {
+        if let Some(pong) = pong_reply(msg) {
             if let Err(e) = stream.write_all(format!("{}\r\n", pong).as_bytes()) {
                 return Some(format!("Failed to send PONG: {}", e));
             }
@@ -195,35 +1324,22 @@ impl IrcClient {
             return Some(format!(">>> Server ping: {}", msg));
         }
 
-        // This block is responsible for the highlighting of a NickServ message that might be
-        // important and make it more readable
-        if msg.contains("NickServ") || msg.contains("nickserv") {
-            let parts: Vec<&str> = msg.splitn(4, ' ').collect();
-            if parts.len() >= 4 {
-                let sender = parts[0].trim_start_matches(':');
-                let command = parts[1];
-                let target = parts[2];
-
-                if (command == "NOTICE" || command == "PRIVMSG")
-                    && target == nickname
-                    && (sender.contains("NickServ") || sender.ends_with("!NickServ@services"))
-                {
-                    return Some(format!("!!! NICKSERV: {}", msg));
-                }
-            }
-        }
-
         Some(msg.to_string())
     }
 
     // This function is reponsible for the propper dropping of the tcp socket
     pub fn quit(&mut self) -> Result<()> {
+        let reason = self.quit_message.clone();
+        self.quit_with(&reason)
+    }
+
+    pub fn quit_with(&mut self, reason: &str) -> Result<()> {
         if let Some(stream) = &mut self.stream {
-            let _ = stream.write_all(b"QUIT :Leaving\r\n");
+            let _ = stream.write_all(format!("QUIT :{}\r\n", reason).as_bytes());
             let _ = stream.flush();
             Ok(())
         } else {
-            Err("Not connected to server".to_string())
+            Err(tr("Not connected to server").to_string())
         }
     }
 }
@@ -233,3 +1349,264 @@ impl Drop for IrcClient {
         let _ = self.quit();
     }
 }
+
+// The PONG for a PING, None for any other line. The token, the last parameter, goes back
+// exactly as it came: "PING :irc.libera.chat", "PING 12345" or ":irc.server PING nick :LAG1"
+fn pong_reply(line: &str) -> Option<String> {
+    let msg = Message::parse(line)?;
+    if msg.command != "PING" {
+        return None;
+    }
+    Some(match msg.trailing() {
+        Some(token) => format!("PONG :{}", token),
+        None => "PONG".to_string(),
+    })
+}
+
+// Lines are tried as UTF-8 first, most of any network is, and read with `fallback`
+// (latin1, cp1252, ...) when they are not
+fn decode_line(bytes: &[u8], fallback: &'static Encoding) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(line) => line.to_string(),
+        Err(_) => fallback.decode_without_bom_handling(bytes).0.into_owned(),
+    }
+}
+
+// What the receiver repairs in a line before decoding it, for strict parser mode
+fn byte_violations(raw: &[u8], crlf: bool) -> Vec<String> {
+    let mut found = Vec::new();
+    if !crlf {
+        found.push("ends in LF, not CRLF".to_string());
+    }
+    if let Some(pos) = raw.iter().position(|&b| b == 0) {
+        found.push(format!("NUL at byte {}", pos));
+    }
+    if let Err(e) = std::str::from_utf8(raw) {
+        let start = e.valid_up_to();
+        let end = start + e.error_len().unwrap_or(raw.len() - start);
+        let bytes: Vec<String> = raw[start..end]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        found.push(format!(
+            "not UTF-8 at byte {} ({}), read as the fallback encoding",
+            start,
+            bytes.join(" ")
+        ));
+    }
+    found
+}
+
+// The line as text with the bytes that are not UTF-8, and control characters that
+// would upset the terminal, written as \xNN
+fn escape_bytes(raw: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in raw.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\0'..='\x1f' | '\x7f' => out.push_str(&format!("\\x{:02x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    out
+}
+
+// Standard base64 with padding, for SASL and FiSH
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IrcClient, pong_reply};
+    use crate::message::Message;
+    use crate::transport::Connection;
+    use std::net::TcpListener;
+
+    // A connection to a listener of our own, nothing is read from it
+    fn reconnect(client: &mut IrcClient, server: &str) -> TcpListener {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = Connection::open("127.0.0.1", port, false, || {}).unwrap();
+        client.attach(server, port, false, stream);
+        listener
+    }
+
+    fn receive(client: &mut IrcClient, line: &str) {
+        client.handle_message(&Message::parse(line).unwrap());
+    }
+
+    #[test]
+    fn reconnect_keeps_the_current_channel_and_pending_joins() {
+        let mut client = IrcClient::new("tester");
+        let _first = reconnect(&mut client, "localhost");
+        receive(&mut client, ":tester!u@h JOIN #rust");
+        client.joining.insert("#go".to_string());
+        assert_eq!(client.current_channel, "#rust");
+
+        client.connection_lost();
+        let _second = reconnect(&mut client, "localhost");
+        assert_eq!(client.current_channel, "#rust");
+        // The rejoin of another channel does not take over
+        receive(&mut client, ":tester!u@h JOIN #irconic");
+        assert_eq!(client.current_channel, "#rust");
+        // A JOIN we sent before the connection dropped still does
+        receive(&mut client, ":tester!u@h JOIN #go");
+        assert_eq!(client.current_channel, "#go");
+    }
+
+    #[test]
+    fn only_remove_as_part_servers_remove_with_a_part() {
+        let mut client = IrcClient::new("tester");
+        let _connection = reconnect(&mut client, "localhost");
+        receive(
+            &mut client,
+            ":irc.example.net 004 tester irc.example.net solanum-1.0-dev",
+        );
+        receive(&mut client, ":tester!u@h JOIN #rust");
+        receive(&mut client, ":tester!u@h JOIN #go");
+        receive(
+            &mut client,
+            ":tester!u@h PART #rust :requested by ChanServ (flooding)",
+        );
+        assert!(client.kicked_from("#rust"));
+        // Our own /part with such a reason is no removal
+        client
+            .send_raw("PART #go :requested by my boss\r\n")
+            .unwrap();
+        receive(&mut client, ":tester!u@h PART #go :requested by my boss");
+        assert!(!client.kicked_from("#go"));
+
+        receive(
+            &mut client,
+            ":irc.example.net 004 tester irc.example.net InspIRCd-3",
+        );
+        receive(&mut client, ":tester!u@h JOIN #rust");
+        receive(
+            &mut client,
+            ":tester!u@h PART #rust :requested by ChanServ (flooding)",
+        );
+        assert!(!client.kicked_from("#rust"));
+    }
+
+    #[test]
+    fn user_modes_follow_our_nick_in_any_case() {
+        let mut client = IrcClient::new("Tester");
+        receive(&mut client, ":tester MODE tester :+iw");
+        receive(&mut client, ":TESTER MODE TESTER :-w");
+        assert_eq!(client.user_modes, "i");
+    }
+
+    #[test]
+    fn another_server_starts_without_a_current_channel() {
+        let mut client = IrcClient::new("tester");
+        let _first = reconnect(&mut client, "localhost");
+        receive(&mut client, ":tester!u@h JOIN #rust");
+        client.joining.insert("#go".to_string());
+
+        client.connection_lost();
+        let _second = reconnect(&mut client, "127.0.0.1");
+        assert_eq!(client.current_channel, "");
+        assert!(client.joining.is_empty());
+    }
+
+    #[test]
+    fn pong_echoes_the_token() {
+        // Solanum and InspIRCd
+        assert_eq!(
+            pong_reply("PING :irc.libera.chat").as_deref(),
+            Some("PONG :irc.libera.chat")
+        );
+        // UnrealIRCd and ngIRCd without the colon
+        assert_eq!(
+            pong_reply("PING irc.example.net").as_deref(),
+            Some("PONG :irc.example.net")
+        );
+        // Numeric tokens, e.g. from ircu and bouncers
+        assert_eq!(
+            pong_reply("PING :1700000000123").as_deref(),
+            Some("PONG :1700000000123")
+        );
+        assert_eq!(
+            pong_reply("PING :TIMEOUTCHECK").as_deref(),
+            Some("PONG :TIMEOUTCHECK")
+        );
+    }
+
+    #[test]
+    fn pong_takes_the_last_of_several_parameters() {
+        assert_eq!(
+            pong_reply(":irc.server PING tester :irc.server").as_deref(),
+            Some("PONG :irc.server")
+        );
+        assert_eq!(
+            pong_reply("PING irc.a.net irc.b.net").as_deref(),
+            Some("PONG :irc.b.net")
+        );
+    }
+
+    #[test]
+    fn pong_keeps_the_token_exact() {
+        assert_eq!(
+            pong_reply("PING :token with spaces").as_deref(),
+            Some("PONG :token with spaces")
+        );
+        assert_eq!(pong_reply("PING ::odd").as_deref(), Some("PONG ::odd"));
+        assert_eq!(
+            pong_reply("PING :PING-PING").as_deref(),
+            Some("PONG :PING-PING")
+        );
+        assert_eq!(pong_reply("PING :").as_deref(), Some("PONG :"));
+        assert_eq!(pong_reply("PING").as_deref(), Some("PONG"));
+    }
+
+    #[test]
+    fn pong_reads_tags_and_case() {
+        assert_eq!(
+            pong_reply("@time=2024-01-01T00:00:00.000Z PING :znc").as_deref(),
+            Some("PONG :znc")
+        );
+        assert_eq!(
+            pong_reply("ping :lowercase").as_deref(),
+            Some("PONG :lowercase")
+        );
+        assert_eq!(pong_reply("PING :x\r\n").as_deref(), Some("PONG :x"));
+    }
+
+    #[test]
+    fn pong_ignores_other_lines_that_mention_ping() {
+        assert_eq!(pong_reply(":bob!u@h PRIVMSG #chan :PING me"), None);
+        assert_eq!(
+            pong_reply(":bob!u@h PRIVMSG tester :\x01PING 123\x01"),
+            None
+        );
+        assert_eq!(
+            pong_reply(":irc.server NOTICE * :*** If you have problems, /quote PONG 1234"),
+            None
+        );
+        assert_eq!(pong_reply(":irc.server PONG irc.server :LAG1"), None);
+        assert_eq!(pong_reply("PINGS :x"), None);
+        assert_eq!(pong_reply(""), None);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::app::App;
+use crate::i18n::{tr, trf};
+use crate::servers::Target;
+
+type Result<T> = std::result::Result<T, String>;
+
+// An irc:// or ircs:// link, e.g. ircs://irc.libera.chat:6697/#rust or
+// irc://irc.example.org/secret?key=hunter2
+pub struct IrcUrl {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub channel: Option<String>,
+    pub key: Option<String>,
+}
+
+// Flags some links put after the target, e.g. irc://host/rust,needkey
+const FLAGS: [&str; 5] = ["isnick", "ischannel", "isserver", "needkey", "needpass"];
+
+pub fn is_url(text: &str) -> bool {
+    text.starts_with("irc://") || text.starts_with("ircs://")
+}
+
+// %23 and friends, links often carry the # of a channel that way
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub fn parse(url: &str) -> Result<IrcUrl> {
+    let (tls, rest) = match url.split_once("://") {
+        Some(("irc", rest)) => (false, rest),
+        Some(("ircs", rest)) => (true, rest),
+        _ => return Err(trf("'{}' is not an irc:// or ircs:// link", &[&url])),
+    };
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    // user@host is allowed but says nothing we use
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let (host, port) = match authority.strip_prefix('[') {
+        // [2001:db8::1]:6697
+        Some(v6) => {
+            let (host, after) = v6
+                .split_once(']')
+                .ok_or_else(|| trf("Unclosed [ in '{}'", &[&url]))?;
+            (host, after.strip_prefix(':'))
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    if host.is_empty() {
+        return Err(trf("No server in '{}'", &[&url]));
+    }
+    let port = match port.filter(|port| !port.is_empty()) {
+        Some(port) => port
+            .parse()
+            .map_err(|_| trf("'{}' is not a port", &[&port]))?,
+        None if tls => 6697,
+        None => 6667,
+    };
+
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let mut key = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("key="))
+        .map(decode);
+    let mut parts = path.split(',');
+    let target = decode(parts.next().unwrap_or_default());
+    let mut is_nick = false;
+    for part in parts {
+        if part == "isnick" {
+            is_nick = true;
+        } else if !FLAGS.contains(&part) && key.is_none() {
+            key = Some(decode(part));
+        }
+    }
+    let channel = match target.as_str() {
+        "" => None,
+        _ if is_nick => return Err(trf("'{}' points at a nick, not a channel", &[&url])),
+        target if target.starts_with(['#', '&']) => Some(target.to_string()),
+        target => Some(format!("#{}", target)),
+    };
+    Ok(IrcUrl {
+        host: host.to_string(),
+        port,
+        tls,
+        channel,
+        key: key.filter(|key| !key.is_empty()),
+    })
+}
+
+impl IrcUrl {
+    // What /join takes, "#channel [key]"
+    pub fn join_args(&self) -> Option<String> {
+        let channel = self.channel.as_ref()?;
+        Some(match &self.key {
+            Some(key) => format!("{} {}", channel, key),
+            None => channel.clone(),
+        })
+    }
+}
+
+impl App {
+    // /open <url>, or the newest irc:// link in the buffer without one. Joins the channel
+    // right away when we are on that server, else connects there and joins after 001
+    pub(crate) fn open_command(&mut self, args: &str) {
+        let url = match args {
+            "" => self.buffers.active().lines.iter().rev().find_map(|line| {
+                line.text
+                    .split_whitespace()
+                    .find(|word| is_url(word))
+                    .map(|word| word.trim_end_matches(['.', ',', ')', '>']).to_string())
+            }),
+            url => Some(url.to_string()),
+        };
+        let Some(url) = url else {
+            self.buffers
+                .push_local(tr("No irc:// link here, use /open <url>").to_string());
+            return;
+        };
+        match parse(&url) {
+            Ok(url) => self.open_url(url),
+            Err(e) => self.buffers.push_local(e),
+        }
+    }
+
+    pub(crate) fn open_url(&mut self, url: IrcUrl) {
+        let here = self.client.stream.is_some()
+            && self.client.server.eq_ignore_ascii_case(&url.host)
+            && self.client.port == url.port;
+        let join = url.join_args();
+        if here && self.client.registered {
+            match join {
+                Some(args) => self.join(&args),
+                None => self
+                    .buffers
+                    .server_mut()
+                    .push(trf("Already connected to {}", &[&url.host])),
+            }
+            return;
+        }
+        // Joined by the 001 handler once we are registered there
+        self.url_join = join.map(|args| (url.host.clone(), args));
+        if !here {
+            self.reconnect_delay = None;
+            self.connect_to(Target {
+                host: url.host,
+                port: url.port,
+                tls: url.tls,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn ports_default_to_the_scheme() {
+        let url = parse("irc://irc.example.org").unwrap();
+        assert_eq!(
+            (url.host.as_str(), url.port, url.tls),
+            ("irc.example.org", 6667, false)
+        );
+        let url = parse("ircs://irc.libera.chat/").unwrap();
+        assert_eq!((url.port, url.tls), (6697, true));
+        let url = parse("ircs://user@irc.libera.chat:7000/rust").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("irc.libera.chat", 7000));
+        assert_eq!(url.channel.as_deref(), Some("#rust"));
+        assert!(parse("irc://irc.example.org:66x7").is_err());
+        assert!(parse("http://irc.example.org").is_err());
+        assert!(parse("irc://:6667/rust").is_err());
+    }
+
+    #[test]
+    fn ipv6_hosts_are_bracketed() {
+        let url = parse("ircs://[2001:db8::1]:6698/#rust").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("2001:db8::1", 6698));
+        let url = parse("irc://[::1]").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 6667));
+        assert!(parse("irc://[::1:6667/rust").is_err());
+    }
+
+    #[test]
+    fn channels_are_decoded() {
+        let url = parse("irc://irc.example.org/%23rust").unwrap();
+        assert_eq!(url.channel.as_deref(), Some("#rust"));
+        let url = parse("irc://irc.example.org/&local").unwrap();
+        assert_eq!(url.channel.as_deref(), Some("&local"));
+        // A lone or broken % stays as it is
+        let url = parse("irc://irc.example.org/100%25%zz").unwrap();
+        assert_eq!(url.channel.as_deref(), Some("#100%%zz"));
+    }
+
+    #[test]
+    fn keys_come_from_the_query_or_after_the_flags() {
+        let url = parse("irc://irc.example.org/secret?key=hunter%232").unwrap();
+        assert_eq!(url.join_args().as_deref(), Some("#secret hunter#2"));
+        let url = parse("irc://irc.example.org/secret,needkey,hunter2").unwrap();
+        assert_eq!(url.key.as_deref(), Some("hunter2"));
+        // The query wins over a key in the path
+        let url = parse("irc://irc.example.org/secret,hunter2?key=other").unwrap();
+        assert_eq!(url.key.as_deref(), Some("other"));
+        let url = parse("irc://irc.example.org/rust,ischannel?key=").unwrap();
+        assert_eq!(url.key, None);
+        assert_eq!(url.join_args().as_deref(), Some("#rust"));
+    }
+
+    #[test]
+    fn nicks_are_not_joined() {
+        assert!(parse("irc://irc.example.org/alice,isnick").is_err());
+        let url = parse("irc://irc.example.org/,isserver").unwrap();
+        assert_eq!(url.channel, None);
+        assert_eq!(url.join_args(), None);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::message::Message;
+
+// Why a JOIN was refused, by numeric. 403, 405, 437 and 476 answer other commands too and
+// only count for a channel we asked to join
+const JOIN_ERRORS: &[(&str, &str)] = &[
+    ("403", "there is no such channel"),
+    ("405", "you are in too many channels"),
+    ("437", "it is temporarily unavailable"),
+    ("471", "it is full"),
+    ("473", "it is invite only, someone there has to /invite you"),
+    ("474", "you are banned from it"),
+    ("475", "the key is missing or wrong, /join {} <key>"),
+    ("476", "that is not a valid channel name"),
+    ("477", "you need to be logged in to services"),
+    ("489", "it only takes TLS connections"),
+    ("520", "it is for IRC operators only"),
+];
+
+pub(crate) fn is_join_error(command: &str) -> bool {
+    JOIN_ERRORS.iter().any(|(code, _)| *code == command)
+}
+
+impl App {
+    // A JOIN the server refused, shown as why instead of the raw numeric. The channel was
+    // never current, nothing to undo there. Returns true when it was handled
+    pub(crate) fn join_failed(&mut self, msg: &Message) -> bool {
+        let Some((code, reason)) = JOIN_ERRORS.iter().find(|(code, _)| *code == msg.command) else {
+            return false;
+        };
+        let Some(name) = msg.param(1).filter(|name| name.starts_with(['#', '&'])) else {
+            return false;
+        };
+        let asked = self.client.forget_join(name);
+        if !asked && matches!(*code, "403" | "405" | "437" | "476") {
+            return false;
+        }
+        let reason = reason.replace("{}", name);
+        let said = msg
+            .trailing()
+            .filter(|text| msg.params.len() > 2 && !text.is_empty())
+            .map(|text| format!(" ({})", text))
+            .unwrap_or_default();
+        let line = format!("!!! Cannot join {}: {}{}", name, reason, said);
+        // The buffer of a channel we were in before, e.g. rejoining after a kick, or the
+        // one the /join was typed in
+        match self.buffer_of(name, BufferKind::Channel) {
+            Some(buffer) => self.buffers.push_to(&buffer, line),
+            None => self.buffers.push_local(line),
+        }
+        true
+    }
+}
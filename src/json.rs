@@ -0,0 +1,73 @@
+use crate::app::App;
+use crate::message::Message;
+use serde::{Deserialize, Serialize};
+
+// What `--dumb --json` prints, one object per line, told apart by "event"
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum JsonEvent<'a> {
+    // Every line from the server, parsed: tags, prefix, command and params
+    Irc {
+        #[serde(flatten)]
+        message: &'a Message,
+    },
+    // A line as it landed in a buffer, our own feedback included
+    Line {
+        buffer: &'a str,
+        text: &'a str,
+    },
+    // Input that could not be understood
+    Error {
+        message: String,
+    },
+}
+
+impl JsonEvent<'_> {
+    pub fn to_line(&self) -> String {
+        // Only strings and maps, which always encode
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+// What `--dumb --json` reads, one object per line, e.g.
+// {"command":"send","target":"#rust","text":"hi"}
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum JsonCommand {
+    // Runs `text` as if typed in the input line, slash commands included
+    Input {
+        text: String,
+    },
+    Send {
+        target: String,
+        text: String,
+    },
+    Join {
+        channel: String,
+        key: Option<String>,
+    },
+    Raw {
+        line: String,
+    },
+    Quit,
+}
+
+impl App {
+    // Runs one line of JSON input, or says what is wrong with it
+    pub fn json_command(&mut self, line: &str) -> Result<(), String> {
+        let command: JsonCommand =
+            serde_json::from_str(line).map_err(|e| format!("Bad command: {}", e))?;
+        match command {
+            JsonCommand::Input { text } => self.execute(&text),
+            JsonCommand::Send { target, text } => {
+                self.execute(&format!("/msg {} {}", target, text))
+            }
+            JsonCommand::Join { channel, key } => {
+                self.join(&format!("{} {}", channel, key.unwrap_or_default()))
+            }
+            JsonCommand::Raw { line } => self.execute(&format!("/raw {}", line)),
+            JsonCommand::Quit => self.execute("/quit"),
+        }
+        Ok(())
+    }
+}
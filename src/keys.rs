@@ -0,0 +1,184 @@
+use crate::app::App;
+use crate::channel;
+use crate::config::{Config, KeysConfig};
+use crate::fish::FishKey;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+type Result<T> = std::result::Result<T, String>;
+
+// In front of a key before it is encrypted, a wrong passphrase does not give it back
+const SEALED: &str = "key:";
+
+// Keys of channels we joined with one, so /join and rejoining do not need them again.
+// Saved in keys.toml next to the config, readable only by the user, as "network/#channel".
+// With [keys] passphrase_env the keys in there are FiSH CBC encrypted with the passphrase
+#[derive(Default)]
+pub struct ChannelKeys {
+    // None keeps them until we quit
+    path: Option<PathBuf>,
+    keys: BTreeMap<String, String>,
+    cipher: Option<FishKey>,
+}
+
+impl ChannelKeys {
+    // A missing or broken file just means no keys are known yet. Keys that cannot be read
+    // back are an error, nothing is saved over them then
+    pub fn load(config: &KeysConfig) -> Result<Self> {
+        let cipher = match &config.passphrase_env {
+            Some(var) => {
+                let passphrase = env::var(var)
+                    .map_err(|_| trf("${} is not set, channel keys are not remembered", &[&var]))?;
+                let cipher = FishKey::new(&format!("cbc:{}", passphrase))
+                    .map_err(|_| trf("The passphrase in ${} needs 4 to 56 bytes", &[&var]))?;
+                Some(cipher)
+            }
+            None => None,
+        };
+        let path = Config::dir()
+            .map(|dir| dir.join("keys.toml"))
+            .ok_or("No config directory, channel keys are not remembered")?;
+        let stored: BTreeMap<String, String> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        let mut keys = BTreeMap::new();
+        for (id, key) in stored {
+            let key = match (&cipher, key.starts_with("+OK ")) {
+                (Some(cipher), true) => cipher
+                    .decrypt(&key)
+                    .and_then(|key| key.strip_prefix(SEALED).map(|key| key.to_string()))
+                    .ok_or(tr(
+                        "keys.toml does not decrypt with the passphrase, channel keys are not remembered",
+                    ))?,
+                (None, true) => {
+                    return Err(tr("keys.toml is encrypted, set [keys] passphrase_env to read it").to_string());
+                }
+                // Plain text from before encryption was turned on, encrypted on the next save
+                (_, false) => key,
+            };
+            keys.insert(id, key);
+        }
+        Ok(ChannelKeys {
+            path: Some(path),
+            keys,
+            cipher,
+        })
+    }
+
+    fn id(network: &str, name: &str) -> String {
+        format!(
+            "{}/{}",
+            network.to_ascii_lowercase(),
+            channel::nick_key(name)
+        )
+    }
+
+    pub fn get(&self, network: &str, name: &str) -> Option<&str> {
+        self.keys
+            .get(&Self::id(network, name))
+            .map(|key| key.as_str())
+    }
+
+    pub fn set(&mut self, network: &str, name: &str, key: &str) -> Result<()> {
+        let old = self.keys.insert(Self::id(network, name), key.to_string());
+        if old.as_deref() == Some(key) {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    pub fn remove(&mut self, network: &str, name: &str) -> Result<()> {
+        if self.keys.remove(&Self::id(network, name)).is_none() {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| trf("Failed to create {}: {}", &[&dir.display(), &e]))?;
+        }
+        let stored: BTreeMap<&String, String> = self
+            .keys
+            .iter()
+            .map(|(id, key)| match &self.cipher {
+                Some(cipher) => (id, cipher.encrypt(&format!("{}{}", SEALED, key))),
+                None => (id, key.clone()),
+            })
+            .collect();
+        let contents = toml::to_string(&stored)
+            .map_err(|e| trf("Failed to encode channel keys: {}", &[&e]))?;
+        // Created private rather than restricted after writing, so the keys are never
+        // readable by others even briefly
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(|e| trf("Failed to open {}: {}", &[&path.display(), &e]))?;
+        file.write_all(contents.as_bytes())
+            .map_err(|e| trf("Failed to write {}: {}", &[&path.display(), &e]))
+    }
+}
+
+impl App {
+    // keys.toml with the passphrase from [keys] passphrase_env, if one is configured
+    pub(crate) fn load_channel_keys(&mut self) {
+        self.channel_keys = match ChannelKeys::load(&self.config.keys) {
+            Ok(keys) => keys,
+            Err(e) => {
+                self.buffers.server_mut().push(format!("!!! {}", e));
+                ChannelKeys::default()
+            }
+        };
+    }
+
+    // Keys are stored per network, before 005 names it the server host stands in
+    pub(crate) fn network_id(&self) -> String {
+        if self.client.network.is_empty() {
+            self.client.server.clone()
+        } else {
+            self.client.network.clone()
+        }
+    }
+
+    // Our JOIN coming back means the key we used was right, 475 means it was not
+    pub(crate) fn channel_key_reply(&mut self, msg: &Message) {
+        let network = self.network_id();
+        match msg.command.as_str() {
+            "JOIN" if msg.nick().is_some_and(|nick| self.client.is_me(nick)) => {
+                let Some(name) = msg.param(0) else {
+                    return;
+                };
+                if let Some(key) = self.pending_keys.remove(&channel::nick_key(name))
+                    && let Err(e) = self.channel_keys.set(&network, name, &key)
+                {
+                    self.buffers.server_mut().push(e);
+                }
+            }
+            // ERR_BADCHANNELKEY
+            "475" => {
+                let Some(name) = msg.param(1) else {
+                    return;
+                };
+                self.pending_keys.remove(&channel::nick_key(name));
+                if let Err(e) = self.channel_keys.remove(&network, name) {
+                    self.buffers.server_mut().push(e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::channel;
+use crate::i18n::trf;
+use crate::message::Message;
+use std::time::{Duration, Instant};
+
+// A channel we were kicked from and want back into, see [kick] in the config
+pub struct Rejoin {
+    pub channel: String,
+    // When to send the next JOIN, None while one is on its way
+    pub at: Option<Instant>,
+    pub attempts: u32,
+}
+
+impl App {
+    // KICK <channel> <nick> :<reason>, or a REMOVE, shown as a line of its own instead of
+    // the raw one. Returns true when it was handled
+    pub(crate) fn kick(&mut self, msg: &Message) -> bool {
+        // Our own PART was decided on by irc_client, see part_is_removal
+        let part_removal = match msg.nick() {
+            Some(nick) if self.client.is_me(nick) => msg
+                .param(0)
+                .is_some_and(|name| self.client.kicked_from(name)),
+            _ => self.client.part_is_removal(msg),
+        };
+        let Some(removal) = Removal::of(msg, part_removal) else {
+            return false;
+        };
+        let reason = removal.reason(msg);
+        let Removal {
+            name,
+            nick,
+            kicker,
+            verb,
+        } = removal;
+        let reason = reason
+            .filter(|reason| !reason.is_empty() && *reason != nick)
+            .map(|reason| format!(": {}", reason))
+            .unwrap_or_default();
+        if !self.client.is_me(nick) {
+            let buffer = self.route(msg);
+            self.buffers.push_to(
+                &buffer,
+                format!(
+                    "<-- {} was {} from {} by {}{}",
+                    nick, verb, name, kicker, reason
+                ),
+            );
+            return true;
+        }
+        let line = format!("You were {} from {} by {}{}", verb, name, kicker, reason);
+        // The channel's buffer keeps it too, notify only reaches the server buffer
+        if let Some(buffer) = self.buffer_of(name, BufferKind::Channel) {
+            self.buffers.push_to(&buffer, format!("!!! {}", line));
+        }
+        self.notify(&line);
+        if self.config.kick.rejoin {
+            let key = channel::nick_key(name);
+            let attempts = self.rejoins.get(&key).map_or(0, |r| r.attempts);
+            self.schedule_rejoin(name, attempts);
+        }
+        true
+    }
+
+    // Typed text for a channel we were thrown out of stays here instead of being queued,
+    // /msg still sends it. Returns true when it was held back
+    pub(crate) fn not_joined(&mut self, name: &str) -> bool {
+        if !self.client.kicked_from(name) {
+            return false;
+        }
+        self.buffers.push_local(trf(
+            "You are not in {} any more, /join {} to go back or /msg {} to send anyway",
+            &[&name, &name, &name],
+        ));
+        true
+    }
+
+    fn schedule_rejoin(&mut self, name: &str, attempts: u32) {
+        let key = channel::nick_key(name);
+        if attempts >= self.config.kick.max_attempts {
+            self.rejoins.remove(&key);
+            self.buffers.server_mut().push(trf(
+                "Gave up rejoining {} after {} tries",
+                &[&name, &attempts],
+            ));
+            return;
+        }
+        let delay = self.config.kick.delay_secs;
+        self.buffers
+            .server_mut()
+            .push(trf("Rejoining {} in {} seconds", &[&name, &delay]));
+        self.rejoins.insert(
+            key,
+            Rejoin {
+                channel: name.to_string(),
+                at: Some(Instant::now() + Duration::from_secs(delay)),
+                attempts,
+            },
+        );
+    }
+
+    // Sends the JOINs that are due, called from tick
+    pub(crate) fn rejoin_due(&mut self) {
+        let now = Instant::now();
+        let network = self.network_id();
+        for rejoin in self.rejoins.values_mut() {
+            if rejoin.at.is_none_or(|at| at > now) {
+                continue;
+            }
+            rejoin.at = None;
+            rejoin.attempts += 1;
+            let key = self.channel_keys.get(&network, &rejoin.channel);
+            if let Err(e) = self.client.join_channel(&rejoin.channel, key) {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Error rejoining {}: {}", &[&rejoin.channel, &e]));
+            }
+        }
+    }
+
+    // Our JOIN coming back ends a rejoin, the channel refusing us tries again later
+    pub(crate) fn rejoin_reply(&mut self, msg: &Message) {
+        match msg.command.as_str() {
+            "JOIN" if msg.nick().is_some_and(|nick| self.client.is_me(nick)) => {
+                if let Some(name) = msg.param(0) {
+                    self.rejoins.remove(&channel::nick_key(name));
+                }
+            }
+            // Full, invite only, banned, wrong key, registered users only
+            "471" | "473" | "474" | "475" | "477" => {
+                let Some(name) = msg.param(1) else {
+                    return;
+                };
+                let key = channel::nick_key(name);
+                if let Some(rejoin) = self.rejoins.get(&key)
+                    && rejoin.at.is_none()
+                {
+                    let attempts = rejoin.attempts;
+                    self.schedule_rejoin(name, attempts);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Who was put out of which channel by whom. REMOVE is a KICK that looks like leaving,
+// servers that have it send "REMOVE <channel> <nick> :<reason>" or, on charybdis and its
+// forks, a PART of the removed nick with "requested by <kicker> (<reason>)"
+struct Removal<'a> {
+    name: &'a str,
+    nick: &'a str,
+    kicker: &'a str,
+    verb: &'static str,
+}
+
+impl<'a> Removal<'a> {
+    // `part_removal` says whether a PART is one, see IrcClient::part_is_removal
+    fn of(msg: &'a Message, part_removal: bool) -> Option<Removal<'a>> {
+        let name = msg.param(0)?;
+        match msg.command.as_str() {
+            "KICK" | "REMOVE" => Some(Removal {
+                name,
+                nick: msg.param(1)?,
+                kicker: msg.nick().unwrap_or("the server"),
+                verb: if msg.command == "KICK" {
+                    "kicked"
+                } else {
+                    "removed"
+                },
+            }),
+            "PART" if part_removal => {
+                let requested = msg.param(1)?.strip_prefix("requested by ")?;
+                Some(Removal {
+                    name,
+                    nick: msg.nick()?,
+                    kicker: requested.split(' ').next().unwrap_or(requested),
+                    verb: "removed",
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn reason(&self, msg: &'a Message) -> Option<&'a str> {
+        if msg.command != "PART" {
+            return msg.param(2);
+        }
+        let requested = msg.param(1)?.strip_prefix("requested by ")?;
+        let (_, reason) = requested.split_once(" (")?;
+        Some(reason.strip_suffix(')').unwrap_or(reason))
+    }
+}
@@ -0,0 +1,65 @@
+mod access;
+mod activity;
+mod app;
+pub mod attach;
+mod auth;
+mod automode;
+mod awaylog;
+mod banlist;
+mod batch;
+mod blowfish;
+pub mod buffer;
+mod channel;
+pub mod clock;
+mod composer;
+pub mod config;
+mod control;
+mod ctcp;
+pub mod daemon;
+mod dbus;
+mod dcc;
+mod dcc_chat;
+mod debug;
+pub mod dumb;
+mod filter;
+mod fish;
+mod help;
+mod highlight;
+mod http_api;
+mod i18n;
+mod identd;
+mod ignore;
+mod invite;
+mod irc_client;
+pub mod irc_url;
+mod joins;
+mod json;
+mod keys;
+mod kick;
+mod logging;
+pub mod message;
+mod nick_change;
+mod nick_menu;
+mod oper;
+mod outbox;
+mod palette;
+mod paste;
+mod profile;
+mod relay;
+pub mod render;
+mod route;
+mod seen;
+mod server_info;
+mod servers;
+mod services;
+mod settings;
+mod setup;
+mod sounds;
+mod terminal;
+mod theme;
+mod topic;
+mod transport;
+pub mod tui_client;
+mod webhook;
+mod who;
+mod whowas;
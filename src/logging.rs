@@ -0,0 +1,47 @@
+use crate::config::{Config, LogConfig};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Registry, fmt, reload};
+
+type Result<T> = std::result::Result<T, String>;
+
+// Swaps the filter of the running subscriber, see set_level
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+// Sends diagnostics to a file rotated daily in the log directory, e.g.
+// ~/.local/state/irconic/irconic.2024-03-01.log. The terminal is in raw mode while the TUI
+// runs, so nothing can go to stderr. IRCONIC_LOG overrides the configured level
+pub fn init(config: &LogConfig) -> Result<PathBuf> {
+    let dir = Config::log_dir().ok_or("No home directory to keep the log in")?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("irconic")
+        .filename_suffix("log")
+        .max_log_files(config.keep_days.max(1))
+        .build(&dir)
+        .map_err(|e| format!("Failed to open the log in {}: {}", dir.display(), e))?;
+    let level = std::env::var("IRCONIC_LOG").unwrap_or_else(|_| config.level.clone());
+    let (filter, handle) = reload::Layer::new(parse(&level)?);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(appender).with_ansi(false))
+        .try_init()
+        .map_err(|e| format!("Failed to start logging: {}", e))?;
+    let _ = FILTER.set(handle);
+    Ok(dir)
+}
+
+// A level like "debug", or per module like "connection::irc_client=trace,info"
+fn parse(level: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(level).map_err(|e| format!("Invalid log level {}: {}", level, e))
+}
+
+// /loglevel, takes effect immediately
+pub fn set_level(level: &str) -> Result<()> {
+    let handle = FILTER.get().ok_or("Logging is not running")?;
+    handle
+        .reload(parse(level)?)
+        .map_err(|e| format!("Failed to change the log level: {}", e))
+}
@@ -1,12 +1,24 @@
-mod irc_client;
-mod tui_client;
+use connection::{attach, daemon, dumb, irc_url, tui_client};
 
 fn main() {
-    match tui_client::run_tui_client() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // Goes with any way of starting the TUI, see profile.rs
+    let profile_frames = args.iter().any(|a| a == "--profile-frames");
+    args.retain(|a| a != "--profile-frames");
+    let result = match args.first().map(|a| a.as_str()) {
+        Some("--daemon") => daemon::start(),
+        Some("--daemon-child") => daemon::run(&args[1..]),
+        Some("--attach") => attach::run(),
+        Some("--dumb") => dumb::run(&args[1..]),
+        // irconic ircs://irc.libera.chat:6697/#rust, instead of the server in the config
+        Some(url) if irc_url::is_url(url) => match irc_url::parse(url) {
+            Ok(url) => tui_client::run_tui_client(Some(url), profile_frames),
+            Err(e) => Err(e.into()),
+        },
+        _ => tui_client::run_tui_client(None, profile_frames),
+    };
+    match result {
         Ok(_) => println!("Client exited normally"),
         Err(e) => eprintln!("Error: {}", e),
     }
 }
-
-
-
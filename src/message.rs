@@ -0,0 +1,194 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+// A single line of the IRC protocol split into its parts:
+// [@tags] [:prefix] COMMAND [params...] [:trailing]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Message {
+    pub tags: HashMap<String, String>,
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl Message {
+    // Parses a raw line as received from the server. Returns None for lines that do not
+    // contain a command at all
+    pub fn parse(line: &str) -> Option<Message> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        let mut tags = HashMap::new();
+        if let Some(stripped) = rest.strip_prefix('@') {
+            let (raw_tags, remainder) = stripped.split_once(' ')?;
+            for tag in raw_tags.split(';').filter(|t| !t.is_empty()) {
+                let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                tags.insert(key.to_string(), unescape_tag_value(value));
+            }
+            rest = remainder.trim_start_matches(' ');
+        }
+
+        let mut prefix = None;
+        if let Some(stripped) = rest.strip_prefix(':') {
+            let (raw_prefix, remainder) = stripped.split_once(' ')?;
+            prefix = Some(raw_prefix.to_string());
+            rest = remainder.trim_start_matches(' ');
+        }
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            // Everything after a leading ':' is one parameter, spaces included
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing.to_string());
+                break;
+            }
+            match rest.split_once(' ') {
+                Some((param, remainder)) => {
+                    params.push(param.to_string());
+                    rest = remainder;
+                }
+                None => {
+                    params.push(rest.to_string());
+                    break;
+                }
+            }
+        }
+
+        Some(Message {
+            tags,
+            prefix,
+            command: command.to_ascii_uppercase(),
+            params,
+        })
+    }
+
+    pub fn param(&self, index: usize) -> Option<&str> {
+        self.params.get(index).map(|p| p.as_str())
+    }
+
+    // The last parameter, which for most commands is the human readable text
+    pub fn trailing(&self) -> Option<&str> {
+        self.params.last().map(|p| p.as_str())
+    }
+
+    // Nick part of the prefix, "nick" for "nick!user@host"
+    pub fn nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        prefix.split(['!', '@']).next()
+    }
+
+    // The body of a CTCP request wrapped in \x01, e.g. "DCC SEND file 2130706433 5000 12"
+    pub fn ctcp(&self) -> Option<&str> {
+        let text = self.trailing()?.strip_prefix('\x01')?;
+        Some(text.strip_suffix('\x01').unwrap_or(text))
+    }
+}
+
+// Tag values escape ';', ' ', '\' and line breaks, see the IRCv3 message-tags spec
+fn unescape_tag_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => out.push(';'),
+            Some('s') => out.push(' '),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+// Commands servers send without a prefix of their own
+const UNPREFIXED: &[&str] = &["PING", "PONG", "ERROR", "AUTHENTICATE"];
+
+// What is wrong with a line by the letter of RFC 1459 and IRCv3, for [parser] mode =
+// "strict". Empty for a well formed line. parse reads most of these anyway
+pub fn violations(line: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let (tags, rest) = match line.strip_prefix('@') {
+        Some(stripped) => match stripped.split_once(' ') {
+            Some((tags, rest)) => (tags, rest),
+            None => (stripped, ""),
+        },
+        None => ("", line),
+    };
+    // 8191 for the tags with their @ and space, 512 for the rest with its CRLF
+    if !tags.is_empty() && tags.len() + 2 > 8191 {
+        found.push(format!("tags take {} bytes, over 8191", tags.len() + 2));
+    }
+    if rest.len() + 2 > 512 {
+        found.push(format!("{} bytes, over 512", rest.len() + 2));
+    }
+    if rest.starts_with(' ') {
+        found.push("more than one space after the tags".to_string());
+    }
+    let Some(msg) = Message::parse(line) else {
+        found.push("no command".to_string());
+        return found;
+    };
+    if msg.prefix.is_none() && !UNPREFIXED.contains(&msg.command.as_str()) {
+        found.push("no prefix".to_string());
+    }
+    if msg.prefix.as_deref() == Some("") {
+        found.push("empty prefix".to_string());
+    }
+    let command = rest
+        .trim_start_matches(' ')
+        .split(' ')
+        .nth(usize::from(msg.prefix.is_some()))
+        .unwrap_or_default();
+    let numeric = command.len() == 3 && command.bytes().all(|b| b.is_ascii_digit());
+    if !numeric && !command.bytes().all(|b| b.is_ascii_alphabetic()) {
+        found.push(format!(
+            "command {} is neither letters nor three digits",
+            command
+        ));
+    }
+    // The trailing parameter may hold any spaces it likes
+    let head = rest.split(" :").next().unwrap_or_default();
+    if head.trim_start_matches(' ').contains("  ") {
+        found.push("more than one space between parameters".to_string());
+    }
+    if msg.params.len() > 15 {
+        found.push(format!("{} parameters, over 15", msg.params.len()));
+    }
+    found
+}
+
+// Splits outgoing text into chunks of at most `max_bytes`, preferring to break at spaces and
+// never splitting inside a UTF-8 character
+pub fn split_text(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim_end();
+    while rest.len() > max_bytes {
+        let mut end = max_bytes;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let cut = match rest[..end].rfind(' ') {
+            Some(space) if space > 0 => space,
+            _ => end,
+        };
+        parts.push(rest[..cut].to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
@@ -0,0 +1,160 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::channel;
+use crate::i18n::{tr, trf};
+use crate::irc_client::ConnectionState;
+use crate::message::Message;
+
+// What servers answer a NICK they will not take with, once we are registered
+const REFUSALS: &[&str] = &["432", "433", "436", "437", "438", "447"];
+
+impl App {
+    // /nick newnick. Once registered the server has the last word, it answers with a NICK
+    // or one of REFUSALS. Without a connection the nick is used for the next one
+    pub(crate) fn change_nick(&mut self, nick: &str) {
+        if nick.is_empty() || nick.contains(' ') {
+            self.buffers
+                .push_local(tr("Usage: /nick newnick").to_string());
+            return;
+        }
+        if !channel::valid_nick(nick) {
+            self.buffers.push_local(format!(
+                "{} is not a nick, those are letters, digits and []\\`_^{{|}}-, not starting with a digit or -",
+                nick
+            ));
+            return;
+        }
+        if let Some(max) = self.client.nicklen
+            && nick.len() > max
+        {
+            self.buffers.push_local(trf(
+                "{} is longer than this server allows ({} bytes)",
+                &[&nick, &max],
+            ));
+            return;
+        }
+        if self.client.nickname == nick {
+            self.buffers.push_local(trf("You are {} already", &[&nick]));
+            return;
+        }
+        // Asked for by name, no fallback to get back from anymore
+        self.client.wanted_nick = None;
+        match self.client.state {
+            ConnectionState::Ready => match self.client.send_raw(&format!("NICK {}\r\n", nick)) {
+                Ok(_) => self
+                    .buffers
+                    .push_local(trf("Asking the server for {}", &[&nick])),
+                Err(e) => self
+                    .buffers
+                    .push_local(trf("Error changing nick: {}", &[&e])),
+            },
+            // Until 001 the server only refuses, the nick we sent last is the one we get
+            ConnectionState::Registering | ConnectionState::Authenticating => {
+                match self.client.send_raw(&format!("NICK {}\r\n", nick)) {
+                    Ok(_) => {
+                        self.client.nickname = nick.to_string();
+                        self.buffers
+                            .push_local(trf("Registering as {} instead", &[&nick]));
+                    }
+                    Err(e) => self
+                        .buffers
+                        .push_local(trf("Error changing nick: {}", &[&e])),
+                }
+            }
+            _ => {
+                self.client.nickname = nick.to_string();
+                self.buffers
+                    .push_local(trf("The next connection registers as {}", &[&nick]));
+            }
+        }
+    }
+
+    // A /nick the server would not take, shown instead of the raw numeric. Returns true
+    // when it was handled
+    pub(crate) fn nick_refused(&mut self, msg: &Message) -> bool {
+        if !self.client.registered || !REFUSALS.contains(&msg.command.as_str()) {
+            return false;
+        }
+        // 437 is for channels too, see joins.rs
+        if msg
+            .param(1)
+            .is_some_and(|name| name.starts_with(['#', '&']))
+        {
+            return false;
+        }
+        let reason = msg.trailing().unwrap_or_default();
+        // 447 (UnrealIRCd's +N channels) names no nick
+        let line = match msg.params.len() {
+            3.. => trf(
+                "!!! Could not change nick to {}: {}. You are still {}",
+                &[
+                    &msg.param(1).unwrap_or_default(),
+                    &reason,
+                    &self.client.nickname,
+                ],
+            ),
+            _ => trf(
+                "!!! Could not change nick: {}. You are still {}",
+                &[&reason, &self.client.nickname],
+            ),
+        };
+        self.buffers.server_mut().push(line);
+        true
+    }
+
+    // NICK, after irc_client renamed them in the nick lists. One line in the buffer of each
+    // channel we share with them and in their query, which is renamed to the new nick like
+    // their DCC chat buffer. A Tab completion in progress follows too. The server buffer
+    // only gets the line when no other buffer did. Returns true when it was handled
+    pub(crate) fn nick_changed(&mut self, msg: &Message) -> bool {
+        let (Some(old), Some(new)) = (msg.nick(), msg.param(0)) else {
+            return false;
+        };
+        let mut shown_in: Vec<String> = self
+            .client
+            .channels
+            .values()
+            .filter(|chan| chan.member(new).is_some())
+            .filter_map(|chan| self.buffer_of(&chan.name, BufferKind::Channel))
+            .collect();
+        shown_in.sort();
+        if self.client.is_me(new) {
+            let line = format!("--- You are now known as {}", new);
+            self.buffers.server_mut().push(line.clone());
+            for name in shown_in {
+                self.buffers.push_to(&name, line.clone());
+            }
+            return true;
+        }
+        for chat in self.chats.iter_mut() {
+            if chat.nick.eq_ignore_ascii_case(old) {
+                let buffer = chat.buffer_name();
+                chat.nick = new.to_string();
+                self.buffers.rename(&buffer, &chat.buffer_name());
+            }
+        }
+        if self.buffer_of(old, BufferKind::Query).is_some() {
+            self.buffers.rename(old, new);
+            if let Some(name) = self.buffer_of(new, BufferKind::Query) {
+                shown_in.push(name);
+            }
+        }
+        if let Some(menu) = self.nick_menu.as_mut()
+            && menu.nick.eq_ignore_ascii_case(old)
+        {
+            menu.nick = new.to_string();
+        }
+        // Only the TUI has a completion to fix up
+        if !self.headless {
+            self.renamed.push((old.to_string(), new.to_string()));
+        }
+        let line = format!("--- {} is now known as {}", old, new);
+        if shown_in.is_empty() {
+            self.buffers.server_mut().push(line.clone());
+        }
+        for name in shown_in {
+            self.buffers.push_to(&name, line.clone());
+        }
+        true
+    }
+}
@@ -0,0 +1,156 @@
+use crate::app::App;
+use crate::channel::{self, Channel, Member};
+use crate::i18n::{tr, trf};
+
+// What the nick menu offers, each with the key that picks it directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NickAction {
+    Whois,
+    Query,
+    Ignore,
+    Op,
+    Deop,
+    Kick,
+    Ban,
+}
+
+impl NickAction {
+    pub const ALL: [NickAction; 7] = [
+        NickAction::Whois,
+        NickAction::Query,
+        NickAction::Ignore,
+        NickAction::Op,
+        NickAction::Deop,
+        NickAction::Kick,
+        NickAction::Ban,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NickAction::Whois => "Whois",
+            NickAction::Query => "Open query",
+            NickAction::Ignore => "Ignore",
+            NickAction::Op => "Op",
+            NickAction::Deop => "Deop",
+            NickAction::Kick => "Kick",
+            NickAction::Ban => "Ban",
+        }
+    }
+
+    pub fn key(self) -> char {
+        match self {
+            NickAction::Whois => 'w',
+            NickAction::Query => 'q',
+            NickAction::Ignore => 'i',
+            NickAction::Op => 'o',
+            NickAction::Deop => 'd',
+            NickAction::Kick => 'k',
+            NickAction::Ban => 'b',
+        }
+    }
+}
+
+// The menu of actions for one nick, opened with Alt+N
+pub struct NickMenu {
+    pub nick: String,
+    pub selected: usize,
+}
+
+impl App {
+    // The members the nick list shows, in order, only those matching the filter while one
+    // is typed
+    pub fn shown_members<'a>(&self, chan: &'a Channel) -> Vec<&'a Member> {
+        let sort = self.buffer_settings(&chan.name).sort;
+        let mut members = chan.members_by(sort, &self.client.prefix_symbols);
+        if let Some(filter) = self.nick_filter.as_deref().filter(|f| !f.is_empty()) {
+            let filter = channel::nick_key(filter);
+            members.retain(|m| channel::nick_key(&m.nick).contains(&filter));
+        }
+        members
+    }
+
+    // The nick highlighted in the nick list with Alt+Up and Alt+Down
+    pub fn selected_nick(&self) -> Option<String> {
+        let chan = self.client.channel(&self.client.current_channel)?;
+        let members = self.shown_members(chan);
+        let index = self.nick_selected?.min(members.len().checked_sub(1)?);
+        Some(members[index].nick.clone())
+    }
+
+    // Moves the nick list highlight, starting at the top
+    pub fn move_nick_selection(&mut self, down: bool) {
+        let Some(chan) = self.client.channel(&self.client.current_channel) else {
+            return;
+        };
+        let last = self.shown_members(chan).len().saturating_sub(1);
+        self.nick_selected = Some(match self.nick_selected {
+            None => 0,
+            Some(index) if down => (index + 1).min(last),
+            Some(index) => index.saturating_sub(1).min(last),
+        });
+    }
+
+    // Alt+L gives the nick list the keyboard, typing then narrows it down
+    pub fn focus_nick_list(&mut self) {
+        if self.client.channel(&self.client.current_channel).is_none() {
+            self.buffers
+                .push_local(tr("Join a channel first, the nick list is shown for it").to_string());
+            return;
+        }
+        self.nick_filter = Some(String::new());
+        self.nick_selected = Some(0);
+    }
+
+    // A change to the filter starts the highlight at the first match again
+    pub fn edit_nick_filter(&mut self, edit: impl FnOnce(&mut String)) {
+        if let Some(filter) = self.nick_filter.as_mut() {
+            edit(filter);
+            self.nick_selected = Some(0);
+        }
+    }
+
+    // Opens the menu for the nick being typed at the end of `input`, or else the one
+    // highlighted in the nick list
+    pub fn open_nick_menu(&mut self, input: &str) {
+        let typed = input.split_whitespace().last().and_then(|word| {
+            let word = word.trim_end_matches([':', ',']);
+            let chan = self.client.channel(&self.client.current_channel)?;
+            chan.members
+                .iter()
+                .find(|m| m.nick.eq_ignore_ascii_case(word))
+                .map(|m| m.nick.clone())
+        });
+        match typed.or_else(|| self.selected_nick()) {
+            Some(nick) => self.nick_menu = Some(NickMenu { nick, selected: 0 }),
+            None => self.buffers.push_local(
+                tr("Type a nick or pick one in the nick list with Alt+Up/Down, then Alt+N")
+                    .to_string(),
+            ),
+        }
+    }
+
+    // Runs `action` for the nick of the open menu and closes it
+    pub fn nick_action(&mut self, action: NickAction) {
+        let Some(nick) = self.nick_menu.take().map(|menu| menu.nick) else {
+            return;
+        };
+        let channel = self.client.current_channel.clone();
+        match action {
+            NickAction::Whois => self.execute(&format!("/whois {}", nick)),
+            NickAction::Query => self.execute(&format!("/query {}", nick)),
+            NickAction::Ignore => self.ignore(&nick, None),
+            NickAction::Op => self.execute(&format!("/mode +o {}", nick)),
+            NickAction::Deop => self.execute(&format!("/mode -o {}", nick)),
+            NickAction::Kick => {
+                if let Err(e) = self.client.send_labeled(
+                    &format!("KICK {} {}", channel, nick),
+                    &format!("kick {}", nick),
+                ) {
+                    self.buffers
+                        .push_local(trf("Error sending KICK: {}", &[&e]));
+                }
+            }
+            NickAction::Ban => self.ban(&nick),
+        }
+    }
+}
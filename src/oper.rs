@@ -0,0 +1,115 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+
+// Starts the lines meant for the whole network, WALLOPS, GLOBOPS and notices to a $mask,
+// so they are drawn apart from chat
+pub const ANNOUNCE: &str = "=== ";
+
+impl App {
+    // /oper name password
+    pub(crate) fn oper(&mut self, args: &str) {
+        let Some((name, password)) = args.split_once(' ') else {
+            self.buffers
+                .push_local(tr("Usage: /oper name password").to_string());
+            return;
+        };
+        // Labeled, so a wrong password shows up as "Your /oper failed"
+        match self
+            .client
+            .send_labeled(&format!("OPER {} {}", name, password.trim()), "/oper")
+        {
+            Ok(_) => self.buffers.push_local(trf("Sending OPER as {}", &[&name])),
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error sending OPER: {}", &[&e])),
+        }
+    }
+
+    // /kill nick reason and /wallops message, refused up front unless we are opered
+    pub(crate) fn oper_command(&mut self, command: &str, args: &str) {
+        if !self.client.user_modes.contains(['o', 'O']) {
+            self.buffers
+                .push_local(trf("/{} needs IRC operator status, see /oper", &[&command]));
+            return;
+        }
+        let line = match (command, args.split_once(' ')) {
+            ("kill", Some((nick, reason))) => format!("KILL {} :{}", nick, reason),
+            ("wallops", _) if !args.is_empty() => format!("WALLOPS :{}", args),
+            _ => {
+                let usage = if command == "kill" {
+                    "/kill nick reason"
+                } else {
+                    "/wallops message"
+                };
+                self.buffers.push_local(trf("Usage: {}", &[&usage]));
+                return;
+            }
+        };
+        if let Err(e) = self.client.send_labeled(&line, &format!("/{}", command)) {
+            self.buffers
+                .push_local(trf("Error sending {}: {}", &[&command, &e]));
+        }
+    }
+
+    // Notices from the server itself (snomasks once opered) and RPL_SNOMASK go to the
+    // "notices" buffer instead of the server buffer. Returns true when it took the line
+    pub(crate) fn server_notice(&mut self, msg: &Message) -> bool {
+        // RPL_YOUREOPER
+        if msg.command == "381" {
+            self.buffers.open("notices", BufferKind::Notices);
+            self.notify(tr(
+                "You are now an IRC operator, server notices go to the notices buffer",
+            ));
+            return false;
+        }
+        let line = match msg.command.as_str() {
+            // Before registering the server talks to "*" or "AUTH", that belongs with the
+            // rest of the connection log
+            "NOTICE"
+                if self.client.registered
+                    && msg
+                        .prefix
+                        .as_deref()
+                        .is_some_and(|p| !p.contains('!') && p.contains('.')) =>
+            {
+                format!(
+                    "-{}- {}",
+                    msg.prefix.as_deref().unwrap_or_default(),
+                    msg.trailing().unwrap_or_default()
+                )
+            }
+            // RPL_SNOMASK
+            "008" => format!("Server notice mask: {}", msg.param(1).unwrap_or_default()),
+            _ => return false,
+        };
+        self.buffers.open("notices", BufferKind::Notices);
+        self.buffers.push_to("notices", line);
+        true
+    }
+
+    // WALLOPS (OPERWALL on ratbox), GLOBOPS and messages to everyone on a server mask like
+    // $*.example.org, as one line in the server buffer. Returns true when it took the line
+    pub(crate) fn announcement(&mut self, msg: &Message) -> bool {
+        let from = msg.nick().or(msg.prefix.as_deref()).unwrap_or("the server");
+        let text = msg.trailing().unwrap_or_default();
+        let line = match msg.command.as_str() {
+            "WALLOPS" | "OPERWALL" | "GLOBOPS" => {
+                format!("{}{} from {}: {}", ANNOUNCE, msg.command, from, text)
+            }
+            "NOTICE" | "PRIVMSG" if msg.param(0).is_some_and(|t| t.starts_with('$')) => {
+                format!(
+                    "{}Global notice from {} to {}: {}",
+                    ANNOUNCE,
+                    from,
+                    msg.param(0).unwrap_or_default(),
+                    text
+                )
+            }
+            _ => return false,
+        };
+        self.buffers.server_mut().push(line);
+        true
+    }
+}
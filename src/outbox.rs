@@ -0,0 +1,92 @@
+use crate::app::App;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+
+// A message typed while we could not send it, kept until we are back in its channel
+pub struct Queued {
+    pub target: String,
+    pub text: String,
+}
+
+impl App {
+    // Sends what was typed to `target`, or queues it while we are offline or not in the
+    // channel yet. Queued lines are marked [pending] and go out after the next JOIN there
+    pub(crate) fn say(&mut self, target: &str, text: &str) {
+        let ready = self.client.registered
+            && (!target.starts_with(['#', '&']) || self.client.channel(target).is_some());
+        if ready {
+            match self.send_chat(target, text) {
+                Ok(shown) => {
+                    self.push_sent(target, format!("-> {}: {}", target, shown));
+                    return;
+                }
+                // The connection is gone and the receiver has not noticed yet
+                Err(e) => self
+                    .buffers
+                    .push_local(trf("Error sending message: {}, queued it", &[&e])),
+            }
+        }
+        self.outbox.push(Queued {
+            target: target.to_string(),
+            text: text.to_string(),
+        });
+        self.push_sent(target, format!("-> {}: {} [pending]", target, text));
+    }
+
+    // Sends the queue of a channel we just joined, and of nicks once we are registered
+    pub(crate) fn flush_outbox(&mut self, msg: &Message) {
+        if self.outbox.is_empty()
+            || msg.command == "JOIN" && !msg.nick().is_some_and(|nick| self.client.is_me(nick))
+        {
+            return;
+        }
+        let ready = |target: &str| match msg.command.as_str() {
+            "001" => !target.starts_with(['#', '&']),
+            "JOIN" => msg.param(0).is_some_and(|c| c.eq_ignore_ascii_case(target)),
+            _ => false,
+        };
+        let (due, kept): (Vec<Queued>, Vec<Queued>) = std::mem::take(&mut self.outbox)
+            .into_iter()
+            .partition(|queued| ready(&queued.target));
+        self.outbox = kept;
+        if due.is_empty() {
+            return;
+        }
+        self.buffers
+            .server_mut()
+            .push(trf("Sending {} queued message(s)", &[&due.len()]));
+        for queued in due {
+            self.say(&queued.target, &queued.text);
+        }
+    }
+
+    // /queue lists what waits to be sent, /queue clear drops it
+    pub(crate) fn queue_command(&mut self, args: &str) {
+        match args.trim() {
+            "" if self.outbox.is_empty() => {
+                self.buffers.push_local(tr("Nothing queued").to_string());
+            }
+            "" => {
+                let lines: Vec<String> = self
+                    .outbox
+                    .iter()
+                    .map(|q| format!("  {}: {}", q.target, q.text))
+                    .collect();
+                self.buffers
+                    .push_local(trf("{} queued until we are back:", &[&lines.len()]));
+                for line in lines {
+                    self.buffers.push_local(line);
+                }
+            }
+            "clear" => {
+                let count = self.outbox.len();
+                self.outbox.clear();
+                self.buffers
+                    .push_local(trf("Dropped {} queued message(s)", &[&count]));
+            }
+            _ => self
+                .buffers
+                .push_local(tr("Usage: /queue [clear]").to_string()),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::app::App;
+use crate::i18n::tr;
+
+// Things the palette offers besides the slash commands, each with the key that does the
+// same
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteAction {
+    NextBuffer,
+    PreviousBuffer,
+    JumpToUnread,
+    PreviousHighlight,
+    NextHighlight,
+    NickMenu,
+    FilterNicks,
+    Zoom,
+}
+
+impl PaletteAction {
+    pub const ALL: [PaletteAction; 8] = [
+        PaletteAction::NextBuffer,
+        PaletteAction::PreviousBuffer,
+        PaletteAction::JumpToUnread,
+        PaletteAction::PreviousHighlight,
+        PaletteAction::NextHighlight,
+        PaletteAction::NickMenu,
+        PaletteAction::FilterNicks,
+        PaletteAction::Zoom,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PaletteAction::NextBuffer => "Next buffer",
+            PaletteAction::PreviousBuffer => "Previous buffer",
+            PaletteAction::JumpToUnread => "Jump to unread",
+            PaletteAction::PreviousHighlight => "Previous highlight",
+            PaletteAction::NextHighlight => "Next highlight",
+            PaletteAction::NickMenu => "Nick actions",
+            PaletteAction::FilterNicks => "Filter nicks",
+            PaletteAction::Zoom => "Zoom pane",
+        }
+    }
+
+    pub fn help(self) -> &'static str {
+        match self {
+            PaletteAction::NextBuffer => "Switch to the next buffer (Alt+Right)",
+            PaletteAction::PreviousBuffer => "Switch to the previous buffer (Alt+Left)",
+            PaletteAction::JumpToUnread => "Scroll back to the first unread line (Alt+U)",
+            PaletteAction::PreviousHighlight => {
+                "Scroll to the highlight before, in any buffer (Alt+,)"
+            }
+            PaletteAction::NextHighlight => "Scroll to the highlight after, in any buffer (Alt+.)",
+            PaletteAction::NickMenu => "Open the menu for the typed or picked nick (Alt+N)",
+            PaletteAction::FilterNicks => "Focus the nick list and type to filter it (Alt+L)",
+            PaletteAction::Zoom => "Give the chat pane the whole screen, or put it back (Alt+Z)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteItem {
+    Command(&'static str),
+    Action(PaletteAction),
+}
+
+// One row of the palette: what it runs, its name and the help shown next to it
+pub struct PaletteEntry {
+    pub item: PaletteItem,
+    pub label: &'static str,
+    pub help: &'static str,
+}
+
+// The Ctrl+P palette while it is open
+#[derive(Default)]
+pub struct Palette {
+    pub query: String,
+    pub selected: usize,
+}
+
+// How well `query` matches `text` as a subsequence, ignoring case, or None when it does
+// not. Letters in a row and at the start of words count for more, long texts for less
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = next + text[next..].iter().position(|c| *c == q)?;
+        score += 1;
+        if last.is_some_and(|last| last + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last = Some(found);
+        next = found + 1;
+    }
+    Some(score * 10 - text.len() as i64)
+}
+
+impl App {
+    // Commands and actions matching `query`, best first. They match by name, or else by
+    // their help text containing the query as typed
+    pub fn palette_entries(&self, query: &str) -> Vec<PaletteEntry> {
+        let commands = self.commands.iter().map(|(cmd, desc)| PaletteEntry {
+            item: PaletteItem::Command(cmd),
+            label: cmd,
+            help: tr(desc),
+        });
+        let actions = PaletteAction::ALL.into_iter().map(|action| PaletteEntry {
+            item: PaletteItem::Action(action),
+            label: tr(action.label()),
+            help: tr(action.help()),
+        });
+        let query = query.trim();
+        let mut scored: Vec<(i64, PaletteEntry)> = commands
+            .chain(actions)
+            .filter_map(|entry| {
+                let score = fuzzy_score(query, entry.label).or_else(|| {
+                    let found = !query.is_empty()
+                        && entry.help.to_lowercase().contains(&query.to_lowercase());
+                    // Below every match by name
+                    found.then_some(i64::MIN / 2)
+                })?;
+                Some((score, entry))
+            })
+            .collect();
+        // Without a query everything is listed as is, commands in alphabetical order
+        // before the actions. The sort is stable, equal scores keep that order too
+        if !query.is_empty() {
+            scored.sort_by_key(|(score, _)| -score);
+        }
+        scored.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    // Runs a palette command that needs nothing more, e.g. /back, and gives back the start
+    // of the input line for one that takes arguments, e.g. "/join "
+    pub fn palette_command(&mut self, command: &str, help: &str) -> Option<String> {
+        if help.contains(&format!("{} ", command)) {
+            return Some(format!("{} ", command));
+        }
+        self.execute(command);
+        None
+    }
+}
@@ -0,0 +1,168 @@
+use crate::app::{App, AppEvent, MAX_MESSAGE_BYTES};
+use crate::buffer::BufferKind;
+use crate::config::PasteService;
+use crate::i18n::{tr, trf};
+use crate::message;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// Lines held back because there were more than [paste] max_lines of them, until the user
+// picks what happens with them
+pub struct PendingPaste {
+    pub target: String,
+    pub lines: Vec<String>,
+}
+
+impl PasteService {
+    fn name(self) -> &'static str {
+        match self {
+            PasteService::ZeroXZero => "0x0.st",
+            PasteService::PasteRs => "paste.rs",
+        }
+    }
+
+    // curl arguments that post stdin, both services answer with the link
+    fn curl_args(self) -> [&'static str; 4] {
+        match self {
+            PasteService::ZeroXZero => ["-sSf", "-F", "file=@-", "https://0x0.st"],
+            PasteService::PasteRs => ["-sSf", "--data-binary", "@-", "https://paste.rs"],
+        }
+    }
+}
+
+// Runs curl with `text` on stdin and returns the link it printed
+fn upload(service: PasteService, text: &str) -> Result<String, String> {
+    let mut child = Command::new("curl")
+        .args(service.curl_args())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("could not write to curl: {}", e))?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && url.starts_with("http") {
+        Ok(url)
+    } else {
+        let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(if error.is_empty() { url } else { error })
+    }
+}
+
+impl App {
+    // Text pasted into the input box. One line just goes into `input`, several are sent to
+    // the current channel together with what was typed before them
+    pub fn paste(&mut self, input: &mut String, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        if !text.contains('\n') {
+            input.push_str(&text);
+            return;
+        }
+        let joined = format!("{}{}", std::mem::take(input), text);
+        let lines: Vec<String> = joined
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.to_string())
+            .collect();
+        if self.buffers.active().kind == BufferKind::DccChat {
+            for line in lines {
+                self.dcc_chat_say(&line);
+            }
+            return;
+        }
+        let channel = self.client.current_channel.clone();
+        if channel.is_empty() {
+            self.buffers
+                .push_local(tr("Join a channel first with /join #channel").to_string());
+            return;
+        }
+        self.send_lines(&channel, lines);
+    }
+
+    // Queues `lines` for `target`, or holds them back and asks first when there are more
+    // than the configured maximum
+    pub(crate) fn send_lines(&mut self, target: &str, lines: Vec<String>) {
+        let max = self.config.paste.max_lines;
+        if max > 0 && lines.len() > max {
+            self.buffers.push_local(trf(
+                "{} lines for {}: /paste uploads them to {} and sends the link, /paste send sends them as they are, /paste cancel drops them",
+                &[&lines.len(), &target, &self.config.paste.service.name()],
+            ));
+            self.pending_paste = Some(PendingPaste {
+                target: target.to_string(),
+                lines,
+            });
+            return;
+        }
+        self.queue_lines(target, lines);
+    }
+
+    pub(crate) fn queue_lines(&mut self, target: &str, lines: Vec<String>) {
+        for line in lines {
+            for part in message::split_text(&line, MAX_MESSAGE_BYTES) {
+                self.outgoing.push_back((target.to_string(), part));
+            }
+        }
+    }
+
+    // /paste [send|cancel], decides about the lines held back by send_lines. /paste cancel
+    // also stops lines that are already going out
+    pub(crate) fn paste_command(&mut self, args: &str) {
+        if args == "cancel" && self.pending_paste.is_none() && !self.outgoing.is_empty() {
+            self.cancel_outgoing();
+            return;
+        }
+        let Some(pending) = self.pending_paste.take() else {
+            self.buffers
+                .push_local(tr("Nothing waiting to be pasted").to_string());
+            return;
+        };
+        match args {
+            "" => {
+                let service = self.config.paste.service;
+                self.buffers.push_local(trf(
+                    "Uploading {} lines to {}...",
+                    &[&pending.lines.len(), &service.name()],
+                ));
+                let target = pending.target.clone();
+                let text = pending.lines.join("\n") + "\n";
+                // Kept until the link is sent, so a failed upload can still be sent as it is
+                self.pending_paste = Some(pending);
+                let events = self.events.clone();
+                thread::spawn(move || {
+                    let result = upload(service, &text);
+                    let _ = events.send(AppEvent::PasteUploaded { target, result });
+                });
+            }
+            "send" => self.queue_lines(&pending.target, pending.lines),
+            "cancel" => self.buffers.push_local(trf(
+                "Dropped {} lines for {}",
+                &[&pending.lines.len(), &pending.target],
+            )),
+            _ => {
+                self.buffers
+                    .push_local(tr("Usage: /paste [send|cancel]").to_string());
+                self.pending_paste = Some(pending);
+            }
+        }
+    }
+
+    pub(crate) fn paste_uploaded(&mut self, target: String, result: Result<String, String>) {
+        match result {
+            Ok(url) => {
+                self.pending_paste = None;
+                self.outgoing.push_back((target, url));
+            }
+            Err(e) => self.buffers.push_local(trf(
+                "Upload failed: {}, /paste send sends the lines as they are",
+                &[&e],
+            )),
+        }
+    }
+}
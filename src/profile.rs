@@ -0,0 +1,53 @@
+use std::time::Duration;
+use tracing::info;
+
+// --profile-frames: every frame's draw time and how long what woke the loop (a key, a
+// server line or the poll timeout) waited until the frame showing it was on screen.
+// Each frame goes to the log, the summary is printed on exit
+#[derive(Default)]
+pub struct FrameProfile {
+    draws: Vec<Duration>,
+    latencies: Vec<Duration>,
+}
+
+// The value below which `percent` of the sorted samples fall
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    sorted[(sorted.len() - 1) * percent / 100]
+}
+
+fn summary_line(name: &str, samples: &[Duration]) -> String {
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    format!(
+        "{}: p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+        name,
+        percentile(&sorted, 50),
+        percentile(&sorted, 90),
+        percentile(&sorted, 99),
+        sorted.last().copied().unwrap_or_default()
+    )
+}
+
+impl FrameProfile {
+    pub fn frame(&mut self, draw: Duration, latency: Duration, lines: usize) {
+        info!(
+            draw_us = draw.as_micros() as u64,
+            latency_us = latency.as_micros() as u64,
+            server_lines = lines,
+            "frame"
+        );
+        self.draws.push(draw);
+        self.latencies.push(latency);
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        vec![
+            format!("{} frames drawn", self.draws.len()),
+            summary_line("Draw time", &self.draws),
+            summary_line("Event loop latency", &self.latencies),
+        ]
+    }
+}
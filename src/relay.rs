@@ -0,0 +1,286 @@
+use crate::app::AppEvent;
+use crate::buffer::{BufferKind, BufferList};
+use crate::control;
+use crate::http_api;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, String>;
+
+// Relay protocol, one UTF-8 line per message in both directions.
+//
+// Over TCP the frontend must authenticate first:
+//   -> auth <password>            <- auth ok | auth failed
+// After that it may send:
+//   -> buffers                    <- buffer <name> <kind> <total> <unread>... then "end buffers"
+//   -> switch <buffer>            makes <buffer> the one `input` goes to. Frontends on the
+//                                 Unix socket share the screen and move it there, marking
+//                                 it read, TCP ones each keep their own
+//   -> history <buffer> [count]   <- history <buffer> <text>... then "end history"
+//   -> input <text>               sends <text> to the buffer switched to, or the one on
+//                                 screen. Only frontends on the Unix socket may also run
+//                                 commands, /exec excepted
+//   -> quit
+// and receives every new line in any buffer as it arrives:
+//   <- line <buffer> <text>
+//
+// The protocol is plain TCP; put it behind a TLS terminator or a WebSocket bridge such as
+// websocat when a frontend is not on the same machine.
+
+// Writes to a frontend that stopped reading give up after this long instead of stalling
+// the main loop
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How long a TCP frontend gets to authenticate, and the longest auth line it may send
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_AUTH_LINE: u64 = 1024;
+
+// Ids handed to frontends, shared by the TCP and the Unix socket listeners
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Accepts frontends and authenticates them on their own threads, then hands every further
+// request to the main loop as AppEvent::RelayRequest
+pub fn start(bind: &str, password: &str, events: Sender<AppEvent>) -> Result<()> {
+    if password.is_empty() {
+        return Err("relay.password must be set".to_string());
+    }
+    let listener =
+        TcpListener::bind(bind).map_err(|e| format!("Failed to bind {}: {}", bind, e))?;
+    let password = password.to_string();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Ok(mut writer) = stream.try_clone() else {
+                continue;
+            };
+            let _ = writer.set_write_timeout(Some(WRITE_TIMEOUT));
+            let _ = stream.set_read_timeout(Some(AUTH_TIMEOUT));
+            let events = events.clone();
+            let password = password.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream);
+                let _ = writer.write_all(b"hello irconic\n");
+                if !authenticate(&mut reader, &mut writer, &password) {
+                    return;
+                }
+                // Authenticated frontends may stay attached and quiet for as long as they like
+                let _ = reader.get_ref().set_read_timeout(None);
+                serve_frontend(reader, Box::new(writer), false, events)
+            });
+        }
+    });
+    Ok(())
+}
+
+// The same protocol on a Unix socket. Access is limited by the socket's file permissions,
+// so frontends skip the auth step. Used by --attach to talk to a --daemon session
+pub fn start_unix(path: &Path, events: Sender<AppEvent>) -> Result<()> {
+    let listener = control::bind_private(path)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Ok(mut writer) = stream.try_clone() else {
+                continue;
+            };
+            let _ = writer.set_write_timeout(Some(WRITE_TIMEOUT));
+            let events = events.clone();
+            thread::spawn(move || {
+                let _ = writer.write_all(b"hello irconic\n");
+                serve_frontend(BufReader::new(stream), Box::new(writer), true, events)
+            });
+        }
+    });
+    Ok(())
+}
+
+// Reads "auth <password>" and answers it. The line is capped at MAX_AUTH_LINE and the
+// password compared in constant time, the caller sets how long the frontend gets
+fn authenticate(reader: &mut impl BufRead, writer: &mut impl Write, password: &str) -> bool {
+    let mut line = String::new();
+    let read = reader.by_ref().take(MAX_AUTH_LINE).read_line(&mut line);
+    let authenticated = read.is_ok()
+        && line.ends_with('\n')
+        && line
+            .trim_end()
+            .strip_prefix("auth ")
+            .is_some_and(|given| http_api::same_token(given, password));
+    let reply: &[u8] = if authenticated {
+        b"auth ok\n"
+    } else {
+        b"auth failed\n"
+    };
+    let _ = writer.write_all(reply);
+    authenticated
+}
+
+// `local` frontends came in on the Unix socket and may run commands, see App::relay_input
+fn serve_frontend(
+    reader: impl BufRead,
+    writer: Box<dyn Write + Send>,
+    local: bool,
+    events: Sender<AppEvent>,
+) {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let event = AppEvent::RelayAttach { id, writer, local };
+    if events.send(event).is_err() {
+        return;
+    }
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim() == "quit" {
+            break;
+        }
+        if events.send(AppEvent::RelayRequest { id, line }).is_err() {
+            return;
+        }
+    }
+    let _ = events.send(AppEvent::RelayDetach { id });
+}
+
+struct Frontend {
+    writer: Box<dyn Write + Send>,
+    // Came in on the Unix socket, see serve_frontend
+    local: bool,
+    // Per buffer, how many lines (Buffer::total) this frontend has already been sent
+    seen: HashMap<String, usize>,
+    // The buffer a TCP frontend switched to, local ones use the one on screen
+    selected: Option<String>,
+}
+
+// The frontends currently attached, owned by the main loop
+#[derive(Default)]
+pub struct Relay {
+    frontends: HashMap<usize, Frontend>,
+}
+
+impl Relay {
+    pub fn attach(
+        &mut self,
+        id: usize,
+        writer: Box<dyn Write + Send>,
+        local: bool,
+        buffers: &BufferList,
+    ) {
+        // Only lines arriving from now on are pushed, older ones are fetched with `history`
+        let seen = buffers.iter().map(|b| (b.name.clone(), b.total)).collect();
+        let frontend = Frontend {
+            writer,
+            local,
+            seen,
+            selected: None,
+        };
+        self.frontends.insert(id, frontend);
+    }
+
+    pub fn detach(&mut self, id: usize) {
+        self.frontends.remove(&id);
+    }
+
+    pub fn is_attached(&self) -> bool {
+        !self.frontends.is_empty()
+    }
+
+    pub fn is_local(&self, id: usize) -> bool {
+        self.frontends.get(&id).is_some_and(|f| f.local)
+    }
+
+    pub fn select(&mut self, id: usize, name: &str) {
+        if let Some(frontend) = self.frontends.get_mut(&id) {
+            frontend.selected = Some(name.to_string());
+        }
+    }
+
+    pub fn selected(&self, id: usize) -> Option<&str> {
+        self.frontends.get(&id)?.selected.as_deref()
+    }
+
+    // Requests other than `input` and `switch`, which change App state and are handled there
+    pub fn handle_request(&mut self, id: usize, request: &str, buffers: &BufferList) {
+        let (verb, args) = request.split_once(' ').unwrap_or((request, ""));
+        let reply: Vec<String> = match verb {
+            "buffers" => buffers
+                .iter()
+                .map(|b| {
+                    let kind = kind_name(b.kind);
+                    format!("buffer {} {} {} {}", b.name, kind, b.total, b.unread())
+                })
+                .chain(std::iter::once("end buffers".to_string()))
+                .collect(),
+            "history" => {
+                let mut parts = args.split_whitespace();
+                let name = parts.next().unwrap_or("");
+                let count = parts.next().and_then(|c| c.parse().ok()).unwrap_or(100);
+                match buffers.get(name) {
+                    Some(buffer) => buffer
+                        .recent(count)
+                        .map(|l| format!("history {} {}", buffer.name, l.text))
+                        .chain(std::iter::once("end history".to_string()))
+                        .collect(),
+                    None => vec![format!("error no buffer named '{}'", name)],
+                }
+            }
+            _ => vec![format!("error unknown request '{}'", verb)],
+        };
+        self.reply(id, &reply);
+    }
+
+    // Pushes lines added to any buffer since the last call to every attached frontend
+    pub fn forward_new_lines(&mut self, buffers: &BufferList) {
+        let ids: Vec<usize> = self.frontends.keys().copied().collect();
+        for id in ids {
+            let mut out = Vec::new();
+            if let Some(frontend) = self.frontends.get_mut(&id) {
+                for buffer in buffers.iter() {
+                    let seen = frontend.seen.entry(buffer.name.clone()).or_insert(0);
+                    out.extend(
+                        buffer
+                            .lines_since(*seen)
+                            .map(|l| format!("line {} {}", buffer.name, l.text)),
+                    );
+                    *seen = buffer.total;
+                }
+            }
+            if !out.is_empty() {
+                self.reply(id, &out);
+            }
+        }
+    }
+
+    pub fn reply(&mut self, id: usize, lines: &[String]) {
+        let Some(frontend) = self.frontends.get_mut(&id) else {
+            return;
+        };
+        let mut data = lines.join("\n");
+        data.push('\n');
+        // A frontend we cannot write to is dropped, it will reattach and ask for history
+        if frontend.writer.write_all(data.as_bytes()).is_err() {
+            self.frontends.remove(&id);
+        }
+    }
+}
+
+pub(crate) fn kind_name(kind: BufferKind) -> &'static str {
+    match kind {
+        BufferKind::Dashboard => "dashboard",
+        BufferKind::Server => "server",
+        BufferKind::Channel => "channel",
+        BufferKind::Query => "query",
+        BufferKind::DccChat => "dccchat",
+        BufferKind::Transfers => "transfers",
+        BufferKind::Invites => "invites",
+        BufferKind::Access => "access",
+        BufferKind::Bans => "bans",
+        BufferKind::Notices => "notices",
+        BufferKind::Debug => "debug",
+        BufferKind::AwayLog => "awaylog",
+        BufferKind::Filtered => "filtered",
+    }
+}
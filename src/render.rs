@@ -0,0 +1,557 @@
+use crate::buffer::{Buffer, BufferKind, Line as BufferLine};
+use crate::channel;
+use crate::clock::TimeFormat;
+use crate::config::{Grouping, Indent, ThemeConfig};
+use crate::filter;
+use crate::message::Message;
+use crate::oper;
+use crate::theme::Theme;
+use chrono::NaiveDate;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Buffer lines wrapped to the width of the chat pane, kept between frames so a frame only
+// wraps the lines that arrived since the last one and builds just the rows on screen
+pub struct LayoutCache {
+    buffers: HashMap<String, Wrapped>,
+    look: Look,
+}
+
+// How lines are turned into rows, from [theme]
+struct Look {
+    theme: &'static Theme,
+    time: TimeFormat,
+    group: Grouping,
+    group_window: Duration,
+    indent: Indent,
+    nick_width: usize,
+}
+
+struct Wrapped {
+    width: usize,
+    // Whether the rows start with the time, /toggle timestamps
+    timestamps: bool,
+    // buffer.total when the rows were last brought up to date
+    total: usize,
+    // Rows of each line the buffer still keeps, oldest first
+    lines: VecDeque<Vec<Line<'static>>>,
+    // Date of the newest wrapped line, a line from another day gets a separator
+    day: Option<NaiveDate>,
+    // Who said the newest wrapped line and when, for grouping
+    speaker: Option<(String, SystemTime)>,
+    // Rows scrolled back from the newest, 0 follows new lines
+    scroll: usize,
+    // Rows on screen at the last frame
+    height: usize,
+    // Number of the highlighted line last jumped to, counted like buffer.total, and
+    // whether the next frame still has to scroll there
+    highlight: Option<usize>,
+    jump: bool,
+}
+
+impl Wrapped {
+    fn new(width: usize, timestamps: bool, height: usize) -> Self {
+        Wrapped {
+            width,
+            timestamps,
+            total: 0,
+            lines: VecDeque::new(),
+            day: None,
+            speaker: None,
+            scroll: 0,
+            height,
+            highlight: None,
+            jump: false,
+        }
+    }
+}
+
+// Lines that stand out and go through Alt+, and Alt+.
+pub fn highlighted(line: &BufferLine) -> bool {
+    line.text.starts_with("!!!")
+}
+
+impl LayoutCache {
+    pub fn new(time: TimeFormat, theme: &ThemeConfig) -> Self {
+        LayoutCache {
+            buffers: HashMap::new(),
+            look: Look {
+                theme: Theme::named(&theme.name),
+                time,
+                group: theme.group,
+                group_window: Duration::from_secs(theme.group_secs),
+                indent: theme.indent,
+                nick_width: theme.nick_width.max(2),
+            },
+        }
+    }
+
+    // Switches to `theme`, true when that changed anything. Every line is laid out again
+    // with its colors, like after a resize
+    pub fn set_theme(&mut self, theme: &'static Theme) -> bool {
+        if std::ptr::eq(self.look.theme, theme) {
+            return false;
+        }
+        self.look.theme = theme;
+        for wrapped in self.buffers.values_mut() {
+            wrapped.width = 0;
+        }
+        true
+    }
+
+    // The newest `height` rows of `buffer` at `width` columns, with the unread rule
+    pub fn rows(
+        &mut self,
+        buffer: &Buffer,
+        width: usize,
+        height: usize,
+        timestamps: bool,
+    ) -> Vec<Line<'static>> {
+        let wrapped = self
+            .buffers
+            .entry(buffer.name.clone())
+            .or_insert_with(|| Wrapped::new(width, timestamps, height));
+        // Fewer lines than we laid out: a new buffer under the name of a closed one, e.g. a
+        // query opened again or renamed to
+        if wrapped.total > buffer.total {
+            *wrapped = Wrapped::new(width, timestamps, height);
+        }
+        // After a resize or a timestamp toggle every kept line is wrapped again
+        if wrapped.width != width || wrapped.timestamps != timestamps {
+            wrapped.width = width;
+            wrapped.timestamps = timestamps;
+            wrapped.lines.clear();
+            wrapped.day = None;
+            wrapped.speaker = None;
+            wrapped.total = buffer.total - buffer.lines.len();
+        }
+        for line in buffer.lines_since(wrapped.total) {
+            let rows = self.look.lay_out(wrapped, buffer.kind, line, width);
+            wrapped.lines.push_back(rows);
+        }
+        wrapped.total = buffer.total;
+        // Lines the buffer dropped or cleared
+        while wrapped.lines.len() > buffer.lines.len() {
+            wrapped.lines.pop_front();
+        }
+
+        // Unread rule above the lines that came in since the user last left this buffer
+        let rule_before = buffer
+            .lines_after_marker()
+            .map(|new| wrapped.lines.len() - new);
+        let total_rows = wrapped.lines.iter().map(|rows| rows.len()).sum::<usize>()
+            + usize::from(rule_before.is_some());
+        wrapped.height = height;
+        // The highlight jumped to goes on the top row
+        let first = buffer.total - buffer.lines.len();
+        if std::mem::take(&mut wrapped.jump)
+            && let Some(index) = wrapped.highlight.and_then(|n| n.checked_sub(first))
+        {
+            let below: usize = wrapped
+                .lines
+                .iter()
+                .skip(index)
+                .map(|rows| rows.len())
+                .sum();
+            let rule = usize::from(rule_before.is_some_and(|rule| rule > index));
+            wrapped.scroll = (below + rule).saturating_sub(height);
+        }
+        wrapped.scroll = wrapped.scroll.min(total_rows.saturating_sub(height));
+        // Counted from the newest row, the ones scrolled past are skipped
+        let mut skip = wrapped.scroll;
+        let mut rows = Vec::with_capacity(height);
+        let mut keep = |row: Line<'static>, rows: &mut Vec<Line<'static>>| {
+            if skip > 0 {
+                skip -= 1;
+            } else if rows.len() < height {
+                rows.push(row);
+            }
+        };
+        for (index, line) in wrapped.lines.iter().enumerate().rev() {
+            for row in line.iter().rev() {
+                keep(row.clone(), &mut rows);
+            }
+            if rule_before == Some(index) {
+                keep(
+                    Line::from(Span::styled(
+                        format!("{:─^width$}", " new messages ", width = width),
+                        Style::default().fg(self.look.theme.alert),
+                    )),
+                    &mut rows,
+                );
+            }
+            if rows.len() == height {
+                break;
+            }
+        }
+        rows.reverse();
+        rows
+    }
+
+    // Scrolls `name` a page back, or forward towards the newest line
+    pub fn page(&mut self, name: &str, back: bool) {
+        if let Some(wrapped) = self.buffers.get_mut(name) {
+            let step = wrapped.height.saturating_sub(1).max(1);
+            wrapped.scroll = if back {
+                wrapped.scroll + step
+            } else {
+                wrapped.scroll.saturating_sub(step)
+            };
+        }
+    }
+
+    // Scrolls so the unread rule is the top row, false when there is none
+    pub fn jump_to_unread(&mut self, buffer: &Buffer) -> bool {
+        let (Some(wrapped), Some(new)) = (
+            self.buffers.get_mut(&buffer.name),
+            buffer.lines_after_marker(),
+        ) else {
+            return false;
+        };
+        let below: usize = wrapped
+            .lines
+            .iter()
+            .rev()
+            .take(new)
+            .map(|rows| rows.len())
+            .sum();
+        wrapped.scroll = (below + 1).saturating_sub(wrapped.height);
+        true
+    }
+
+    // Scrolls to the highlighted line before (back) or after the one last jumped to. The
+    // first jump back starts from the newest line, the first one forward from the unread
+    // rule. False when there is no highlight that way
+    pub fn jump_to_highlight(&mut self, buffer: &Buffer, back: bool) -> bool {
+        let Some(wrapped) = self.buffers.get_mut(&buffer.name) else {
+            return false;
+        };
+        let first = buffer.total - buffer.lines.len();
+        let mut highlights = buffer
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| highlighted(line))
+            .map(|(index, _)| first + index);
+        let target = match (back, wrapped.highlight) {
+            (true, from) => highlights.rev().find(|&n| n < from.unwrap_or(buffer.total)),
+            (false, Some(from)) => highlights.find(|&n| n > from),
+            (false, None) => highlights.find(|&n| n >= buffer.marker),
+        };
+        let Some(target) = target else {
+            return false;
+        };
+        wrapped.highlight = Some(target);
+        wrapped.jump = true;
+        true
+    }
+
+    // Jumps to a highlight of `buffer` just switched to: the first unread one going forward,
+    // otherwise the newest
+    pub fn enter_highlight(&mut self, buffer: &Buffer, back: bool) -> bool {
+        // Laid out on the next frame like any buffer seen for the first time
+        let wrapped = self
+            .buffers
+            .entry(buffer.name.clone())
+            .or_insert_with(|| Wrapped::new(0, true, 0));
+        wrapped.highlight = None;
+        self.jump_to_highlight(buffer, back) || self.jump_to_highlight(buffer, true)
+    }
+
+    // The user left `buffer`. When it was scrolled back the unread rule goes below the last
+    // line they had on screen rather than below the newest one, and the view follows new
+    // lines again for when they come back
+    pub fn leave(&mut self, buffer: &mut Buffer) {
+        let Some(wrapped) = self.buffers.get_mut(&buffer.name) else {
+            return;
+        };
+        let mut rows = 0;
+        let mut unseen = 0;
+        for line in wrapped.lines.iter().rev() {
+            rows += line.len();
+            if rows > wrapped.scroll {
+                break;
+            }
+            unseen += 1;
+        }
+        buffer.marker = buffer.total - unseen;
+        wrapped.scroll = 0;
+        wrapped.highlight = None;
+    }
+}
+
+impl Look {
+    // Rows of one line: a separator if the day changed since the line before, the time and
+    // the text, with the sender left out when they also said the line before
+    fn lay_out(
+        &self,
+        wrapped: &mut Wrapped,
+        kind: BufferKind,
+        line: &BufferLine,
+        width: usize,
+    ) -> Vec<Line<'static>> {
+        let mut rows = Vec::new();
+        let day = self.time.day(line.time);
+        if wrapped.day.is_some_and(|last| last != day) {
+            rows.push(Line::from(Span::styled(
+                format!(
+                    "{:─^width$}",
+                    format!(" {} ", self.time.day_name(line.time)),
+                    width = width
+                ),
+                Style::default().fg(self.theme.dim),
+            )));
+            wrapped.speaker = None;
+        }
+        wrapped.day = Some(day);
+
+        let style = if highlighted(line) {
+            Style::default()
+                .fg(self.theme.accent)
+                .add_modifier(Modifier::BOLD)
+        } else if line.text.starts_with(filter::DIM) {
+            Style::default().fg(self.theme.dim)
+        } else if line.text.starts_with(oper::ANNOUNCE) {
+            Style::default().fg(self.theme.alert)
+        } else {
+            Style::default()
+        };
+        let mut parts = Vec::new();
+        // The debug buffer has its own stamps with milliseconds
+        let stamp = match kind {
+            BufferKind::Debug => None,
+            _ if !wrapped.timestamps => None,
+            _ => self.time.stamp(line.time),
+        };
+        if let Some(stamp) = stamp {
+            parts.push((format!("{} ", stamp), style));
+        }
+
+        let speaker = speaker(&line.text);
+        let repeated = match (&speaker, &wrapped.speaker) {
+            (Some((who, ..)), Some((last, at))) => {
+                who == last
+                    && line
+                        .time
+                        .duration_since(*at)
+                        .is_ok_and(|gap| gap <= self.group_window)
+            }
+            _ => false,
+        };
+        match speaker {
+            Some((who, label, text)) if repeated && self.group != Grouping::Off => {
+                let nick = self.nick_column(&label);
+                let nick = match self.group {
+                    Grouping::Dim => (nick, Style::default().fg(self.theme.dim)),
+                    _ => (" ".repeat(nick.width()), style),
+                };
+                parts.push(nick);
+                parts.push((text.to_string(), style));
+                wrapped.speaker = Some((who, line.time));
+            }
+            Some((who, label, text)) => {
+                parts.push((self.nick_column(&label), self.nick_style(&who, style)));
+                parts.push((text.to_string(), style));
+                wrapped.speaker = Some((who, line.time));
+            }
+            None => {
+                parts.push((line.text.clone(), style));
+                wrapped.speaker = None;
+            }
+        }
+        // Wrapped rows start under the text, past the time and the nick
+        let indent = match self.indent {
+            Indent::Off => 0,
+            _ => parts[..parts.len() - 1]
+                .iter()
+                .map(|(text, _)| text.width())
+                .sum(),
+        };
+        rows.extend(wrap(&parts, width, indent));
+        rows
+    }
+
+    // The nick in front of a message, e.g. "<bob> ", as wide as nick_width and right
+    // aligned with indent = "fixed"
+    fn nick_column(&self, nick: &str) -> String {
+        if self.indent != Indent::Fixed {
+            return nick.to_string();
+        }
+        let nick = nick.trim_end();
+        let width = self.nick_width;
+        if nick.width() <= width {
+            return format!("{:>width$} ", nick, width = width);
+        }
+        let mut cut = String::new();
+        for c in nick.chars() {
+            if cut.width() + c.width().unwrap_or(0) >= width {
+                break;
+            }
+            cut.push(c);
+        }
+        format!("{:<width$}+ ", cut, width = width - 1)
+    }
+
+    // The sender's color from the theme, our own lines and highlights keep theirs
+    fn nick_style(&self, who: &str, style: Style) -> Style {
+        match who.split(' ').next() {
+            Some(nick) if nick != "->" && style == Style::default() => {
+                style.fg(self.theme.nick(nick))
+            }
+            _ => style,
+        }
+    }
+}
+
+// Who said a chat line, what goes in the nick column and the text after it, as
+// ("nick target", label, text). Our own lines are "-> target: text", DCC chats
+// "<nick> text". Lines from the server keep the whole ":nick!user@host PRIVMSG #chan :text"
+// and are shown as "<nick:#chan> text", or "<nick> text" when sent to us, behind the "!!! "
+// of a highlight or a filter's "~~~ ". /me is "* nick:#chan text". A message for only the
+// ops of #chan, to "@#chan", gets "<nick:#chan> (to ops) ". /host nick has the rest
+fn speaker(text: &str) -> Option<(String, String, &str)> {
+    if let Some(rest) = text.strip_prefix("-> ")
+        && let Some((target, said)) = rest.split_once(": ")
+    {
+        let column = text.len() - said.len();
+        return Some((format!("-> {}", target), text[..column].to_string(), said));
+    }
+    if let Some(rest) = text.strip_prefix('<')
+        && let Some((nick, said)) = rest.split_once("> ")
+    {
+        return Some((nick.to_string(), format!("<{}> ", nick), said));
+    }
+    let marker = ["!!! ", filter::DIM]
+        .into_iter()
+        .find(|marker| text.starts_with(marker))
+        .unwrap_or_default();
+    let message = Message::parse(&text[marker.len()..])?;
+    if message.command != "PRIVMSG" {
+        return None;
+    }
+    // The body as it is in `text`, so what is shown borrows from it
+    let body = &text[text.strip_suffix(message.trailing()?)?.len()..];
+    let (said, action) = match message.ctcp() {
+        // Other CTCPs stay as they are
+        Some(_) => {
+            let said = body.strip_prefix("\x01ACTION ")?;
+            (said.strip_suffix('\x01').unwrap_or(said), true)
+        }
+        None => (body, false),
+    };
+    let (nick, target) = (message.nick()?, message.param(0)?);
+    let (open, close) = if action { ("* ", "") } else { ("<", ">") };
+    let label = match channel::status_target(target) {
+        Some((symbols, name)) => format!(
+            "{}{}{}:{}{} (to {}) ",
+            marker,
+            open,
+            nick,
+            name,
+            close,
+            status_name(symbols)
+        ),
+        None if target.starts_with(['#', '&']) => {
+            format!("{}{}{}:{}{} ", marker, open, nick, target, close)
+        }
+        None => format!("{}{}{}{} ", marker, open, nick, close),
+    };
+    Some((format!("{} {}", nick, target), label, said))
+}
+
+// Who a STATUSMSG reaches, named after its symbol. Everyone ranked above gets it too
+fn status_name(symbols: &str) -> &'static str {
+    match symbols.chars().last() {
+        Some('~') => "owners",
+        Some('&') => "admins",
+        Some('@') => "ops",
+        Some('%') => "halfops",
+        Some('+') => "voiced",
+        _ => "some",
+    }
+}
+
+// One plain line wrapped with its continuation rows under the text after the nick, for
+// panes that do not keep a layout cache like the attach client
+pub fn hanging_rows(text: &str, width: usize) -> Vec<Line<'static>> {
+    match speaker(text) {
+        Some((_, label, said)) => {
+            let indent = label.width();
+            let line = format!("{}{}", label, said);
+            wrap(&[(line, Style::default())], width, indent)
+        }
+        None => wrap(&[(text.to_string(), Style::default())], width, 0),
+    }
+}
+
+// Word wraps one buffer line made of differently styled parts, words wider than the pane
+// are cut. Rows after the first start `indent` columns in, unless that leaves too little
+// room for the text
+pub fn wrap(parts: &[(String, Style)], width: usize, indent: usize) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let indent = if indent * 2 > width { 0 } else { indent };
+    let chars: Vec<(char, Style)> = parts
+        .iter()
+        .flat_map(|(text, style)| text.chars().map(move |c| (c, *style)))
+        .collect();
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut used = 0;
+    // Where the text of the current row starts, past the indent on continuation rows
+    let mut row_start = 0;
+    let new_row = |rows: &mut Vec<_>, row: &mut Vec<(char, Style)>| {
+        rows.push(std::mem::take(row));
+        row.extend(std::iter::repeat_n((' ', Style::default()), indent));
+        indent
+    };
+    // Spaces are kept except where a row is broken, so a blanked nick column keeps its width
+    let mut broke = false;
+    // Where the current word starts in `chars`
+    let mut start = 0;
+    for word in chars.split(|(c, _)| *c == ' ') {
+        let word_width: usize = word.iter().map(|(c, _)| c.width().unwrap_or(0)).sum();
+        if used > row_start && used + 1 + word_width > width {
+            used = new_row(&mut rows, &mut row);
+            row_start = used;
+            broke = true;
+        } else if start > 0 && !broke {
+            row.push(chars[start - 1]);
+            used += 1;
+        }
+        start += word.len() + 1;
+        for &(c, style) in word {
+            let w = c.width().unwrap_or(0);
+            if used + w > width && used > row_start {
+                used = new_row(&mut rows, &mut row);
+                row_start = used;
+            }
+            row.push((c, style));
+            used += w;
+            broke = false;
+        }
+    }
+    rows.push(row);
+    rows.into_iter().map(spans).collect()
+}
+
+// One row of styled characters as spans, a new span wherever the style changes
+fn spans(row: Vec<(char, Style)>) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut text = String::new();
+    let mut current = None;
+    for (c, style) in row {
+        if current.is_some_and(|s| s != style) {
+            spans.push(Span::styled(
+                std::mem::take(&mut text),
+                current.unwrap_or_default(),
+            ));
+        }
+        current = Some(style);
+        text.push(c);
+    }
+    spans.push(Span::styled(text, current.unwrap_or_default()));
+    Line::from(spans)
+}
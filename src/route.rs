@@ -0,0 +1,225 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::channel;
+use crate::message::Message;
+
+// Commands about one channel, named in their first parameter
+const CHANNEL_COMMANDS: &[&str] = &[
+    "PRIVMSG", "NOTICE", "JOIN", "PART", "KICK", "REMOVE", "TOPIC", "MODE",
+];
+
+impl App {
+    // The buffer a line from the server belongs in: its channel's, the query with whoever
+    // messaged us, or the server buffer for everything else. A channel buffer opens on our
+    // JOIN, a query on the first private message
+    pub(crate) fn route(&mut self, msg: &Message) -> String {
+        let Some((name, kind)) = self.destination(msg) else {
+            return "server".to_string();
+        };
+        if self.buffers.get(&name).is_none() {
+            self.buffers.open(&name, kind);
+            // Not open yet when replayed() looked, the new buffer starts with this msgid
+            if let (Some(msgid), Some(buffer)) =
+                (msg.tags.get("msgid"), self.buffers.get_mut(&name))
+            {
+                buffer.record_msgid(msgid);
+            }
+        }
+        name
+    }
+
+    // Playback that overlaps what we already have, e.g. from a bouncer: the buffer `msg`
+    // belongs in has seen its msgid. Records the msgid otherwise
+    pub(crate) fn replayed(&mut self, msg: &Message) -> bool {
+        let Some(msgid) = msg.tags.get("msgid") else {
+            return false;
+        };
+        let name = self
+            .destination(msg)
+            .map_or_else(|| "server".to_string(), |(name, _)| name);
+        self.buffers
+            .get_mut(&name)
+            .is_some_and(|buffer| !buffer.record_msgid(msgid))
+    }
+
+    // Name and kind of the channel or query buffer for `msg`, open or not, None for the
+    // server buffer
+    fn destination(&self, msg: &Message) -> Option<(String, BufferKind)> {
+        let channel_name = match msg.command.as_str() {
+            command if CHANNEL_COMMANDS.contains(&command) => msg.param(0),
+            // RPL_TOPIC and RPL_TOPICWHOTIME when joining
+            "332" | "333" => msg.param(1),
+            _ => None,
+        }
+        .map(channel::strip_status)
+        .filter(|name| name.starts_with(['#', '&']));
+        if let Some(name) = channel_name {
+            if let Some(buffer) = self.buffer_of(name, BufferKind::Channel) {
+                return Some((buffer, BufferKind::Channel));
+            }
+            if let Some(chan) = self.client.channel(name) {
+                return Some((chan.name.clone(), BufferKind::Channel));
+            }
+            // Our own JOIN, before irc_client has the channel
+            if msg.command == "JOIN" && msg.nick().is_some_and(|nick| self.client.is_me(nick)) {
+                return Some((name.to_string(), BufferKind::Channel));
+            }
+            return None;
+        }
+        // Other CTCPs than /me are answered, not part of the conversation
+        let private = msg.command == "PRIVMSG"
+            && msg.ctcp().is_none_or(|ctcp| ctcp.starts_with("ACTION "))
+            && msg.prefix.as_deref().is_some_and(|p| p.contains('!'));
+        let (true, Some(nick), Some(target)) = (private, msg.nick(), msg.param(0)) else {
+            return None;
+        };
+        // Our own, played back by a bouncer, go with the one we sent them to
+        let with = if self.client.is_me(nick) {
+            target
+        } else if self.client.is_me(target) {
+            nick
+        } else {
+            return None;
+        };
+        match self.buffer_of(with, BufferKind::Query) {
+            Some(buffer) => Some((buffer, BufferKind::Query)),
+            // A nick named like one of our own buffers stays in the server buffer
+            None if self.buffers.get(with).is_none() => Some((with.to_string(), BufferKind::Query)),
+            None => None,
+        }
+    }
+
+    // Where our own line to `target` goes: its channel or query buffer, or the buffer on
+    // screen when it has none
+    pub(crate) fn push_sent(&mut self, target: &str, line: String) {
+        let name = self
+            .buffer_of(channel::strip_status(target), BufferKind::Channel)
+            .or_else(|| self.buffer_of(target, BufferKind::Query));
+        match name {
+            Some(name) => self.buffers.push_to(&name, line),
+            None => self.buffers.push_local(line),
+        }
+    }
+
+    // Shows the buffer of `target`, opening a query buffer for a nick. Typing then goes
+    // there, see follow_target
+    pub(crate) fn show_target(&mut self, target: &str) {
+        let kind = if target.starts_with(['#', '&']) {
+            BufferKind::Channel
+        } else {
+            BufferKind::Query
+        };
+        self.client.current_channel = target.to_string();
+        match self.buffer_of(target, kind) {
+            Some(name) => {
+                self.buffers.select_name(&name);
+            }
+            // A nick named like one of our own buffers stays in the server buffer
+            None if kind == BufferKind::Query && self.buffers.get(target).is_none() => {
+                self.buffers.open(target, kind);
+                self.buffers.select_name(target);
+            }
+            None if self.buffers.active().kind.is_view() => {
+                self.buffers.select_kind(BufferKind::Server);
+            }
+            None => {}
+        }
+    }
+
+    // Keeps the current channel and the buffer on screen together. Whichever changed since
+    // the last tick wins: a /join or a kick moves the view, switching to a channel or query
+    // buffer makes it the one typed into
+    pub(crate) fn follow_target(&mut self) {
+        let current = self.client.current_channel.clone();
+        let active = self.buffers.active();
+        let (active_name, active_kind) = (active.name.clone(), active.kind);
+        let (followed_target, followed_buffer) = &self.followed;
+        if !current.eq_ignore_ascii_case(followed_target) {
+            // Not while reading something else, e.g. a DCC chat or the debug buffer
+            let here = active_kind.is_view()
+                || matches!(
+                    active_kind,
+                    BufferKind::Server | BufferKind::Channel | BufferKind::Query
+                );
+            if here
+                && let Some(name) = self
+                    .buffer_of(&current, BufferKind::Channel)
+                    .or_else(|| self.buffer_of(&current, BufferKind::Query))
+            {
+                self.buffers.select_name(&name);
+            }
+        } else if active_name != *followed_buffer
+            && matches!(active_kind, BufferKind::Channel | BufferKind::Query)
+        {
+            self.client.current_channel = active_name;
+        }
+        self.followed = (
+            self.client.current_channel.clone(),
+            self.buffers.active().name.clone(),
+        );
+    }
+
+    // Name of the buffer of `kind` called `name`, in the case it was opened with
+    pub(crate) fn buffer_of(&self, name: &str, kind: BufferKind) -> Option<String> {
+        self.buffers
+            .get(name)
+            .filter(|buffer| buffer.kind == kind)
+            .map(|buffer| buffer.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::App;
+    use crate::config::Config;
+    use crate::irc_client::IrcClient;
+    use std::sync::mpsc;
+
+    fn app() -> App {
+        let (events, _) = mpsc::channel();
+        let mut app = App::new(IrcClient::new("tester"), Config::default(), events);
+        app.handle_server_line(":tester!u@h JOIN #rust".to_string());
+        app.handle_server_line(":tester!u@h JOIN #irconic".to_string());
+        app
+    }
+
+    fn lines(app: &App, name: &str) -> Vec<String> {
+        let buffer = app.buffers.get(name).unwrap();
+        buffer.lines.iter().map(|line| line.text.clone()).collect()
+    }
+
+    #[test]
+    fn a_replayed_msgid_is_shown_once() {
+        let mut app = app();
+        let line = "@msgid=abc :bob!u@h PRIVMSG #rust :hello";
+        app.handle_server_line(line.to_string());
+        app.handle_server_line(line.to_string());
+        let said: Vec<String> = lines(&app, "#rust")
+            .into_iter()
+            .filter(|line| line.ends_with(":hello"))
+            .collect();
+        assert_eq!(said.len(), 1);
+    }
+
+    #[test]
+    fn msgids_are_kept_per_buffer() {
+        let mut app = app();
+        app.handle_server_line("@msgid=abc :bob!u@h PRIVMSG #rust :hello".to_string());
+        app.handle_server_line("@msgid=abc :bob!u@h PRIVMSG #irconic :hello".to_string());
+        assert!(
+            lines(&app, "#irconic")
+                .iter()
+                .any(|line| line.ends_with(":hello"))
+        );
+        // The first message of a query opens it, its replay is still dropped
+        let private = "@msgid=def :bob!u@h PRIVMSG tester :psst";
+        app.handle_server_line(private.to_string());
+        app.handle_server_line(private.to_string());
+        assert_eq!(lines(&app, "bob").len(), 1);
+        assert!(
+            !lines(&app, "server")
+                .iter()
+                .any(|line| line.ends_with(":hello"))
+        );
+    }
+}
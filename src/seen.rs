@@ -0,0 +1,142 @@
+use crate::app::App;
+use crate::channel;
+use crate::clock;
+use crate::config::Config;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Writing on every line would be a lot of disk traffic in busy channels
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+// The last thing a nick was seen doing
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Sighting {
+    // Seconds since the epoch
+    pub time: u64,
+    pub action: String,
+}
+
+// Last sightings of every nick, saved in seen.toml under the state directory as
+// "network/nick"
+pub struct Seen {
+    path: Option<PathBuf>,
+    nicks: BTreeMap<String, Sighting>,
+    dirty: bool,
+    saved: Instant,
+}
+
+impl Seen {
+    // A missing or broken file just means nobody was seen yet
+    pub fn load() -> Self {
+        let path = Config::log_dir().map(|dir| dir.join("seen.toml"));
+        let nicks = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Seen {
+            path,
+            nicks,
+            dirty: false,
+            saved: Instant::now(),
+        }
+    }
+
+    fn id(network: &str, nick: &str) -> String {
+        format!(
+            "{}/{}",
+            network.to_ascii_lowercase(),
+            channel::nick_key(nick)
+        )
+    }
+
+    fn record(&mut self, network: &str, nick: &str, action: String) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.nicks
+            .insert(Self::id(network, nick), Sighting { time, action });
+        self.dirty = true;
+    }
+
+    pub fn get(&self, network: &str, nick: &str) -> Option<&Sighting> {
+        self.nicks.get(&Self::id(network, nick))
+    }
+
+    // Writes the file when something changed and the last write is a while ago, or
+    // right away with `now`
+    pub fn save(&mut self, now: bool) -> Result<(), String> {
+        if !self.dirty || (!now && self.saved.elapsed() < SAVE_INTERVAL) {
+            return Ok(());
+        }
+        self.dirty = false;
+        self.saved = Instant::now();
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| trf("Failed to create {}: {}", &[&dir.display(), &e]))?;
+        }
+        let contents = toml::to_string(&self.nicks)
+            .map_err(|e| trf("Failed to encode seen nicks: {}", &[&e]))?;
+        fs::write(path, contents).map_err(|e| trf("Failed to write {}: {}", &[&path.display(), &e]))
+    }
+}
+
+impl App {
+    // Notes what the sender of `msg` is doing, for /seen
+    pub(crate) fn seen_message(&mut self, msg: &Message) {
+        let Some(nick) = msg.nick() else {
+            return;
+        };
+        let target = channel::strip_status(msg.param(0).unwrap_or(""));
+        let action = match msg.command.as_str() {
+            "PRIVMSG" if target.starts_with('#') => format!("talking in {}", target),
+            "PRIVMSG" => "messaging you".to_string(),
+            "JOIN" => format!("joining {}", target),
+            "PART" => format!("leaving {}", target),
+            "QUIT" => match msg.param(0) {
+                Some(reason) if !reason.is_empty() => format!("quitting ({})", reason),
+                _ => "quitting".to_string(),
+            },
+            "NICK" => format!("changing nick to {}", target),
+            _ => return,
+        };
+        let network = self.network_id();
+        self.seen.record(&network, nick, action);
+        // The new nick is around as well
+        if msg.command == "NICK" {
+            self.seen
+                .record(&network, target, format!("changing nick from {}", nick));
+        }
+    }
+
+    // /seen nick
+    pub(crate) fn seen_command(&mut self, nick: &str) {
+        if nick.is_empty() {
+            self.buffers.push_local(tr("Usage: /seen nick").to_string());
+            return;
+        }
+        let line = match self.seen.get(&self.network_id(), nick) {
+            Some(sighting) => {
+                let time = UNIX_EPOCH + Duration::from_secs(sighting.time);
+                let elapsed = SystemTime::now().duration_since(time).unwrap_or_default();
+                format!(
+                    "{} was last seen {} ago ({} UTC), {}",
+                    nick,
+                    clock::ago(elapsed),
+                    clock::date_minutes(time),
+                    sighting.action
+                )
+            }
+            None => trf("{} has not been seen on this network", &[&nick]),
+        };
+        self.buffers.push_local(line);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::app::App;
+use crate::i18n::{tr, trf};
+use crate::irc_client::IrcClient;
+use crate::message::Message;
+
+type Result<T> = std::result::Result<T, String>;
+
+// Queries about the server whose replies we collect and show as one block
+pub const COMMANDS: [&str; 6] = ["MOTD", "TIME", "VERSION", "ADMIN", "LINKS", "STATS"];
+
+// Which query a numeric answers, and whether it is the last line of the answer
+fn answers(numeric: &str) -> Option<(&'static str, bool)> {
+    Some(match numeric {
+        "375" | "372" => ("MOTD", false),
+        // RPL_ENDOFMOTD, ERR_NOMOTD
+        "376" | "422" => ("MOTD", true),
+        "391" => ("TIME", true),
+        "351" => ("VERSION", true),
+        // RPL_ADMINME, ADMINLOC1, ADMINLOC2, ADMINEMAIL, which comes last
+        "256" | "257" | "258" => ("ADMIN", false),
+        "259" => ("ADMIN", true),
+        "364" => ("LINKS", false),
+        "365" => ("LINKS", true),
+        // RPL_ENDOFSTATS
+        "219" => ("STATS", true),
+        // RPL_UMODEIS sits in the middle but is about us
+        "221" => return None,
+        n if ("211".."251").contains(&n) => ("STATS", false),
+        _ => return None,
+    })
+}
+
+// One reply line as text, without our nick in front
+fn describe(msg: &Message) -> Option<String> {
+    let param = |i| msg.param(i).unwrap_or("");
+    let text = match msg.command.as_str() {
+        // Our own headers take the place of these
+        "375" | "376" | "365" | "219" => return None,
+        "372" => {
+            let text = param(1);
+            text.strip_prefix("- ").unwrap_or(text).to_string()
+        }
+        // <me> <server> :<time>
+        "391" => format!("Time on {}: {}", param(1), param(2)),
+        // <me> <version> <server> :<comments>
+        "351" => format!("{} runs {} {}", param(2), param(1), param(3)),
+        // <me> <mask> <server> :<hops> <info>
+        "364" => format!("{} via {}: {}", param(1), param(2), param(3)),
+        _ => msg
+            .params
+            .iter()
+            .skip(1)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    Some(text)
+}
+
+impl IrcClient {
+    // MOTD, TIME and the rest, for `target` or the server we are on
+    pub fn query_server(&mut self, command: &str, target: &str) -> Result<()> {
+        let line = format!("{} {}", command, target);
+        self.send_labeled(line.trim_end(), &format!("/{}", command.to_lowercase()))
+    }
+}
+
+impl App {
+    // /motd, /time, /version, /admin, /links and /stats with a query letter
+    pub(crate) fn server_info_command(&mut self, command: &'static str, args: &str) {
+        if command == "STATS" && args.is_empty() {
+            self.buffers
+                .push_local(tr("Usage: /stats <query> [server], e.g. /stats u").to_string());
+            return;
+        }
+        match self.client.query_server(command, args) {
+            Ok(_) => {
+                self.server_info.insert(command, Vec::new());
+            }
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error sending {}: {}", &[&command, &e])),
+        }
+    }
+
+    // Collects replies to a query we sent, shown together once the last one is in.
+    // Returns true for those, the rest (e.g. the MOTD at connect) are shown as they come
+    pub(crate) fn server_info_reply(&mut self, msg: &Message) -> bool {
+        let Some((command, last)) = answers(&msg.command) else {
+            return false;
+        };
+        let Some(lines) = self.server_info.get_mut(command) else {
+            return false;
+        };
+        if let Some(text) = describe(msg) {
+            lines.push(text);
+        }
+        if last && let Some(lines) = self.server_info.remove(command) {
+            let server = msg.prefix.as_deref().unwrap_or(&self.client.server);
+            let server_buffer = self.buffers.server_mut();
+            server_buffer.push(format!("---- {} from {} ----", command, server));
+            for line in lines {
+                server_buffer.push(line);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,248 @@
+use crate::app::{App, AppEvent};
+use crate::auth;
+use crate::i18n::{tr, trf};
+use crate::irc_client::ConnectionState;
+use crate::transport::Connection;
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Result<T> = std::result::Result<T, String>;
+
+// Where /connect goes, parsed from "host [port] [-tls]"
+pub struct Target {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+fn parse_target(args: &str) -> Result<Target> {
+    let mut host = None;
+    let mut port = None;
+    let mut tls = false;
+    for word in args.split_whitespace() {
+        if word == "-tls" || word == "-ssl" {
+            tls = true;
+        } else if host.is_none() {
+            host = Some(word.to_string());
+        } else if port.is_none() {
+            // "+6697" is the usual way of saying TLS on that port
+            let number = word
+                .strip_prefix('+')
+                .inspect(|_| tls = true)
+                .unwrap_or(word);
+            port = Some(
+                number
+                    .parse()
+                    .map_err(|_| trf("'{}' is not a port", &[&word]))?,
+            );
+        } else {
+            return Err(trf("Unexpected '{}'", &[&word]));
+        }
+    }
+    let host = host.ok_or("Usage: /connect host [port] [-tls]")?;
+    Ok(Target {
+        host,
+        port: port.unwrap_or(if tls { 6697 } else { 6667 }),
+        tls,
+    })
+}
+
+impl App {
+    // /connect host [port] [-tls], also /server. Leaves the current server first, one
+    // network at a time
+    pub(crate) fn connect_command(&mut self, args: &str) {
+        match parse_target(args) {
+            Ok(target) => {
+                self.reconnect_delay = None;
+                self.connect_to(target);
+            }
+            Err(e) => self.buffers.server_mut().push(e),
+        }
+    }
+
+    // /disconnect [network] [reason]
+    pub(crate) fn disconnect_command(&mut self, args: &str) {
+        // Naming the network is optional, there is only the one
+        let (first, rest) = args.split_once(' ').unwrap_or((args, ""));
+        let named = !first.is_empty()
+            && (first.eq_ignore_ascii_case(&self.client.network)
+                || first.eq_ignore_ascii_case(&self.client.server));
+        let reason = if named { rest.trim() } else { args };
+        if let ConnectionState::Reconnecting { .. } = self.client.state {
+            self.client.state = ConnectionState::Disconnected;
+            self.reconnect_delay = None;
+            self.buffers
+                .server_mut()
+                .push(tr("Stopped reconnecting").to_string());
+            return;
+        }
+        if self.client.stream.is_none() {
+            self.buffers.server_mut().push("Not connected".to_string());
+            return;
+        }
+        let reason = if reason.is_empty() {
+            self.config.quit.message.as_str()
+        } else {
+            reason
+        };
+        self.client.disconnect_with(reason);
+        self.buffers
+            .server_mut()
+            .push(trf("Disconnected from {}", &[&self.client.server]));
+    }
+
+    // /reconnect, to the server we were last connected to
+    pub(crate) fn reconnect_command(&mut self) {
+        if self.client.server.is_empty() {
+            self.buffers
+                .server_mut()
+                .push(tr("Not connected anywhere yet, use /connect").to_string());
+            return;
+        }
+        self.reconnect_delay = None;
+        self.connect_to(Target {
+            host: self.client.server.clone(),
+            port: self.client.port,
+            tls: self.client.tls,
+        });
+    }
+
+    // Opens the connection on a thread so the screen keeps showing how far it got, the
+    // result comes back as AppEvent::Connected
+    pub(crate) fn connect_to(&mut self, target: Target) {
+        if self.server_lines.is_none() {
+            return;
+        }
+        if self.client.stream.is_some() {
+            self.client.disconnect_with("Changing servers");
+        }
+        self.buffers.server_mut().push(trf(
+            "Connecting to {}:{}{}...",
+            &[
+                &target.host,
+                &target.port,
+                &if target.tls { " (TLS)" } else { "" },
+            ],
+        ));
+        // The login of the old server does not belong to the new one
+        self.client.password = None;
+        self.client.sasl = None;
+        auth::apply_login(&mut self.client, &self.config, &target.host, !self.headless);
+        if let Err(e) = self.client.prepare_connect() {
+            self.buffers.server_mut().push(e);
+            return;
+        }
+        // Only the newest attempt counts, an older one still under way is dropped
+        self.connect_attempt += 1;
+        let attempt = self.connect_attempt;
+        let events = self.events();
+        thread::spawn(move || {
+            let handshaking = || {
+                let _ = events.send(AppEvent::Handshaking { attempt });
+            };
+            let result = Connection::open(&target.host, target.port, target.tls, handshaking);
+            let _ = events.send(AppEvent::Connected {
+                attempt,
+                target,
+                result,
+            });
+        });
+    }
+
+    pub(crate) fn handshaking(&mut self, attempt: u32) {
+        if attempt == self.connect_attempt && self.client.state == ConnectionState::Connecting {
+            self.client.state = ConnectionState::TlsHandshake;
+        }
+    }
+
+    // The connection opened by connect_to, or why it could not be
+    pub(crate) fn connected(&mut self, attempt: u32, target: Target, result: Result<Connection>) {
+        if attempt != self.connect_attempt {
+            if let Ok(stream) = result {
+                stream.shutdown();
+            }
+            return;
+        }
+        let Some(lines) = self.server_lines.clone() else {
+            return;
+        };
+        let result = result
+            .map(|stream| {
+                self.client
+                    .attach(&target.host, target.port, target.tls, stream)
+            })
+            .and_then(|_| self.client.register())
+            .and_then(|_| self.client.start_receiver(lines));
+        match result {
+            Ok(_) => self.buffers.server_mut().push(trf(
+                "Connected to {}, registering as {}",
+                &[&target.host, &self.client.nickname],
+            )),
+            Err(e) => {
+                let _ = self.client.disconnect();
+                self.client.connect_failed();
+                self.buffers
+                    .server_mut()
+                    .push(trf("Could not connect to {}: {}", &[&target.host, &e]));
+                // A failed reconnect tries again later, a failed /connect is left alone
+                if self.reconnect_delay.is_some() {
+                    self.schedule_reconnect();
+                }
+            }
+        }
+    }
+
+    // After the connection was lost, waits [reconnect] delay_secs before trying again and
+    // twice as long after every failed try
+    pub(crate) fn schedule_reconnect(&mut self) {
+        let config = &self.config.reconnect;
+        if !config.enabled || self.client.server.is_empty() {
+            return;
+        }
+        let delay = match self.reconnect_delay {
+            Some(last) => (last * 2).min(config.max_delay_secs),
+            None => config.delay_secs,
+        };
+        self.reconnect_delay = Some(delay);
+        self.client.state = ConnectionState::Reconnecting {
+            at: Instant::now() + Duration::from_secs(delay),
+        };
+        self.buffers
+            .server_mut()
+            .push(trf("Reconnecting in {}s, /disconnect stops it", &[&delay]));
+    }
+
+    // The [servers."host"] perform list, run in order once we are registered. $nick and $me
+    // become our nick, $network the server's host
+    pub(crate) fn perform(&mut self) {
+        let Some(server) = self.config.server(&self.client.server) else {
+            return;
+        };
+        let commands: Vec<String> = server
+            .perform
+            .iter()
+            .map(|command| {
+                command
+                    .replace("$nick", &self.client.nickname)
+                    .replace("$me", &self.client.nickname)
+                    .replace("$network", &self.client.server)
+            })
+            .collect();
+        for command in commands {
+            self.execute(&command);
+        }
+    }
+
+    // Called on every tick
+    pub(crate) fn reconnect_due(&mut self) {
+        if let ConnectionState::Reconnecting { at } = self.client.state
+            && at <= Instant::now()
+        {
+            self.connect_to(Target {
+                host: self.client.server.clone(),
+                port: self.client.port,
+                tls: self.client.tls,
+            });
+        }
+    }
+}
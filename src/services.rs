@@ -0,0 +1,152 @@
+use crate::app::App;
+use crate::config::Recover;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+
+// Shortcut commands and the service each one talks to
+const SERVICES: &[(&str, &str)] = &[
+    ("/ns", "NickServ"),
+    ("/nickserv", "NickServ"),
+    ("/cs", "ChanServ"),
+    ("/chanserv", "ChanServ"),
+    ("/ms", "MemoServ"),
+    ("/memoserv", "MemoServ"),
+    ("/os", "OperServ"),
+    ("/operserv", "OperServ"),
+];
+
+// Splits "/cs op nick" into the service and what to send it
+pub fn parse(input: &str) -> Option<(&'static str, &str)> {
+    let (command, rest) = input.split_once(' ').unwrap_or((input, ""));
+    SERVICES
+        .iter()
+        .find(|(name, _)| command.eq_ignore_ascii_case(name))
+        .map(|(_, service)| (*service, rest.trim()))
+}
+
+impl App {
+    // Gets our nick back after registering under a fallback, as the config says, and
+    // finishes a GHOST once NickServ has answered it
+    pub(crate) fn nick_recovery(&mut self, msg: &Message) {
+        if msg.command == "001"
+            && let Some(wanted) = self.client.wanted_nick.clone()
+        {
+            let mut line = trf(
+                "Could not use {}, registered as {}",
+                &[&wanted, &self.client.nickname],
+            );
+            if self.config.nickserv.recover == Recover::Ask {
+                line.push_str(". /regain or /ghost gets it back");
+            }
+            self.notify(&line);
+            match self.config.nickserv.recover {
+                Recover::Regain => self.recover_nick(false),
+                Recover::Ghost => self.recover_nick(true),
+                Recover::Off | Recover::Ask => {}
+            }
+        }
+        if matches!(msg.command.as_str(), "432" | "433") && self.client.nicks_exhausted {
+            self.notify(tr(
+                "No nick left to try, giving up. Set alt_nicks in the config",
+            ));
+            if let Err(e) = self.client.disconnect() {
+                self.buffers
+                    .server_mut()
+                    .push(trf("Error disconnecting: {}", &[&e]));
+            }
+        }
+        // Whatever NickServ said, the old session is gone or it never will be
+        if msg.command == "NOTICE"
+            && msg
+                .nick()
+                .is_some_and(|n| n.eq_ignore_ascii_case("NickServ"))
+            && let Some(nick) = self.ghosting.take()
+            && let Err(e) = self.client.send_raw(&format!("NICK {}\r\n", nick))
+        {
+            self.buffers
+                .server_mut()
+                .push(trf("Error changing nick: {}", &[&e]));
+        }
+    }
+
+    // /ns, /cs, /ms and /os. ChanServ commands that leave out the channel get the current one
+    pub(crate) fn send_to_service(&mut self, service: &str, command: &str) {
+        let mut words = command.split_whitespace();
+        let Some(verb) = words.next() else {
+            let service = service.to_lowercase();
+            self.buffers.push_local(trf(
+                "Usage: /{} command [args], e.g. /{} help",
+                &[&service, &service],
+            ));
+            return;
+        };
+        let mut line = command.to_string();
+        if service == "ChanServ"
+            && !verb.eq_ignore_ascii_case("HELP")
+            && !words.next().is_some_and(|w| w.starts_with('#'))
+            && !self.client.current_channel.is_empty()
+        {
+            let args = command[verb.len()..].trim_start();
+            line = format!("{} {} {}", verb, self.client.current_channel, args)
+                .trim_end()
+                .to_string();
+        }
+        // Keep passwords out of the buffer, SET PASSWORD included
+        let password = verb.eq_ignore_ascii_case("SET")
+            && command[verb.len()..]
+                .trim_start()
+                .to_uppercase()
+                .starts_with("PASSWORD");
+        let shown = if password
+            || ["IDENTIFY", "REGISTER", "GHOST", "REGAIN"]
+                .iter()
+                .any(|v| verb.eq_ignore_ascii_case(v))
+        {
+            format!("{} ...", verb)
+        } else {
+            line.clone()
+        };
+        match self.client.send_message(service, &line) {
+            Ok(_) => self
+                .buffers
+                .push_local(format!("-> *{}* {}", service, shown)),
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error sending to {}: {}", &[&service, &e])),
+        }
+    }
+
+    // /regain and /ghost. REGAIN switches us to the nick by itself, GHOST only frees it
+    pub(crate) fn recover_nick(&mut self, ghost: bool) {
+        let Some(wanted) = self.client.wanted_nick.clone() else {
+            self.buffers
+                .push_local(tr("You already have the nick you asked for").to_string());
+            return;
+        };
+        let verb = if ghost { "GHOST" } else { "REGAIN" };
+        let password = self
+            .config
+            .server(&self.client.server)
+            .and_then(|s| s.nickserv_password.clone())
+            .or_else(|| self.client.sasl.as_ref().and_then(|s| s.password.clone()));
+        let command = match password {
+            Some(password) => format!("{} {} {}", verb, wanted, password),
+            None => format!("{} {}", verb, wanted),
+        };
+        match self.client.send_message("NickServ", &command) {
+            // Without the password, it has no business in a buffer
+            Ok(_) => {
+                self.buffers
+                    .server_mut()
+                    .push(format!("-> *NickServ* {} {}", verb, wanted));
+                if ghost {
+                    self.ghosting = Some(wanted);
+                }
+            }
+            Err(e) => self
+                .buffers
+                .server_mut()
+                .push(trf("Error sending to NickServ: {}", &[&e])),
+        }
+    }
+}
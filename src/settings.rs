@@ -0,0 +1,339 @@
+use crate::app::App;
+use crate::buffer::BufferKind;
+use crate::channel::NickSort;
+use crate::clock;
+use crate::config::Config;
+use crate::i18n::{tr, trf};
+use crate::sounds::SoundEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+type Result<T> = std::result::Result<T, String>;
+
+// Preferences of one buffer, changed with /buffer set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BufferSettings {
+    // Leave out other people's JOIN, PART and QUIT
+    pub hide_joins: bool,
+    // Never ring the bell for this buffer
+    pub mute: bool,
+    // Messages containing one of these, ignoring case, are highlighted and ring the bell
+    pub highlights: Vec<String>,
+    // Append the buffer's lines to a file under the state directory
+    pub log: bool,
+    // Order of the nick list, kept under the channel's name and set with /sortnicks
+    pub sort: NickSort,
+    // Sound file for highlights and private messages here instead of [sounds], "off" for
+    // none, empty for the usual ones
+    pub sound: String,
+    // What /toggle and F2, F3, F4 hid in this buffer
+    pub hide_timestamps: bool,
+    pub hide_nicklist: bool,
+    pub hide_topic: bool,
+}
+
+const KEYS: &str = "hide_joins, mute, highlights, log, sound, hide_timestamps, hide_nicklist, \
+                    hide_topic";
+
+// What /toggle can show and hide, as (name, what it is called in messages)
+const TOGGLES: [(&str, &str); 3] = [
+    ("timestamps", "Timestamps"),
+    ("nicklist", "The nick list"),
+    ("topic", "The topic bar"),
+];
+
+impl BufferSettings {
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let flag = || match value {
+            "on" | "true" | "yes" => Ok(true),
+            "off" | "false" | "no" => Ok(false),
+            _ => Err(trf("{} is on or off, not '{}'", &[&key, &value])),
+        };
+        match key {
+            "hide_joins" => self.hide_joins = flag()?,
+            "mute" => self.mute = flag()?,
+            "log" => self.log = flag()?,
+            "sound" => self.sound = value.to_string(),
+            "hide_timestamps" => self.hide_timestamps = flag()?,
+            "hide_nicklist" => self.hide_nicklist = flag()?,
+            "hide_topic" => self.hide_topic = flag()?,
+            "highlights" => {
+                self.highlights = value
+                    .split(',')
+                    .map(|word| word.trim().to_string())
+                    .filter(|word| !word.is_empty())
+                    .collect()
+            }
+            _ => return Err(trf("Unknown setting '{}', try {}", &[&key, &KEYS])),
+        }
+        Ok(())
+    }
+
+    pub fn highlighted(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        self.highlights
+            .iter()
+            .any(|word| text.contains(&word.to_lowercase()))
+    }
+
+    fn describe(&self) -> String {
+        let flag = |on: bool| if on { "on" } else { "off" };
+        format!(
+            "hide_joins {}, mute {}, highlights {}, log {}, sound {}, hide_timestamps {}, \
+             hide_nicklist {}, hide_topic {}",
+            flag(self.hide_joins),
+            flag(self.mute),
+            if self.highlights.is_empty() {
+                "none".to_string()
+            } else {
+                self.highlights.join(",")
+            },
+            flag(self.log),
+            if self.sound.is_empty() {
+                "default"
+            } else {
+                &self.sound
+            },
+            flag(self.hide_timestamps),
+            flag(self.hide_nicklist),
+            flag(self.hide_topic)
+        )
+    }
+}
+
+// Settings of every buffer that has any, saved in buffers.toml next to the config as
+// "network/buffer"
+pub struct Settings {
+    path: Option<PathBuf>,
+    buffers: BTreeMap<String, BufferSettings>,
+    // Lines of each logged buffer already written, by buffer name
+    logged: BTreeMap<String, usize>,
+}
+
+impl Settings {
+    // A missing or broken file just means nothing was changed yet
+    pub fn load() -> Self {
+        let path = Config::dir().map(|dir| dir.join("buffers.toml"));
+        let buffers = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Settings {
+            path,
+            buffers,
+            logged: BTreeMap::new(),
+        }
+    }
+
+    fn id(network: &str, name: &str) -> String {
+        format!("{}/{}", network.to_ascii_lowercase(), name.to_lowercase())
+    }
+
+    pub fn get(&self, network: &str, name: &str) -> BufferSettings {
+        self.buffers
+            .get(&Self::id(network, name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Stores the settings of `name`, dropping them when they are all defaults again
+    fn put(&mut self, network: &str, name: &str, settings: BufferSettings) -> Result<()> {
+        let id = Self::id(network, name);
+        if settings == BufferSettings::default() {
+            self.buffers.remove(&id);
+        } else {
+            self.buffers.insert(id, settings);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Err(tr("No config directory to save buffer settings in").to_string());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| trf("Failed to create {}: {}", &[&dir.display(), &e]))?;
+        }
+        let contents = toml::to_string(&self.buffers)
+            .map_err(|e| format!("Failed to encode buffer settings: {}", e))?;
+        fs::write(path, contents).map_err(|e| trf("Failed to write {}: {}", &[&path.display(), &e]))
+    }
+}
+
+impl App {
+    pub(crate) fn buffer_settings(&self, name: &str) -> BufferSettings {
+        self.settings.get(&self.network_id(), name)
+    }
+
+    // Plays the sound for something in buffer `name`, the bell without one, unless it is
+    // muted or the user is looking at it already
+    pub(crate) fn ring(&mut self, name: &str, event: SoundEvent) {
+        let watching = self.focused && self.buffers.active().name.eq_ignore_ascii_case(name);
+        if !self.buffer_settings(name).mute && !watching {
+            self.sound(event, name);
+            self.alert = Some(name.to_string());
+            self.desktop_notify(name);
+        }
+    }
+
+    // /buffer shows the current buffer's settings, /buffer set <key> <value> changes one
+    pub(crate) fn buffer_command(&mut self, args: &str) {
+        let name = self.buffers.active().name.clone();
+        if self.buffers.active().kind.is_view() {
+            self.buffers
+                .push_local(tr("This panel has no settings").to_string());
+            return;
+        }
+        let mut parts = args.splitn(3, ' ');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(""), _, _) => {
+                let line = format!("{}: {}", name, self.buffer_settings(&name).describe());
+                self.buffers.push_local(line);
+            }
+            (Some("set"), Some(key), value) => {
+                let network = self.network_id();
+                let mut settings = self.settings.get(&network, &name);
+                if let Err(e) = settings.set(key, value.unwrap_or("").trim()) {
+                    self.buffers.push_local(e);
+                    return;
+                }
+                // Logging starts with what comes in from now on
+                if settings.log && !self.settings.get(&network, &name).log {
+                    let total = self.buffers.get(&name).map_or(0, |b| b.total);
+                    self.settings.logged.insert(name.clone(), total);
+                }
+                let line = format!("{}: {}", name, settings.describe());
+                self.buffers.push_local(line);
+                if let Err(e) = self.settings.put(&network, &name, settings) {
+                    self.buffers.push_local(e);
+                }
+            }
+            _ => self.buffers.push_local(trf(
+                "Usage: /buffer [set <key> <value>], keys are {}",
+                &[&KEYS],
+            )),
+        }
+    }
+
+    // /toggle timestamps|nicklist|topic shows or hides that part of the current buffer,
+    // remembered with its other settings
+    pub(crate) fn toggle_command(&mut self, args: &str) {
+        let name = self.buffers.active().name.clone();
+        if self.buffers.active().kind.is_view() {
+            self.buffers
+                .push_local(tr("This panel has no settings").to_string());
+            return;
+        }
+        let Some(&(what, label)) = TOGGLES.iter().find(|(what, _)| *what == args.trim()) else {
+            self.buffers
+                .push_local(tr("Usage: /toggle timestamps|nicklist|topic").to_string());
+            return;
+        };
+        let network = self.network_id();
+        let mut settings = self.settings.get(&network, &name);
+        let hidden = match what {
+            "timestamps" => &mut settings.hide_timestamps,
+            "nicklist" => &mut settings.hide_nicklist,
+            _ => &mut settings.hide_topic,
+        };
+        *hidden = !*hidden;
+        let state = if *hidden { "hidden" } else { "shown" };
+        self.buffers
+            .push_local(format!("{} {} in {}", label, state, name));
+        if let Err(e) = self.settings.put(&network, &name, settings) {
+            self.buffers.push_local(e);
+        }
+    }
+
+    // /sortnicks [status|alpha|activity] shows or changes how the current channel's nick
+    // list is ordered, remembered per channel
+    pub(crate) fn sortnicks_command(&mut self, args: &str) {
+        let name = self.client.current_channel.clone();
+        if name.is_empty() {
+            self.buffers
+                .push_local(tr("Join a channel first with /join #channel").to_string());
+            return;
+        }
+        let network = self.network_id();
+        let mut settings = self.settings.get(&network, &name);
+        settings.sort = match args {
+            "" => {
+                self.buffers.push_local(trf(
+                    "Nick list of {} is sorted by {}",
+                    &[&name, &settings.sort.name()],
+                ));
+                return;
+            }
+            "status" => NickSort::Status,
+            "alpha" => NickSort::Alpha,
+            "activity" => NickSort::Activity,
+            _ => {
+                self.buffers
+                    .push_local(tr("Usage: /sortnicks [status|alpha|activity]").to_string());
+                return;
+            }
+        };
+        self.buffers.push_local(trf(
+            "Nick list of {} is now sorted by {}",
+            &[&name, &settings.sort.name()],
+        ));
+        if let Err(e) = self.settings.put(&network, &name, settings) {
+            self.buffers.push_local(e);
+        }
+    }
+
+    // Appends new lines of buffers with log on to
+    // $XDG_STATE_HOME/irconic/logs/<network>/<buffer>.log
+    pub(crate) fn write_logs(&mut self) {
+        let network = self.network_id();
+        let Some(dir) = Config::log_dir().map(|dir| dir.join("logs").join(&network)) else {
+            return;
+        };
+        let mut failed = Vec::new();
+        for buffer in self.buffers.iter() {
+            if buffer.kind == BufferKind::Debug || !self.settings.get(&network, &buffer.name).log {
+                continue;
+            }
+            let seen = self.settings.logged.entry(buffer.name.clone()).or_insert(0);
+            if *seen == buffer.total {
+                continue;
+            }
+            let mut text = String::new();
+            for line in buffer.lines_since(*seen) {
+                text.push_str(&format!("{} {}\n", clock::iso8601(line.time), line.text));
+            }
+            *seen = buffer.total;
+            let path = dir.join(format!("{}.log", buffer.name.replace('/', "_")));
+            let written = fs::create_dir_all(&dir).and_then(|_| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)?
+                    .write_all(text.as_bytes())
+            });
+            if let Err(e) = written {
+                failed.push((buffer.name.clone(), path, e));
+            }
+        }
+        // Turned off for the session rather than failing again on every tick
+        for (name, path, e) in failed {
+            if let Some(settings) = self
+                .settings
+                .buffers
+                .get_mut(&Settings::id(&network, &name))
+            {
+                settings.log = false;
+            }
+            self.buffers.server_mut().push(trf(
+                "Failed to write {}: {}, logging {} is off",
+                &[&path.display(), &e, &name],
+            ));
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use crate::channel;
+use crate::config::{Config, ConnectConfig};
+use crate::i18n::{tr, trf};
+use crate::terminal::TerminalGuard;
+use crate::tui_client::ConnectionDetails;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use ratatui::{
+    Frame, Terminal,
+    backend::CrosstermBackend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+use std::error::Error;
+use std::io::stdout;
+
+// The rows of the form, top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Nick,
+    Server,
+    Port,
+    Tls,
+    SaslUser,
+    SaslPassword,
+    Channels,
+}
+
+impl Field {
+    const ALL: [Field; 7] = [
+        Field::Nick,
+        Field::Server,
+        Field::Port,
+        Field::Tls,
+        Field::SaslUser,
+        Field::SaslPassword,
+        Field::Channels,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Field::Nick => "Nick",
+            Field::Server => "Server",
+            Field::Port => "Port",
+            Field::Tls => "TLS",
+            Field::SaslUser => "SASL account",
+            Field::SaslPassword => "SASL password",
+            Field::Channels => "Channels",
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            Field::Nick => "What others see you as",
+            Field::Server => "e.g. irc.libera.chat",
+            Field::Port => "6667, or 6697 with TLS",
+            Field::Tls => "Space toggles",
+            Field::SaslUser | Field::SaslPassword => "Optional, both or neither",
+            Field::Channels => "Joined after connecting, e.g. #rust #irconic",
+        }
+    }
+}
+
+// What has been typed so far
+struct Form {
+    nick: String,
+    server: String,
+    port: String,
+    tls: bool,
+    sasl_user: String,
+    sasl_password: String,
+    channels: String,
+    focus: usize,
+    error: Option<String>,
+}
+
+impl Form {
+    fn new(connect: &ConnectConfig) -> Self {
+        Form {
+            nick: connect.nick.clone(),
+            server: connect.server.clone(),
+            port: connect.port.to_string(),
+            tls: connect.tls,
+            sasl_user: String::new(),
+            sasl_password: String::new(),
+            channels: connect.channels.join(" "),
+            focus: 0,
+            error: None,
+        }
+    }
+
+    fn text_mut(&mut self, field: Field) -> Option<&mut String> {
+        match field {
+            Field::Nick => Some(&mut self.nick),
+            Field::Server => Some(&mut self.server),
+            Field::Port => Some(&mut self.port),
+            Field::Tls => None,
+            Field::SaslUser => Some(&mut self.sasl_user),
+            Field::SaslPassword => Some(&mut self.sasl_password),
+            Field::Channels => Some(&mut self.channels),
+        }
+    }
+
+    fn shown(&self, field: Field) -> String {
+        match field {
+            Field::Nick => self.nick.clone(),
+            Field::Server => self.server.clone(),
+            Field::Port => self.port.clone(),
+            Field::Tls => if self.tls { "[x]" } else { "[ ]" }.to_string(),
+            Field::SaslUser => self.sasl_user.clone(),
+            Field::SaslPassword => "*".repeat(self.sasl_password.chars().count()),
+            Field::Channels => self.channels.clone(),
+        }
+    }
+
+    // Switching TLS moves the port along when it is still the usual one for the other
+    fn toggle_tls(&mut self) {
+        self.tls = !self.tls;
+        match (self.tls, self.port.as_str()) {
+            (true, "6667") => self.port = "6697".to_string(),
+            (false, "6697") => self.port = "6667".to_string(),
+            _ => {}
+        }
+    }
+
+    // The answers as config, or what is wrong and the field to fix it in
+    fn validate(&self) -> Result<ConnectConfig, (Field, String)> {
+        let nick = self.nick.trim();
+        if nick.is_empty() {
+            return Err((Field::Nick, tr("A nick is needed").to_string()));
+        }
+        if !channel::valid_nick(nick) {
+            return Err((
+                Field::Nick,
+                tr("Nicks are letters, digits and []\\`_^{|}-, not starting with a digit or -")
+                    .to_string(),
+            ));
+        }
+        let server = self.server.trim();
+        if server.is_empty() || server.contains(char::is_whitespace) {
+            return Err((
+                Field::Server,
+                tr("A server host name is needed").to_string(),
+            ));
+        }
+        let port = match self.port.trim().parse::<u16>() {
+            Ok(port) if port > 0 => port,
+            _ => {
+                return Err((
+                    Field::Port,
+                    tr("The port is a number from 1 to 65535").to_string(),
+                ));
+            }
+        };
+        if self.sasl_user.trim().is_empty() != self.sasl_password.is_empty() {
+            let field = if self.sasl_user.trim().is_empty() {
+                Field::SaslUser
+            } else {
+                Field::SaslPassword
+            };
+            return Err((
+                field,
+                tr("SASL needs both the account and the password").to_string(),
+            ));
+        }
+        let channels: Vec<String> = self
+            .channels
+            .split([' ', ','])
+            .filter(|c| !c.is_empty())
+            .map(|c| c.to_string())
+            .collect();
+        if let Some(bad) = channels.iter().find(|c| !c.starts_with(['#', '&'])) {
+            return Err((
+                Field::Channels,
+                trf("{} is not a channel, they start with # or &", &[bad]),
+            ));
+        }
+        Ok(ConnectConfig {
+            nick: nick.to_string(),
+            server: server.to_string(),
+            port,
+            tls: self.tls,
+            channels,
+        })
+    }
+
+    // Checks the answers and writes them to the config. Keeps the form open with the
+    // problem shown when either fails
+    fn submit(&mut self) -> Option<ConnectionDetails> {
+        let connect = match self.validate() {
+            Ok(connect) => connect,
+            Err((field, error)) => {
+                self.focus = Field::ALL.iter().position(|f| *f == field).unwrap_or(0);
+                self.error = Some(error);
+                return None;
+            }
+        };
+        let sasl = Some((self.sasl_user.trim(), self.sasl_password.as_str()))
+            .filter(|(user, _)| !user.is_empty());
+        if let Err(e) = Config::save_connect(&connect, sasl) {
+            self.error = Some(e);
+            return None;
+        }
+        Some(ConnectionDetails {
+            nickname: connect.nick,
+            server: connect.server,
+            port: connect.port,
+            tls: connect.tls,
+        })
+    }
+}
+
+// The first-run form, filled in with whatever `connect` already has. Gives back where to
+// connect once the answers are saved, or None when the user quit with Esc
+pub fn run(connect: &ConnectConfig) -> Result<Option<ConnectionDetails>, Box<dyn Error>> {
+    let _guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let mut form = Form::new(connect);
+    loop {
+        terminal.draw(|f| draw(f, &form))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        let field = Field::ALL[form.focus];
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(None);
+            }
+            KeyCode::Enter => {
+                if let Some(details) = form.submit() {
+                    return Ok(Some(details));
+                }
+            }
+            KeyCode::Tab | KeyCode::Down => form.focus = (form.focus + 1) % Field::ALL.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                form.focus = (form.focus + Field::ALL.len() - 1) % Field::ALL.len();
+            }
+            KeyCode::Char(' ') if field == Field::Tls => form.toggle_tls(),
+            KeyCode::Char(c) => {
+                if let Some(text) = form.text_mut(field) {
+                    text.push(c);
+                }
+            }
+            KeyCode::Backspace => {
+                if let Some(text) = form.text_mut(field) {
+                    text.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(f: &mut Frame, form: &Form) {
+    let size = f.size();
+    let width = size.width.min(90);
+    let height = (Field::ALL.len() as u16 + 8).min(size.height);
+    let area = Rect {
+        x: (size.width - width) / 2,
+        y: (size.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .title(tr(" Welcome to Irconic, where should we connect? "))
+        .borders(Borders::ALL);
+
+    let mut lines = vec![Line::from("")];
+    for (index, field) in Field::ALL.into_iter().enumerate() {
+        let value_style = if index == form.focus {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        let mut value = form.shown(field);
+        if index == form.focus && field != Field::Tls {
+            value.push('_');
+        }
+        lines.push(Line::from(vec![
+            Span::raw(format!(" {:<14} ", tr(field.label()))),
+            Span::styled(format!("{:<24}", value), value_style),
+            Span::styled(
+                format!("  {}", tr(field.hint())),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+    }
+    lines.push(Line::from(""));
+    lines.push(match &form.error {
+        Some(error) => Line::styled(format!(" {}", error), Style::default().fg(Color::Red)),
+        None => Line::from(""),
+    });
+    lines.push(Line::from(""));
+    lines.push(Line::styled(
+        tr(" Tab/Up/Down move, Enter saves to config.toml and connects, Esc quits"),
+        Style::default().fg(Color::DarkGray),
+    ));
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
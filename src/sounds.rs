@@ -0,0 +1,85 @@
+use crate::app::App;
+use crate::i18n::{tr, trf};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+// What a sound can be played for, each with its own file in [sounds]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    Highlight,
+    Private,
+    Connect,
+    Disconnect,
+}
+
+impl SoundEvent {
+    // Highlights and private messages rang the bell before there were sounds and still do
+    // without a file, connecting and disconnecting stay quiet
+    fn bell_fallback(self) -> bool {
+        matches!(self, SoundEvent::Highlight | SoundEvent::Private)
+    }
+}
+
+impl App {
+    // /sounds [on|off], without an argument flips between the two
+    pub(crate) fn sounds_command(&mut self, args: &str) {
+        self.sounds_muted = match args.trim() {
+            "" => !self.sounds_muted,
+            "on" => false,
+            "off" => true,
+            _ => {
+                self.buffers
+                    .push_local(tr("Usage: /sounds [on|off]").to_string());
+                return;
+            }
+        };
+        let state = if self.sounds_muted { "off" } else { "on" };
+        self.buffers.push_local(trf("Sounds are {}", &[&state]));
+    }
+
+    // Plays the sound for `event` in `buffer`. The buffer's `sound` setting replaces the
+    // file of highlights and private messages there, "off" silences them
+    pub(crate) fn sound(&mut self, event: SoundEvent, buffer: &str) {
+        if self.sounds_muted {
+            return;
+        }
+        let sounds = &self.config.sounds;
+        let mut file = match event {
+            SoundEvent::Highlight => sounds.highlight.clone(),
+            SoundEvent::Private => sounds.private.clone(),
+            SoundEvent::Connect => sounds.connect.clone(),
+            SoundEvent::Disconnect => sounds.disconnect.clone(),
+        };
+        if event.bell_fallback() {
+            match self.buffer_settings(buffer).sound.as_str() {
+                "" => {}
+                "off" => return,
+                own => file = own.to_string(),
+            }
+        }
+        if file.is_empty() {
+            self.bell |= event.bell_fallback();
+            return;
+        }
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let file = match (Path::new(&file).strip_prefix("~"), home) {
+            (Ok(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(file),
+        };
+        let played = Command::new(&sounds.player)
+            .arg(&file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match played {
+            // Reaped once it is done, nobody waits for a sound
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            // No player, the bell will have to do
+            Err(_) => self.bell = true,
+        }
+    }
+}
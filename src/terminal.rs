@@ -0,0 +1,74 @@
+use crossterm::{
+    cursor::Show,
+    event::{DisableBracketedPaste, DisableFocusChange, EnableBracketedPaste, EnableFocusChange},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use std::io::{self, stdout};
+use std::panic;
+use std::sync::Once;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+// Whether a TerminalGuard is alive, i.e. the terminal needs restoring before anything is
+// printed
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Raw mode and the alternate screen for as long as this lives. Dropping it, whether on a
+// normal return, an error passed up with ? or unwinding from a panic, gives the user their
+// shell back
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn enter() -> io::Result<TerminalGuard> {
+        install_panic_hook();
+        enable_raw_mode()?;
+        ACTIVE.store(true, Ordering::SeqCst);
+        execute!(
+            stdout(),
+            EnterAlternateScreen,
+            EnableBracketedPaste,
+            EnableFocusChange
+        )?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+fn restore() {
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        let _ = disable_raw_mode();
+        let _ = execute!(
+            stdout(),
+            DisableFocusChange,
+            DisableBracketedPaste,
+            LeaveAlternateScreen,
+            Show
+        );
+    }
+}
+
+// A panic in the draw/input loop restores the terminal before the message is printed, so
+// it ends up readable in the shell instead of in raw mode on the alternate screen. Panics
+// in background threads only go to the log, the UI keeps running and printing them would
+// scribble over it
+fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            tracing::error!("{}", info);
+            if !ACTIVE.load(Ordering::SeqCst) {
+                default(info);
+            } else if thread::current().name() == Some("main") {
+                restore();
+                default(info);
+            }
+        }));
+    });
+}
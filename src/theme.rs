@@ -0,0 +1,194 @@
+use crate::app::App;
+use crate::config::Config;
+use crate::i18n::trf;
+use ratatui::style::Color;
+
+// Colors of the chat pane and the nick list, picked with [theme] name or /theme
+pub struct Theme {
+    pub name: &'static str,
+    pub description: &'static str,
+    // Day separators, grouped nicks, away members
+    pub dim: Color,
+    // Highlighted lines and the focused nick list
+    pub accent: Color,
+    // The unread rule and input over the server's limit
+    pub alert: Color,
+    // Nick list ranks ~ & @ % + in that order
+    pub ranks: [Color; 5],
+    // Nicks in chat lines, each nick always gets the same one
+    pub nicks: &'static [Color],
+}
+
+// Solarized accents, https://ethanschoonover.com/solarized/
+const SOLARIZED_BASE01: Color = Color::Rgb(88, 110, 117);
+const SOLARIZED_YELLOW: Color = Color::Rgb(181, 137, 0);
+const SOLARIZED_ORANGE: Color = Color::Rgb(203, 75, 22);
+const SOLARIZED_RED: Color = Color::Rgb(220, 50, 47);
+const SOLARIZED_MAGENTA: Color = Color::Rgb(211, 54, 130);
+const SOLARIZED_VIOLET: Color = Color::Rgb(108, 113, 196);
+const SOLARIZED_BLUE: Color = Color::Rgb(38, 139, 210);
+const SOLARIZED_CYAN: Color = Color::Rgb(42, 161, 152);
+const SOLARIZED_GREEN: Color = Color::Rgb(133, 153, 0);
+
+// Okabe and Ito's palette, told apart with every common kind of color blindness
+const OKABE_ORANGE: Color = Color::Rgb(230, 159, 0);
+const OKABE_SKY_BLUE: Color = Color::Rgb(86, 180, 233);
+const OKABE_BLUISH_GREEN: Color = Color::Rgb(0, 158, 115);
+const OKABE_YELLOW: Color = Color::Rgb(240, 228, 66);
+const OKABE_BLUE: Color = Color::Rgb(0, 114, 178);
+const OKABE_VERMILLION: Color = Color::Rgb(213, 94, 0);
+const OKABE_PURPLE: Color = Color::Rgb(204, 121, 167);
+
+pub const THEMES: [Theme; 4] = [
+    Theme {
+        name: "default",
+        description: "The terminal's own colors",
+        dim: Color::DarkGray,
+        accent: Color::Yellow,
+        alert: Color::Red,
+        ranks: [
+            Color::LightRed,
+            Color::Magenta,
+            Color::Green,
+            Color::Cyan,
+            Color::Yellow,
+        ],
+        nicks: &[
+            Color::Cyan,
+            Color::Green,
+            Color::Magenta,
+            Color::Blue,
+            Color::Yellow,
+            Color::LightCyan,
+            Color::LightGreen,
+            Color::LightMagenta,
+            Color::LightBlue,
+            Color::LightRed,
+        ],
+    },
+    Theme {
+        name: "solarized",
+        description: "Solarized accents, for terminals with a Solarized background",
+        dim: SOLARIZED_BASE01,
+        accent: SOLARIZED_YELLOW,
+        alert: SOLARIZED_RED,
+        ranks: [
+            SOLARIZED_ORANGE,
+            SOLARIZED_MAGENTA,
+            SOLARIZED_GREEN,
+            SOLARIZED_CYAN,
+            SOLARIZED_YELLOW,
+        ],
+        nicks: &[
+            SOLARIZED_YELLOW,
+            SOLARIZED_ORANGE,
+            SOLARIZED_RED,
+            SOLARIZED_MAGENTA,
+            SOLARIZED_VIOLET,
+            SOLARIZED_BLUE,
+            SOLARIZED_CYAN,
+            SOLARIZED_GREEN,
+        ],
+    },
+    Theme {
+        name: "high-contrast",
+        description: "Only bright colors, nothing dark gray on black",
+        dim: Color::Gray,
+        accent: Color::LightYellow,
+        alert: Color::LightRed,
+        ranks: [
+            Color::LightRed,
+            Color::LightMagenta,
+            Color::LightGreen,
+            Color::LightCyan,
+            Color::LightYellow,
+        ],
+        nicks: &[
+            Color::White,
+            Color::LightYellow,
+            Color::LightCyan,
+            Color::LightGreen,
+            Color::LightMagenta,
+        ],
+    },
+    Theme {
+        name: "deuteranopia",
+        description: "Okabe-Ito colors that stay apart without telling red from green",
+        dim: Color::Gray,
+        accent: OKABE_YELLOW,
+        alert: OKABE_VERMILLION,
+        ranks: [
+            OKABE_VERMILLION,
+            OKABE_PURPLE,
+            OKABE_BLUE,
+            OKABE_SKY_BLUE,
+            OKABE_ORANGE,
+        ],
+        nicks: &[
+            OKABE_ORANGE,
+            OKABE_SKY_BLUE,
+            OKABE_BLUISH_GREEN,
+            OKABE_YELLOW,
+            OKABE_BLUE,
+            OKABE_VERMILLION,
+            OKABE_PURPLE,
+        ],
+    },
+];
+
+impl Theme {
+    pub fn find(name: &str) -> Option<&'static Theme> {
+        THEMES.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    // The one named in the config, the default one for a name we do not know
+    pub fn named(name: &str) -> &'static Theme {
+        Self::find(name).unwrap_or(&THEMES[0])
+    }
+
+    // Picked by a hash of the nick, ignoring case like IRC does
+    pub fn nick(&self, nick: &str) -> Color {
+        let hash = nick
+            .to_lowercase()
+            .bytes()
+            .fold(5381u32, |hash, b| hash.wrapping_mul(33) ^ u32::from(b));
+        self.nicks[hash as usize % self.nicks.len()]
+    }
+
+    pub fn rank(&self, symbol: char) -> Color {
+        match "~&@%+".find(symbol) {
+            Some(index) => self.ranks[index],
+            None => Color::Reset,
+        }
+    }
+}
+
+impl App {
+    // /theme lists the built-in themes, /theme <name> switches to one and saves it
+    pub(crate) fn theme_command(&mut self, args: &str) {
+        let name = args.trim();
+        if name.is_empty() {
+            let current = Theme::named(&self.config.theme.name).name;
+            for theme in &THEMES {
+                let mark = if theme.name == current { "*" } else { " " };
+                self.buffers
+                    .push_local(format!("{} {:<14} {}", mark, theme.name, theme.description));
+            }
+            return;
+        }
+        let Some(theme) = Theme::find(name) else {
+            let names: Vec<&str> = THEMES.iter().map(|t| t.name).collect();
+            self.buffers.push_local(trf(
+                "No theme named '{}', try {}",
+                &[&name, &names.join(", ")],
+            ));
+            return;
+        };
+        self.config.theme.name = theme.name.to_string();
+        let line = match Config::save_theme(theme.name) {
+            Ok(_) => trf("Theme {}, saved in config.toml", &[&theme.name]),
+            Err(e) => trf("Theme {} for now, not saved: {}", &[&theme.name, &e]),
+        };
+        self.buffers.push_local(line);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::app::App;
+use crate::i18n::{tr, trf};
+
+// The /topic -edit overlay: the topic of `channel` being edited, with the cursor as a
+// char index into `text`
+pub struct TopicEditor {
+    pub channel: String,
+    pub text: String,
+    pub cursor: usize,
+}
+
+impl TopicEditor {
+    fn byte_index(&self) -> usize {
+        self.text
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(self.text.len(), |(index, _)| index)
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let index = self.byte_index();
+        self.text.insert(index, c);
+        self.cursor += 1;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let index = self.byte_index();
+            self.text.remove(index);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            let index = self.byte_index();
+            self.text.remove(index);
+        }
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    // Text with a _ where the cursor is, for drawing
+    pub fn shown(&self) -> String {
+        let index = self.byte_index();
+        format!("{}_{}", &self.text[..index], &self.text[index..])
+    }
+}
+
+impl App {
+    // /topic -edit, the current topic pre-filled in the overlay
+    pub(crate) fn edit_topic(&mut self) {
+        let channel = self.client.current_channel.clone();
+        let Some(topic) = self.client.channel(&channel).map(|c| c.topic.clone()) else {
+            self.buffers
+                .push_local(tr("Join a channel first to edit its topic").to_string());
+            return;
+        };
+        let cursor = topic.chars().count();
+        self.topic_editor = Some(TopicEditor {
+            channel,
+            text: topic,
+            cursor,
+        });
+    }
+
+    // Enter in the overlay. A topic over TOPICLEN keeps the overlay open to shorten it
+    pub(crate) fn submit_topic(&mut self) {
+        let Some(editor) = self.topic_editor.as_ref() else {
+            return;
+        };
+        let text = editor.text.trim().to_string();
+        if let Some(max) = self.client.topiclen
+            && text.len() > max
+        {
+            self.buffers.push_local(trf(
+                "Topic too long: {} bytes, the server allows {}",
+                &[&text.len(), &max],
+            ));
+            return;
+        }
+        let Some(editor) = self.topic_editor.take() else {
+            return;
+        };
+        let current = self
+            .client
+            .channel(&editor.channel)
+            .map(|c| c.topic.trim().to_string());
+        if current.as_deref() == Some(text.as_str()) {
+            self.buffers
+                .push_local(tr("Topic unchanged, nothing sent").to_string());
+            return;
+        }
+        // An empty topic clears it, TOPIC #chan : with nothing after the colon
+        let line = format!("TOPIC {} :{}", editor.channel, text);
+        if let Err(e) = self.client.send_labeled(&line, "/topic") {
+            self.buffers
+                .push_local(trf("Error sending TOPIC: {}", &[&e]));
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, String>;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The connection to the server, plain TCP or TLS. Clones share it, so the receiver thread
+// reads while the main thread writes
+#[derive(Clone)]
+pub struct Connection {
+    tcp: Arc<TcpStream>,
+    // The TLS session wraps its own handle of `tcp`. Reading holds the lock, so reads time
+    // out quickly to let writes in between
+    tls: Option<Arc<Mutex<StreamOwned<ClientConnection, TcpStream>>>>,
+    // Set by shutdown, so the receiver can tell our own disconnects from lost connections
+    closed: Arc<AtomicBool>,
+}
+
+impl Connection {
+    // `handshaking` is called once TCP is up and the TLS handshake starts
+    pub fn open(
+        server: &str,
+        port: u16,
+        tls: bool,
+        handshaking: impl FnOnce(),
+    ) -> Result<Connection> {
+        let addresses = (server, port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve {}: {}", server, e))?;
+        let mut last_error = format!("No address found for {}", server);
+        let mut tcp = None;
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+                Ok(stream) => {
+                    tcp = Some(stream);
+                    break;
+                }
+                Err(e) => last_error = format!("Failed to connect: {}", e),
+            }
+        }
+        let tcp = tcp.ok_or(last_error)?;
+        let timeout = |read| {
+            tcp.set_read_timeout(Some(read))
+                .map_err(|e| format!("Failed to set read timeout: {}", e))
+        };
+        timeout(Duration::from_secs(30))?;
+        tcp.set_write_timeout(Some(Duration::from_secs(10)))
+            .map_err(|e| format!("Failed to set write timeout: {}", e))?;
+
+        let tls = if tls {
+            handshaking();
+            let stream = handshake(server, &tcp)?;
+            timeout(Duration::from_millis(200))?;
+            Some(Arc::new(Mutex::new(stream)))
+        } else {
+            None
+        };
+        Ok(Connection {
+            tcp: Arc::new(tcp),
+            tls,
+            closed: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    // Our port and the server's
+    pub fn ports(&self) -> Option<(u16, u16)> {
+        Some((
+            self.tcp.local_addr().ok()?.port(),
+            self.tcp.peer_addr().ok()?.port(),
+        ))
+    }
+
+    pub fn local_ip(&self) -> Option<IpAddr> {
+        self.tcp.local_addr().ok().map(|addr| addr.ip())
+    }
+
+    // Ends the connection for every clone, the receiver thread sees it closed
+    pub fn shutdown(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some(tls) = &self.tls
+            && let Ok(mut tls) = tls.lock()
+        {
+            tls.conn.send_close_notify();
+            let _ = tls.flush();
+        }
+        let _ = self.tcp.shutdown(Shutdown::Both);
+    }
+
+    pub fn closed_by_us(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+}
+
+fn handshake(server: &str, tcp: &TcpStream) -> Result<StreamOwned<ClientConnection, TcpStream>> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(server.to_string())
+        .map_err(|e| format!("Invalid server name {}: {}", server, e))?;
+    let conn = ClientConnection::new(Arc::new(config), name)
+        .map_err(|e| format!("TLS setup failed: {}", e))?;
+    let tcp = tcp
+        .try_clone()
+        .map_err(|e| format!("Failed to clone stream: {}", e))?;
+    let mut stream = StreamOwned::new(conn, tcp);
+    // Finish the handshake now so certificate errors show up as connect errors
+    while stream.conn.is_handshaking() {
+        stream
+            .conn
+            .complete_io(&mut stream.sock)
+            .map_err(|e| format!("TLS handshake failed: {}", e))?;
+    }
+    Ok(stream)
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &self.tls {
+            Some(tls) => tls.lock().unwrap().read(buf),
+            None => (&*self.tcp).read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.tls {
+            Some(tls) => tls.lock().unwrap().write(buf),
+            None => (&*self.tcp).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &self.tls {
+            Some(tls) => tls.lock().unwrap().flush(),
+            None => (&*self.tcp).flush(),
+        }
+    }
+}
@@ -1,54 +1,111 @@
-use crate::irc_client::IrcClient;
+use crate::access::AccessList;
+use crate::activity::{self, ChannelActivity};
+use crate::app::App;
+use crate::auth;
+use crate::banlist::BanList;
+use crate::buffer::{Buffer, BufferKind};
+use crate::channel::{self, Channel};
+use crate::clock::TimeFormat;
+use crate::composer::Composer;
+use crate::config::{Config, Grouping};
+use crate::dcc::{self, Transfer, TransferState};
+use crate::help::HelpPager;
+use crate::i18n;
+use crate::i18n::{tr, trf};
+use crate::invite::Invite;
+use crate::irc_client::{ConnectionState, IrcClient};
+use crate::irc_url::IrcUrl;
+use crate::logging;
+use crate::nick_menu::{NickAction, NickMenu};
+use crate::palette::{Palette, PaletteAction, PaletteItem};
+use crate::profile::FrameProfile;
+use crate::render::{self, LayoutCache};
+use crate::setup;
+use crate::terminal::TerminalGuard;
+use crate::theme::Theme;
+use crate::topic::TopicEditor;
 //Imports for crossterm
-use crossterm::{
-    event::{self, Event, KeyCode},
-    execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
-};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
 // Imports for ratatui
 use ratatui::{
-    Terminal,
+    Frame, Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{BarChart, Block, Borders, Clear, Gauge, Paragraph, Sparkline, Wrap},
 };
-use std::collections::BTreeMap;
-use std::io::{self, Write, stdout};
+use std::io::{self, Stdout, Write, stdout};
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::thread;
-use std::time::Duration;
-
-pub fn run_tui_client() -> Result<(), Box<dyn std::error::Error>> {
-    // Setup phase - Get user inputs
-    println!("OrangeIRC - TUI IRC Client");
-    println!("--------------------------");
-
-    // Get user input for connection details
-    println!("Enter your nickname:");
-    let mut nickname = String::new();
-    std::io::stdin().read_line(&mut nickname).unwrap();
-    let nickname = nickname.trim();
-
-    println!("Enter the server address (e.g., irc.libera.chat):");
-    let mut server = String::new();
-    std::io::stdin().read_line(&mut server).unwrap();
-    let server = server.trim();
-
-    println!("Enter the port (default: 6667):");
-    let mut port_str = String::new();
-    std::io::stdin().read_line(&mut port_str).unwrap();
-    let port = match port_str.trim().parse::<u16>() {
-        Ok(p) if p > 0 => p,
-        _ => 6667, // Default port
+use std::time::{Duration, Instant};
+use tracing::info;
+
+// What the user typed at the startup prompts
+pub struct ConnectionDetails {
+    pub nickname: String,
+    pub server: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+// Where to connect, from [connect] in the config or else asked for by the first-run setup.
+// None when the user quit the setup
+pub fn connection_details() -> Result<Option<ConnectionDetails>, Box<dyn std::error::Error>> {
+    details_for(None)
+}
+
+// The server of an irc:// link given on the command line replaces the configured one
+fn details_for(
+    url: Option<&IrcUrl>,
+) -> Result<Option<ConnectionDetails>, Box<dyn std::error::Error>> {
+    let mut connect = Config::load().map(|c| c.connect).unwrap_or_default();
+    if let Some(url) = url {
+        connect.server = url.host.clone();
+        connect.port = url.port;
+        connect.tls = url.tls;
+    }
+    if !connect.is_complete() {
+        return setup::run(&connect);
+    }
+    Ok(Some(ConnectionDetails {
+        nickname: connect.nick,
+        server: connect.server,
+        port: connect.port,
+        tls: connect.tls,
+    }))
+}
+
+// `profile_frames` is --profile-frames, see profile.rs
+pub fn run_tui_client(
+    url: Option<IrcUrl>,
+    profile_frames: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Before the setup, it is the first thing shown. Once only, the setup cannot change it
+    let language = Config::load()
+        .map(|c| c.locale.language)
+        .unwrap_or_default();
+    let language_error = i18n::init(&language).err();
+    let Some(details) = details_for(url.as_ref())? else {
+        return Ok(());
+    };
+    let (nickname, server, port) = (&details.nickname, &details.server, details.port);
+
+    let (config, config_error) = match Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
     };
+    let log_error = logging::init(&config.log).err();
+    info!(%server, port, tls = details.tls, %nickname, "starting the TUI");
 
     // Setup IRC client
     let mut client = IrcClient::new(nickname);
+    client.alt_nicks = config.alt_nicks.clone();
+    client.quit_message = config.quit.message.clone();
+    auth::apply_login(&mut client, &config, server, true);
 
     println!("Connecting to {}:{}...", server, port);
-    if let Err(e) = client.connect(server, port) {
+    if let Err(e) = client.connect(server, port, details.tls) {
         println!("Connection error: {}", e);
         return Ok(());
     }
@@ -70,239 +127,1613 @@ pub fn run_tui_client() -> Result<(), Box<dyn std::error::Error>> {
     // Wait for initial server messages
     thread::sleep(Duration::from_secs(1));
 
-    // Initialize TUI
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
+    // Initialize TUI, restored when the guard goes out of scope
+    let guard = TerminalGuard::enter()?;
+    let backend = CrosstermBackend::new(stdout());
     let mut terminal = Terminal::new(backend)?;
+    // The first-run setup may have left its form behind
+    terminal.clear()?;
 
     let mut input = String::new();
-    let mut messages: Vec<String> = vec!["Welcome to OrangeIRC".into()];
-
-    // Add some initial server messages
-    while let Ok(msg) = rx.try_recv() {
-        messages.push(msg);
+    let (events_tx, events_rx) = channel();
+    let mut app = App::new(client, config, events_tx);
+    app.server_lines = Some(tx);
+    app.url_join = url.and_then(|url| url.join_args().map(|args| (url.host, args)));
+    app.buffers
+        .server_mut()
+        .push(tr("Welcome to OrangeIRC").into());
+    if let Some(e) = config_error {
+        app.buffers
+            .server_mut()
+            .push(trf("Config error: {}", &[&e]));
+    }
+    if let Some(e) = language_error {
+        app.buffers
+            .server_mut()
+            .push(trf("Config error: {}", &[&e]));
+    }
+    if let Some(e) = log_error {
+        app.buffers.server_mut().push(trf("Log error: {}", &[&e]));
     }
 
-    // Commands with descriptions
-    let commands: BTreeMap<&str, &str> = BTreeMap::from([
-        ("/help", "Display all available commands with descriptions"),
-        ("/clear", "Clear the chat window"),
-        ("/join", "Join a channel: /join #channel"),
-        ("/msg", "Send a private message: /msg target message"),
-        ("/nickserv", "Send command to NickServ: /nickserv command"),
-        ("/quit", "Exit the application"),
-    ]);
+    // Keep the socket alive for the whole session, dropping it removes the file
+    let _control = app.start_services();
 
     // Tab completion state
     let mut completion_matches: Vec<String> = Vec::new();
     let mut completion_index: usize = 0;
     let mut last_input: String = String::new();
 
+    // Frames are only drawn when something changed: input, server lines, events, new lines
+    // from elsewhere or a resize. Once a second anyway for anything that slips through
+    let time_format = match TimeFormat::new(&app.config.theme) {
+        Ok(format) => format,
+        Err(e) => {
+            app.buffers
+                .server_mut()
+                .push(trf("Config error: {}", &[&e]));
+            TimeFormat::default()
+        }
+    };
+    if Theme::find(&app.config.theme.name).is_none() {
+        app.buffers.server_mut().push(trf(
+            "Config error: no theme named '{}', /theme lists them",
+            &[&app.config.theme.name],
+        ));
+    }
+    // Without the nick a grouped line makes no sense read out on its own
+    let accessible = app.config.theme.accessible;
+    let mut theme = app.config.theme.clone();
+    if accessible {
+        theme.group = Grouping::Off;
+    }
+    let mut layout = LayoutCache::new(time_format, &theme);
+    // What the accessible mode's status row says, see announce
+    let mut announcement = String::new();
+    let mut dirty = true;
+    let mut drawn_total = 0;
+    let mut drawn_size = terminal.size()?;
+    let mut last_draw = Instant::now();
+    let mut shown = app.buffers.active().name.clone();
+    let mut profile = profile_frames.then(FrameProfile::default);
+    // When the last poll returned, and server lines handled since the last frame
+    let mut woke = Instant::now();
+    let mut lines_since_draw = 0;
+
     loop {
+        if app.buffers.active().name != shown {
+            if let Some(left) = app.buffers.get_mut(&shown) {
+                layout.leave(left);
+            }
+            shown = app.buffers.active().name.clone();
+            let active = app.buffers.active();
+            announcement = trf(
+                "Now in {}, {} unread",
+                &[&active.name, &active.total.saturating_sub(active.read)],
+            );
+        }
         // Check for new messages from server
         while let Ok(msg) = rx.try_recv() {
-            messages.push(msg);
-            // Keep message list at a reasonable size
-            if messages.len() > 1000 {
-                messages.remove(0);
+            app.handle_server_line(msg);
+            lines_since_draw += 1;
+            dirty = true;
+        }
+        // Results of background jobs such as /exec
+        while let Ok(event) = events_rx.try_recv() {
+            app.handle_event(event);
+            dirty = true;
+        }
+        // Lines only count as read once someone can see them
+        if app.focused {
+            app.buffers.mark_active_read();
+        }
+        app.tick();
+        // /theme switched to another one
+        if layout.set_theme(Theme::named(&app.config.theme.name)) {
+            dirty = true;
+        }
+
+        let total: usize = app.buffers.iter().map(|b| b.total).sum();
+        let size = terminal.size()?;
+        if dirty
+            || total != drawn_total
+            || size != drawn_size
+            || last_draw.elapsed() >= Duration::from_secs(1)
+        {
+            let started = Instant::now();
+            draw(&mut terminal, &app, &input, &mut layout, &announcement)?;
+            if let Some(profile) = profile.as_mut() {
+                profile.frame(started.elapsed(), woke.elapsed(), lines_since_draw);
             }
+            lines_since_draw = 0;
+            dirty = false;
+            drawn_total = total;
+            drawn_size = size;
+            last_draw = Instant::now();
         }
 
-        // Draw UI
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(1)
-                .constraints([Constraint::Min(5), Constraint::Length(3)].as_ref())
-                .split(f.size());
-
-            // Chat history
-            let messages_block = Block::default()
-                .title(format!(
-                    "Server: {} - Channel: {}",
-                    if client.server.is_empty() {
-                        "Not connected"
+        if let Some(name) = app.alert.take() {
+            announcement = announce_alert(&app, &name);
+            dirty = true;
+        }
+        if app.bell {
+            terminal.backend_mut().write_all(b"\x07")?;
+            terminal.backend_mut().flush()?;
+            app.bell = false;
+        }
+        // Someone in the "nick: " completions being cycled through changed nick
+        for (old, new) in app.renamed.drain(..) {
+            let (old, new) = (format!("{}: ", old), format!("{}: ", new));
+            for entry in completion_matches
+                .iter_mut()
+                .chain([&mut input, &mut last_input])
+            {
+                if *entry == old {
+                    *entry = new.clone();
+                    dirty = true;
+                }
+            }
+        }
+
+        // Handle input
+        let event = if event::poll(std::time::Duration::from_millis(200))? {
+            Some(event::read()?)
+        } else {
+            None
+        };
+        woke = Instant::now();
+        // Pasted text arrives in one piece, several lines of it may be offered to a paste
+        // service instead of flooding the channel
+        match event {
+            Some(Event::FocusGained) => {
+                app.focused = true;
+                dirty = true;
+            }
+            // What comes in while the window is in the background gets the unread rule
+            Some(Event::FocusLost) => {
+                app.focused = false;
+                app.buffers.mark_active_left();
+            }
+            _ => {}
+        }
+        if let Some(Event::Paste(text)) = &event {
+            app.user_activity();
+            dirty = true;
+            if app.secret_prompt.is_some() {
+                input.push_str(text.lines().next().unwrap_or_default());
+            } else if let Some(composer) = app.composer.as_mut() {
+                // The composer takes pastes as they are, to be looked over before sending
+                composer
+                    .text
+                    .push_str(&text.replace("\r\n", "\n").replace('\r', "\n"));
+            } else {
+                app.paste(&mut input, text);
+            }
+        }
+        if let Some(Event::Key(key)) = event {
+            app.user_activity();
+            dirty = true;
+            // Any other key takes back a first Esc
+            let confirming = std::mem::take(&mut app.confirm_quit);
+            match key.code {
+                // A password prompt takes all typing, nothing of it may reach execute
+                KeyCode::Enter if app.secret_prompt.is_some() => {
+                    app.submit_secret(Some(std::mem::take(&mut input)));
+                }
+                KeyCode::Esc if app.secret_prompt.is_some() => {
+                    input.clear();
+                    app.submit_secret(None);
+                }
+                KeyCode::Char(c) if app.secret_prompt.is_some() => {
+                    input.push(c);
+                }
+                KeyCode::Backspace if app.secret_prompt.is_some() => {
+                    input.pop();
+                }
+                // History, completion and the F-keys would show or change the hidden input
+                _ if app.secret_prompt.is_some() => {}
+                // So does the nick menu while it is open
+                code if app.nick_menu.is_some() => nick_menu_key(&mut app, code),
+                // And the nick list while it has focus
+                code if app.nick_filter.is_some() => nicklist_key(&mut app, code),
+                // And the composer, the topic editor, the help pager and the command palette
+                code if app.composer.is_some() => composer_key(&mut app, code, key.modifiers),
+                code if app.topic_editor.is_some() => topic_editor_key(&mut app, code),
+                code if app.help.is_some() => help_key(&mut app, code),
+                code if app.palette.is_some() => {
+                    palette_key(&mut app, code, &mut input, &mut layout);
+                    if app.should_quit {
+                        break;
+                    }
+                }
+                KeyCode::Esc if app.stats_shown.is_some() => {
+                    app.stats_shown = None;
+                }
+                // Alt+Enter opens the composer for a message of several lines
+                KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.open_composer(&mut input);
+                }
+                KeyCode::Enter => {
+                    app.execute(&input);
+                    input.clear();
+                    if app.should_quit {
+                        break;
+                    }
+                }
+                // Quick-join shortcuts for the favorites listed on the dashboard
+                KeyCode::Char(c)
+                    if input.is_empty()
+                        && app.buffers.active().kind == BufferKind::Dashboard
+                        && c.is_ascii_digit()
+                        && c != '0'
+                        && app.config.favorites.len() > c as usize - '1' as usize =>
+                {
+                    let channel = app.config.favorites[c as usize - '1' as usize].clone();
+                    app.join(&channel);
+                }
+                // Transfers panel: c cancels the highlighted transfer, r retries it
+                KeyCode::Char(c @ ('c' | 'r'))
+                    if input.is_empty()
+                        && app.buffers.active().kind == BufferKind::Transfers
+                        && app.transfer_selected < app.transfers.len() =>
+                {
+                    let index = app.transfer_selected;
+                    if c == 'c' {
+                        app.dcc_cancel_index(index);
                     } else {
-                        &client.server
-                    },
-                    if client.current_channel.is_empty() {
-                        "None"
+                        let id = app.transfers[index].id.to_string();
+                        app.dcc_retry(&id);
+                    }
+                }
+                // Invitations panel: j joins the highlighted channel, d dismisses it
+                KeyCode::Char(c @ ('j' | 'd'))
+                    if input.is_empty() && app.buffers.active().kind == BufferKind::Invites =>
+                {
+                    let index = app.invite_selected;
+                    if c == 'j' {
+                        app.accept_invite(index);
                     } else {
-                        &client.current_channel
+                        app.dismiss_invite(index);
                     }
-                ))
-                .borders(Borders::ALL);
-
-            let message_height = chunks[0].height as usize - 2; // Account for borders
-            let messages_to_show = if messages.len() > message_height {
-                &messages[messages.len() - message_height..]
-            } else {
-                &messages[..]
-            };
-
-            let msg_paragraph = Paragraph::new(
-                messages_to_show
-                    .iter()
-                    .map(|m| {
-                        if m.starts_with("!!!") {
-                            Line::from(vec![Span::styled(
-                                m,
-                                Style::default()
-                                    .fg(Color::Yellow)
-                                    .add_modifier(Modifier::BOLD),
-                            )])
-                        } else {
-                            Line::from(vec![Span::raw(m)])
-                        }
-                    })
-                    .collect::<Vec<_>>(),
-            )
-            .block(messages_block)
-            .wrap(Wrap { trim: true });
-
-            f.render_widget(msg_paragraph, chunks[0]);
-
-            let input_text = Text::from(input.clone());
-            let input_block = Paragraph::new(input_text)
-                .block(
-                    Block::default()
-                        .title(format!(
-                            "Input (Current channel: {})",
-                            if client.current_channel.is_empty() {
-                                "None"
-                            } else {
-                                &client.current_channel
-                            }
-                        ))
-                        .borders(Borders::ALL),
-                )
-                .style(Style::default());
-            f.render_widget(input_block, chunks[1]);
-
-            // Blinking cursor
-            f.set_cursor(chunks[1].x + input.len() as u16 + 1, chunks[1].y + 1);
-        })?;
-
-        // Handle input
-        if event::poll(std::time::Duration::from_millis(200))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Enter => {
-                        // Process commands
-                        if input.starts_with("/join ") {
-                            let channel = &input[6..];
-                            if channel.is_empty() {
-                                messages.push("Usage: /join #channel".to_string());
-                            } else {
-                                match client.join_channel(channel) {
-                                    Ok(_) => messages.push(format!("Joining channel: {}", channel)),
-                                    Err(e) => {
-                                        messages.push(format!("Error joining channel: {}", e))
-                                    }
-                                }
-                            }
-                        } else if input.starts_with("/msg ") {
-                            let parts: Vec<&str> = input[5..].splitn(2, ' ').collect();
-                            if parts.len() != 2 {
-                                messages.push("Usage: /msg target message".to_string());
-                            } else {
-                                let target = parts[0];
-                                let message = parts[1];
-
-                                match client.send_message(target, message) {
-                                    Ok(_) => messages.push(format!("-> *{}* {}", target, message)),
-                                    Err(e) => {
-                                        messages.push(format!("Error sending message: {}", e))
-                                    }
-                                }
-                            }
-                        } else if input.starts_with("/nickserv ") {
-                            let command = &input[9..];
-                            match client.send_message("NickServ", command) {
-                                Ok(_) => messages.push(format!("-> *NickServ* {}", command)),
-                                Err(e) => {
-                                    messages.push(format!("Error sending to NickServ: {}", e))
-                                }
-                            }
-                        } else if input == "/clear" {
-                            messages.clear();
-                            messages.push("Chat cleared.".to_string());
-                        } else if input == "/quit" || input == "/exit" {
-                            let _ = client.quit();
-                            break;
-                        } else if input == "/help" {
-                            messages.push("---- Command Help ----".to_string());
-                            for (cmd, desc) in &commands {
-                                messages.push(format!("{} - {}", cmd, desc));
-                            }
-                        } else if !input.is_empty() {
-                            // Send message to current channel
-                            let current_channel = client.current_channel.clone();
-                            if client.current_channel.is_empty() {
-                                messages
-                                    .push("Join a channel first with /join #channel".to_string());
-                            } else {
-                                match client.send_message(&current_channel, &input) {
-                                    Ok(_) => messages
-                                        .push(format!("-> {}: {}", client.current_channel, input)),
-                                    Err(e) => {
-                                        messages.push(format!("Error sending message: {}", e))
-                                    }
-                                }
-                            }
+                }
+                KeyCode::Up if app.buffers.active().kind == BufferKind::Invites => {
+                    app.invite_selected = app.invite_selected.saturating_sub(1);
+                }
+                KeyCode::Down
+                    if app.buffers.active().kind == BufferKind::Invites
+                        && app.invite_selected + 1 < app.invites.len() =>
+                {
+                    app.invite_selected += 1;
+                }
+                // ChanServ panel: a and e fill in the command to add or edit an entry, d removes
+                // the highlighted one and r reloads the list
+                KeyCode::Char(c @ ('a' | 'e' | 'd' | 'r'))
+                    if input.is_empty() && app.buffers.active().kind == BufferKind::Access =>
+                {
+                    let selected = app
+                        .access
+                        .as_ref()
+                        .and_then(|list| list.entries.get(app.access_selected))
+                        .map(|entry| (entry.mask.clone(), entry.flags.clone()));
+                    match (c, selected) {
+                        ('a', _) => input = "/access add ".to_string(),
+                        ('e', Some((mask, flags))) => {
+                            input = format!("/access add {} {}", mask, flags)
                         }
-                        input.clear();
+                        ('d', Some((mask, _))) => app.access_change(&mask, None),
+                        ('r', _) => app.refresh_access(),
+                        _ => {}
                     }
-                    KeyCode::Char(c) => {
-                        input.push(c);
+                }
+                // Bans panel: u lifts the highlighted ban, r reloads the list
+                KeyCode::Char(c @ ('u' | 'r'))
+                    if input.is_empty() && app.buffers.active().kind == BufferKind::Bans =>
+                {
+                    if c == 'u' {
+                        app.unban(app.ban_selected);
+                    } else {
+                        app.reload_bans();
                     }
-                    KeyCode::Backspace => {
-                        input.pop();
+                }
+                KeyCode::Up if app.buffers.active().kind == BufferKind::Bans => {
+                    app.ban_selected = app.ban_selected.saturating_sub(1);
+                }
+                KeyCode::Down
+                    if app.buffers.active().kind == BufferKind::Bans
+                        && app
+                            .bans
+                            .as_ref()
+                            .is_some_and(|list| app.ban_selected + 1 < list.entries.len()) =>
+                {
+                    app.ban_selected += 1;
+                }
+                KeyCode::Up if app.buffers.active().kind == BufferKind::Access => {
+                    app.access_selected = app.access_selected.saturating_sub(1);
+                }
+                KeyCode::Down
+                    if app.buffers.active().kind == BufferKind::Access
+                        && app
+                            .access
+                            .as_ref()
+                            .is_some_and(|list| app.access_selected + 1 < list.entries.len()) =>
+                {
+                    app.access_selected += 1;
+                }
+                KeyCode::Up if app.buffers.active().kind == BufferKind::Transfers => {
+                    app.transfer_selected = app.transfer_selected.saturating_sub(1);
+                }
+                KeyCode::Down
+                    if app.buffers.active().kind == BufferKind::Transfers
+                        && app.transfer_selected + 1 < app.transfers.len() =>
+                {
+                    app.transfer_selected += 1;
+                }
+                // Alt+U jumps back to the first unread line
+                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    let jumped = layout.jump_to_unread(app.buffers.active());
+                    if !jumped {
+                        app.buffers
+                            .push_local(tr("No unread lines here").to_string());
                     }
-                    KeyCode::Tab => {
-                        if input.starts_with('/') {
-                            // Reset match list if input changed
-                            if input != last_input {
-                                completion_matches = commands
-                                    .keys()
-                                    .filter(|cmd| cmd.starts_with(&input))
-                                    .map(|s| s.to_string())
-                                    .collect();
-                                completion_index = 0;
-                                last_input = input.clone();
-                            }
-
-                            if !completion_matches.is_empty() {
-                                input = completion_matches[completion_index].clone();
-                                completion_index =
-                                    (completion_index + 1) % completion_matches.len();
-                            }
-                        }
+                }
+                // Alt+, and Alt+. go to the previous and next highlight
+                KeyCode::Char(',') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    jump_to_highlight(&mut app, &mut layout, true);
+                }
+                KeyCode::Char('.') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    jump_to_highlight(&mut app, &mut layout, false);
+                }
+                // Alt+Up and Alt+Down pick a nick in the nick list, Alt+N opens its menu
+                KeyCode::Up if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.move_nick_selection(false);
+                }
+                KeyCode::Down if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.move_nick_selection(true);
+                }
+                KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.open_nick_menu(&input);
+                }
+                // Alt+L gives the nick list the keyboard to filter it
+                KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.focus_nick_list();
+                }
+                // F2, F3 and F4 show or hide the timestamps, nick list and topic bar
+                KeyCode::F(2) => app.toggle_command("timestamps"),
+                KeyCode::F(3) => app.toggle_command("nicklist"),
+                KeyCode::F(4) => app.toggle_command("topic"),
+                // Alt+Z maximizes the chat pane, a second press restores the layout
+                KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.zoomed = !app.zoomed;
+                }
+                // Ctrl+P opens the command palette
+                KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app.palette = Some(Palette::default());
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                }
+                KeyCode::PageUp => layout.page(&app.buffers.active().name, true),
+                KeyCode::PageDown => layout.page(&app.buffers.active().name, false),
+                KeyCode::Left if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.buffers.previous();
+                }
+                KeyCode::Right if key.modifiers.contains(KeyModifiers::ALT) => {
+                    app.buffers.next();
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                // On an empty line, Tab goes through the last speakers as "nick: "
+                KeyCode::Tab
+                    if input.is_empty()
+                        || (input == last_input
+                            && !input.starts_with('/')
+                            && !completion_matches.is_empty()) =>
+                {
+                    if input.is_empty() {
+                        completion_matches = speaker_completions(&app);
+                        completion_index = 0;
                     }
-                    KeyCode::Esc => {
-                        let _ = client.quit();
-                        break;
+                    if !completion_matches.is_empty() {
+                        input = completion_matches[completion_index].clone();
+                        completion_index = (completion_index + 1) % completion_matches.len();
+                        last_input = input.clone();
                     }
-                    _ => {}
                 }
+                KeyCode::Tab if input.starts_with('/') => {
+                    // Reset match list if input changed
+                    if input != last_input {
+                        completion_matches = app
+                            .commands
+                            .keys()
+                            .filter(|cmd| cmd.starts_with(&input))
+                            .map(|s| s.to_string())
+                            .collect();
+                        completion_index = 0;
+                        last_input = input.clone();
+                    }
 
-                // Reset tab-completion if any non-tab key pressed
-                if key.code != KeyCode::Tab {
-                    completion_matches.clear();
-                    completion_index = 0;
-                    last_input.clear();
+                    if !completion_matches.is_empty() {
+                        input = completion_matches[completion_index].clone();
+                        completion_index = (completion_index + 1) % completion_matches.len();
+                    }
+                }
+                // Esc stops queued lines that are still going out before it quits
+                KeyCode::Esc if app.outgoing_progress().is_some() => app.cancel_outgoing(),
+                KeyCode::Esc if app.config.quit.confirm && !confirming => {
+                    app.confirm_quit = true;
                 }
+                KeyCode::Esc => {
+                    let _ = app.client.quit();
+                    break;
+                }
+                _ => {}
+            }
+
+            // Reset tab-completion if any non-tab key pressed
+            if key.code != KeyCode::Tab {
+                completion_matches.clear();
+                completion_index = 0;
+                last_input.clear();
             }
         }
     }
 
     // Clean up
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    drop(guard);
+    if let Err(e) = app.seen.save(true) {
+        println!("{}", e);
+    }
 
+    if let Some(profile) = profile {
+        for line in profile.summary() {
+            info!("{}", line);
+            println!("{}", line);
+        }
+    }
     println!("Disconnected. Goodbye!");
     Ok(())
 }
+
+// "nick: " for everyone who spoke in the current channel lately, newest first
+fn speaker_completions(app: &App) -> Vec<String> {
+    let Some(chan) = app.client.channel(&app.client.current_channel) else {
+        return Vec::new();
+    };
+    chan.recent_speakers()
+        .into_iter()
+        .filter(|nick| !app.client.is_me(nick))
+        .take(10)
+        .map(|nick| format!("{}: ", nick))
+        .collect()
+}
+
+// Up and Down move through the nick menu, Enter or an action's key runs it, Esc closes it
+fn nick_menu_key(app: &mut App, code: KeyCode) {
+    let Some(menu) = app.nick_menu.as_mut() else {
+        return;
+    };
+    let action = match code {
+        KeyCode::Up => {
+            menu.selected = menu.selected.saturating_sub(1);
+            return;
+        }
+        KeyCode::Down => {
+            menu.selected = (menu.selected + 1).min(NickAction::ALL.len() - 1);
+            return;
+        }
+        KeyCode::Esc => {
+            app.nick_menu = None;
+            return;
+        }
+        KeyCode::Enter => Some(NickAction::ALL[menu.selected]),
+        KeyCode::Char(c) => NickAction::ALL.into_iter().find(|a| a.key() == c),
+        _ => None,
+    };
+    if let Some(action) = action {
+        app.nick_action(action);
+    }
+}
+
+// Typing filters the focused nick list, Up and Down move the highlight, Enter opens the
+// menu for the highlighted nick and Esc gives the keyboard back to the input line
+fn nicklist_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Char(c) => app.edit_nick_filter(|filter| filter.push(c)),
+        KeyCode::Backspace => app.edit_nick_filter(|filter| {
+            filter.pop();
+        }),
+        KeyCode::Up => app.move_nick_selection(false),
+        KeyCode::Down => app.move_nick_selection(true),
+        KeyCode::PageUp | KeyCode::PageDown => {
+            for _ in 0..10 {
+                app.move_nick_selection(code == KeyCode::PageDown);
+            }
+        }
+        KeyCode::Enter => {
+            // The highlighted nick, whatever is in the input line
+            if let Some(nick) = app.selected_nick() {
+                app.open_nick_menu(&nick);
+            }
+        }
+        KeyCode::Esc => {
+            app.nick_filter = None;
+            app.nick_selected = None;
+        }
+        _ => {}
+    }
+}
+
+// Typing narrows the palette down, Up and Down pick an entry, Enter runs it and Esc closes
+// the palette. A command that takes arguments ends up in the input line instead
+fn palette_key(app: &mut App, code: KeyCode, input: &mut String, layout: &mut LayoutCache) {
+    let Some(query) = app.palette.as_ref().map(|p| p.query.clone()) else {
+        return;
+    };
+    let count = app.palette_entries(&query).len();
+    let Some(palette) = app.palette.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => {
+            palette.query.push(c);
+            palette.selected = 0;
+        }
+        KeyCode::Backspace => {
+            palette.query.pop();
+            palette.selected = 0;
+        }
+        KeyCode::Up => palette.selected = palette.selected.saturating_sub(1),
+        KeyCode::Down => palette.selected = (palette.selected + 1).min(count.saturating_sub(1)),
+        KeyCode::Esc => app.palette = None,
+        KeyCode::Enter => {
+            let selected = palette.selected;
+            app.palette = None;
+            let entries = app.palette_entries(&query);
+            let Some(entry) = entries.get(selected) else {
+                return;
+            };
+            match entry.item {
+                PaletteItem::Command(command) => {
+                    if let Some(text) = app.palette_command(command, entry.help) {
+                        *input = text;
+                    }
+                }
+                PaletteItem::Action(PaletteAction::NextBuffer) => app.buffers.next(),
+                PaletteItem::Action(PaletteAction::PreviousBuffer) => app.buffers.previous(),
+                PaletteItem::Action(PaletteAction::JumpToUnread) => {
+                    if !layout.jump_to_unread(app.buffers.active()) {
+                        app.buffers
+                            .push_local(tr("No unread lines here").to_string());
+                    }
+                }
+                PaletteItem::Action(PaletteAction::PreviousHighlight) => {
+                    jump_to_highlight(app, layout, true)
+                }
+                PaletteItem::Action(PaletteAction::NextHighlight) => {
+                    jump_to_highlight(app, layout, false)
+                }
+                PaletteItem::Action(PaletteAction::NickMenu) => app.open_nick_menu(input),
+                PaletteItem::Action(PaletteAction::FilterNicks) => app.focus_nick_list(),
+                PaletteItem::Action(PaletteAction::Zoom) => app.zoomed = !app.zoomed,
+            }
+        }
+        _ => {}
+    }
+}
+
+// Enter starts a new line, Alt+Enter sends it all and Esc drops it
+fn composer_key(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
+    let Some(composer) = app.composer.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Enter if modifiers.contains(KeyModifiers::ALT) => app.send_composer(),
+        KeyCode::Enter => composer.text.push('\n'),
+        KeyCode::Char(c) => composer.text.push(c),
+        KeyCode::Backspace => {
+            composer.text.pop();
+        }
+        KeyCode::Esc => app.composer = None,
+        _ => {}
+    }
+}
+
+// Typing edits the topic, Enter sends it and Esc leaves it as it was
+fn topic_editor_key(app: &mut App, code: KeyCode) {
+    let Some(editor) = app.topic_editor.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => editor.insert(c),
+        KeyCode::Backspace => editor.backspace(),
+        KeyCode::Delete => editor.delete(),
+        KeyCode::Left => editor.left(),
+        KeyCode::Right => editor.right(),
+        KeyCode::Home => editor.home(),
+        KeyCode::End => editor.end(),
+        KeyCode::Enter => app.submit_topic(),
+        KeyCode::Esc => app.topic_editor = None,
+        _ => {}
+    }
+}
+
+// Up, Down, PgUp, PgDn, Home and End scroll the help, / searches, n finds the next match
+// and Esc or q closes it
+fn help_key(app: &mut App, code: KeyCode) {
+    let Some(help) = app.help.as_mut() else {
+        return;
+    };
+    // What is on screen between the borders above the input line
+    let page = crossterm::terminal::size().map_or(20, |(_, rows)| rows.saturating_sub(7) as usize);
+    if let Some(typing) = help.typing.as_mut() {
+        match code {
+            KeyCode::Char(c) => typing.push(c),
+            KeyCode::Backspace => {
+                typing.pop();
+            }
+            KeyCode::Enter => {
+                help.query = help.typing.take().unwrap_or_default();
+                let from = help.scroll;
+                help.find_next(from);
+            }
+            KeyCode::Esc => help.typing = None,
+            _ => {}
+        }
+        return;
+    }
+    match code {
+        KeyCode::Up => help.scroll_by(-1, page),
+        KeyCode::Down => help.scroll_by(1, page),
+        KeyCode::PageUp => help.scroll_by(-(page as isize), page),
+        KeyCode::PageDown => help.scroll_by(page as isize, page),
+        KeyCode::Home => help.scroll = 0,
+        KeyCode::End => help.scroll_by(isize::MAX, page),
+        KeyCode::Char('/') => help.typing = Some(String::new()),
+        KeyCode::Char('n') => {
+            let from = help.scroll + 1;
+            help.find_next(from);
+        }
+        KeyCode::Esc | KeyCode::Char('q') => app.help = None,
+        _ => {}
+    }
+}
+
+// Goes to the highlight before or after the last one jumped to in this buffer, and on to
+// the buffers before or after it when there are no more here
+fn jump_to_highlight(app: &mut App, layout: &mut LayoutCache, back: bool) {
+    if layout.jump_to_highlight(app.buffers.active(), back) {
+        return;
+    }
+    let names: Vec<&str> = app.buffers.iter().map(|b| b.name.as_str()).collect();
+    let active = app.buffers.active().name.as_str();
+    let here = names.iter().position(|&name| name == active).unwrap_or(0);
+    let count = names.len();
+    let next = (1..count)
+        .map(|step| {
+            let index = if back {
+                here + count - step
+            } else {
+                here + step
+            };
+            names[index % count].to_string()
+        })
+        .find(|name| {
+            app.buffers
+                .get(name)
+                .is_some_and(|b| !b.kind.is_view() && b.lines.iter().any(render::highlighted))
+        });
+    let Some(name) = next else {
+        app.buffers.push_local(tr("No more highlights").to_string());
+        return;
+    };
+    app.buffers.select_name(&name);
+    layout.enter_highlight(app.buffers.active(), back);
+}
+
+// One frame: the active buffer or panel above, the input line below
+fn draw(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &App,
+    input: &str,
+    layout: &mut LayoutCache,
+    announcement: &str,
+) -> io::Result<()> {
+    let accessible = app.config.theme.accessible;
+    terminal.draw(|f| {
+        // Accessible mode has a status row above the input line
+        let status = if accessible { 1 } else { 0 };
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(if app.zoomed { 0 } else { 1 })
+            .constraints(
+                [
+                    Constraint::Min(5),
+                    Constraint::Length(status),
+                    Constraint::Length(3),
+                ]
+                .as_ref(),
+            )
+            .split(f.size());
+        if accessible {
+            f.render_widget(Paragraph::new(announcement), chunks[1]);
+        }
+        let chunks = [chunks[0], chunks[2]];
+
+        let active = app.buffers.active();
+        if active.kind == BufferKind::Dashboard {
+            draw_dashboard(f, chunks[0], app);
+        } else if active.kind == BufferKind::Transfers {
+            draw_transfers(f, chunks[0], &app.transfers, app.transfer_selected);
+        } else if active.kind == BufferKind::Invites {
+            draw_invites(f, chunks[0], &app.invites, app.invite_selected);
+        } else if active.kind == BufferKind::Bans {
+            draw_bans(f, chunks[0], app.bans.as_ref(), app.ban_selected);
+        } else if active.kind == BufferKind::Access {
+            draw_access(f, chunks[0], app.access.as_ref(), app.access_selected);
+        } else {
+            draw_messages(f, chunks[0], app, active, layout);
+        }
+        if let Some(activity) = app
+            .stats_shown
+            .as_ref()
+            .and_then(|name| app.activity.get(name))
+        {
+            draw_stats(f, chunks[0], activity);
+        }
+        if let Some(menu) = &app.nick_menu {
+            draw_nick_menu(f, chunks[0], menu);
+        }
+        if let Some(help) = &app.help {
+            draw_help(f, chunks[0], help);
+        }
+        if let Some(palette) = &app.palette {
+            draw_palette(f, chunks[0], app, palette);
+        }
+        if let Some(editor) = &app.topic_editor {
+            draw_topic_editor(f, chunks[0], app, editor);
+        }
+        if let Some(composer) = &app.composer {
+            draw_composer(f, chunks[0], composer);
+        }
+
+        // Running DCC transfers are shown next to the channel, e.g. "DCC: file.zip 42%"
+        let mut title = trf(
+            "Input as {} (Current channel: {})",
+            &[&app.client.nickname, &channel_label(&app.client)],
+        );
+        let running: Vec<String> = app
+            .transfers
+            .iter()
+            .filter(|t| t.state == TransferState::Active)
+            .map(|t| format!("{} {}%", t.filename, t.percent()))
+            .collect();
+        if !running.is_empty() {
+            title.push_str(&format!(" DCC: {}", running.join(", ")));
+        }
+        if app.confirm_quit {
+            title.push_str(tr(" [Esc again to quit, any other key to stay]"));
+        }
+        // Why nothing is arriving, when that is the case
+        if app.client.state != ConnectionState::Ready {
+            title.push_str(&format!(" [{}]", state_label(app.client.state)));
+        }
+        if !app.client.user_modes.is_empty() {
+            title.push_str(&format!(" [+{}]", app.client.user_modes));
+        }
+        if app.client.away.is_some() {
+            title.push_str(tr(" [away]"));
+        }
+        if !app.invites.is_empty() {
+            title.push_str(&trf(" [{} invites]", &[&app.invites.len()]));
+        }
+        // Progress of a paste or /exec -o going out, e.g. [sending 3/12, Esc stops]
+        if let Some((sent, total)) = app.outgoing_progress() {
+            title.push_str(&trf(" [sending {}/{}, Esc stops]", &[&sent, &total]));
+        }
+        // Byte counter once the input gets near a server limit
+        let mut title_style = Style::default();
+        if let Some((_, used, max)) = app.input_limit(input)
+            && used * 5 >= max * 4
+        {
+            title.push_str(&format!(" [{}/{}]", used, max));
+            if used > max {
+                title_style = title_style.fg(Theme::named(&app.config.theme.name).alert);
+            }
+        }
+
+        // Secrets are only ever drawn as asterisks
+        let shown = match app.secret_title() {
+            Some(secret_title) => {
+                title = secret_title.to_string();
+                "*".repeat(input.chars().count())
+            }
+            None => input.to_string(),
+        };
+        let input_text = Text::from(shown.clone());
+        let input_block = Paragraph::new(input_text)
+            .block(
+                Block::default()
+                    .title(Span::styled(title, title_style))
+                    .borders(Borders::ALL),
+            )
+            .style(Style::default());
+        f.render_widget(input_block, chunks[1]);
+
+        // Blinking cursor
+        f.set_cursor(chunks[1].x + shown.len() as u16 + 1, chunks[1].y + 1);
+        if accessible {
+            strip_decorations(f.buffer_mut());
+        }
+    })?;
+    Ok(())
+}
+
+// Screen readers read box drawing out loud, accessible mode blanks it along with the
+// block elements of gauges and charts. Titles and labels stay
+fn strip_decorations(buffer: &mut ratatui::buffer::Buffer) {
+    for cell in buffer.content.iter_mut() {
+        if cell
+            .symbol()
+            .chars()
+            .next()
+            .is_some_and(|c| ('\u{2500}'..='\u{259F}').contains(&c))
+        {
+            cell.set_symbol(" ");
+        }
+    }
+}
+
+// The connection state as the status bar and dashboard show it
+fn state_label(state: ConnectionState) -> String {
+    match state {
+        ConnectionState::Reconnecting { at } => trf(
+            "reconnecting in {}s",
+            &[&(at.saturating_duration_since(Instant::now()).as_secs() + 1)],
+        ),
+        ConnectionState::Disconnected => tr("disconnected").to_string(),
+        ConnectionState::Connecting => tr("connecting").to_string(),
+        ConnectionState::TlsHandshake => tr("TLS handshake").to_string(),
+        ConnectionState::Registering => tr("registering").to_string(),
+        ConnectionState::Authenticating => tr("authenticating").to_string(),
+        ConnectionState::Ready => tr("ready").to_string(),
+    }
+}
+
+// The current channel for the input and chat titles, marked when we were thrown out of it.
+// A query shows why the nick is away, so nobody waits for an answer
+fn channel_label(client: &IrcClient) -> String {
+    let name = &client.current_channel;
+    if name.is_empty() {
+        tr("None").to_string()
+    } else if client.kicked_from(name) {
+        trf("{} (not joined)", &[name])
+    } else if name.starts_with(['#', '&']) {
+        name.clone()
+    } else {
+        match client.away_message(name) {
+            Some("") => trf("{} (away)", &[name]),
+            Some(message) => trf("{} (away: {})", &[name, &message]),
+            None => name.clone(),
+        }
+    }
+}
+
+// What accessible mode says when a buffer rings: where, and its newest line
+fn announce_alert(app: &App, name: &str) -> String {
+    let line = app
+        .buffers
+        .get(name)
+        .and_then(|buffer| buffer.lines.back())
+        .map(|line| line.text.as_str())
+        .unwrap_or_default();
+    trf("Alert in {}: {}", &[&name, &line])
+}
+
+// Every DCC transfer with a progress bar, two rows each
+fn draw_transfers(f: &mut Frame, area: Rect, transfers: &[Transfer], selected: usize) {
+    let block = Block::default()
+        .title(tr(
+            "Transfers (Up/Down select, c cancel, r retry, /dcc list for text)",
+        ))
+        .borders(Borders::ALL);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    if transfers.is_empty() {
+        f.render_widget(Paragraph::new(tr("No DCC transfers yet.")), inner);
+        return;
+    }
+
+    // Keep the selection on screen when there are more transfers than fit
+    let rows = (inner.height / 2).max(1) as usize;
+    let first = selected.saturating_sub(rows - 1);
+    for (row, (index, transfer)) in transfers
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .enumerate()
+    {
+        let y = inner.y + row as u16 * 2;
+        let mut info = transfer.describe();
+        if transfer.state == TransferState::Active {
+            if let Some(speed) = transfer.speed() {
+                info.push_str(&format!(" {}/s", dcc::format_size(speed as u64)));
+            }
+            if let Some(eta) = transfer.eta() {
+                let secs = eta.as_secs();
+                info.push_str(&format!(" {}:{:02} left", secs / 60, secs % 60));
+            }
+        }
+        let style = if index == selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        f.render_widget(
+            Paragraph::new(Line::styled(info, style)),
+            Rect::new(inner.x, y, inner.width, 1),
+        );
+
+        let color = match transfer.state {
+            TransferState::Done => Color::Green,
+            TransferState::Failed(_) | TransferState::Cancelled => Color::Red,
+            _ => Color::Cyan,
+        };
+        let ratio = if transfer.size == 0 {
+            0.0
+        } else {
+            (transfer.transferred as f64 / transfer.size as f64).min(1.0)
+        };
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(color))
+            .ratio(ratio)
+            .label(format!(
+                "{} / {}",
+                dcc::format_size(transfer.transferred),
+                dcc::format_size(transfer.size)
+            ));
+        if y + 1 < inner.y + inner.height {
+            f.render_widget(gauge, Rect::new(inner.x, y + 1, inner.width, 1));
+        }
+    }
+}
+
+// Pending invitations, one per row
+fn draw_invites(f: &mut Frame, area: Rect, invites: &[Invite], selected: usize) {
+    let block = Block::default()
+        .title(tr("Invitations (Up/Down select, j join, d dismiss)"))
+        .borders(Borders::ALL);
+    if invites.is_empty() {
+        f.render_widget(
+            Paragraph::new(tr("No pending invitations.")).block(block),
+            area,
+        );
+        return;
+    }
+    let lines: Vec<Line> = invites
+        .iter()
+        .enumerate()
+        .map(|(index, invite)| {
+            let style = if index == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::styled(format!("{} from {}", invite.channel, invite.from), style)
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// A channel's bans, one per row, scrolled to keep the highlighted one in view
+fn draw_bans(f: &mut Frame, area: Rect, list: Option<&BanList>, selected: usize) {
+    let block = Block::default()
+        .title(trf(
+            "Bans in {} (Up/Down select, u unban, r reload)",
+            &[&list.map(|l| l.channel.as_str()).unwrap_or_default()],
+        ))
+        .borders(Borders::ALL);
+    let Some(list) = list.filter(|l| !l.entries.is_empty()) else {
+        let text = if list.is_some_and(|l| l.loading) {
+            tr("Asking the server...")
+        } else {
+            tr("No bans.")
+        };
+        f.render_widget(Paragraph::new(text).block(block), area);
+        return;
+    };
+    let height = area.height.saturating_sub(2).max(1) as usize;
+    let lines: Vec<Line> = list
+        .entries
+        .iter()
+        .enumerate()
+        .skip((selected + 1).saturating_sub(height))
+        .map(|(index, entry)| {
+            let style = if index == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(format!("{:<40} ", entry.mask), style),
+                Span::styled(entry.origin(), Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// A channel's ChanServ access list, one entry per row
+fn draw_access(f: &mut Frame, area: Rect, list: Option<&AccessList>, selected: usize) {
+    let channel = list.map(|l| l.channel.as_str()).unwrap_or_default();
+    let block = Block::default()
+        .title(trf(
+            "ChanServ access for {} (Up/Down select, a add, e edit, d remove, r reload)",
+            &[&channel],
+        ))
+        .borders(Borders::ALL);
+    let Some(list) = list.filter(|l| !l.entries.is_empty()) else {
+        let text = match list {
+            Some(AccessList {
+                status: Some(status),
+                ..
+            }) => status.as_str(),
+            Some(AccessList { loading: true, .. }) => tr("Asking ChanServ..."),
+            _ => tr("No entries."),
+        };
+        f.render_widget(Paragraph::new(text).block(block), area);
+        return;
+    };
+    let lines: Vec<Line> = list
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let style = if index == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::styled(format!("{:<32} {}", entry.mask, entry.flags), style)
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_messages(f: &mut Frame, area: Rect, app: &App, buffer: &Buffer, layout: &mut LayoutCache) {
+    let client = &app.client;
+    let settings = app.buffer_settings(&buffer.name);
+    // A channel buffer's channel, in the server buffer the current one
+    let chan = match buffer.kind {
+        BufferKind::Channel => client.channel(&buffer.name),
+        BufferKind::Server => client.channel(&client.current_channel),
+        _ => None,
+    };
+    // Its topic in a bar on top
+    let area = match chan {
+        Some(chan) if !settings.hide_topic && !app.zoomed && area.height > 6 => {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(1)].as_ref())
+                .split(area);
+            let topic = if chan.topic.is_empty() {
+                "(no topic)"
+            } else {
+                &chan.topic
+            };
+            let bar = Paragraph::new(format!(" {}: {}", chan.name, topic))
+                .style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_widget(bar, rows[0]);
+            rows[1]
+        }
+        _ => area,
+    };
+    // The server buffer carries the current channel, give it a nick list on the right.
+    // Not in accessible mode, where rows would read as messages and nicks mixed up
+    let area = match chan {
+        Some(chan)
+            if !settings.hide_nicklist
+                && !app.zoomed
+                && area.width > 60
+                && !app.config.theme.accessible =>
+        {
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(20), Constraint::Length(22)].as_ref())
+                .split(area);
+            draw_nicklist(f, panes[1], app, chan);
+            panes[0]
+        }
+        _ => area,
+    };
+
+    // Chat history
+    let messages_block = Block::default()
+        .title(trf(
+            "[{}] Server: {} ({}) - Channel: {}{}",
+            &[
+                &buffer.name,
+                &if client.server.is_empty() {
+                    tr("Not connected")
+                } else {
+                    &client.server
+                },
+                &state_label(client.state),
+                &channel_label(client),
+                &if app.zoomed {
+                    tr(" (zoomed, Alt+Z)")
+                } else {
+                    ""
+                },
+            ],
+        ))
+        .borders(Borders::ALL);
+
+    // Account for borders
+    let width = (area.width as usize).saturating_sub(2);
+    let height = (area.height as usize).saturating_sub(2);
+    let lines = layout.rows(buffer, width, height, !settings.hide_timestamps);
+    f.render_widget(Paragraph::new(lines).block(messages_block), area);
+}
+
+// Members of `chan`, ops first. Users known to be away are dimmed and marked
+fn draw_nicklist(f: &mut Frame, area: Rect, app: &App, chan: &Channel) {
+    let theme = Theme::named(&app.config.theme.name);
+    let client = &app.client;
+    let members = app.shown_members(chan);
+    let shown = members.len();
+    let selected = app
+        .nick_selected
+        .map(|index| index.min(members.len().saturating_sub(1)));
+    // Scrolled so the highlight stays in view
+    let height = area.height.saturating_sub(2).max(1) as usize;
+    let first = selected.map_or(0, |index| (index + 1).saturating_sub(height));
+    let lines: Vec<Line> = members
+        .into_iter()
+        .enumerate()
+        .skip(first)
+        .take(height)
+        .map(|(index, member)| {
+            let away = client
+                .users
+                .get(&channel::nick_key(&member.nick))
+                .is_some_and(|u| u.away);
+            // Only the highest status is shown, like most clients do
+            let symbol = member.prefix.chars().next().unwrap_or(' ');
+            // The account is only worth the space when it differs from the nick
+            let account = match client.account(&member.nick) {
+                Some(account) if !account.eq_ignore_ascii_case(&member.nick) => {
+                    format!(" [{}]", account)
+                }
+                _ => String::new(),
+            };
+            let line = if away {
+                Line::from(Span::styled(
+                    format!("{}{}{} (away)", symbol, member.nick, account),
+                    Style::default().fg(theme.dim).add_modifier(Modifier::DIM),
+                ))
+            } else {
+                Line::from(Span::styled(
+                    format!("{}{}{}", symbol, member.nick, account),
+                    Style::default().fg(theme.rank(symbol)),
+                ))
+            };
+            if selected == Some(index) {
+                line.patch_style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    // Focused, the border is highlighted and the title shows the filter
+    let block = match &app.nick_filter {
+        Some(filter) if filter.is_empty() => Block::default().title(tr(" Type to filter ")),
+        Some(filter) => Block::default().title(trf(
+            " {}: {} of {} ",
+            &[filter, &shown, &chan.members.len()],
+        )),
+        None => Block::default().title(trf(" {} users ", &[&chan.members.len()])),
+    }
+    .borders(Borders::ALL)
+    .border_style(match app.nick_filter {
+        Some(_) => Style::default().fg(theme.accent),
+        None => Style::default(),
+    });
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// The actions for one nick in a box over the messages, each with the key that picks it
+fn draw_nick_menu(f: &mut Frame, area: Rect, menu: &NickMenu) {
+    let width = (menu.nick.chars().count() as u16 + 4)
+        .max(20)
+        .min(area.width);
+    let height = (NickAction::ALL.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let lines: Vec<Line> = NickAction::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, action)| {
+            let style = if index == menu.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(
+                format!("{} {}", action.key(), tr(action.label())),
+                style,
+            ))
+        })
+        .collect();
+    let block = Block::default()
+        .title(format!(" {} ", menu.nick))
+        .borders(Borders::ALL);
+    f.render_widget(Clear, popup);
+    f.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+// The palette near the top, the query on its first row and the matches below with their
+// help dimmed
+fn draw_palette(f: &mut Frame, area: Rect, app: &App, palette: &Palette) {
+    let width = area.width.min(100);
+    let height = area.height.min(18);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + 1.min(area.height - height),
+        width,
+        height,
+    };
+    let entries = app.palette_entries(&palette.query);
+    let selected = palette.selected.min(entries.len().saturating_sub(1));
+    let rows = height.saturating_sub(3) as usize;
+    let mut lines = vec![Line::from(format!("> {}_", palette.query))];
+    if entries.is_empty() {
+        lines.push(Line::styled(
+            tr("Nothing matches"),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    for (index, entry) in entries
+        .iter()
+        .enumerate()
+        .skip((selected + 1).saturating_sub(rows))
+        .take(rows)
+    {
+        let style = if index == selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:<16}", entry.label), style),
+            Span::styled(
+                format!(" {}", entry.help),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+    }
+    let block = Block::default()
+        .title(tr(
+            " Commands (type to search, Up/Down pick, Enter runs, Esc closes) ",
+        ))
+        .borders(Borders::ALL);
+    f.render_widget(Clear, popup);
+    f.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+// A box in the middle of the chat area, the byte count against TOPICLEN in its title
+fn draw_topic_editor(f: &mut Frame, area: Rect, app: &App, editor: &TopicEditor) {
+    let width = area.width.min(80);
+    let inner = width.saturating_sub(2).max(1) as usize;
+    let shown = editor.shown();
+    let rows = (shown.chars().count() / inner + 1) as u16;
+    let height = (rows + 3).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let used = editor.text.trim().len();
+    let (count, over) = match app.client.topiclen {
+        Some(max) => (trf("{}/{} bytes", &[&used, &max]), used > max),
+        None => (trf("{} bytes", &[&used]), false),
+    };
+    let title_style = if over {
+        Style::default().fg(Theme::named(&app.config.theme.name).alert)
+    } else {
+        Style::default()
+    };
+    let lines = vec![
+        Line::from(shown),
+        Line::styled(
+            tr("Enter sets it, Esc cancels"),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ];
+    let block = Block::default()
+        .title(Span::styled(
+            trf(" Topic for {}, {} ", &[&editor.channel, &count]),
+            title_style,
+        ))
+        .borders(Borders::ALL);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block),
+        popup,
+    );
+}
+
+// Rows `text` takes wrapped at `width`, near enough to keep the end of it in view
+fn wrapped_rows(text: &str, width: u16) -> u16 {
+    let width = width.max(1) as usize;
+    text.split('\n')
+        .map(|line| line.chars().count() / width + 1)
+        .sum::<usize>() as u16
+}
+
+// The composer over the chat area: the text being written on top and below it the
+// messages it will be sent as, numbered with their size
+fn draw_composer(f: &mut Frame, area: Rect, composer: &Composer) {
+    let width = area.width.min(100);
+    let height = area.height.saturating_sub(2).max(3);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let halves = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(popup);
+    let inner = width.saturating_sub(2);
+
+    let text = format!("{}_", composer.text);
+    let scroll = wrapped_rows(&text, inner).saturating_sub(halves[0].height.saturating_sub(2));
+    let editor = Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll, 0))
+        .block(
+            Block::default()
+                .title(trf(
+                    " Compose to {} (Enter new line, Alt+Enter sends, Esc drops it) ",
+                    &[&composer.target],
+                ))
+                .borders(Borders::ALL),
+        );
+
+    let parts = composer.parts();
+    let lines: Vec<Line> = parts
+        .iter()
+        .enumerate()
+        .map(|(index, part)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:>2}. [{}] ", index + 1, part.len()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(part.as_str()),
+            ])
+        })
+        .collect();
+    let preview_text = lines
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let scroll =
+        wrapped_rows(&preview_text, inner).saturating_sub(halves[1].height.saturating_sub(2));
+    let title = match parts.len() {
+        1 => tr(" Sent as 1 message ").to_string(),
+        count => trf(" Sent as {} messages, throttled like pastes ", &[&count]),
+    };
+    let preview = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll, 0))
+        .block(Block::default().title(title).borders(Borders::ALL));
+
+    f.render_widget(Clear, popup);
+    f.render_widget(editor, halves[0]);
+    f.render_widget(preview, halves[1]);
+}
+
+// The help pager over the whole chat area, matches of the last search highlighted and
+// the search being typed on the bottom row
+fn draw_help(f: &mut Frame, area: Rect, help: &HelpPager) {
+    let status = match &help.typing {
+        Some(typing) => format!(" /{} ", typing),
+        None if !help.query.is_empty() && !help.lines.iter().any(|l| help.matches(l)) => {
+            trf(" No match for {} ", &[&help.query])
+        }
+        None => format!(" {}/{} ", help.scroll + 1, help.lines.len()),
+    };
+    let block = Block::default()
+        .title(tr(
+            " Help (Up/Down/PgUp/PgDn scroll, / search, n next match, Esc close) ",
+        ))
+        .title_bottom(status)
+        .borders(Borders::ALL);
+    let height = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = help
+        .lines
+        .iter()
+        .skip(help.scroll)
+        .take(height)
+        .map(|line| {
+            let style = if help.matches(line) {
+                Style::default().fg(Color::Black).bg(Color::Yellow)
+            } else if !line.is_empty() && !line.starts_with(' ') {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Line::styled(line.as_str(), style)
+        })
+        .collect();
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// Messages per hour as a sparkline over today's top talkers as bars, in a box over the
+// messages. Esc closes it
+fn draw_stats(f: &mut Frame, area: Rect, activity: &ChannelActivity) {
+    let popup = Rect {
+        x: area.x + area.width / 8,
+        y: area.y + area.height / 8,
+        width: area.width - area.width / 4,
+        height: area.height - area.height / 4,
+    };
+    let block = Block::default()
+        .title(trf(" {} activity, Esc closes ", &[&activity.name]))
+        .borders(Borders::ALL);
+    let inner = block.inner(popup);
+    f.render_widget(Clear, popup);
+    f.render_widget(block, popup);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(5), Constraint::Min(3)].as_ref())
+        .split(inner);
+
+    let hours = activity.per_hour();
+    let total: u64 = hours.iter().sum();
+    let sparkline = Sparkline::default()
+        .block(Block::default().title(trf(
+            "Messages per hour, last {}h: {}",
+            &[&activity::HOURS, &total],
+        )))
+        .data(&hours)
+        .style(Style::default().fg(Color::Cyan));
+    f.render_widget(sparkline, rows[0]);
+
+    let talkers = activity.top_talkers(10);
+    let bars: Vec<(&str, u64)> = talkers.iter().map(|(n, c)| (n.as_str(), *c)).collect();
+    let chart = BarChart::default()
+        .block(Block::default().title(tr("Top talkers today (UTC)")))
+        .data(&bars)
+        .bar_width(8)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(Color::Yellow))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Yellow));
+    f.render_widget(chart, rows[1]);
+}
+
+// The landing view after connecting: what we know about the network on the left, channels
+// to jump into on the right
+fn draw_dashboard(f: &mut Frame, area: Rect, app: &App) {
+    let (client, favorites) = (&app.client, &app.config.favorites);
+    // One above the other for accessible mode, so each row belongs to one pane
+    let direction = if app.config.theme.accessible {
+        Direction::Vertical
+    } else {
+        Direction::Horizontal
+    };
+    let panes = Layout::default()
+        .direction(direction)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)].as_ref())
+        .split(area);
+
+    let label = Style::default().add_modifier(Modifier::BOLD);
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<9}", name), label),
+            Span::raw(value),
+        ])
+    };
+
+    let mut server_lines = vec![
+        field(
+            tr("Network"),
+            if client.network.is_empty() {
+                tr("Unknown").to_string()
+            } else {
+                client.network.clone()
+            },
+        ),
+        field(tr("Server"), client.server.clone()),
+        field(tr("Nick"), client.nickname.clone()),
+        field(
+            tr("Modes"),
+            if client.user_modes.is_empty() {
+                tr("none").to_string()
+            } else {
+                format!("+{}", client.user_modes)
+            },
+        ),
+        field(
+            tr("Status"),
+            if let Some(message) = &client.away {
+                trf("Away: {}", &[message])
+            } else {
+                let state = state_label(client.state);
+                let mut chars = state.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            },
+        ),
+        Line::raw(""),
+        Line::styled(tr("Message of the day"), label),
+    ];
+
+    // Skip the ASCII-art borders most servers wrap their MOTD in
+    let mut motd = client
+        .motd
+        .iter()
+        .map(|l| l.trim())
+        .filter(|l| l.chars().any(|c| c.is_alphanumeric()))
+        .peekable();
+    if motd.peek().is_none() {
+        server_lines.push(Line::raw(tr("(not received yet)")));
+    }
+    server_lines.extend(motd.map(|l| Line::raw(l.to_string())));
+
+    let server_pane = Paragraph::new(server_lines)
+        .block(Block::default().title(tr("Server")).borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+    f.render_widget(server_pane, panes[0]);
+
+    let mut channel_lines = Vec::new();
+    if favorites.is_empty() {
+        channel_lines.push(Line::raw(tr("No favorite channels configured.")));
+        channel_lines.push(Line::raw(trf(
+            "Add favorites = [\"#channel\"] to {}",
+            &[&Config::path()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "config.toml".to_string())],
+        )));
+    } else {
+        for (i, channel) in favorites.iter().take(9).enumerate() {
+            let mut line = vec![
+                Span::styled(format!("[{}] ", i + 1), label),
+                Span::raw(channel.clone()),
+            ];
+            if client.channel(channel).is_some() {
+                line.push(Span::styled(
+                    tr(" (joined)"),
+                    Style::default().fg(Color::Green),
+                ));
+            }
+            channel_lines.push(Line::from(line));
+        }
+        channel_lines.push(Line::raw(""));
+        channel_lines.push(Line::raw(tr("Press 1-9 to join a favorite.")));
+    }
+    channel_lines.push(Line::raw(tr("Use /join #channel for any other channel.")));
+    channel_lines.push(Line::raw(tr("Alt+Left/Right switches buffers.")));
+
+    let channel_pane = Paragraph::new(channel_lines)
+        .block(Block::default().title(tr("Channels")).borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+    f.render_widget(channel_pane, panes[1]);
+}
@@ -0,0 +1,74 @@
+use crate::app::{App, AppEvent};
+use crate::awaylog::Mention;
+use crate::message::Message;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// The text of a JSON string without its quotes, so a template stays valid JSON whatever
+// was said
+fn escape(text: &str) -> String {
+    let quoted = serde_json::to_string(text).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+// Fills in {nick}, {target}, {text} and {network}
+fn fill(template: &str, mention: &Mention, network: &str) -> String {
+    template
+        .replace("{nick}", &escape(&mention.nick))
+        .replace("{target}", &escape(&mention.place))
+        .replace("{text}", &escape(&mention.text))
+        .replace("{network}", &escape(network))
+}
+
+// POSTs `body` with curl, like paste uploads, and gives back why it failed
+fn post(url: &str, headers: &[String], body: &str) -> Result<(), String> {
+    let mut command = Command::new("curl");
+    command.args(["-sSf", "-H", "Content-Type: application/json"]);
+    for header in headers {
+        command.args(["-H", header]);
+    }
+    let mut child = command
+        .args(["--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(body.as_bytes())
+            .map_err(|e| format!("could not write to curl: {}", e))?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+impl App {
+    // Forwards private messages and highlights to [webhook] url, only while nobody is
+    // reading along unless `always` is set
+    pub(crate) fn webhook(&mut self, msg: &Message) {
+        let config = &self.config.webhook;
+        if config.url.is_empty() || !(config.always || self.missing_out()) {
+            return;
+        }
+        let Some(mention) = self.mention(msg) else {
+            return;
+        };
+        if mention.place != "private" && self.quiet_hours() {
+            return;
+        }
+        let body = fill(&config.template, &mention, &self.client.network);
+        let (url, headers) = (config.url.clone(), config.headers.clone());
+        let events = self.events();
+        thread::spawn(move || {
+            if let Err(error) = post(&url, &headers, &body) {
+                let _ = events.send(AppEvent::WebhookFailed { error });
+            }
+        });
+    }
+}
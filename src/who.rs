@@ -0,0 +1,55 @@
+use crate::app::App;
+use crate::channel;
+use crate::message::Message;
+use std::time::{Duration, Instant};
+
+impl App {
+    // Replies to a WHO sent by IrcClient::poll_who. The client already took what it needs
+    // from them, so they are kept out of the buffer. Returns true for those
+    pub(crate) fn quiet_who(&mut self, msg: &Message) -> bool {
+        let name = match msg.command.as_str() {
+            "352" | "315" => msg.param(1),
+            "354" => msg.param(2),
+            _ => None,
+        };
+        let Some(key) = name.map(channel::nick_key) else {
+            return false;
+        };
+        if msg.command == "315" {
+            return self.client.who_quiet.remove(&key);
+        }
+        self.client.who_quiet.contains(&key)
+    }
+
+    // WHO for one channel at a time, spread so each is asked about once per
+    // [who] poll_minutes
+    pub(crate) fn poll_members(&mut self) {
+        let period = Duration::from_secs(self.config.who.poll_minutes * 60);
+        if period.is_zero() || !self.client.registered || self.client.channels.is_empty() {
+            return;
+        }
+        let mut names: Vec<String> = self
+            .client
+            .channels
+            .values()
+            .filter(|chan| chan.members.len() <= self.config.who.max_members)
+            .map(|chan| chan.name.clone())
+            .collect();
+        if names.is_empty() {
+            return;
+        }
+        names.sort();
+        let step = period / names.len() as u32;
+        if self.who_polled.elapsed() < step {
+            return;
+        }
+        self.who_polled = Instant::now();
+        let name = &names[self.who_next % names.len()];
+        self.who_next = self.who_next.wrapping_add(1);
+        // Still waiting for the last answer, e.g. from a slow server
+        if !self.client.who_quiet.contains(&channel::nick_key(name)) {
+            let name = name.clone();
+            self.client.poll_who(&name);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::app::App;
+use crate::channel;
+use crate::clock;
+use crate::i18n::{tr, trf};
+use crate::message::Message;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+impl App {
+    // /whowas nick [count], who used a nick that is gone now
+    pub(crate) fn whowas_command(&mut self, args: &str) {
+        let mut words = args.split_whitespace();
+        let (Some(nick), count) = (words.next(), words.next()) else {
+            self.buffers
+                .push_local(tr("Usage: /whowas nick [count]").to_string());
+            return;
+        };
+        if count.is_some_and(|count| count.parse::<u32>().is_err()) {
+            self.buffers
+                .push_local(tr("Usage: /whowas nick [count]").to_string());
+            return;
+        }
+        let line = match count {
+            Some(count) => format!("WHOWAS {} {}", nick, count),
+            None => format!("WHOWAS {}", nick),
+        };
+        match self
+            .client
+            .send_labeled(&line, &format!("/whowas {}", nick))
+        {
+            Ok(_) => {
+                self.whowas.insert(channel::nick_key(nick), Vec::new());
+            }
+            Err(e) => self
+                .buffers
+                .push_local(trf("Error sending WHOWAS: {}", &[&e])),
+        }
+    }
+
+    // Collects RPL_WHOWASUSER (314), RPL_WHOISSERVER (312) and the rest for a /whowas,
+    // shown as one block at RPL_ENDOFWHOWAS (369). Returns true for those
+    pub(crate) fn whowas_reply(&mut self, msg: &Message) -> bool {
+        let Some(nick) = msg.param(1) else {
+            return false;
+        };
+        let key = channel::nick_key(nick);
+        // 312 and friends answer /whois too
+        if self.whois_pending.contains(&key) {
+            return false;
+        }
+        let Some(lines) = self.whowas.get_mut(&key) else {
+            return false;
+        };
+        let param = |i| msg.param(i).unwrap_or_default();
+        match msg.command.as_str() {
+            // <me> <nick> <user> <host> * :<realname>
+            "314" => lines.push(format!("{}!{}@{} ({})", nick, param(2), param(3), param(5))),
+            // <me> <nick> <server> :<when they left, on most servers>
+            "312" => lines.push(format!("  on {}, {}", param(2), param(3))),
+            // ERR_WASNOSUCHNICK
+            "406" => lines.push(trf("No one used {} lately", &[&nick])),
+            "369" => {
+                let lines = self.whowas.remove(&key).unwrap_or_default();
+                let server_buffer = self.buffers.server_mut();
+                server_buffer.push(format!("---- WHOWAS {} ----", nick));
+                for line in lines {
+                    server_buffer.push(line);
+                }
+            }
+            // RPL_WHOISACCOUNT, RPL_WHOISACTUALLY and whatever else a server adds
+            command if command.starts_with(['2', '3']) && command.len() == 3 => {
+                let rest: Vec<&str> = msg.params.iter().skip(2).map(|p| p.as_str()).collect();
+                lines.push(format!("  {}", rest.join(" ")));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // ERR_NOSUCHNICK for a /whois: the nick is gone, /whowas may still know who it was
+    // when we saw them around
+    pub(crate) fn whois_missing(&mut self, msg: &Message) {
+        if msg.command != "401" {
+            return;
+        }
+        let Some(nick) = msg.param(1) else {
+            return;
+        };
+        if !self.whois_pending.remove(&channel::nick_key(nick)) {
+            return;
+        }
+        let Some(sighting) = self.seen.get(&self.network_id(), nick) else {
+            return;
+        };
+        let time = UNIX_EPOCH + Duration::from_secs(sighting.time);
+        let elapsed = SystemTime::now().duration_since(time).unwrap_or_default();
+        self.buffers.server_mut().push(format!(
+            "{} is not online, last seen {} ago, {}. /whowas {} shows who it was",
+            nick,
+            clock::ago(elapsed),
+            sighting.action,
+            nick
+        ));
+    }
+}